@@ -0,0 +1,583 @@
+// A minimal language server on stdin/stdout. Documents are synced incrementally, a change only
+// parses the statements around it again and splices them into the ledger, see
+// `Document::splice`. Each document is a ledger of its own, together with the files it includes,
+// which are parsed in full. Diagnostics are published for the document only, those of included
+// files when they are opened.
+use beanrust::core::ledger::Ledger;
+use beanrust::core::rewrite;
+use beanrust::core::transform;
+use beanrust::core::types::EntryVariant;
+use beanrust::core::validation::{self, UnbalancedTransactions};
+use beanrust::io::parser::error::{ParseError, Severity};
+use beanrust::io::parser::{
+    FileDirective, IncrementalParser, Limits, ParsedEntries, StatementEdit, StatementResult,
+};
+use beanrust::io::report::{AmountFormatter, ReportOptions};
+use jiff::civil::Date;
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+struct Document {
+    // The text as edited by the client, with the parse result of each statement.
+    parser: IncrementalParser,
+    ledger: Ledger,
+    // Parse errors, of the included files as well, and those of the transforms. The errors of
+    // `validation::validate` are found again for each change.
+    errors: Vec<ParseError>,
+    // False if the document pushes tags, or it or its includes have customs transforming other
+    // entries: changes then need a new ledger.
+    spliceable: bool,
+}
+
+// Customs removed by the transforms of `Server::update`, which change other entries.
+const TRANSFORMS: [&str; 3] = ["alias", "recur", "rewrite"];
+
+impl Document {
+    // Splices the statements re-parsed by `edit` into the ledger, with the transforms of
+    // `Server::update` that only change the entry itself. Returns false, leaving the ledger as
+    // it was, if it must be built again: when the statements have directives or customs
+    // affecting the entries around them.
+    fn splice(&mut self, path: &Path, edit: StatementEdit) -> bool {
+        let statements = self.parser.statements();
+        let added = &statements[edit.start..edit.start + edit.added];
+        let local = |r: &StatementResult| {
+            let custom = match &r.entry {
+                Some(EntryVariant::Custom(c)) => TRANSFORMS.contains(&c.name.as_str()),
+                _ => false,
+            };
+            r.directive.is_none() && !custom
+        };
+        if !self.spliceable || !edit.removed.iter().chain(added).all(|s| local(&s.result)) {
+            return false;
+        }
+        let mut entries = ParsedEntries::default();
+        for s in added {
+            entries.push_result(s.result.clone());
+        }
+        let mut errors = std::mem::take(&mut entries.errors);
+        errors.extend(validation::check_balances(
+            &mut entries,
+            UnbalancedTransactions::Flag,
+        ));
+
+        // The errors of the removed statements go, those after them move with their lines.
+        self.errors.retain_mut(|e| {
+            if e.file.as_deref().is_none_or(|file| file == path)
+                && let Some(line) = &mut e.line
+            {
+                if edit.lines.contains(line) {
+                    return false;
+                }
+                if *line >= edit.lines.end {
+                    *line = line.saturating_add_signed(edit.line_delta);
+                }
+            }
+            true
+        });
+        self.errors.extend(errors);
+        self.ledger
+            .shift_lines(path, edit.lines.end, edit.line_delta);
+        self.ledger.splice(
+            statements[..edit.start]
+                .iter()
+                .filter_map(|s| s.result.entry.as_ref()),
+            edit.removed.iter().filter_map(|s| s.result.entry.as_ref()),
+            entries,
+        );
+        true
+    }
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, Document>,
+}
+
+pub fn run() -> Result<(), String> {
+    serve(&mut std::io::stdin().lock(), &mut std::io::stdout().lock())
+}
+
+fn serve(input: &mut impl BufRead, out: &mut impl Write) -> Result<(), String> {
+    let mut server = Server::default();
+    while let Some(body) = read_message(input)? {
+        // A body that is no JSON is answered with a parse error, the next message may be fine.
+        let message: Value = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                let error = json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": -32700, "message": format!("Invalid message: {e}") },
+                });
+                write_message(out, &error)?;
+                continue;
+            }
+        };
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let result = match method {
+            "initialize" => Some(json!({
+                "capabilities": {
+                    "textDocumentSync": 2,
+                    "completionProvider": {},
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                },
+            })),
+            "shutdown" => Some(Value::Null),
+            "exit" => return Ok(()),
+            "textDocument/didOpen" => {
+                let doc = &params["textDocument"];
+                if let (Some(uri), Some(text)) = (doc["uri"].as_str(), doc["text"].as_str()) {
+                    let parser = IncrementalParser::for_file(text.to_string(), &uri_to_path(uri));
+                    server.update(out, uri, parser)?;
+                }
+                None
+            }
+            "textDocument/didChange" => {
+                if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                    server.change(out, uri, &params["contentChanges"])?;
+                }
+                None
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                server.documents.remove(uri);
+                None
+            }
+            "textDocument/completion" => Some(server.completion()),
+            "textDocument/hover" => Some(server.hover(params)),
+            "textDocument/definition" => Some(server.definition(params)),
+            _ => None,
+        };
+        // Notifications have no id and get no response.
+        let Some(id) = message.get("id") else {
+            continue;
+        };
+        let response = match result {
+            Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            None => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": format!("Unsupported method `{method}`") },
+            }),
+        };
+        write_message(out, &response)?;
+    }
+    Ok(())
+}
+
+impl Server {
+    // Applies the changes of a document in order. A change with a range replaces that part of
+    // the text, one without replaces the whole text and builds the ledger again.
+    fn change(&mut self, out: &mut impl Write, uri: &str, changes: &Value) -> Result<(), String> {
+        let Some(mut document) = self.documents.remove(uri) else {
+            return Ok(());
+        };
+        let path = uri_to_path(uri);
+        let mut rebuild = false;
+        for change in changes.as_array().into_iter().flatten() {
+            let text = change["text"].as_str().unwrap_or_default();
+            match change.get("range") {
+                Some(range) => {
+                    let parser = &mut document.parser;
+                    let start = offset_at(parser.text(), &range["start"]);
+                    let end = offset_at(parser.text(), &range["end"]).max(start);
+                    let edit = parser.edit_statements(start..end, text);
+                    rebuild = rebuild || !document.splice(&path, edit);
+                }
+                None => {
+                    document.parser = IncrementalParser::for_file(text.to_string(), &path);
+                    rebuild = true;
+                }
+            }
+        }
+        if rebuild {
+            return self.update(out, uri, document.parser);
+        }
+        self.publish(out, uri, document)
+    }
+
+    // Builds the ledger of a document from scratch and publishes its diagnostics.
+    fn update(
+        &mut self,
+        out: &mut impl Write,
+        uri: &str,
+        parser: IncrementalParser,
+    ) -> Result<(), String> {
+        let mut entries = parser.to_parsed_entries_with_includes();
+        let pushes_tags = parser
+            .statements()
+            .iter()
+            .any(|s| matches!(s.result.directive, Some((FileDirective::PushTag(_), _))));
+        let transforms = entries
+            .custom
+            .iter()
+            .any(|c| TRANSFORMS.contains(&c.name.as_str()));
+        // Unbalanced transactions are kept, so completion and hover still see them.
+        let mut errors = entries.errors.clone();
+        errors.extend(transform::resolve_aliases(&mut entries));
+        errors.extend(transform::expand_recurring(&mut entries));
+        errors.extend(rewrite::apply_rewrite_rules(&mut entries, &[]));
+        errors.extend(validation::check_balances(
+            &mut entries,
+            UnbalancedTransactions::Flag,
+        ));
+        let document = Document {
+            parser,
+            ledger: Ledger::new(entries),
+            errors,
+            spliceable: !pushes_tags && !transforms,
+        };
+        self.publish(out, uri, document)
+    }
+
+    // Publishes the diagnostics of a document and keeps it.
+    fn publish(
+        &mut self,
+        out: &mut impl Write,
+        uri: &str,
+        document: Document,
+    ) -> Result<(), String> {
+        let path = uri_to_path(uri);
+        let text = document.parser.text();
+        let validation_errors = validation::validate(&document.ledger);
+        let diagnostics: Vec<Value> = document
+            .errors
+            .iter()
+            .chain(&validation_errors)
+            // Lines and columns of errors in included files are not positions in `text`.
+            .filter(|e| e.file.as_deref().is_none_or(|file| file == path))
+            .map(|e| {
+                let line = e.line.unwrap_or(1).saturating_sub(1);
+                // Error columns count chars, LSP positions UTF-16 code units.
+                let text = text.lines().nth(line).unwrap_or_default();
+                let start: usize = text
+                    .chars()
+                    .take(e.column.unwrap_or(1).saturating_sub(1))
+                    .map(char::len_utf16)
+                    .sum();
+                let end = text.encode_utf16().count();
+                json!({
+                    "range": {
+                        "start": { "line": line, "character": start },
+                        "end": { "line": line, "character": end.max(start) },
+                    },
+                    "severity": match e.code.severity() {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                        Severity::Info => 3,
+                    },
+                    "code": e.code.as_str(),
+                    "source": "beanrust",
+                    "message": e.context,
+                })
+            })
+            .collect();
+        write_message(
+            out,
+            &json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/publishDiagnostics",
+                "params": { "uri": uri, "diagnostics": diagnostics },
+            }),
+        )?;
+        self.documents.insert(uri.to_string(), document);
+        Ok(())
+    }
+
+    fn entries(&self) -> impl Iterator<Item = &ParsedEntries> {
+        self.documents.values().map(|d| d.ledger.entries())
+    }
+
+    // Accounts (kind 6, variable), currencies (kind 21, constant) and payees (kind 1, text)
+    // of all open documents. Clients filter by the typed prefix themselves.
+    fn completion(&self) -> Value {
+        let mut accounts = BTreeSet::new();
+        let mut currencies = BTreeSet::new();
+        let mut payees = BTreeSet::new();
+        for entries in self.entries() {
+            for o in &entries.open {
+                accounts.insert(o.account.to_string());
+                currencies.extend(o.allowed_currencies.iter().flatten().map(|c| c.to_string()));
+            }
+            currencies.extend(entries.commodity.iter().map(|c| c.currency.to_string()));
+            for t in &entries.transactions {
+                payees.extend(t.payee.clone());
+                for p in &t.postings {
+                    accounts.insert(p.account.to_string());
+                    currencies.insert(p.amount.currency.to_string());
+                }
+            }
+        }
+        let items = |labels: BTreeSet<String>, kind: u32| {
+            labels
+                .into_iter()
+                .map(move |label| json!({ "label": label, "kind": kind }))
+        };
+        Value::Array(
+            items(accounts, 6)
+                .chain(items(currencies, 21))
+                .chain(items(payees, 1))
+                .collect(),
+        )
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let Some((document, account)) = self.account_at(params) else {
+            return Value::Null;
+        };
+        let balance = document.ledger.balance(account, Date::MAX);
+        if balance.is_empty() {
+            return Value::Null;
+        }
+        let options = ReportOptions::default();
+        let formatter = AmountFormatter::new(&document.ledger, &options);
+        let amounts: Vec<String> = balance.iter().map(|a| formatter.format(a)).collect();
+        json!({ "contents": format!("{account}: {}", amounts.join(", ")) })
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let Some((document, account)) = self.account_at(params) else {
+            return Value::Null;
+        };
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let open = document
+            .ledger
+            .entries()
+            .open
+            .iter()
+            .find(|o| *o.account == *account);
+        match open {
+            Some(o) => {
+                let uri = o.span.file.as_deref().map_or(uri.to_string(), path_to_uri);
+                // Entries not read from a file, e.g. from `add_opening_balances`, have no lines.
+                let line = o.span.lines.start.saturating_sub(1);
+                json!({
+                    "uri": uri,
+                    "range": {
+                        "start": { "line": line, "character": 0 },
+                        "end": { "line": line, "character": 0 },
+                    },
+                })
+            }
+            None => Value::Null,
+        }
+    }
+
+    // The document and the whitespace separated word at the position of a request.
+    fn account_at(&self, params: &Value) -> Option<(&Document, &str)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let document = self.documents.get(uri)?;
+        let line = document
+            .parser
+            .text()
+            .lines()
+            .nth(params["position"]["line"].as_u64()? as usize)?;
+        let character = params["position"]["character"].as_u64()? as usize;
+        Some((document, word_at(line, character)?))
+    }
+}
+
+// The word around `character`, counted in UTF-16 code units as LSP positions are.
+fn word_at(line: &str, character: usize) -> Option<&str> {
+    let offset = utf16_offset(line, character);
+    let start = line[..offset]
+        .rfind(char::is_whitespace)
+        .map_or(0, |i| i + 1);
+    let end = line[offset..]
+        .find(char::is_whitespace)
+        .map_or(line.len(), |i| offset + i);
+    let word = &line[start..end];
+    (!word.is_empty()).then_some(word)
+}
+
+// The byte offset of an LSP position in `text`. Positions past the end of a line or of the
+// text are clamped to it.
+fn offset_at(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or_default();
+    let character = position["character"].as_u64().unwrap_or_default() as usize;
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let rest = &text[start..];
+    start + utf16_offset(&rest[..rest.find('\n').unwrap_or(rest.len())], character)
+}
+
+// The byte offset in `line` of the char at `character`, counted in UTF-16 code units as LSP
+// positions are.
+fn utf16_offset(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    line.char_indices()
+        .find(|(_, c)| {
+            units += c.len_utf16();
+            units > character
+        })
+        .map_or(line.len(), |(i, _)| i)
+}
+
+fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    // Decode percent escapes such as %20 for spaces.
+    let mut bytes = vec![];
+    let mut it = path.bytes();
+    while let Some(b) = it.next() {
+        let escaped = (b == b'%')
+            .then(|| {
+                let hex = [it.clone().next()?, it.clone().nth(1)?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()
+            })
+            .flatten();
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                it.nth(1);
+            }
+            None => bytes.push(b),
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = "file://".to_string();
+    for b in path.to_string_lossy().bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{b:02X}"));
+        }
+    }
+    uri
+}
+
+// The body of the next message, None at the end of the input.
+fn read_message(input: &mut impl BufRead) -> Result<Option<Vec<u8>>, String> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).map_err(|e| e.to_string())? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or("Missing Content-Length header")?;
+    // A message holds at most one document, so its body is bounded like a file before allocating.
+    let max_length = Limits::default().max_file_size;
+    if length as u64 > max_length {
+        return Err(format!(
+            "Content-Length of {length} bytes is more than the limit of {max_length}"
+        ));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).map_err(|e| e.to_string())?;
+    Ok(Some(body))
+}
+
+fn write_message(out: &mut impl Write, message: &Value) -> Result<(), String> {
+    let body = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{body}", body.len())
+        .and_then(|_| out.flush())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{body}", body.len())
+    }
+
+    #[test]
+    fn test_splice_changes() {
+        let uri = "file:///ledger/main.beancount";
+        let text = "2024-01-01 open Assets:Cash
+
+2024-01-02 *
+  Assets:Cash -5 CHF
+  Expenses:Food 5 CHF
+
+2024-01-03 balance Assets:Cash 0 CHF
+";
+        let parser = IncrementalParser::for_file(text.to_string(), &uri_to_path(uri));
+        let mut server = Server::default();
+        server.update(&mut vec![], uri, parser).unwrap();
+        let range = |line: u32, start: u32, end: u32| {
+            json!({
+                "start": { "line": line, "character": start },
+                "end": { "line": line, "character": end },
+            })
+        };
+        // Unbalance the transaction, then open its expense account above it.
+        let changes = json!([
+            { "range": range(4, 16, 17), "text": "4" },
+            { "range": range(1, 0, 0), "text": "2024-01-01 open Expenses:Food\n" },
+        ]);
+        let diagnostics = |out: Vec<u8>| {
+            let out = String::from_utf8(out).unwrap();
+            let message: Value =
+                serde_json::from_str(out.split_once("\r\n\r\n").unwrap().1).unwrap();
+            let mut diagnostics: Vec<String> = message["params"]["diagnostics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(Value::to_string)
+                .collect();
+            diagnostics.sort();
+            diagnostics
+        };
+        let mut out = vec![];
+        server.change(&mut out, uri, &changes).unwrap();
+        let spliced = diagnostics(out);
+        let document = &server.documents[uri];
+        let text = document.parser.text().to_string();
+        assert!(text.contains("Expenses:Food\n\n2024-01-02 *"));
+        let (normalized, by_date) = (
+            document.ledger.normalize(),
+            document.ledger.by_date().to_vec(),
+        );
+
+        let mut out = vec![];
+        let parser = IncrementalParser::for_file(text, &uri_to_path(uri));
+        server.update(&mut out, uri, parser).unwrap();
+        assert_eq!(spliced, diagnostics(out));
+        assert_eq!(spliced.len(), 2, "{spliced:?}");
+        let ledger = &server.documents[uri].ledger;
+        assert_eq!(normalized, ledger.normalize());
+        assert_eq!(by_date, ledger.by_date());
+    }
+
+    #[test]
+    fn test_invalid_message() {
+        let shutdown = frame(r#"{"jsonrpc":"2.0","id":1,"method":"shutdown"}"#);
+        let input = format!("{}{shutdown}", frame("{not json"));
+        let mut out = vec![];
+        serve(&mut input.as_bytes(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let responses: Vec<Value> = out
+            .split("Content-Length: ")
+            .skip(1)
+            .map(|m| serde_json::from_str(m.split_once("\r\n\r\n").unwrap().1).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], Value::Null);
+        assert_eq!(responses[0]["error"]["code"], -32700);
+        assert_eq!(
+            responses[1],
+            json!({ "jsonrpc": "2.0", "id": 1, "result": null })
+        );
+    }
+}
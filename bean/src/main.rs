@@ -1,10 +1,885 @@
-use beanrust::*;
+use beanrust::core::ledger::Ledger;
+use beanrust::core::ledger_set::LedgerSet;
+use beanrust::core::plugin::{PluginRegistry, Rewrite};
+use beanrust::core::rewrite::{self, RewriteRule};
+use beanrust::core::script::{Scripts, TransactionScript};
+use beanrust::core::transform;
+use beanrust::core::types::{EntryVariant, Transaction};
+use beanrust::core::validation::{self, UnbalancedTransactions};
+use beanrust::io::chart::{NET_WORTH_ACCOUNTS, monthly_balances, series_to_json, svg_sparkline};
+use beanrust::io::config::Config;
+use beanrust::io::decode::{InvalidUtf8, read_source};
+use beanrust::io::format::{FormatOptions, format_ledger, rename_account_in_text};
+use beanrust::io::html::html_report;
+use beanrust::io::import::categorize::{Categorizer, categorize};
+use beanrust::io::import::json::{JsonImportConfig, import_transactions};
+use beanrust::io::import::remove_known;
+use beanrust::io::import::routing::{Routing, append_routed};
+use beanrust::io::import::state::ImportState;
+use beanrust::io::parser::error::{ErrorCode, ParseError, Severity};
+use beanrust::io::parser::{IncrementalParser, ParseOptions};
+use beanrust::io::printer::PrintOptions;
+use beanrust::io::report::{
+    ReportOptions, TODO_ACCOUNTS, balances_report, consolidated_report, events_report,
+    missing_prices_report, register_report, stale_assertions_report, uncategorized_report,
+};
+use beanrust::io::{cache, compat, parser};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-fn main() {
+mod lsp;
+mod review;
+
+const USAGE: &str = "usage:
+    bean check <ledger> [--compat beancount] [--format default|bean-check|json]
+        [--cache <file>] [--allow <code>]... [--deny warnings|<code>]...
+        [--encoding utf8|lossy|latin1] [--unbalanced reject|flag] [--rules <file>]
+        [--auto-open] [--rounding half-even|half-up] [--rounding-scale <n>]
+        [--max-scale <n>] [--documents <dir>] [--no-future] [--budgets]
+    bean import --json <config>|--importer <name> <input> [--route <path template>] [--skip-known <ledger>]
+        [--suggest <ledger>] [--min-confidence <n>] [--interactive] [--state <file>]
+    bean format <ledger> [--amount-column <n>] [--indent <n>] [--sort]
+    bean print <ledger> [--resolve-pads] [--resolve-aliases] [--expand-recurring] [--rewrite]
+        [--rules <file>] [--assign-ids]
+    bean balances <ledger> [--date <date>] [<report options>]
+    bean consolidate <ledger>... [--currency <currency>] [--date <date>] [<report options>]
+    bean register <ledger> <account> [--exclude-pending] [<report options>]
+    bean uncategorized <ledger> [--account <account>]... [<report options>]
+    bean events <ledger> <name>... [--from <date>] [--to <date>]
+    bean missing-prices <ledger> --currency <currency> [--max-age <days>] [--from <date>]
+        [--to <date>]
+    bean stale-assertions <ledger> [--max-age <days>] [--date <date>]
+    bean chart <ledger> --currency <currency> [--account <account>]... [--from <date>]
+        [--to <date>] [--svg]
+    bean html <ledger> [--from <date>] [--to <date>] [<report options>]
+    bean rename <ledger> <old account> <new account> [--write]
+    bean auto-open <ledger> [--write]
+    bean depreciate <ledger> <account> --months <n>
+        [--method straight-line|declining-balance]
+    bean lsp
+
+report options: [--symbol <currency>=<symbol>]... [--locale <locale>]";
+
+fn main() -> ExitCode {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
-    let e = core::types::Entry {
-        date: "foo".to_string(),
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<ExitCode, String> {
+    match args.first().map(String::as_str) {
+        Some("check") => check(&args[1..]),
+        Some("import") => import(&args[1..]),
+        Some("format") => format(&args[1..]),
+        Some("print") => print(&args[1..]),
+        Some("balances") => balances(&args[1..]),
+        Some("consolidate") => consolidate(&args[1..]),
+        Some("register") => register(&args[1..]),
+        Some("uncategorized") => uncategorized(&args[1..]),
+        Some("events") => events(&args[1..]),
+        Some("missing-prices") => missing_prices(&args[1..]),
+        Some("stale-assertions") => stale_assertions(&args[1..]),
+        Some("chart") => chart(&args[1..]),
+        Some("html") => html(&args[1..]),
+        Some("depreciate") => depreciate(&args[1..]),
+        Some("rename") => rename(&args[1..]),
+        Some("auto-open") => auto_open(&args[1..]),
+        Some("lsp") => lsp::run().map(|()| ExitCode::SUCCESS),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn check(args: &[String]) -> Result<ExitCode, String> {
+    let mut ledger = None;
+    let mut compat = false;
+    let mut format = "default";
+    let mut cache_path = None;
+    let mut allowed: Vec<ErrorCode> = vec![];
+    let mut deny_warnings = false;
+    let mut denied: Vec<ErrorCode> = vec![];
+    let mut encoding = InvalidUtf8::Fail;
+    let mut unbalanced = UnbalancedTransactions::Reject;
+    let mut rules = vec![];
+    let mut auto_open = false;
+    let mut no_future = false;
+    let mut budgets = false;
+    let mut max_scale = None;
+    let mut documents = None;
+    let mut options = ParseOptions::default();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--compat" => match it.next().map(String::as_str) {
+                Some("beancount") => compat = true,
+                other => return Err(format!("Unsupported --compat target: {other:?}")),
+            },
+            "--format" => match it.next().map(String::as_str) {
+                Some(f @ ("default" | "bean-check" | "json")) => format = f,
+                other => return Err(format!("Unsupported --format: {other:?}")),
+            },
+            "--cache" => cache_path = Some(it.next().ok_or(USAGE)?),
+            "--allow" => allowed.push(it.next().ok_or(USAGE)?.parse()?),
+            "--deny" => match it.next().map(String::as_str) {
+                Some("warnings") => deny_warnings = true,
+                Some(code) => denied.push(code.parse()?),
+                None => return Err(USAGE.to_string()),
+            },
+            "--encoding" => {
+                encoding = match it.next().map(String::as_str) {
+                    Some("utf8") => InvalidUtf8::Fail,
+                    Some("lossy") => InvalidUtf8::Replace,
+                    Some("latin1") => InvalidUtf8::Latin1,
+                    other => return Err(format!("Unsupported --encoding: {other:?}")),
+                }
+            }
+            "--unbalanced" => {
+                unbalanced = match it.next().map(String::as_str) {
+                    Some("reject") => UnbalancedTransactions::Reject,
+                    Some("flag") => UnbalancedTransactions::Flag,
+                    other => return Err(format!("Unsupported --unbalanced: {other:?}")),
+                }
+            }
+            "--rules" => rules = read_rules(it.next().ok_or(USAGE)?)?,
+            "--auto-open" => auto_open = true,
+            "--no-future" => no_future = true,
+            "--budgets" => budgets = true,
+            "--max-scale" => {
+                let value = it.next().ok_or(USAGE)?;
+                max_scale = Some(
+                    value
+                        .parse()
+                        .map_err(|e| format!("Invalid --max-scale `{value}`: {e}"))?,
+                );
+            }
+            "--documents" => documents = Some(PathBuf::from(it.next().ok_or(USAGE)?)),
+            "--rounding" => options.rounding.mode = it.next().ok_or(USAGE)?.parse()?,
+            "--rounding-scale" => {
+                let value = it.next().ok_or(USAGE)?;
+                options.rounding.scale = value
+                    .parse()
+                    .map_err(|e| format!("Invalid --rounding-scale `{value}`: {e}"))?;
+            }
+            _ if ledger.is_none() => ledger = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let ledger = ledger.ok_or(USAGE)?;
+    // The flags take precedence over the project configuration.
+    let config = Config::for_ledger(Path::new(ledger))?;
+    for code in &config.lint.allow {
+        allowed.push(code.parse()?);
+    }
+    for code in &config.lint.deny {
+        match code.as_str() {
+            "warnings" => deny_warnings = true,
+            code => denied.push(code.parse()?),
+        }
+    }
+    max_scale = max_scale.or(config.lint.max_scale);
+    no_future |= config.lint.no_future;
+    budgets |= config.lint.budgets;
+    documents = documents.or(config.documents.clone());
+    options.limits = config.limits;
+    let mut plugins: Vec<&str> = config.plugins.iter().map(String::as_str).collect();
+    if auto_open && !plugins.contains(&"auto-open") {
+        plugins.push("auto-open");
+    }
+    let mut registry = PluginRegistry::new();
+    registry.register(Rewrite { rules });
+    registry.register(Scripts {
+        scripts: load_scripts(&config)?,
+    });
+    let entries = match (cache_path, encoding) {
+        (Some(cache_path), InvalidUtf8::Fail) => {
+            cache::parse_entries_cached(Path::new(ledger), Path::new(cache_path), &options)
+        }
+        (Some(_), _) => return Err("--cache requires UTF-8 input".to_string()),
+        (None, InvalidUtf8::Fail) => {
+            parser::parse_entries_from_file_parallel(Path::new(ledger), &options)
+        }
+        (None, encoding) => {
+            read_source(Path::new(ledger), encoding).and_then(|(input, warning)| {
+                let mut entries =
+                    parser::parse_entries_from_string_parallel(input, Path::new(ledger), &options)?;
+                entries.errors.splice(0..0, warning);
+                Ok(entries)
+            })
+        }
+    }
+    .map_err(|e| e.to_string())?;
+
+    let severity = |code: ErrorCode| match code.severity() {
+        _ if denied.contains(&code) => Severity::Error,
+        Severity::Warning if deny_warnings => Severity::Error,
+        severity => severity,
+    };
+    let mut problems = 0;
+    if compat {
+        for issue in compat::check_beancount_compat(&entries) {
+            println!("{issue}");
+            problems += 1;
+        }
+    } else {
+        let mut ledger_entries = Ledger::new(entries);
+        let mut validation_errors = registry.run(
+            &["resolve-aliases", "expand-recurring", "rewrite", "scripts"],
+            &mut ledger_entries,
+        )?;
+        validation_errors.extend(
+            ledger_entries.modify(|entries| validation::check_balances(entries, unbalanced)),
+        );
+        // The configured plugins see balanced transactions, as in beancount.
+        validation_errors.extend(registry.run(&plugins, &mut ledger_entries)?);
+        validation_errors.extend(registry.run_directives(&mut ledger_entries));
+        validation_errors.extend(validation::validate(&ledger_entries));
+        if no_future {
+            validation_errors.extend(validation::check_future(&ledger_entries, config.today()?));
+        }
+        if budgets {
+            validation_errors.extend(validation::check_budgets(&ledger_entries, config.today()?));
+        }
+        if let Some(max_scale) = max_scale {
+            validation_errors.extend(validation::check_scale(&ledger_entries, max_scale));
+        }
+        if let Some(documents) = documents {
+            validation_errors.extend(validation::check_documents(&ledger_entries, &documents));
+        }
+        let errors = ledger_entries
+            .entries()
+            .errors
+            .iter()
+            .chain(&validation_errors);
+        for e in errors.filter(|e| !allowed.contains(&e.code)) {
+            let severity = severity(e.code);
+            match format {
+                "bean-check" => eprintln!("{}", format_bean_check_error(ledger, e)),
+                // JSON lines, one diagnostic per line.
+                "json" => println!("{}", e.to_json(severity)),
+                _ if severity == Severity::Error => println!("{e}"),
+                _ => println!("{severity}: {e}"),
+            }
+            // Warnings and infos are reported, but don't fail the check.
+            if severity == Severity::Error {
+                problems += 1;
+            }
+        }
+    }
+    log::info!("Checked {ledger}: {problems} problem(s) found");
+    Ok(if problems == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+fn format(args: &[String]) -> Result<ExitCode, String> {
+    let mut ledger = None;
+    let mut options = FormatOptions::default();
+    let mut sort = false;
+    let mut it = args.iter();
+    let number = |arg: Option<&String>| {
+        arg.and_then(|n| n.parse().ok())
+            .ok_or_else(|| USAGE.to_string())
+    };
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--amount-column" => options.amount_column = number(it.next())?,
+            "--indent" => options.indent_width = number(it.next())?,
+            "--sort" => sort = true,
+            _ if ledger.is_none() => ledger = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let ledger = ledger.ok_or(USAGE)?;
+    if sort {
+        // Entries that fail to parse can't be printed, refuse rather than drop them.
+        let entries =
+            parser::parse_entries_from_file(Path::new(ledger)).map_err(|e| e.to_string())?;
+        if let Some(e) = entries.errors.first() {
+            return Err(format!(
+                "Unable to sort {ledger}, fix its errors first:\n{e}"
+            ));
+        }
+        let print_options = PrintOptions {
+            indent_width: options.indent_width,
+            amount_column: options.align_currencies.then_some(options.amount_column),
+            ..PrintOptions::aligned()
+        };
+        print!("{}", Ledger::new(entries).print_by_month(&print_options));
+        return Ok(ExitCode::SUCCESS);
+    }
+    let input =
+        std::fs::read_to_string(ledger).map_err(|e| format!("Unable to read {ledger}: {e}"))?;
+    print!("{}", format_ledger(&input, &options));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print(args: &[String]) -> Result<ExitCode, String> {
+    let mut ledger = None;
+    let mut resolve_pads = false;
+    let mut resolve_aliases = false;
+    let mut expand_recurring = false;
+    let mut rewrite = false;
+    let mut rules = vec![];
+    let mut assign_ids = false;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--resolve-pads" => resolve_pads = true,
+            "--resolve-aliases" => resolve_aliases = true,
+            "--expand-recurring" => expand_recurring = true,
+            "--rewrite" => rewrite = true,
+            "--assign-ids" => assign_ids = true,
+            "--rules" => {
+                rules = read_rules(it.next().ok_or(USAGE)?)?;
+                rewrite = true;
+            }
+            _ if ledger.is_none() => ledger = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let ledger = ledger.ok_or(USAGE)?;
+    let mut entries =
+        parser::parse_entries_from_file(Path::new(ledger)).map_err(|e| e.to_string())?;
+    if let Some(e) = entries.errors.first() {
+        return Err(format!(
+            "Unable to print {ledger}, fix its errors first:\n{e}"
+        ));
+    }
+    if resolve_aliases && let Some(e) = transform::resolve_aliases(&mut entries).first() {
+        return Err(format!("Unable to resolve aliases:\n{e}"));
+    }
+    if expand_recurring && let Some(e) = transform::expand_recurring(&mut entries).first() {
+        return Err(format!("Unable to expand recurring transactions:\n{e}"));
+    }
+    if rewrite && let Some(e) = rewrite::apply_rewrite_rules(&mut entries, &rules).first() {
+        return Err(format!("Unable to rewrite transactions:\n{e}"));
+    }
+    if resolve_pads {
+        transform::resolve_pads(&mut entries);
+    }
+    if assign_ids {
+        transform::assign_ids(&mut entries);
+    }
+    print!("{}", Ledger::new(entries).normalize());
+    Ok(ExitCode::SUCCESS)
+}
+
+// Prints the balance of each account at the start of --date, after all entries by default.
+fn balances(args: &[String]) -> Result<ExitCode, String> {
+    let mut ledger = None;
+    let mut date = jiff::civil::Date::MAX;
+    let mut options = ReportOptions::default();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--date" => date = parse_date(it.next())?,
+            _ if report_option(arg, &mut it, &mut options)? => {}
+            _ if ledger.is_none() => ledger = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let ledger = ledger.ok_or(USAGE)?;
+    let ledger = Ledger::load(Path::new(ledger)).map_err(|e| e.to_string())?;
+    print!("{}", balances_report(&ledger, date, &options));
+    Ok(ExitCode::SUCCESS)
+}
+
+// Prints the net worth of each ledger and their total at the start of --date, valued in
+// --currency with the prices of all ledgers. The currency defaults to the operating currency of
+// the first ledger.
+fn consolidate(args: &[String]) -> Result<ExitCode, String> {
+    let mut ledgers = vec![];
+    let mut currency = None;
+    let mut date = jiff::civil::Date::MAX;
+    let mut options = ReportOptions::default();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--currency" => currency = Some(it.next().ok_or(USAGE)?.clone()),
+            "--date" => date = parse_date(it.next())?,
+            _ if report_option(arg, &mut it, &mut options)? => {}
+            _ => ledgers.push(Path::new(arg)),
+        }
+    }
+    let first = ledgers.first().ok_or(USAGE)?;
+    let currency = match currency {
+        Some(currency) => currency,
+        None => Config::for_ledger(first)?.operating_currency.ok_or(USAGE)?,
+    };
+    let set = LedgerSet::load(&ledgers).map_err(|e| e.to_string())?;
+    print!("{}", consolidated_report(&set, &currency, date, &options));
+    Ok(ExitCode::SUCCESS)
+}
+
+// Prints the postings of an account and its sub-accounts with their running balance, including
+// the transactions that pad directives stand for.
+fn register(args: &[String]) -> Result<ExitCode, String> {
+    let mut positional = vec![];
+    let mut exclude_pending = false;
+    let mut options = ReportOptions::default();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--exclude-pending" => exclude_pending = true,
+            _ if report_option(arg, &mut it, &mut options)? => {}
+            _ => positional.push(arg),
+        }
+    }
+    let [ledger, account] = positional.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+    let mut entries =
+        parser::parse_entries_from_file(Path::new(ledger)).map_err(|e| e.to_string())?;
+    transform::resolve_pads(&mut entries);
+    let ledger = Ledger::new(entries);
+    print!(
+        "{}",
+        register_report(&ledger, account, exclude_pending, &options)
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+// Lists the postings still to be categorized, largest first.
+fn uncategorized(args: &[String]) -> Result<ExitCode, String> {
+    let mut ledger = None;
+    let mut accounts = vec![];
+    let mut options = ReportOptions::default();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--account" => accounts.push(it.next().ok_or(USAGE)?.as_str()),
+            _ if report_option(arg, &mut it, &mut options)? => {}
+            _ if ledger.is_none() => ledger = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    if accounts.is_empty() {
+        accounts = TODO_ACCOUNTS.to_vec();
+    }
+    let ledger = ledger.ok_or(USAGE)?;
+    let ledger = Ledger::load(Path::new(ledger)).map_err(|e| e.to_string())?;
+    print!("{}", uncategorized_report(&ledger, &accounts, &options));
+    Ok(ExitCode::SUCCESS)
+}
+
+// Prints the timeline of the named events from --from, the first of them by default, until
+// --to (exclusive), today by default.
+fn events(args: &[String]) -> Result<ExitCode, String> {
+    let mut positional = vec![];
+    let mut from = None;
+    let mut to = None;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--from" => from = Some(parse_date(it.next())?),
+            "--to" => to = Some(parse_date(it.next())?),
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    let [ledger, names @ ..] = positional.as_slice() else {
+        return Err(USAGE.to_string());
+    };
+    if names.is_empty() {
+        return Err(USAGE.to_string());
+    }
+    let ledger_path = ledger;
+    let ledger = Ledger::load(Path::new(ledger)).map_err(|e| e.to_string())?;
+    let first = ledger
+        .entries()
+        .event
+        .iter()
+        .filter(|e| names.contains(&e.name.as_str()))
+        .map(|e| e.date)
+        .min();
+    let from = from.or(first).unwrap_or(jiff::civil::Date::MIN);
+    let to = match to {
+        Some(to) => to,
+        None => Config::for_ledger(Path::new(ledger_path))?.today()?,
+    };
+    print!("{}", events_report(&ledger, names, from..to));
+    Ok(ExitCode::SUCCESS)
+}
+
+// Lists the month ends at which a held currency has no price in --currency within --max-age
+// days, 7 by default.
+fn missing_prices(args: &[String]) -> Result<ExitCode, String> {
+    let mut ledger = None;
+    let mut currency = None;
+    let mut max_age = 7;
+    let mut from = jiff::civil::Date::MIN;
+    let mut to = jiff::civil::Date::MAX;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--currency" => currency = Some(it.next().ok_or(USAGE)?),
+            "--max-age" => {
+                let value = it.next().ok_or(USAGE)?;
+                max_age = value
+                    .parse()
+                    .map_err(|e| format!("Invalid --max-age `{value}`: {e}"))?;
+            }
+            "--from" => from = parse_date(it.next())?,
+            "--to" => to = parse_date(it.next())?,
+            _ if ledger.is_none() => ledger = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let ledger = ledger.ok_or(USAGE)?;
+    let config = Config::for_ledger(Path::new(ledger))?;
+    let currency = currency
+        .or(config.operating_currency.as_ref())
+        .ok_or(USAGE)?;
+    let ledger = Ledger::load(Path::new(ledger)).map_err(|e| e.to_string())?;
+    print!(
+        "{}",
+        missing_prices_report(&ledger, currency, max_age, from..to)
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+// Lists the accounts without a balance assertion within --max-age days, 90 by default, before
+// --date, today by default.
+fn stale_assertions(args: &[String]) -> Result<ExitCode, String> {
+    let mut ledger = None;
+    let mut max_age = 90;
+    let mut date = None;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--max-age" => {
+                let value = it.next().ok_or(USAGE)?;
+                max_age = value
+                    .parse()
+                    .map_err(|e| format!("Invalid --max-age `{value}`: {e}"))?;
+            }
+            "--date" => date = Some(parse_date(it.next())?),
+            _ if ledger.is_none() => ledger = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let ledger = ledger.ok_or(USAGE)?;
+    let date = match date {
+        Some(date) => date,
+        None => Config::for_ledger(Path::new(ledger))?.today()?,
+    };
+    let ledger = Ledger::load(Path::new(ledger)).map_err(|e| e.to_string())?;
+    print!("{}", stale_assertions_report(&ledger, max_age, date));
+    Ok(ExitCode::SUCCESS)
+}
+
+// Prints the monthly balances of the accounts, the net worth by default, as JSON or as an SVG
+// chart.
+fn chart(args: &[String]) -> Result<ExitCode, String> {
+    let mut ledger = None;
+    let mut currency = None;
+    let mut accounts = vec![];
+    let mut from = jiff::civil::Date::MIN;
+    let mut to = jiff::civil::Date::MAX;
+    let mut svg = false;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--currency" => currency = Some(it.next().ok_or(USAGE)?),
+            "--account" => accounts.push(it.next().ok_or(USAGE)?.as_str()),
+            "--from" => from = parse_date(it.next())?,
+            "--to" => to = parse_date(it.next())?,
+            "--svg" => svg = true,
+            _ if ledger.is_none() => ledger = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    if accounts.is_empty() {
+        accounts = NET_WORTH_ACCOUNTS.to_vec();
+    }
+    let ledger = ledger.ok_or(USAGE)?;
+    let config = Config::for_ledger(Path::new(ledger))?;
+    let currency = currency
+        .or(config.operating_currency.as_ref())
+        .ok_or(USAGE)?;
+    let ledger = Ledger::load(Path::new(ledger)).map_err(|e| e.to_string())?;
+    let series = monthly_balances(&ledger, &accounts, currency, from..to);
+    if svg {
+        println!("{}", svg_sparkline(&series, 400, 100));
+    } else {
+        println!("{}", series_to_json(&series));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Prints an HTML page with the balance sheet at --to and the income statement from --from
+// until --to (exclusive), of all entries by default.
+fn html(args: &[String]) -> Result<ExitCode, String> {
+    let mut ledger = None;
+    let mut from = jiff::civil::Date::MIN;
+    let mut to = jiff::civil::Date::MAX;
+    let mut options = ReportOptions::default();
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--from" => from = parse_date(it.next())?,
+            "--to" => to = parse_date(it.next())?,
+            _ if report_option(arg, &mut it, &mut options)? => {}
+            _ if ledger.is_none() => ledger = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let ledger = ledger.ok_or(USAGE)?;
+    let ledger = Ledger::load(Path::new(ledger)).map_err(|e| e.to_string())?;
+    print!("{}", html_report(&ledger, from..to, &options));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn parse_date(arg: Option<&String>) -> Result<jiff::civil::Date, String> {
+    let value = arg.ok_or(USAGE)?;
+    value
+        .parse()
+        .map_err(|e| format!("Invalid date `{value}`: {e}"))
+}
+
+// Handles the options shared by the reports. Returns whether `arg` was one of them.
+fn report_option(
+    arg: &str,
+    it: &mut std::slice::Iter<String>,
+    options: &mut ReportOptions,
+) -> Result<bool, String> {
+    match arg {
+        "--symbol" => {
+            let value = it.next().ok_or(USAGE)?;
+            let (currency, symbol) = value
+                .split_once('=')
+                .ok_or_else(|| format!("Expected <currency>=<symbol>, found `{value}`"))?;
+            options
+                .symbols
+                .insert(currency.to_string(), symbol.to_string());
+        }
+        "--locale" => options.numbers = it.next().ok_or(USAGE)?.parse()?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+// Renames an account in the ledger file, keeping its formatting and comments. Prints the result
+// unless --write is given.
+fn rename(args: &[String]) -> Result<ExitCode, String> {
+    let (ledger, old, new, write) = match args {
+        [ledger, old, new] => (ledger, old, new, false),
+        [ledger, old, new, write] if write == "--write" => (ledger, old, new, true),
+        _ => return Err(USAGE.to_string()),
+    };
+    let input =
+        std::fs::read_to_string(ledger).map_err(|e| format!("Unable to read {ledger}: {e}"))?;
+    let renamed = rename_account_in_text(&input, old, new);
+    if !write {
+        print!("{renamed}");
+    } else if renamed != input {
+        std::fs::write(ledger, renamed).map_err(|e| format!("Unable to write {ledger}: {e}"))?;
+        log::info!("Renamed {old} to {new} in {ledger}");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Adds open directives for the accounts that are used but never opened to the ledger file,
+// each dated on the first use of its account. Prints the result unless --write is given.
+fn auto_open(args: &[String]) -> Result<ExitCode, String> {
+    let (ledger, write) = match args {
+        [ledger] => (ledger, false),
+        [ledger, write] if write == "--write" => (ledger, true),
+        _ => return Err(USAGE.to_string()),
+    };
+    let input =
+        std::fs::read_to_string(ledger).map_err(|e| format!("Unable to read {ledger}: {e}"))?;
+    let entries = parser::parse_entries_from_string(input.clone(), Path::new(ledger))
+        .map_err(|e| e.to_string())?;
+    let (opens, _) = validation::missing_opens(&Ledger::new(entries));
+    let mut text = IncrementalParser::new(input);
+    for open in opens {
+        log::info!("Opening {} on {}", open.account, open.date);
+        text.insert_entry(&EntryVariant::Open(open));
+    }
+    if write {
+        std::fs::write(ledger, text.text())
+            .map_err(|e| format!("Unable to write {ledger}: {e}"))?;
+    } else {
+        print!("{}", text.text());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn read_rules(path: &str) -> Result<Vec<RewriteRule>, String> {
+    let json = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))?;
+    rewrite::rules_from_json(&json)
+}
+
+// Prints the depreciation transactions for each purchase on `account`, i.e. each posting of a
+// positive amount to it.
+fn depreciate(args: &[String]) -> Result<ExitCode, String> {
+    let mut positional = vec![];
+    let mut months = None;
+    let mut method = transform::DepreciationMethod::StraightLine;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--months" => months = it.next().and_then(|n| n.parse().ok()),
+            "--method" => method = it.next().ok_or(USAGE)?.parse()?,
+            _ => positional.push(arg),
+        }
+    }
+    let ([ledger, account], Some(months)) = (positional.as_slice(), months) else {
+        return Err(USAGE.to_string());
     };
-    log::info!("Hello, world! {}", e.date);
+    let entries = parser::parse_entries_from_file(Path::new(ledger)).map_err(|e| e.to_string())?;
+    let purchases = entries.transactions.iter().flat_map(|t| {
+        t.postings
+            .iter()
+            .filter(|p| p.account == account.as_str() && p.amount.number.is_sign_positive())
+            .map(|p| (t.date, p))
+    });
+    for (date, purchase) in purchases {
+        for t in transform::depreciate(date, purchase, months, method)? {
+            println!("{t}\n");
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn load_scripts(config: &Config) -> Result<Vec<TransactionScript>, String> {
+    config
+        .scripts
+        .iter()
+        .map(|path| TransactionScript::load(path))
+        .collect()
+}
+
+fn import(args: &[String]) -> Result<ExitCode, String> {
+    let (flag, config, input, options) = match args {
+        [flag, config, input, options @ ..] => (flag, config, input, options),
+        _ => return Err(USAGE.to_string()),
+    };
+    let mut route = None;
+    let mut known = None;
+    let mut history = None;
+    let mut min_confidence = rust_decimal::Decimal::new(5, 1);
+    let mut interactive = false;
+    let mut state_path = None;
+    let mut it = options.iter();
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            "--route" => route = Some(it.next().ok_or(USAGE)?),
+            "--skip-known" => known = Some(it.next().ok_or(USAGE)?),
+            "--suggest" => history = Some(it.next().ok_or(USAGE)?),
+            "--min-confidence" => {
+                let value = it.next().ok_or(USAGE)?;
+                min_confidence = value
+                    .parse()
+                    .map_err(|e| format!("Invalid --min-confidence `{value}`: {e}"))?;
+            }
+            "--interactive" => interactive = true,
+            "--state" => state_path = Some(Path::new(it.next().ok_or(USAGE)?)),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let read = |path: &String| {
+        std::fs::read_to_string(path).map_err(|e| format!("Unable to read {path}: {e}"))
+    };
+    // The beanrust.toml of the working directory.
+    let mut project = Config::load(Path::new("."))?;
+    let config = match flag.as_str() {
+        "--json" => JsonImportConfig::from_json(&read(config)?)?,
+        "--importer" => project
+            .importers
+            .remove(config)
+            .ok_or_else(|| format!("Unknown importer `{config}`"))?
+            .into_config()?,
+        _ => return Err(format!("Unsupported import format: {flag}")),
+    };
+    let mut transactions = import_transactions(&read(input)?, &config)?;
+    // Fingerprints are taken before the transactions get changed, like the input they stand for,
+    // so the state keeps the imported transactions as they were, by index.
+    let mut state = state_path.map(ImportState::load).transpose()?;
+    let mut originals = vec![];
+    if let Some(state) = &state {
+        let skipped = state.remove_seen(&mut transactions);
+        log::info!("Skipped {skipped} transaction(s) imported before");
+        originals = transactions.clone();
+    }
+    let mut indices: Vec<usize> = (0..transactions.len()).collect();
+    for script in load_scripts(&project)? {
+        for t in &mut transactions {
+            script.apply(t)?;
+        }
+    }
+    if let Some(known) = known {
+        let ledger =
+            parser::parse_entries_from_file(Path::new(known)).map_err(|e| e.to_string())?;
+        let before: Vec<u64> = transactions.iter().map(Transaction::fingerprint).collect();
+        let skipped = remove_known(&mut transactions, &ledger.transactions);
+        log::info!("Skipped {skipped} transaction(s) already in {known}");
+        // Known transactions are removed by fingerprint, so the others keep theirs.
+        let kept: HashSet<u64> = transactions.iter().map(Transaction::fingerprint).collect();
+        indices = (indices.into_iter().zip(before))
+            .filter(|(_, fingerprint)| kept.contains(fingerprint))
+            .map(|(i, _)| i)
+            .collect();
+    }
+    if let Some(history) = history {
+        let ledger =
+            parser::parse_entries_from_file(Path::new(history)).map_err(|e| e.to_string())?;
+        let categorizer = Categorizer::new(&ledger.transactions, &config.counter_account);
+        let categorized = categorize(
+            &mut transactions,
+            &config.counter_account,
+            &categorizer,
+            min_confidence,
+        );
+        log::info!("Categorized {categorized} transaction(s) like the ones in {history}");
+    }
+    if interactive {
+        let total = transactions.len();
+        let accepted = review::review(transactions)?;
+        log::info!("Accepted {} of {total} transaction(s)", accepted.len());
+        (indices, transactions) = accepted.into_iter().map(|(i, t)| (indices[i], t)).unzip();
+    }
+    if let Some(route) = route {
+        for path in append_routed(&Routing::new(route.as_str()), transactions)? {
+            log::info!("Appended to {}", path.display());
+        }
+    } else {
+        for t in transactions {
+            println!("{t}\n");
+        }
+    }
+    // Only the transactions written are recorded, skipped or rejected ones can be imported
+    // again. Saved last, so a failed run imports the same transactions again.
+    if let (Some(state), Some(path)) = (&mut state, state_path) {
+        let written: Vec<Transaction> = indices.iter().map(|&i| originals[i].clone()).collect();
+        state.record(&config.account, &written);
+        state.save(path)?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Mirrors bean-check's error rendering (`<file>:<line>: <message>` followed by the indented
+// entry), so editor integrations written for bean-check can parse our output.
+fn format_bean_check_error(file: &str, e: &ParseError) -> String {
+    let file = e
+        .file
+        .as_deref()
+        .map_or(file.into(), |f| f.display().to_string());
+    let mut out = format!("{}:{:8}: {}\n", file, e.line.unwrap_or(0), e.context);
+    out.push('\n');
+    for line in e.failed_statement.trim().lines() {
+        out.push_str("   ");
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
 }
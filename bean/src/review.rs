@@ -0,0 +1,81 @@
+// Interactive review of imported transactions before they are written: each one is shown on
+// stderr and accepted, re-categorized or skipped by a command read from stdin.
+use beanrust::core::types::Transaction;
+use beanrust::io::import::categorize::CONFIDENCE_KEY;
+use std::io::{BufRead, Write};
+
+const HELP: &str = "[a]ccept (default), [e]dit account, [s]kip, [q]uit dropping the rest";
+
+// The transactions accepted, in order, with their index in `transactions`. The counter posting,
+// i.e. each one but the first, can be moved to another account. Stops at the end of the input
+// as if quit.
+pub fn review(transactions: Vec<Transaction>) -> Result<Vec<(usize, Transaction)>, String> {
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::stderr().lock();
+    review_with(transactions, &mut input, &mut output).map_err(|e| format!("Review failed: {e}"))
+}
+
+fn review_with(
+    transactions: Vec<Transaction>,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> std::io::Result<Vec<(usize, Transaction)>> {
+    let total = transactions.len();
+    let mut accepted = vec![];
+    for (i, mut t) in transactions.into_iter().enumerate() {
+        loop {
+            writeln!(output, "\n({}/{total})\n{t}\n{HELP}", i + 1)?;
+            let Some(command) = read_line(input, output, "> ")? else {
+                return Ok(accepted);
+            };
+            match command.as_str() {
+                "" | "a" => {
+                    accepted.push((i, t));
+                    break;
+                }
+                "s" => break,
+                "q" => return Ok(accepted),
+                "e" => {
+                    let Some(account) = read_line(input, output, "account: ")? else {
+                        return Ok(accepted);
+                    };
+                    if !is_account(&account) {
+                        writeln!(output, "Not an account: `{account}`")?;
+                        continue;
+                    }
+                    for p in t.postings.iter_mut().skip(1) {
+                        p.account = account.as_str().into();
+                    }
+                    // Chosen by hand, not suggested.
+                    t.meta.0.retain(|(key, _)| key != CONFIDENCE_KEY);
+                }
+                _ => writeln!(output, "Unknown command `{command}`")?,
+            }
+        }
+    }
+    Ok(accepted)
+}
+
+// The next line without surrounding whitespace, None at the end of the input.
+fn read_line(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    prompt: &str,
+) -> std::io::Result<Option<String>> {
+    write!(output, "{prompt}")?;
+    output.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+// E.g. `Expenses:Food`: capitalized components separated by colons.
+fn is_account(account: &str) -> bool {
+    account.contains(':')
+        && account.split(':').all(|component| {
+            component.starts_with(|c: char| c.is_uppercase() || c.is_ascii_digit())
+                && !component.contains(char::is_whitespace)
+        })
+}
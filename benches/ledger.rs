@@ -0,0 +1,107 @@
+use beanrust::core::ledger::Ledger;
+use beanrust::io::parser::{StatementIterator, parse_entries_from_string};
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use jiff::civil::date;
+use std::fmt::Write;
+use std::hint::black_box;
+use std::path::Path;
+
+const POSTINGS: [usize; 3] = [10_000, 100_000, 1_000_000];
+
+// A ledger with the given number of postings, two per transaction, over a few accounts.
+fn generate_ledger(postings: usize) -> String {
+    let mut ledger = String::new();
+    let expenses = ["Food", "Rent", "Travel", "Books"];
+    ledger.push_str("2020-01-01 open Assets:Cash CHF\n2020-01-01 open Income:Salary CHF\n");
+    for e in expenses {
+        writeln!(ledger, "2020-01-01 open Expenses:{e} CHF").unwrap();
+    }
+    let mut day = date(2020, 1, 2);
+    for i in 0..postings / 2 {
+        if i % 100 == 0 {
+            day = day.tomorrow().unwrap();
+        }
+        let account = expenses[i % expenses.len()];
+        writeln!(
+            ledger,
+            "{day} * \"Shop{}\" \"Narration\"\n  Expenses:{account}  {}.{:02} CHF\n  Assets:Cash  -{}.{:02} CHF",
+            i % 20,
+            i % 500,
+            i % 100,
+            i % 500,
+            i % 100
+        )
+        .unwrap();
+    }
+    ledger
+}
+
+fn bench_segmentation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("segmentation");
+    group.sample_size(10);
+    for postings in POSTINGS {
+        let input = generate_ledger(postings);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(postings), &input, |b, input| {
+            b.iter(|| StatementIterator::new(black_box(input)).count())
+        });
+    }
+    group.finish();
+}
+
+fn bench_parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parsing");
+    group.sample_size(10);
+    for postings in POSTINGS {
+        let input = generate_ledger(postings);
+        group.throughput(Throughput::Elements(postings as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(postings), &input, |b, input| {
+            b.iter_batched(
+                || input.clone(),
+                |input| parse_entries_from_string(input, Path::new("")).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+        // Run with `--features parallel` to compare against the sequential parser.
+        #[cfg(feature = "parallel")]
+        group.bench_with_input(
+            BenchmarkId::new("parallel", postings),
+            &input,
+            |b, input| {
+                b.iter_batched(
+                    || input.clone(),
+                    |input| {
+                        beanrust::io::parser::parse_entries_from_string_parallel(
+                            input,
+                            Path::new(""),
+                            &beanrust::io::parser::ParseOptions::default(),
+                        )
+                        .unwrap()
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_balance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("balance");
+    group.sample_size(10);
+    for postings in POSTINGS {
+        let entries = parse_entries_from_string(generate_ledger(postings), Path::new("")).unwrap();
+        assert!(entries.errors.is_empty());
+        let ledger = Ledger::new(entries);
+        group.throughput(Throughput::Elements(postings as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(postings),
+            &ledger,
+            |b, ledger| b.iter(|| ledger.balance(black_box("Expenses:Food"), date(2100, 1, 1))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_segmentation, bench_parsing, bench_balance);
+criterion_main!(benches);
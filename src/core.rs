@@ -1 +1,10 @@
+pub mod ledger;
+pub mod ledger_set;
+pub mod plugin;
+pub mod prices;
+pub mod rewrite;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod transform;
 pub mod types;
+pub mod validation;
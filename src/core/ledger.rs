@@ -0,0 +1,1482 @@
+use crate::core::plugin::PluginRegistry;
+use crate::core::transform;
+use crate::core::types::*;
+use crate::core::validation::{self, UnbalancedTransactions};
+use crate::io::decode::{InvalidUtf8, read_source};
+use crate::io::parser::error::ParseError;
+use crate::io::parser::{self, ParseOptions, ParsedEntries, StatementIterator};
+use crate::io::printer::{BeancountRenderer, EntryRenderer, PrintOptions};
+use jiff::civil::{Date, Time};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::marker::PhantomData;
+use std::ops::{Deref, Index, Range};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Typed index of an entry of a `Ledger`. The entries of each kind are stored contiguously,
+/// so a handle is just a position, and `ledger[handle]` cannot mix up the kinds.
+pub struct Handle<T> {
+    // u32 keeps `EntryRef` at 8 bytes, which makes the indexes denser.
+    index: u32,
+    _entry: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    fn new(index: usize) -> Self {
+        Handle {
+            index: index.try_into().expect("too many entries for a ledger"),
+            _entry: PhantomData,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+// Implemented by hand, deriving would require `T` to implement the traits as well.
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> PartialOrd for Handle<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Handle<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.index.cmp(&other.index)
+    }
+}
+
+impl<T> std::hash::Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state)
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.index)
+    }
+}
+
+/// Entry types stored in a `Ledger`, mapping each to its storage.
+pub trait Stored: Sized {
+    fn stored(entries: &ParsedEntries) -> &[Self];
+}
+
+impl Stored for Open {
+    fn stored(entries: &ParsedEntries) -> &[Self] {
+        &entries.open
+    }
+}
+
+impl Stored for Balance {
+    fn stored(entries: &ParsedEntries) -> &[Self] {
+        &entries.balance
+    }
+}
+
+impl Stored for Commodity {
+    fn stored(entries: &ParsedEntries) -> &[Self] {
+        &entries.commodity
+    }
+}
+
+impl Stored for PriceDirective {
+    fn stored(entries: &ParsedEntries) -> &[Self] {
+        &entries.price
+    }
+}
+
+impl Stored for Transaction {
+    fn stored(entries: &ParsedEntries) -> &[Self] {
+        &entries.transactions
+    }
+}
+
+impl Stored for Close {
+    fn stored(entries: &ParsedEntries) -> &[Self] {
+        &entries.close
+    }
+}
+
+impl Stored for Note {
+    fn stored(entries: &ParsedEntries) -> &[Self] {
+        &entries.note
+    }
+}
+
+impl Stored for Document {
+    fn stored(entries: &ParsedEntries) -> &[Self] {
+        &entries.document
+    }
+}
+
+impl Stored for Pad {
+    fn stored(entries: &ParsedEntries) -> &[Self] {
+        &entries.pad
+    }
+}
+
+impl Stored for Event {
+    fn stored(entries: &ParsedEntries) -> &[Self] {
+        &entries.event
+    }
+}
+
+impl Stored for Custom {
+    fn stored(entries: &ParsedEntries) -> &[Self] {
+        &entries.custom
+    }
+}
+
+/// Points to an entry of a `Ledger`. Entries of the same day are ordered by `precedence`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EntryRef {
+    Open(Handle<Open>),
+    Balance(Handle<Balance>),
+    Commodity(Handle<Commodity>),
+    Price(Handle<PriceDirective>),
+    Event(Handle<Event>),
+    Custom(Handle<Custom>),
+    Pad(Handle<Pad>),
+    Transaction(Handle<Transaction>),
+    Note(Handle<Note>),
+    Document(Handle<Document>),
+    Close(Handle<Close>),
+}
+
+impl EntryRef {
+    /// Rank among the entries of the same day, like beancount: opens first, then balance
+    /// assertions, which check the balance at the start of the day, before pads and
+    /// transactions change it. Notes and documents follow the transactions, closes come last.
+    /// Entries of the same rank are ordered by their `time` metadata, then by kind in the order
+    /// of the `EntryRef` variants, e.g. commodities before prices. Entries of the same kind keep
+    /// their input order.
+    pub fn precedence(self) -> u8 {
+        match self {
+            EntryRef::Open(_) => 0,
+            EntryRef::Balance(_) => 1,
+            EntryRef::Commodity(_)
+            | EntryRef::Price(_)
+            | EntryRef::Event(_)
+            | EntryRef::Custom(_) => 2,
+            EntryRef::Pad(_) => 3,
+            EntryRef::Transaction(_) => 4,
+            EntryRef::Note(_) | EntryRef::Document(_) => 5,
+            EntryRef::Close(_) => 6,
+        }
+    }
+
+    // The kind of the entry, numbered in the order of the variants, and its index among the
+    // entries of that kind.
+    fn position(self) -> (usize, usize) {
+        match self {
+            EntryRef::Open(h) => (0, h.index()),
+            EntryRef::Balance(h) => (1, h.index()),
+            EntryRef::Commodity(h) => (2, h.index()),
+            EntryRef::Price(h) => (3, h.index()),
+            EntryRef::Event(h) => (4, h.index()),
+            EntryRef::Custom(h) => (5, h.index()),
+            EntryRef::Pad(h) => (6, h.index()),
+            EntryRef::Transaction(h) => (7, h.index()),
+            EntryRef::Note(h) => (8, h.index()),
+            EntryRef::Document(h) => (9, h.index()),
+            EntryRef::Close(h) => (10, h.index()),
+        }
+    }
+
+    // The inverse of `position`.
+    fn at(kind: usize, index: usize) -> Self {
+        match kind {
+            0 => EntryRef::Open(Handle::new(index)),
+            1 => EntryRef::Balance(Handle::new(index)),
+            2 => EntryRef::Commodity(Handle::new(index)),
+            3 => EntryRef::Price(Handle::new(index)),
+            4 => EntryRef::Event(Handle::new(index)),
+            5 => EntryRef::Custom(Handle::new(index)),
+            6 => EntryRef::Pad(Handle::new(index)),
+            7 => EntryRef::Transaction(Handle::new(index)),
+            8 => EntryRef::Note(Handle::new(index)),
+            9 => EntryRef::Document(Handle::new(index)),
+            _ => EntryRef::Close(Handle::new(index)),
+        }
+    }
+}
+
+// Number of entry kinds, see `EntryRef::position`.
+const KINDS: usize = 11;
+
+// The kind of `entry` as numbered by `EntryRef::position`.
+fn kind(entry: &EntryVariant) -> usize {
+    match entry {
+        EntryVariant::Open(_) => 0,
+        EntryVariant::Balance(_) => 1,
+        EntryVariant::Commodity(_) => 2,
+        EntryVariant::Price(_) => 3,
+        EntryVariant::Event(_) => 4,
+        EntryVariant::Custom(_) => 5,
+        EntryVariant::Pad(_) => 6,
+        EntryVariant::Transaction(_) => 7,
+        EntryVariant::Note(_) => 8,
+        EntryVariant::Document(_) => 9,
+        EntryVariant::Close(_) => 10,
+    }
+}
+
+/// Time spent in each phase of loading a ledger, see `Ledger::load_with_stats`.
+#[derive(Debug, Default, Clone)]
+pub struct LoadStats {
+    pub read: Duration,
+    // Splitting the input into statements.
+    pub segment: Duration,
+    pub parse: Duration,
+    // Building the account and date indexes.
+    pub index: Duration,
+    pub statements: usize,
+}
+
+/// Callbacks of `Ledger::load_with_hooks`, run in the order they were added, e.g. to add
+/// checks of an application that embeds beanrust. Each returns the problems it found.
+#[derive(Default)]
+pub struct LoadHooks<'a> {
+    after_parse: Vec<Hook<'a, ParsedEntries>>,
+    after_booking: Vec<Hook<'a, Ledger>>,
+    after_validation: Vec<Check<'a>>,
+}
+
+type Hook<'a, T> = Box<dyn FnMut(&mut T) -> Vec<ParseError> + 'a>;
+type Check<'a> = Box<dyn FnMut(&Ledger) -> Vec<ParseError> + 'a>;
+
+impl<'a> LoadHooks<'a> {
+    /// Called with the entries of all files before they are transformed.
+    pub fn after_parse(
+        mut self,
+        hook: impl FnMut(&mut ParsedEntries) -> Vec<ParseError> + 'a,
+    ) -> Self {
+        self.after_parse.push(Box::new(hook));
+        self
+    }
+
+    /// Called once the transactions are balanced and the plugins have run.
+    pub fn after_booking(mut self, hook: impl FnMut(&mut Ledger) -> Vec<ParseError> + 'a) -> Self {
+        self.after_booking.push(Box::new(hook));
+        self
+    }
+
+    /// Called with the final ledger after `validation::validate`.
+    pub fn after_validation(mut self, hook: impl FnMut(&Ledger) -> Vec<ParseError> + 'a) -> Self {
+        self.after_validation.push(Box::new(hook));
+        self
+    }
+}
+
+/// Parsed entries together with indexes built once at load time, so that queries for an
+/// account or a date range only visit the relevant entries. Queries only borrow the ledger, so
+/// one loaded ledger can serve concurrent queries, e.g. shared in an `Arc`.
+pub struct Ledger {
+    entries: ParsedEntries,
+    // Entries referring to each account, in date order. A transaction is listed once per
+    // account, even if it has several postings to it.
+    by_account: HashMap<Account, Vec<EntryRef>>,
+    // All entries in date order.
+    by_date: Vec<EntryRef>,
+}
+
+// Keeps the ledger shareable between threads. Caches filled by queries need a lock, e.g. a
+// `OnceLock` or `RwLock`, not a `Cell` or `RefCell`.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Ledger>();
+    assert_send_sync::<EntryRef>();
+};
+
+impl Ledger {
+    pub fn new(entries: ParsedEntries) -> Self {
+        let mut by_date: Vec<EntryRef> = handles(&entries.open)
+            .map(EntryRef::Open)
+            .chain(handles(&entries.balance).map(EntryRef::Balance))
+            .chain(handles(&entries.commodity).map(EntryRef::Commodity))
+            .chain(handles(&entries.price).map(EntryRef::Price))
+            .chain(handles(&entries.transactions).map(EntryRef::Transaction))
+            .chain(handles(&entries.close).map(EntryRef::Close))
+            .chain(handles(&entries.note).map(EntryRef::Note))
+            .chain(handles(&entries.document).map(EntryRef::Document))
+            .chain(handles(&entries.pad).map(EntryRef::Pad))
+            .chain(handles(&entries.event).map(EntryRef::Event))
+            .chain(handles(&entries.custom).map(EntryRef::Custom))
+            .collect();
+        by_date.sort_by_key(|r| sort_key(&entries, *r));
+
+        let mut by_account: HashMap<Account, Vec<EntryRef>> = HashMap::new();
+        for r in &by_date {
+            for account in accounts_of(&entries, *r) {
+                add_ref(&mut by_account, account, *r);
+            }
+        }
+
+        Ledger {
+            entries,
+            by_account,
+            by_date,
+        }
+    }
+
+    pub fn load(fpath: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(Ledger::new(parser::parse_entries_from_file(fpath)?))
+    }
+
+    /// Loads `fpath` as `bean check` does, calling `hooks` between the phases: parsing,
+    /// booking, i.e. the built-in transforms, balancing and the `plugin` directives, and
+    /// validation. Returns the ledger with the problems found by all phases and hooks, not
+    /// including the parse errors, which stay in `entries().errors`.
+    pub fn load_with_hooks(
+        fpath: &Path,
+        mut hooks: LoadHooks,
+    ) -> Result<(Self, Vec<ParseError>), Box<dyn Error>> {
+        let mut entries = parser::parse_entries_from_file(fpath)?;
+        let mut errors = vec![];
+        for hook in &mut hooks.after_parse {
+            errors.extend(hook(&mut entries));
+        }
+
+        let registry = PluginRegistry::new();
+        let mut ledger = Ledger::new(entries);
+        errors.extend(registry.run(
+            &["resolve-aliases", "expand-recurring", "rewrite"],
+            &mut ledger,
+        )?);
+        errors.extend(
+            ledger.modify(|entries| {
+                validation::check_balances(entries, UnbalancedTransactions::Reject)
+            }),
+        );
+        errors.extend(registry.run_directives(&mut ledger));
+        for hook in &mut hooks.after_booking {
+            errors.extend(hook(&mut ledger));
+        }
+
+        errors.extend(validation::validate(&ledger));
+        for hook in &mut hooks.after_validation {
+            errors.extend(hook(&ledger));
+        }
+        Ok((ledger, errors))
+    }
+
+    /// Same as `load`, but runs the phases one after the other and measures each of them.
+    /// Included files are read and parsed as part of the parse phase.
+    pub fn load_with_stats(fpath: &Path) -> Result<(Self, LoadStats), Box<dyn Error>> {
+        let mut stats = LoadStats::default();
+        let options = ParseOptions::default();
+
+        let start = Instant::now();
+        options
+            .limits
+            .check_file_size(std::fs::metadata(fpath)?.len(), fpath)?;
+        let (input, _) = read_source(fpath, InvalidUtf8::Fail)?;
+        stats.read = start.elapsed();
+
+        let start = Instant::now();
+        let statements: Vec<&str> = StatementIterator::new(&input).collect();
+        stats.segment = start.elapsed();
+        stats.statements = statements.len();
+
+        let start = Instant::now();
+        let locator = parser::Locator::new(&input, fpath);
+        let mut entries = ParsedEntries::default();
+        for s in statements {
+            entries.push_result(parser::parse_statement(&locator, s, &options));
+        }
+        entries.end_of_file();
+        parser::load_included_files(&mut entries, fpath, &options);
+        stats.parse = start.elapsed();
+
+        let start = Instant::now();
+        let ledger = Ledger::new(entries);
+        stats.index = start.elapsed();
+
+        Ok((ledger, stats))
+    }
+
+    pub fn entries(&self) -> &ParsedEntries {
+        &self.entries
+    }
+
+    pub fn into_entries(self) -> ParsedEntries {
+        self.entries
+    }
+
+    /// Renames `old` and its sub-accounts to `new` in all entries and rebuilds the indexes.
+    /// Returns the number of renamed references. See `format::rename_account_in_text` for
+    /// updating the source files.
+    pub fn rename_account(&mut self, old: &str, new: &str) -> usize {
+        self.modify(|entries| transform::rename_account(entries, old, new))
+    }
+
+    /// Applies `f` to the entries and rebuilds the indexes, e.g. to run a transform of
+    /// `core::transform` on a loaded ledger.
+    pub fn modify<R>(&mut self, f: impl FnOnce(&mut ParsedEntries) -> R) -> R {
+        let mut entries = std::mem::take(&mut self.entries);
+        let result = f(&mut entries);
+        *self = Ledger::new(entries);
+        result
+    }
+
+    /// Replaces a run of entries, e.g. those of an edited part of a file, with `added` and
+    /// updates the indexes in place instead of building them again like `modify`. The entries
+    /// of each kind are stored in input order: `preceding` are the entries before the run and
+    /// `removed` those of the run, of which only the kinds are used. Only the entries of
+    /// `added` are taken, not its errors or options.
+    pub fn splice<'a>(
+        &mut self,
+        preceding: impl IntoIterator<Item = &'a EntryVariant>,
+        removed: impl IntoIterator<Item = &'a EntryVariant>,
+        added: ParsedEntries,
+    ) {
+        let mut start = [0; KINDS];
+        preceding.into_iter().for_each(|e| start[kind(e)] += 1);
+        let mut end = start;
+        removed.into_iter().for_each(|e| end[kind(e)] += 1);
+        let e = &mut self.entries;
+        let symbols = &mut e.symbols;
+        let inserted = [
+            replace(&mut e.open, start[0]..end[0], added.open, symbols),
+            replace(&mut e.balance, start[1]..end[1], added.balance, symbols),
+            replace(&mut e.commodity, start[2]..end[2], added.commodity, symbols),
+            replace(&mut e.price, start[3]..end[3], added.price, symbols),
+            replace(&mut e.event, start[4]..end[4], added.event, symbols),
+            replace(&mut e.custom, start[5]..end[5], added.custom, symbols),
+            replace(&mut e.pad, start[6]..end[6], added.pad, symbols),
+            replace(
+                &mut e.transactions,
+                start[7]..end[7],
+                added.transactions,
+                symbols,
+            ),
+            replace(&mut e.note, start[8]..end[8], added.note, symbols),
+            replace(&mut e.document, start[9]..end[9], added.document, symbols),
+            replace(&mut e.close, start[10]..end[10], added.close, symbols),
+        ];
+
+        // Entries after the run move by the difference, which keeps the indexes sorted.
+        let renumber = |r: &mut EntryRef| {
+            let (kind, index) = r.position();
+            if index >= end[kind] {
+                *r = EntryRef::at(kind, index + inserted[kind] - (end[kind] - start[kind]));
+            }
+            index < start[kind] || index >= end[kind]
+        };
+        self.by_date.retain_mut(renumber);
+        for refs in self.by_account.values_mut() {
+            refs.retain_mut(renumber);
+        }
+        self.by_account.retain(|_, refs| !refs.is_empty());
+
+        let entries = &self.entries;
+        for kind in 0..KINDS {
+            for index in start[kind]..start[kind] + inserted[kind] {
+                let r = EntryRef::at(kind, index);
+                let key = sort_key(entries, r);
+                let pos = self
+                    .by_date
+                    .partition_point(|x| sort_key(entries, *x) < key);
+                self.by_date.insert(pos, r);
+                for account in accounts_of(entries, r) {
+                    let refs = self.by_account.entry(account.clone()).or_default();
+                    let pos = refs.partition_point(|x| sort_key(entries, *x) < key);
+                    if refs.get(pos) != Some(&r) {
+                        refs.insert(pos, r);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Moves the entries and errors of `file` on or after `line` by `delta` lines, e.g. after
+    /// lines were added above them. The indexes don't depend on lines and stay as they are.
+    pub fn shift_lines(&mut self, file: &Path, line: usize, delta: isize) {
+        let shift = |l: &mut usize| {
+            if *l >= line {
+                *l = l.saturating_add_signed(delta);
+            }
+        };
+        let e = &mut self.entries;
+        let spans = (e.open.iter_mut().map(|x| &mut x.span))
+            .chain(e.balance.iter_mut().map(|x| &mut x.span))
+            .chain(e.commodity.iter_mut().map(|x| &mut x.span))
+            .chain(e.price.iter_mut().map(|x| &mut x.span))
+            .chain(e.event.iter_mut().map(|x| &mut x.span))
+            .chain(e.custom.iter_mut().map(|x| &mut x.span))
+            .chain(e.pad.iter_mut().map(|x| &mut x.span))
+            .chain(e.transactions.iter_mut().map(|x| &mut x.span))
+            .chain(e.note.iter_mut().map(|x| &mut x.span))
+            .chain(e.document.iter_mut().map(|x| &mut x.span))
+            .chain(e.close.iter_mut().map(|x| &mut x.span));
+        for span in spans.filter(|s| s.file.as_deref() == Some(file)) {
+            shift(&mut span.lines.start);
+            shift(&mut span.lines.end);
+        }
+        for error in e
+            .errors
+            .iter_mut()
+            .filter(|e| e.file.as_deref() == Some(file))
+        {
+            if let Some(l) = &mut error.line {
+                shift(l);
+            }
+        }
+    }
+
+    pub fn date(&self, entry: EntryRef) -> Date {
+        date_of(&self.entries, entry)
+    }
+
+    pub fn span(&self, entry: EntryRef) -> &Span {
+        match entry {
+            EntryRef::Open(h) => &self[h].span,
+            EntryRef::Balance(h) => &self[h].span,
+            EntryRef::Commodity(h) => &self[h].span,
+            EntryRef::Price(h) => &self[h].span,
+            EntryRef::Transaction(h) => &self[h].span,
+            EntryRef::Close(h) => &self[h].span,
+            EntryRef::Note(h) => &self[h].span,
+            EntryRef::Document(h) => &self[h].span,
+            EntryRef::Pad(h) => &self[h].span,
+            EntryRef::Event(h) => &self[h].span,
+            EntryRef::Custom(h) => &self[h].span,
+        }
+    }
+
+    /// The ledger printed in date order, one entry per paragraph, with its comments but without
+    /// the original formatting. Parsing the output yields the same entries (apart from the lines
+    /// of their spans), so normalizing is idempotent:
+    /// `normalize(parse(normalize(x))) == normalize(x)`.
+    pub fn normalize(&self) -> String {
+        self.render(&BeancountRenderer::default())
+    }
+
+    /// All entries in date order, rendered by `renderer`.
+    pub fn render(&self, renderer: &impl EntryRenderer) -> String {
+        let rendered = self
+            .by_date
+            .iter()
+            .map(|entry| renderer.comments(self.render_entry(*entry, renderer), self.span(*entry)))
+            .collect();
+        renderer.join(rendered)
+    }
+
+    /// The ledger printed in date order and grouped by month: each month starts with a
+    /// `; 2024-01` header comment and is separated from the previous one by two blank lines.
+    /// Month headers read back as comments of the entries are not repeated, so printing a
+    /// printed ledger again yields the same text.
+    pub fn print_by_month(&self, options: &PrintOptions) -> String {
+        let renderer = BeancountRenderer {
+            options: options.clone(),
+        };
+        let mut out = String::new();
+        let mut month = None;
+        for entry in &self.by_date {
+            let date = self.date(*entry);
+            let header = format!("; {:04}-{:02}", date.year(), date.month());
+            if month.as_ref() != Some(&header) {
+                if month.is_some() {
+                    out.push_str("\n\n");
+                }
+                out.push_str(&header);
+                out.push('\n');
+            }
+            out.push('\n');
+            let mut span = self.span(*entry).clone();
+            if let Some(comments) = &mut span.comments {
+                comments.leading.retain(|c| *c != header);
+            }
+            out.push_str(&renderer.comments(self.render_entry(*entry, &renderer), &span));
+            out.push('\n');
+            month = Some(header);
+        }
+        out
+    }
+
+    // The entry without its comments.
+    fn render_entry(&self, entry: EntryRef, renderer: &impl EntryRenderer) -> String {
+        match entry {
+            EntryRef::Open(h) => renderer.open(&self[h]),
+            EntryRef::Balance(h) => renderer.balance(&self[h]),
+            EntryRef::Commodity(h) => renderer.commodity(&self[h]),
+            EntryRef::Price(h) => renderer.price(&self[h]),
+            EntryRef::Transaction(h) => renderer.transaction(&self[h]),
+            EntryRef::Close(h) => renderer.close(&self[h]),
+            EntryRef::Note(h) => renderer.note(&self[h]),
+            EntryRef::Document(h) => renderer.document(&self[h]),
+            EntryRef::Pad(h) => renderer.pad(&self[h]),
+            EntryRef::Event(h) => renderer.event(&self[h]),
+            EntryRef::Custom(h) => renderer.custom(&self[h]),
+        }
+    }
+
+    /// All entries in date order.
+    pub fn by_date(&self) -> &[EntryRef] {
+        &self.by_date
+    }
+
+    /// Entries dated within `range`, in date order.
+    pub fn between(&self, range: Range<Date>) -> &[EntryRef] {
+        let start = self
+            .by_date
+            .partition_point(|r| self.date(*r) < range.start);
+        let end = self.by_date.partition_point(|r| self.date(*r) < range.end);
+        &self.by_date[start..end.max(start)]
+    }
+
+    /// Entries referring to `account`, in date order.
+    pub fn for_account(&self, account: &str) -> &[EntryRef] {
+        self.by_account.get(account).map_or(&[], Vec::as_slice)
+    }
+
+    /// The transactions with the link `^link`, in date order, e.g. an invoice and its payment.
+    pub fn for_link<'a>(&'a self, link: &'a str) -> impl Iterator<Item = &'a Transaction> {
+        self.by_date
+            .iter()
+            .filter_map(|r| match *r {
+                EntryRef::Transaction(h) => Some(&self[h]),
+                _ => None,
+            })
+            .filter(move |t| t.links.iter().any(|l| l == link))
+    }
+
+    /// All postings in date order, with their transactions. Narrow them down with the
+    /// adapters of `Postings`, e.g. `ledger.postings().within("Assets").currency("CHF")`.
+    pub fn postings(&self) -> Postings<'_> {
+        let transactions = self.by_date.iter().filter_map(|r| match *r {
+            EntryRef::Transaction(h) => Some(h),
+            _ => None,
+        });
+        Postings::new(self, transactions)
+    }
+
+    /// The register of `account`: its postings in date order, with their transactions.
+    pub fn register<'a>(&'a self, account: &'a str) -> Postings<'a> {
+        let transactions = self.for_account(account).iter().filter_map(|r| match *r {
+            EntryRef::Transaction(h) => Some(h),
+            _ => None,
+        });
+        Postings::new(self, transactions).account(account)
+    }
+
+    /// The (last) `commodity` directive declaring `currency`.
+    pub fn commodity(&self, currency: &str) -> Option<&Commodity> {
+        self.entries
+            .commodity
+            .iter()
+            .rfind(|c| *c.currency == *currency)
+    }
+
+    /// The latest price directive of `currency` in `quote` dated on or before `date`, either
+    /// `currency` priced in `quote` or the inverse. Of several on the same day, the last one.
+    pub fn price(&self, currency: &str, quote: &str, date: Date) -> Option<&PriceDirective> {
+        self.entries
+            .price
+            .iter()
+            .filter(|p| p.date <= date)
+            .filter(|p| {
+                let pair = (&*p.currency, &*p.amount.currency);
+                pair == (currency, quote) || pair == (quote, currency)
+            })
+            .max_by_key(|p| p.date)
+    }
+
+    /// The balance of `account` and its sub-accounts at the start of `date`, one amount per
+    /// currency. Prices and costs of postings are not taken into account.
+    pub fn balance(&self, account: &str, date: Date) -> Vec<Amount> {
+        let amounts = self
+            .by_account
+            .keys()
+            .filter(|a| is_within(a, account))
+            .flat_map(|a| self.register(a).take_while(|(t, _)| t.date < date))
+            .map(|(_, p)| &p.amount);
+        sum_by_currency(amounts)
+            .into_iter()
+            .map(|(currency, number)| Amount::new(number, currency))
+            .collect()
+    }
+
+    /// The balances of all accounts at the start of `date`, like `balance`. Parent accounts
+    /// such as `Assets` are included, with the totals of their sub-accounts.
+    pub fn balances_at(&self, date: Date) -> BTreeMap<String, Vec<Amount>> {
+        self.changes_between(Date::MIN..date)
+    }
+
+    /// The sum of the postings of each account dated within `range`, e.g. the income and
+    /// expenses of a month. Parent accounts are included, as in `balances_at`.
+    pub fn changes_between(&self, range: Range<Date>) -> BTreeMap<String, Vec<Amount>> {
+        let mut totals: BTreeMap<&str, BTreeMap<&Currency, Decimal>> = BTreeMap::new();
+        for entry in self.between(range) {
+            let EntryRef::Transaction(h) = *entry else {
+                continue;
+            };
+            for p in &self[h].postings {
+                let account: &str = &p.account;
+                // The account itself and each of its parents.
+                let ends = account.match_indices(':').map(|(i, _)| i);
+                for end in ends.chain([account.len()]) {
+                    let total = totals.entry(&account[..end]).or_default();
+                    *total.entry(&p.amount.currency).or_default() += p.amount.number;
+                }
+            }
+        }
+        totals
+            .into_iter()
+            .map(|(account, totals)| (account.to_string(), into_amounts(totals)))
+            .collect()
+    }
+}
+
+// Whether `account` is `parent` or one of its sub-accounts.
+pub(crate) fn is_within(account: &str, parent: &str) -> bool {
+    account
+        .strip_prefix(parent)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+}
+
+fn into_amounts(totals: BTreeMap<&Currency, Decimal>) -> Vec<Amount> {
+    totals
+        .into_iter()
+        .map(|(currency, number)| Amount::new(number, currency.clone()))
+        .collect()
+}
+
+/// A transaction of a `Ledger` together with its handle.
+#[derive(Debug, Clone, Copy)]
+pub struct TransactionRef<'a> {
+    pub handle: Handle<Transaction>,
+    pub transaction: &'a Transaction,
+}
+
+impl Deref for TransactionRef<'_> {
+    type Target = Transaction;
+
+    fn deref(&self) -> &Transaction {
+        self.transaction
+    }
+}
+
+/// Postings of a `Ledger` in date order, each with its transaction.
+pub struct Postings<'a> {
+    inner: Box<dyn Iterator<Item = (TransactionRef<'a>, &'a Posting)> + 'a>,
+}
+
+impl<'a> Postings<'a> {
+    fn new(
+        ledger: &'a Ledger,
+        transactions: impl Iterator<Item = Handle<Transaction>> + 'a,
+    ) -> Self {
+        let inner = transactions.flat_map(move |handle| {
+            let transaction = &ledger[handle];
+            let t = TransactionRef {
+                handle,
+                transaction,
+            };
+            transaction.postings.iter().map(move |p| (t, p))
+        });
+        Postings {
+            inner: Box::new(inner),
+        }
+    }
+
+    fn filter(self, f: impl FnMut(&(TransactionRef<'a>, &'a Posting)) -> bool + 'a) -> Self {
+        Postings {
+            inner: Box::new(self.inner.filter(f)),
+        }
+    }
+
+    /// Postings to exactly `account`.
+    pub fn account(self, account: &'a str) -> Self {
+        self.filter(move |(_, p)| p.account == account)
+    }
+
+    /// Postings to `account` or one of its sub-accounts.
+    pub fn within(self, account: &'a str) -> Self {
+        self.filter(move |(_, p)| is_within(&p.account, account))
+    }
+
+    /// Postings in `currency`.
+    pub fn currency(self, currency: &'a str) -> Self {
+        self.filter(move |(_, p)| p.amount.currency == currency)
+    }
+
+    /// Postings of transactions dated within `range`.
+    pub fn between(self, range: Range<Date>) -> Self {
+        let inner = self
+            .inner
+            .skip_while(move |(t, _)| t.date < range.start)
+            .take_while(move |(t, _)| t.date < range.end);
+        Postings {
+            inner: Box::new(inner),
+        }
+    }
+}
+
+impl<'a> Iterator for Postings<'a> {
+    type Item = (TransactionRef<'a>, &'a Posting);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<T: Stored> Index<Handle<T>> for Ledger {
+    type Output = T;
+
+    fn index(&self, handle: Handle<T>) -> &T {
+        get(&self.entries, handle)
+    }
+}
+
+impl From<ParsedEntries> for Ledger {
+    fn from(entries: ParsedEntries) -> Self {
+        Ledger::new(entries)
+    }
+}
+
+fn handles<T>(stored: &[T]) -> impl Iterator<Item = Handle<T>> + use<T> {
+    (0..stored.len()).map(Handle::new)
+}
+
+fn get<T: Stored>(entries: &ParsedEntries, handle: Handle<T>) -> &T {
+    &T::stored(entries)[handle.index()]
+}
+
+// Transactions with a `time` come after those without one on the same day, in time order.
+fn time_of(entries: &ParsedEntries, entry: EntryRef) -> Option<Time> {
+    match entry {
+        EntryRef::Transaction(h) => get(entries, h).time().and_then(Result::ok),
+        _ => None,
+    }
+}
+
+fn date_of(entries: &ParsedEntries, entry: EntryRef) -> Date {
+    match entry {
+        EntryRef::Open(h) => get(entries, h).date,
+        EntryRef::Balance(h) => get(entries, h).date,
+        EntryRef::Commodity(h) => get(entries, h).date,
+        EntryRef::Price(h) => get(entries, h).date,
+        EntryRef::Transaction(h) => get(entries, h).date,
+        EntryRef::Close(h) => get(entries, h).date,
+        EntryRef::Note(h) => get(entries, h).date,
+        EntryRef::Document(h) => get(entries, h).date,
+        EntryRef::Pad(h) => get(entries, h).date,
+        EntryRef::Event(h) => get(entries, h).date,
+        EntryRef::Custom(h) => get(entries, h).date,
+    }
+}
+
+// Position of an entry in `Ledger::by_date`.
+fn sort_key(entries: &ParsedEntries, r: EntryRef) -> (Date, u8, Option<Time>, EntryRef) {
+    (date_of(entries, r), r.precedence(), time_of(entries, r), r)
+}
+
+// Accounts an entry is listed under in `Ledger::by_account`, possibly several times.
+fn accounts_of(entries: &ParsedEntries, r: EntryRef) -> Vec<&Account> {
+    match r {
+        EntryRef::Open(h) => vec![&get(entries, h).account],
+        EntryRef::Balance(h) => vec![&get(entries, h).account],
+        EntryRef::Close(h) => vec![&get(entries, h).account],
+        EntryRef::Transaction(h) => get(entries, h)
+            .postings
+            .iter()
+            .map(|p| &p.account)
+            .collect(),
+        EntryRef::Note(h) => vec![&get(entries, h).account],
+        EntryRef::Document(h) => vec![&get(entries, h).account],
+        EntryRef::Pad(h) => {
+            let pad = get(entries, h);
+            vec![&pad.account, &pad.source_account]
+        }
+        EntryRef::Custom(h) => get(entries, h)
+            .values
+            .iter()
+            .filter_map(|v| match v {
+                CustomValue::Account(account) => Some(account),
+                _ => None,
+            })
+            .collect(),
+        EntryRef::Commodity(_) | EntryRef::Price(_) | EntryRef::Event(_) => vec![],
+    }
+}
+
+// Replaces `range` of `stored` with `added`, interned with the ledger's symbols. Returns the
+// number of added entries.
+fn replace<T: Intern>(
+    stored: &mut Vec<T>,
+    range: Range<usize>,
+    added: Vec<T>,
+    symbols: &mut Interner,
+) -> usize {
+    let count = added.len();
+    stored.splice(
+        range,
+        added.into_iter().map(|mut e| {
+            e.intern(symbols);
+            e
+        }),
+    );
+    count
+}
+
+fn add_ref(by_account: &mut HashMap<Account, Vec<EntryRef>>, account: &Account, entry: EntryRef) {
+    let refs = by_account.entry(account.clone()).or_default();
+    if refs.last() != Some(&entry) {
+        refs.push(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::validation::error_at;
+    use crate::io::parser::error::ErrorCode;
+    use crate::io::parser::{IncrementalParser, parse_entries_from_string};
+    use jiff::civil::date;
+    use std::path::Path;
+
+    const LEDGER: &str = "2024-01-01 open Assets:Cash
+2024-03-01 *
+  Assets:Cash -5 CHF
+  Assets:Cash -1 CHF
+  Expenses:Food 6 CHF
+2024-01-01 open Expenses:Food
+2024-02-01 *
+  Assets:Cash 100 CHF
+  Income:Salary -100 CHF
+2024-02-15 balance Assets:Cash 100 CHF
+2024-03-01 close Assets:Cash
+2024-02-20 *
+  Assets:Cash 10 EUR
+  Income:Salary -10 EUR
+";
+
+    fn ledger() -> Ledger {
+        Ledger::new(parse_entries_from_string(LEDGER.to_string(), Path::new("")).unwrap())
+    }
+
+    #[test]
+    fn test_for_link() {
+        let input = "2024-03-10 * \"Payment\" ^invoice-7
+  Assets:Cash -50 CHF
+  Liabilities:Payable 50 CHF
+2024-02-01 * \"Invoice\" ^invoice-7 ^other
+  Expenses:Food 50 CHF
+  Liabilities:Payable -50 CHF
+2024-02-05 * \"Unrelated\" ^invoice-70
+  Expenses:Food 5 CHF
+  Assets:Cash -5 CHF
+";
+        let ledger =
+            Ledger::new(parse_entries_from_string(input.to_string(), Path::new("")).unwrap());
+        let linked: Vec<&str> = ledger
+            .for_link("invoice-7")
+            .map(|t| t.narration.as_deref().unwrap())
+            .collect();
+        assert_eq!(linked, ["Invoice", "Payment"]);
+        assert_eq!(ledger.for_link("invoice").count(), 0);
+    }
+
+    #[test]
+    fn test_by_date() {
+        let ledger = ledger();
+        assert_eq!(
+            ledger.by_date(),
+            [
+                EntryRef::Open(Handle::new(0)),
+                EntryRef::Open(Handle::new(1)),
+                EntryRef::Transaction(Handle::new(1)),
+                EntryRef::Balance(Handle::new(0)),
+                EntryRef::Transaction(Handle::new(2)),
+                EntryRef::Transaction(Handle::new(0)),
+                EntryRef::Close(Handle::new(0)),
+            ]
+        );
+        assert_eq!(
+            ledger.between(date(2024, 2, 1)..date(2024, 3, 1)),
+            [
+                EntryRef::Transaction(Handle::new(1)),
+                EntryRef::Balance(Handle::new(0)),
+                EntryRef::Transaction(Handle::new(2))
+            ]
+        );
+        assert!(
+            ledger
+                .between(date(2025, 1, 1)..date(2024, 1, 1))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_same_day_order() {
+        let input = "2024-01-01 close Assets:Cash
+2024-01-01 note Assets:Cash \"closing\"
+2024-01-01 *
+  Assets:Cash -5 CHF
+  Expenses:Food 5 CHF
+2024-01-01 pad Assets:Cash Equity:Opening
+2024-01-01 balance Assets:Cash 0 CHF
+2024-01-01 open Assets:Cash
+";
+        let ledger =
+            Ledger::new(parse_entries_from_string(input.to_string(), Path::new("")).unwrap());
+        let kinds: Vec<u8> = ledger.by_date().iter().map(|r| r.precedence()).collect();
+        assert_eq!(kinds, [0, 1, 3, 4, 5, 6]);
+        assert!(crate::core::validation::check_assertions(&ledger).is_empty());
+    }
+
+    #[test]
+    fn test_splice() {
+        let input = "2024-01-01 open Assets:Cash
+2024-01-01 open Expenses:Food
+
+2024-01-02 * \"Shop\"
+  Assets:Cash -5 CHF
+  Expenses:Food 5 CHF
+
+2024-01-03 balance Assets:Cash -5 CHF
+2024-01-04 *
+  Assets:Cash -1 CHF
+  Expenses:Food 1 CHF
+";
+        let path = Path::new("main.beancount");
+        let mut parser = IncrementalParser::for_file(input.to_string(), path);
+        let mut ledger = Ledger::new(parser.to_parsed_entries());
+        let edits = [
+            // A new account with two postings, and a note, of which there was none before.
+            (
+                "Expenses:Food 5 CHF",
+                "Expenses:Food 3 CHF
+  Expenses:Drinks 1 CHF
+  Expenses:Drinks 1 CHF
+2024-01-02 note Assets:Cash \"Receipt\"",
+            ),
+            ("2024-01-03 balance", "2024-01-01 balance"),
+            ("2024-01-01 open Expenses:Food\n", ""),
+            (
+                "2024-01-04 *\n  Assets:Cash -1 CHF\n  Expenses:Food 1 CHF\n",
+                "",
+            ),
+        ];
+        for (old, new) in edits {
+            let pos = parser.text().find(old).unwrap();
+            let edit = parser.edit_statements(pos..pos + old.len(), new);
+            let mut added = ParsedEntries::default();
+            for s in &parser.statements()[edit.start..edit.start + edit.added] {
+                added.push_result(s.result.clone());
+            }
+            let preceding = &parser.statements()[..edit.start];
+            ledger.shift_lines(path, edit.lines.end, edit.line_delta);
+            ledger.splice(
+                preceding.iter().filter_map(|s| s.result.entry.as_ref()),
+                edit.removed.iter().filter_map(|s| s.result.entry.as_ref()),
+                added,
+            );
+
+            let expected = Ledger::new(parser.to_parsed_entries());
+            assert_eq!(ledger.by_date(), expected.by_date(), "{new}");
+            assert_eq!(ledger.by_account.len(), expected.by_account.len());
+            for account in expected.by_account.keys() {
+                assert_eq!(ledger.for_account(account), expected.for_account(account));
+            }
+            let lines = |l: &Ledger| {
+                let spans = l.by_date().iter().map(|r| l.span(*r).lines.clone());
+                spans.collect::<Vec<_>>()
+            };
+            assert_eq!(lines(&ledger), lines(&expected));
+            assert_eq!(ledger.normalize(), expected.normalize());
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let input = "2024-01-02 event \"location\" \"Zurich\"
+2024-01-01   open Assets:Cash   CHF,EUR ; cash
+2024-01-02 note Assets:Cash \"A note\"
+2024-01-02 document Assets:Cash \"/tmp/receipt.pdf\"
+2024-01-02 pad Assets:Cash Equity:Opening
+2024-01-03 * \"Some shop\" \"Lunch with friends\"
+  Assets:Cash  -5 CHF
+  Expenses:Food 5 CHF
+";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("test")).unwrap();
+        let normalized = Ledger::new(entries).normalize();
+        assert_eq!(
+            normalized,
+            "2024-01-01 open Assets:Cash CHF,EUR ; cash
+
+2024-01-02 event \"location\" \"Zurich\"
+
+2024-01-02 pad Assets:Cash Equity:Opening
+
+2024-01-02 note Assets:Cash \"A note\"
+
+2024-01-02 document Assets:Cash \"/tmp/receipt.pdf\"
+
+2024-01-03 * \"Some shop\" \"Lunch with friends\"
+    Assets:Cash -5 CHF
+    Expenses:Food 5 CHF
+"
+        );
+        let entries = parse_entries_from_string(normalized.clone(), Path::new("test")).unwrap();
+        assert_eq!(Ledger::new(entries).normalize(), normalized);
+    }
+
+    #[test]
+    fn test_print_by_month() {
+        let input = "2024-02-01 close Assets:Cash
+; Salary
+2024-01-31 *
+  Assets:Cash 10.5 CHF
+  Income:Salary -10.5 CHF
+2024-01-01 open Assets:Cash
+";
+        let options = PrintOptions {
+            amount_column: Some(24),
+            ..PrintOptions::aligned()
+        };
+        let entries = parse_entries_from_string(input.to_string(), Path::new("test")).unwrap();
+        let printed = Ledger::new(entries).print_by_month(&options);
+        assert_eq!(
+            printed,
+            "; 2024-01
+
+2024-01-01 open Assets:Cash
+
+; Salary
+2024-01-31 *
+  Assets:Cash       10.5 CHF
+  Income:Salary    -10.5 CHF
+
+
+; 2024-02
+
+2024-02-01 close Assets:Cash
+"
+        );
+        let entries = parse_entries_from_string(printed.clone(), Path::new("test")).unwrap();
+        assert_eq!(Ledger::new(entries).print_by_month(&options), printed);
+    }
+
+    #[test]
+    fn test_load_with_stats() -> Result<(), Box<dyn Error>> {
+        let fpath = std::env::temp_dir().join(format!("beanrust-ledger-{}", std::process::id()));
+        std::fs::write(&fpath, LEDGER)?;
+        let (ledger, stats) = Ledger::load_with_stats(&fpath)?;
+        std::fs::remove_file(&fpath)?;
+        assert_eq!(stats.statements, 7);
+        assert_eq!(ledger.by_date().len(), 7);
+
+        // Includes and pushed tags are handled as by `load`.
+        let dir = std::env::temp_dir().join(format!("beanrust-stats-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join("other.beancount"), LEDGER)?;
+        let fpath = dir.join("main.beancount");
+        std::fs::write(&fpath, "include \"other.beancount\"\npushtag #trip\n")?;
+        let (ledger, stats) = Ledger::load_with_stats(&fpath)?;
+        let loaded = Ledger::load(&fpath)?;
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(stats.statements, 2);
+        assert_eq!(ledger.by_date().len(), 7);
+        assert_eq!(ledger.entries().errors.len(), 1);
+        assert_eq!(ledger.normalize(), loaded.normalize());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_with_hooks() -> Result<(), Box<dyn Error>> {
+        let fpath = std::env::temp_dir().join(format!("beanrust-hooks-{}", std::process::id()));
+        std::fs::write(&fpath, LEDGER)?;
+        let phases = std::cell::RefCell::new(vec![]);
+        let hooks = LoadHooks::default()
+            .after_parse(|entries| {
+                phases.borrow_mut().push("parse");
+                entries.transactions.retain(|t| t.date != date(2024, 2, 20));
+                vec![]
+            })
+            .after_booking(|ledger| {
+                phases.borrow_mut().push("booking");
+                assert_eq!(ledger.entries().transactions.len(), 2);
+                vec![]
+            })
+            .after_validation(|ledger| {
+                phases.borrow_mut().push("validation");
+                ledger
+                    .entries()
+                    .transactions
+                    .iter()
+                    .filter(|t| t.payee.is_none())
+                    .map(|t| {
+                        let context = "Transaction without payee".to_string();
+                        error_at(&t.span, ErrorCode::PluginError, context)
+                    })
+                    .collect()
+            });
+        let (ledger, errors) = Ledger::load_with_hooks(&fpath, hooks)?;
+        std::fs::remove_file(&fpath)?;
+        assert_eq!(*phases.borrow(), ["parse", "booking", "validation"]);
+        assert_eq!(ledger.by_date().len(), 6);
+        let codes: Vec<ErrorCode> = errors.iter().map(|e| e.code).collect();
+        assert_eq!(
+            codes,
+            [
+                ErrorCode::UnopenedAccount,
+                ErrorCode::PluginError,
+                ErrorCode::PluginError
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_of_day() {
+        let input = "2024-01-05 * \"Sell\"
+  time: \"15:10\"
+  Assets:Broker -1 META {300 USD}
+  Assets:Cash 300 USD
+2024-01-05 * \"Buy\"
+  time: \"09:30:05\"
+  Assets:Broker 1 META {300 USD}
+  Assets:Cash -300 USD
+2024-01-05 * \"Fee\"
+  Assets:Cash -1 USD
+  Expenses:Fees 1 USD
+2024-01-05 * \"Typo\"
+  time: \"25:00\"
+  Assets:Cash -1 USD
+  Expenses:Fees 1 USD
+";
+        let ledger =
+            Ledger::new(parse_entries_from_string(input.to_string(), Path::new("")).unwrap());
+        let order: Vec<&str> = ledger
+            .by_date()
+            .iter()
+            .map(|&r| match r {
+                EntryRef::Transaction(h) => ledger[h].narration.as_deref().unwrap(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(order, ["Fee", "Typo", "Buy", "Sell"]);
+        let EntryRef::Transaction(buy) = ledger.by_date()[2] else {
+            panic!("expected a transaction");
+        };
+        assert_eq!(ledger[buy].time(), Some(Ok(Time::constant(9, 30, 5, 0))));
+        let errors = crate::core::validation::validate(&ledger);
+        assert!(errors.iter().any(|e| e.code == ErrorCode::InvalidTime));
+    }
+
+    #[test]
+    fn test_concurrent_queries() {
+        let ledger = ledger();
+        let balances: Vec<Vec<Amount>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| ledger.balance("Assets", Date::MAX)))
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        assert!(
+            balances
+                .iter()
+                .all(|b| *b == ledger.balance("Assets", Date::MAX))
+        );
+    }
+
+    #[test]
+    fn test_index() {
+        let ledger = ledger();
+        let EntryRef::Transaction(t) = ledger.by_date()[2] else {
+            panic!("expected a transaction");
+        };
+        assert_eq!(ledger[t].date, date(2024, 2, 1));
+        assert_eq!(ledger[Handle::<Open>::new(1)].account, "Expenses:Food");
+        assert_eq!(std::mem::size_of::<EntryRef>(), 8);
+    }
+
+    #[test]
+    fn test_for_account() {
+        let ledger = ledger();
+        assert_eq!(
+            ledger.for_account("Assets:Cash"),
+            [
+                EntryRef::Open(Handle::new(0)),
+                EntryRef::Transaction(Handle::new(1)),
+                EntryRef::Balance(Handle::new(0)),
+                EntryRef::Transaction(Handle::new(2)),
+                EntryRef::Transaction(Handle::new(0)),
+                EntryRef::Close(Handle::new(0)),
+            ]
+        );
+        assert_eq!(
+            ledger.for_account("Income:Salary"),
+            [
+                EntryRef::Transaction(Handle::new(1)),
+                EntryRef::Transaction(Handle::new(2))
+            ]
+        );
+        assert!(ledger.for_account("Assets:Bank").is_empty());
+    }
+
+    #[test]
+    fn test_postings_and_balance() {
+        let ledger = ledger();
+        let register: Vec<(Date, String)> = ledger
+            .register("Assets:Cash")
+            .map(|(t, p)| (t.date, p.amount.to_string()))
+            .collect();
+        assert_eq!(
+            register,
+            [
+                (date(2024, 2, 1), "100 CHF".to_string()),
+                (date(2024, 2, 20), "10 EUR".to_string()),
+                (date(2024, 3, 1), "-5 CHF".to_string()),
+                (date(2024, 3, 1), "-1 CHF".to_string()),
+            ]
+        );
+
+        assert!(ledger.balance("Assets:Cash", date(2024, 2, 1)).is_empty());
+        assert_eq!(
+            ledger.balance("Assets:Cash", date(2024, 3, 1)),
+            [
+                Amount::new(100.into(), "CHF"),
+                Amount::new(10.into(), "EUR")
+            ]
+        );
+        assert_eq!(
+            ledger.balance("Assets:Cash", date(2024, 3, 2)),
+            [Amount::new(94.into(), "CHF"), Amount::new(10.into(), "EUR")]
+        );
+    }
+
+    #[test]
+    fn test_balances_with_sub_accounts() {
+        let input = "2024-01-01 *
+  Assets:Bank:Checking 100 CHF
+  Assets:Bank:Savings 50 EUR
+  Assets:Banking 1 CHF
+  Income:Salary -101 CHF
+  Income:Salary -50 EUR
+2024-02-01 *
+  Assets:Bank 10 CHF
+  Income:Salary -10 CHF
+";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("test")).unwrap();
+        let ledger = Ledger::new(entries);
+        assert_eq!(
+            ledger.balance("Assets:Bank", date(2024, 1, 2)),
+            [
+                Amount::new(100.into(), "CHF"),
+                Amount::new(50.into(), "EUR")
+            ]
+        );
+        assert_eq!(
+            ledger.balance("Assets:Bank", date(2024, 3, 1)),
+            [
+                Amount::new(110.into(), "CHF"),
+                Amount::new(50.into(), "EUR")
+            ]
+        );
+
+        let balances = ledger.balances_at(date(2024, 1, 2));
+        let accounts: Vec<&str> = balances.keys().map(String::as_str).collect();
+        assert_eq!(
+            accounts,
+            [
+                "Assets",
+                "Assets:Bank",
+                "Assets:Bank:Checking",
+                "Assets:Bank:Savings",
+                "Assets:Banking",
+                "Income",
+                "Income:Salary"
+            ]
+        );
+        assert_eq!(
+            balances["Assets"],
+            [
+                Amount::new(101.into(), "CHF"),
+                Amount::new(50.into(), "EUR")
+            ]
+        );
+        assert_eq!(
+            balances["Assets:Bank:Savings"],
+            [Amount::new(50.into(), "EUR")]
+        );
+        assert_eq!(
+            ledger.balances_at(date(2024, 3, 1))["Income"],
+            [
+                Amount::new((-111).into(), "CHF"),
+                Amount::new((-50).into(), "EUR")
+            ]
+        );
+        assert_eq!(
+            ledger.changes_between(date(2024, 1, 2)..date(2024, 3, 1))["Income"],
+            [Amount::new((-10).into(), "CHF")]
+        );
+    }
+
+    #[test]
+    fn test_rename_account() {
+        let input = "2024-01-01 open Assets:Old
+2024-01-01 open Assets:Old:Cash
+2024-01-01 open Assets:Older
+2024-01-02 *
+  Assets:Old:Cash -5 CHF
+  Assets:Older 5 CHF
+";
+        let mut ledger = parse_entries_from_string(input.to_string(), Path::new(""))
+            .unwrap()
+            .into_ledger();
+        assert_eq!(ledger.rename_account("Assets:Old", "Assets:New"), 3);
+        assert!(ledger.for_account("Assets:Old:Cash").is_empty());
+        assert_eq!(ledger.for_account("Assets:New:Cash").len(), 2);
+        assert_eq!(ledger.for_account("Assets:Older").len(), 2);
+    }
+
+    #[test]
+    fn test_postings() {
+        let ledger = ledger();
+        assert_eq!(ledger.postings().count(), 7);
+        let amounts = |postings: Postings| -> Vec<String> {
+            postings.map(|(_, p)| p.amount.to_string()).collect()
+        };
+        assert_eq!(
+            amounts(ledger.postings().currency("EUR")),
+            ["10 EUR", "-10 EUR"]
+        );
+        assert_eq!(
+            amounts(
+                ledger
+                    .postings()
+                    .within("Assets")
+                    .between(date(2024, 2, 2)..date(2024, 3, 2))
+            ),
+            ["10 EUR", "-5 CHF", "-1 CHF"]
+        );
+        assert!(ledger.postings().account("Assets").next().is_none());
+        let (t, _) = ledger.postings().account("Expenses:Food").next().unwrap();
+        assert_eq!(t.handle, Handle::new(0));
+        assert_eq!(t.date, date(2024, 3, 1));
+    }
+}
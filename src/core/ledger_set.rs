@@ -0,0 +1,134 @@
+use crate::core::ledger::Ledger;
+use crate::core::prices::PriceDb;
+use crate::core::types::*;
+use jiff::civil::Date;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+/// Independent ledgers, e.g. a personal and a business one, sharing the prices and commodities
+/// of all of them for consolidated reports. Each ledger keeps its own accounts and entries.
+pub struct LedgerSet {
+    ledgers: Vec<(String, Ledger)>,
+    prices: PriceDb,
+}
+
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<LedgerSet>();
+};
+
+impl LedgerSet {
+    /// The ledgers with their names, in order, see `PriceDb` for which directives take
+    /// precedence.
+    pub fn new(ledgers: Vec<(String, Ledger)>) -> Result<Self, String> {
+        for (i, (name, _)) in ledgers.iter().enumerate() {
+            if ledgers[..i].iter().any(|(n, _)| n == name) {
+                return Err(format!("Duplicate ledger name `{name}`"));
+            }
+        }
+        let prices = PriceDb::from_ledgers(ledgers.iter().map(|(_, l)| l));
+        Ok(LedgerSet { ledgers, prices })
+    }
+
+    /// Loads each of `paths`, named by its file name without the extension.
+    pub fn load(paths: &[&Path]) -> Result<Self, Box<dyn Error>> {
+        let mut ledgers = vec![];
+        for path in paths {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            ledgers.push((name.into_owned(), Ledger::load(path)?));
+        }
+        Ok(LedgerSet::new(ledgers)?)
+    }
+
+    pub fn ledgers(&self) -> impl Iterator<Item = (&str, &Ledger)> {
+        self.ledgers.iter().map(|(name, l)| (name.as_str(), l))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Ledger> {
+        self.ledgers().find(|(n, _)| *n == name).map(|(_, l)| l)
+    }
+
+    pub fn prices(&self) -> &PriceDb {
+        &self.prices
+    }
+
+    /// The balances of the accounts of all ledgers at the start of `date`, those of accounts
+    /// with the same name added up, see `Ledger::balances_at`.
+    pub fn balances_at(&self, date: Date) -> BTreeMap<String, Vec<Amount>> {
+        let mut totals: BTreeMap<String, Vec<Amount>> = BTreeMap::new();
+        for (_, ledger) in &self.ledgers {
+            for (account, amounts) in ledger.balances_at(date) {
+                totals.entry(account).or_default().extend(amounts);
+            }
+        }
+        totals
+            .into_iter()
+            .map(|(account, amounts)| {
+                let amounts = sum_by_currency(&amounts)
+                    .into_iter()
+                    .map(|(currency, number)| Amount::new(number, currency))
+                    .collect();
+                (account, amounts)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::parser::parse_entries_from_string;
+
+    fn ledger(input: &str) -> Ledger {
+        Ledger::new(parse_entries_from_string(input.to_string(), Path::new("")).unwrap())
+    }
+
+    #[test]
+    fn test_ledger_set() {
+        let personal = ledger(
+            "2024-01-05 *
+  Assets:Bank 100 CHF
+  Income:Salary -100 CHF
+2024-01-01 price USD 0.90 CHF
+",
+        );
+        let business = ledger(
+            "2024-01-10 *
+  Assets:Bank 50 USD
+  Income:Sales -50 USD
+",
+        );
+        let set = LedgerSet::new(vec![
+            ("personal".to_string(), personal),
+            ("business".to_string(), business),
+        ])
+        .unwrap();
+        let names: Vec<&str> = set.ledgers().map(|(n, _)| n).collect();
+        assert_eq!(names, ["personal", "business"]);
+        // The business ledger has no prices of its own.
+        assert!(
+            set.get("business")
+                .unwrap()
+                .price("USD", "CHF", Date::MAX)
+                .is_none()
+        );
+        assert!(set.prices().price("USD", "CHF", Date::MAX).is_some());
+
+        let balances = set.balances_at(Date::MAX);
+        assert_eq!(
+            balances["Assets"],
+            [
+                Amount::try_from("100 CHF").unwrap(),
+                Amount::try_from("50 USD").unwrap()
+            ]
+        );
+        assert_eq!(balances["Income:Sales"].len(), 1);
+
+        let duplicate = LedgerSet::new(vec![
+            ("a".to_string(), ledger("")),
+            ("a".to_string(), ledger("")),
+        ]);
+        assert!(duplicate.is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use crate::core::ledger::Ledger;
+use crate::core::rewrite::{RewriteRule, apply_rewrite_rules};
+use crate::core::transform;
+use crate::core::types::EntryVariant;
+use crate::core::validation::{error_at, missing_opens};
+use crate::io::parser::PluginDirective;
+use crate::io::parser::error::{ErrorCode, ParseError};
+#[cfg(feature = "wasm")]
+use std::path::Path;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+/// A transform of a loaded ledger, run by name like a beancount plugin, e.g. from the
+/// `plugins` of `beanrust.toml`. Returns the problems found, which are reported with the
+/// validation errors.
+pub trait LedgerPlugin {
+    fn name(&self) -> &str;
+    fn transform(&self, ledger: &mut Ledger) -> Vec<ParseError>;
+}
+
+/// The plugins available by name, in registration order.
+pub struct PluginRegistry {
+    plugins: Vec<Box<dyn LedgerPlugin>>,
+}
+
+impl PluginRegistry {
+    /// Without any plugins.
+    pub fn empty() -> Self {
+        PluginRegistry { plugins: vec![] }
+    }
+
+    /// With the transforms of beanrust: `resolve-aliases`, `expand-recurring`, `rewrite` (the
+    /// `custom "rewrite"` directives only), `resolve-pads`, `implicit-prices` and `auto-open`.
+    pub fn new() -> Self {
+        let mut registry = PluginRegistry::empty();
+        registry.register(ResolveAliases);
+        registry.register(ExpandRecurring);
+        registry.register(Rewrite::default());
+        registry.register(ResolvePads);
+        registry.register(ImplicitPrices);
+        registry.register(AutoOpen);
+        registry
+    }
+
+    /// Adds `plugin`, replacing the one of the same name, e.g. to give `rewrite` more rules.
+    pub fn register(&mut self, plugin: impl LedgerPlugin + 'static) {
+        match self.plugins.iter_mut().find(|p| p.name() == plugin.name()) {
+            Some(registered) => *registered = Box::new(plugin),
+            None => self.plugins.push(Box::new(plugin)),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn LedgerPlugin> {
+        self.plugins
+            .iter()
+            .find(|p| p.name() == name)
+            .map(|p| p.as_ref())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.plugins.iter().map(|p| p.name())
+    }
+
+    /// Runs the plugins called `names` on `ledger` one after the other, each on the result of
+    /// the previous one. Fails before running any if a name is unknown.
+    pub fn run(&self, names: &[&str], ledger: &mut Ledger) -> Result<Vec<ParseError>, String> {
+        let plugins = names
+            .iter()
+            .map(|&name| self.get(name).ok_or(format!("Unknown plugin `{name}`")))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(plugins
+            .into_iter()
+            .flat_map(|p| p.transform(ledger))
+            .collect())
+    }
+
+    /// Runs the `plugin` directives of `ledger` in order: `.wasm` files, if built with the
+    /// `wasm` feature, and the plugins of the registry otherwise. Plugins that cannot be loaded
+    /// and problems without a location are reported at their directive.
+    pub fn run_directives(&self, ledger: &mut Ledger) -> Vec<ParseError> {
+        let mut errors = vec![];
+        for directive in ledger.entries().plugins.clone() {
+            let span = &directive.span;
+            let result = match directive.name.ends_with(".wasm") {
+                true => load_wasm(&directive).map(|plugin| plugin.transform(ledger)),
+                false => self
+                    .get(&directive.name)
+                    .map(|plugin| plugin.transform(ledger))
+                    .ok_or(format!("Unknown plugin `{}`", directive.name)),
+            };
+            match result {
+                Ok(found) => errors.extend(found.into_iter().map(|e| match e.file {
+                    Some(_) => e,
+                    None => error_at(span, e.code, e.context),
+                })),
+                Err(e) => errors.push(error_at(span, ErrorCode::PluginError, e)),
+            }
+        }
+        errors
+    }
+}
+
+// The path is relative to the file of the directive, as for includes.
+#[cfg(feature = "wasm")]
+fn load_wasm(directive: &PluginDirective) -> Result<Box<dyn LedgerPlugin>, String> {
+    let dir = directive.span.file.as_deref().and_then(Path::parent);
+    let path = dir.map_or_else(
+        || directive.name.clone().into(),
+        |dir| dir.join(&directive.name),
+    );
+    let plugin = wasm::WasmPlugin::load(&path, directive.config.clone())?;
+    Ok(Box::new(plugin))
+}
+
+#[cfg(not(feature = "wasm"))]
+fn load_wasm(directive: &PluginDirective) -> Result<Box<dyn LedgerPlugin>, String> {
+    Err(format!(
+        "Plugin `{}` requires beanrust built with the `wasm` feature",
+        directive.name
+    ))
+}
+
+impl Default for PluginRegistry {
+    fn default() -> Self {
+        PluginRegistry::new()
+    }
+}
+
+/// See `transform::resolve_aliases`.
+pub struct ResolveAliases;
+
+impl LedgerPlugin for ResolveAliases {
+    fn name(&self) -> &str {
+        "resolve-aliases"
+    }
+
+    fn transform(&self, ledger: &mut Ledger) -> Vec<ParseError> {
+        ledger.modify(transform::resolve_aliases)
+    }
+}
+
+/// See `transform::expand_recurring`.
+pub struct ExpandRecurring;
+
+impl LedgerPlugin for ExpandRecurring {
+    fn name(&self) -> &str {
+        "expand-recurring"
+    }
+
+    fn transform(&self, ledger: &mut Ledger) -> Vec<ParseError> {
+        ledger.modify(transform::expand_recurring)
+    }
+}
+
+/// Applies `rules` and the `custom "rewrite"` directives, see `rewrite::apply_rewrite_rules`.
+#[derive(Default)]
+pub struct Rewrite {
+    pub rules: Vec<RewriteRule>,
+}
+
+impl LedgerPlugin for Rewrite {
+    fn name(&self) -> &str {
+        "rewrite"
+    }
+
+    fn transform(&self, ledger: &mut Ledger) -> Vec<ParseError> {
+        ledger.modify(|entries| apply_rewrite_rules(entries, &self.rules))
+    }
+}
+
+/// See `transform::resolve_pads`.
+pub struct ResolvePads;
+
+impl LedgerPlugin for ResolvePads {
+    fn name(&self) -> &str {
+        "resolve-pads"
+    }
+
+    fn transform(&self, ledger: &mut Ledger) -> Vec<ParseError> {
+        ledger.modify(transform::resolve_pads);
+        vec![]
+    }
+}
+
+/// See `transform::add_implicit_prices`.
+pub struct ImplicitPrices;
+
+impl LedgerPlugin for ImplicitPrices {
+    fn name(&self) -> &str {
+        "implicit-prices"
+    }
+
+    fn transform(&self, ledger: &mut Ledger) -> Vec<ParseError> {
+        ledger.modify(transform::add_implicit_prices);
+        vec![]
+    }
+}
+
+/// See `validation::auto_open`.
+pub struct AutoOpen;
+
+impl LedgerPlugin for AutoOpen {
+    fn name(&self) -> &str {
+        "auto-open"
+    }
+
+    fn transform(&self, ledger: &mut Ledger) -> Vec<ParseError> {
+        let (opens, warnings) = missing_opens(ledger);
+        if !opens.is_empty() {
+            ledger.modify(|entries| {
+                for o in opens {
+                    entries.push(EntryVariant::Open(o));
+                }
+            });
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::Transaction;
+    use crate::io::parser::parse_entries_from_string;
+    use std::path::Path;
+
+    // Tags every transaction, to check that user plugins run in order with the built-in ones.
+    struct Tag(&'static str);
+
+    impl LedgerPlugin for Tag {
+        fn name(&self) -> &str {
+            "tag"
+        }
+
+        fn transform(&self, ledger: &mut Ledger) -> Vec<ParseError> {
+            ledger.modify(|entries| {
+                for t in &mut entries.transactions {
+                    t.tags.push(self.0.to_string());
+                }
+            });
+            vec![]
+        }
+    }
+
+    #[test]
+    fn test_plugin_registry() {
+        let input = "2024-01-01 pad Assets:Cash Equity:Opening
+2024-01-05 *
+  Assets:Cash 100 USD @ 0.9 CHF
+  Assets:Bank -90 CHF
+2024-01-10 balance Assets:Cash 150 USD
+";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        let mut ledger = Ledger::new(entries);
+        let mut registry = PluginRegistry::new();
+        registry.register(Tag("old"));
+        registry.register(Tag("imported"));
+        assert_eq!(registry.names().filter(|&n| n == "tag").count(), 1);
+
+        let warnings = registry
+            .run(
+                &["resolve-pads", "tag", "implicit-prices", "auto-open"],
+                &mut ledger,
+            )
+            .unwrap();
+        // Assets:Cash, Assets:Bank and Equity:Opening.
+        assert_eq!(warnings.len(), 3);
+        let entries = ledger.entries();
+        assert!(entries.pad.is_empty());
+        assert_eq!(entries.open.len(), 3);
+        assert_eq!(entries.price.len(), 1);
+        let tags: Vec<&Vec<String>> = entries.transactions.iter().map(|t| &t.tags).collect();
+        assert_eq!(tags, [&vec!["imported".to_string()]; 2]);
+        assert_eq!(ledger.by_date().len(), 7);
+
+        assert_eq!(
+            registry.run(&["tag", "nope"], &mut ledger).unwrap_err(),
+            "Unknown plugin `nope`"
+        );
+        assert!(
+            ledger
+                .entries()
+                .transactions
+                .iter()
+                .all(|t: &Transaction| t.tags.len() == 1)
+        );
+        assert!(PluginRegistry::empty().get("rewrite").is_none());
+    }
+
+    #[test]
+    fn test_run_directives() {
+        let input = "plugin \"auto-open\"
+plugin \"beancount.plugins.auto_accounts\"
+plugin \"missing.wasm\" \"config\"
+2024-01-05 *
+  Assets:Cash 10 CHF
+  Income:Gift -10 CHF
+";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("main.bean")).unwrap();
+        assert!(entries.errors.is_empty());
+        assert_eq!(entries.plugins[2].config.as_deref(), Some("config"));
+        let mut ledger = Ledger::new(entries);
+        let errors: Vec<String> = PluginRegistry::new()
+            .run_directives(&mut ledger)
+            .iter()
+            .map(|e| format!("{}:{} {}", e.line.unwrap(), e.code, e.context))
+            .collect();
+        assert_eq!(errors.len(), 4);
+        assert_eq!(
+            errors[2],
+            "2:B0013 Unknown plugin `beancount.plugins.auto_accounts`"
+        );
+        assert!(errors[3].starts_with("3:B0013 "));
+        assert_eq!(ledger.entries().open.len(), 2);
+    }
+}
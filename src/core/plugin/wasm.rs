@@ -0,0 +1,258 @@
+// Plugins compiled to WebAssembly, loaded from `plugin "file.wasm"` directives. They run in a
+// sandbox: no imports are provided, so a plugin can neither read files nor use the network,
+// and its instructions and memory are limited.
+//
+// A plugin module exports:
+// - `memory`,
+// - `alloc(len: i32) -> i32`, returning the address of `len` bytes the host can write to,
+// - `transform(ptr: i32, len: i32) -> i64`, called with the input written to such a buffer,
+//   returning the address of the output in the upper 32 bits and its length in the lower ones.
+// The input is the JSON object `{"entries": ..., "config": ...}`, with the entries as
+// serialized by `ParsedEntries` and the second string of the directive or null. The output is
+// `{"entries": ..., "errors": [{"message": ..., "file": ..., "line": ...}]}`. The entries
+// replace those of the ledger, they are left as they are if omitted. Errors without a file are
+// reported at the directive.
+use super::LedgerPlugin;
+use crate::core::ledger::Ledger;
+use crate::io::parser::ParsedEntries;
+use crate::io::parser::error::{ErrorCode, ParseError};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use wasmi::{Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+// Instructions a plugin may run per call by default, enough for large ledgers but stopping
+// endless loops.
+const FUEL: u64 = 20_000_000_000;
+const MEMORY_LIMIT: usize = 1 << 30;
+
+pub struct WasmPlugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+    config: Option<String>,
+    fuel: u64,
+}
+
+#[derive(Serialize)]
+struct Input<'a> {
+    entries: &'a ParsedEntries,
+    config: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct Output {
+    entries: Option<ParsedEntries>,
+    #[serde(default)]
+    errors: Vec<Diagnostic>,
+}
+
+#[derive(Deserialize)]
+struct Diagnostic {
+    message: String,
+    file: Option<PathBuf>,
+    line: Option<usize>,
+}
+
+impl WasmPlugin {
+    pub fn load(path: &Path, config: Option<String>) -> Result<Self, String> {
+        let wasm =
+            std::fs::read(path).map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+        WasmPlugin::new(path.display().to_string(), &wasm, config)
+    }
+
+    /// The plugin in the binary or text format `wasm`.
+    pub fn new(name: String, wasm: &[u8], config: Option<String>) -> Result<Self, String> {
+        let mut engine_config = wasmi::Config::default();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config);
+        let module =
+            Module::new(&engine, wasm).map_err(|e| format!("Invalid plugin `{name}`: {e}"))?;
+        Ok(WasmPlugin {
+            name,
+            engine,
+            module,
+            config,
+            fuel: FUEL,
+        })
+    }
+
+    /// Limits the instructions the plugin may run per call to `fuel`.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    fn call(&self, input: &[u8]) -> Result<Vec<u8>, wasmi::Error> {
+        let limits = StoreLimitsBuilder::new().memory_size(MEMORY_LIMIT).build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits: &mut StoreLimits| limits);
+        store.set_fuel(self.fuel)?;
+        let linker = Linker::new(&self.engine);
+        let instance = linker.instantiate_and_start(&mut store, &self.module)?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| wasmi::Error::new("No exported memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc")?;
+        let transform = instance.get_typed_func::<(i32, i32), i64>(&store, "transform")?;
+        let len = i32::try_from(input.len()).map_err(|_| wasmi::Error::new("Input too large"))?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, ptr as u32 as usize, input)?;
+        let result = transform.call(&mut store, (ptr, len))? as u64;
+        // Checked against the memory before copying, so the plugin cannot make the host allocate
+        // more than it has.
+        let (start, len) = ((result >> 32) as usize, (result & 0xffff_ffff) as usize);
+        start
+            .checked_add(len)
+            .and_then(|end| memory.data(&store).get(start..end))
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| {
+                wasmi::Error::new(format!(
+                    "Output of {len} bytes at {start} is outside the plugin memory"
+                ))
+            })
+    }
+
+    fn run(&self, entries: &ParsedEntries) -> Result<Output, String> {
+        let input = serde_json::to_vec(&Input {
+            entries,
+            config: self.config.as_deref(),
+        })
+        .map_err(|e| e.to_string())?;
+        let output = self
+            .call(&input)
+            .map_err(|e| format!("Plugin failed: {e}"))?;
+        serde_json::from_slice(&output).map_err(|e| format!("Invalid plugin output: {e}"))
+    }
+}
+
+impl LedgerPlugin for WasmPlugin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn transform(&self, ledger: &mut Ledger) -> Vec<ParseError> {
+        let error = |context, file, line| ParseError {
+            code: ErrorCode::PluginError,
+            context,
+            failed_statement: String::new(),
+            file,
+            line,
+            column: line.map(|_| 1),
+        };
+        let output = match self.run(ledger.entries()) {
+            Ok(output) => output,
+            Err(e) => return vec![error(e, None, None)],
+        };
+        if let Some(mut entries) = output.entries {
+            entries.intern_all();
+            *ledger = Ledger::new(entries);
+        }
+        output
+            .errors
+            .into_iter()
+            .map(|d| error(d.message, d.file, d.line))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::parser::parse_entries_from_string;
+
+    // Allocates from a bump pointer, growing the memory as needed.
+    const ALLOC: &str = r#"
+  (memory (export "memory") 1)
+  (global $next (mut i32) (i32.const 1024))
+  (func (export "alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $next))
+    (global.set $next (i32.add (local.get $ptr) (local.get $len)))
+    (block $done
+      (loop $grow
+        (br_if $done
+          (i32.le_u (global.get $next) (i32.mul (memory.size) (i32.const 65536))))
+        (if (i32.eq (memory.grow (i32.const 1)) (i32.const -1)) (then unreachable))
+        (br $grow)))
+    (local.get $ptr))"#;
+
+    fn plugin(transform: &str, data: &str) -> WasmPlugin {
+        let wat = format!("(module {ALLOC}\n  (data (i32.const 0) \"{data}\")\n  {transform})");
+        WasmPlugin::new("test.wasm".to_string(), wat.as_bytes(), None).unwrap()
+    }
+
+    fn ledger() -> Ledger {
+        let input = "2024-01-01 open Assets:Bank
+2024-01-05 * \"Shop\"
+  Assets:Bank -10 CHF
+  Expenses:Food 10 CHF
+";
+        Ledger::new(parse_entries_from_string(input.to_string(), Path::new("main.bean")).unwrap())
+    }
+
+    #[test]
+    fn test_wasm_plugin() {
+        // Returns its input, whose entries survive the round trip.
+        let echo = plugin(
+            r#"(func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+    (i64.or (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+            (i64.extend_i32_u (local.get $len))))"#,
+            "",
+        );
+        let mut ledger = ledger();
+        assert!(echo.transform(&mut ledger).is_empty());
+        assert_eq!(ledger.by_date().len(), 2);
+        assert_eq!(
+            ledger.entries().transactions[0].to_string(),
+            "2024-01-05 * \"Shop\"\n    Assets:Bank -10 CHF\n    Expenses:Food 10 CHF"
+        );
+
+        let output =
+            r#"{\"errors\":[{\"message\":\"Too expensive\",\"file\":\"main.bean\",\"line\":2}]}"#;
+        let check = plugin(
+            &format!(
+                r#"(func (export "transform") (param i32 i32) (result i64) (i64.const {}))"#,
+                output.replace('\\', "").len()
+            ),
+            output,
+        );
+        let errors = check.transform(&mut ledger);
+        assert_eq!(
+            errors.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["main.bean:2:1: B0013 Too expensive"]
+        );
+        assert_eq!(ledger.by_date().len(), 2);
+    }
+
+    #[test]
+    fn test_wasm_plugin_sandbox() {
+        let endless = plugin(
+            r#"(func (export "transform") (param i32 i32) (result i64) (loop $l (br $l)) (i64.const 0))"#,
+            "",
+        )
+        .with_fuel(1_000_000);
+        let errors = endless.transform(&mut ledger());
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].context.starts_with("Plugin failed"));
+
+        // Claims 4 GiB of output at the start of its single page of memory.
+        let oversized = plugin(
+            r#"(func (export "transform") (param i32 i32) (result i64) (i64.const 0xffffffff))"#,
+            "",
+        );
+        let errors = oversized.transform(&mut ledger());
+        assert_eq!(
+            errors[0].context,
+            "Plugin failed: Output of 4294967295 bytes at 0 is outside the plugin memory"
+        );
+
+        let wasi = WasmPlugin::new(
+            "wasi.wasm".to_string(),
+            br#"(module (import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32))))"#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(wasi.transform(&mut ledger()).len(), 1);
+        assert!(WasmPlugin::new("bad.wasm".to_string(), b"(module", None).is_err());
+    }
+}
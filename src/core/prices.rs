@@ -0,0 +1,146 @@
+use crate::core::ledger::Ledger;
+use crate::core::types::*;
+use jiff::civil::Date;
+use rust_decimal::Decimal;
+
+/// The price and commodity directives of one or more ledgers, e.g. to value the holdings of a
+/// personal and a business ledger with the same prices. Lookups behave as those of `Ledger`,
+/// with the directives of ledgers added later taking precedence on the same day.
+#[derive(Debug, Clone, Default)]
+pub struct PriceDb {
+    prices: Vec<PriceDirective>,
+    commodities: Vec<Commodity>,
+}
+
+impl PriceDb {
+    pub fn new() -> Self {
+        PriceDb::default()
+    }
+
+    /// With the prices and commodities of `ledgers`, in order.
+    pub fn from_ledgers<'a>(ledgers: impl IntoIterator<Item = &'a Ledger>) -> Self {
+        let mut db = PriceDb::new();
+        for ledger in ledgers {
+            db.add_ledger(ledger);
+        }
+        db
+    }
+
+    pub fn add_ledger(&mut self, ledger: &Ledger) {
+        let entries = ledger.entries();
+        self.prices.extend(entries.price.iter().cloned());
+        self.commodities.extend(entries.commodity.iter().cloned());
+    }
+
+    pub fn add_price(&mut self, price: PriceDirective) {
+        self.prices.push(price);
+    }
+
+    /// The (last) `commodity` directive declaring `currency`.
+    pub fn commodity(&self, currency: &str) -> Option<&Commodity> {
+        self.commodities.iter().rfind(|c| *c.currency == *currency)
+    }
+
+    /// The latest price directive of `currency` in `quote` dated on or before `date`, see
+    /// `Ledger::price`.
+    pub fn price(&self, currency: &str, quote: &str, date: Date) -> Option<&PriceDirective> {
+        self.prices
+            .iter()
+            .filter(|p| p.date <= date)
+            .filter(|p| {
+                let pair = (&*p.currency, &*p.amount.currency);
+                pair == (currency, quote) || pair == (quote, currency)
+            })
+            .max_by_key(|p| p.date)
+    }
+
+    /// The number of `quote` one unit of `currency` was worth on `date`, inverting the price
+    /// if it is given the other way round. One if the currencies are the same.
+    pub fn rate(&self, currency: &str, quote: &str, date: Date) -> Option<Decimal> {
+        if currency == quote {
+            return Some(Decimal::ONE);
+        }
+        let price = self.price(currency, quote, date)?;
+        match *price.currency == *currency {
+            true => Some(price.amount.number),
+            false => Decimal::ONE.checked_div(price.amount.number),
+        }
+    }
+
+    /// `amount` in `quote` at the prices of `date`, None without a price.
+    pub fn convert(&self, amount: &Amount, quote: &str, date: Date) -> Option<Amount> {
+        let rate = self.rate(&amount.currency, quote, date)?;
+        Some(Amount::new(amount.number * rate, quote.to_string()))
+    }
+
+    /// The total of `amounts` in `quote` at the prices of `date`, and the amounts without a
+    /// price, which are left out of the total.
+    pub fn value<'a>(
+        &self,
+        amounts: impl IntoIterator<Item = &'a Amount>,
+        quote: &str,
+        date: Date,
+    ) -> (Decimal, Vec<Amount>) {
+        let mut total = Decimal::ZERO;
+        let mut unpriced = vec![];
+        for (currency, number) in sum_by_currency(amounts) {
+            let amount = Amount::new(number, currency);
+            match self.convert(&amount, quote, date) {
+                Some(converted) => total += converted.number,
+                None => unpriced.push(amount),
+            }
+        }
+        (total, unpriced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::parser::parse_entries_from_string;
+    use std::path::Path;
+
+    fn ledger(input: &str) -> Ledger {
+        Ledger::new(parse_entries_from_string(input.to_string(), Path::new("")).unwrap())
+    }
+
+    #[test]
+    fn test_price_db() {
+        let personal = ledger(
+            "2024-01-01 commodity USD
+  precision: 2
+2024-01-01 price USD 0.90 CHF
+2024-02-01 price EUR 0.95 CHF
+",
+        );
+        let business = ledger(
+            "2024-01-01 commodity USD
+  precision: 4
+2024-01-15 price CHF 1.25 USD
+",
+        );
+        let db = PriceDb::from_ledgers([&personal, &business]);
+        let date = |s: &str| s.parse::<Date>().unwrap();
+        assert_eq!(db.commodity("USD").unwrap().precision(), Some(4));
+        assert_eq!(
+            db.rate("USD", "CHF", date("2024-01-10")),
+            Some("0.90".parse().unwrap())
+        );
+        assert_eq!(
+            db.rate("USD", "CHF", date("2024-01-15")),
+            Some("0.8".parse().unwrap())
+        );
+        assert_eq!(db.rate("EUR", "CHF", date("2024-01-31")), None);
+        assert_eq!(db.rate("XYZ", "XYZ", Date::MIN), Some(Decimal::ONE));
+
+        let amounts = [
+            Amount::try_from("100 USD").unwrap(),
+            Amount::try_from("10 CHF").unwrap(),
+            Amount::try_from("-20 USD").unwrap(),
+            Amount::try_from("5 EUR").unwrap(),
+        ];
+        let (total, unpriced) = db.value(&amounts, "CHF", date("2024-01-20"));
+        assert_eq!(total, "74.0".parse().unwrap());
+        assert_eq!(unpriced, [Amount::try_from("5 EUR").unwrap()]);
+    }
+}
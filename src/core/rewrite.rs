@@ -0,0 +1,343 @@
+use crate::core::types::*;
+use crate::core::validation::error_at;
+use crate::io::parser::ParsedEntries;
+use crate::io::parser::error::{ErrorCode, ParseError};
+use jiff::civil::Date;
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/// What the pattern of a rule is matched against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RewriteField {
+    Payee,
+    Narration,
+    // The account of any posting.
+    Account,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RewriteAction {
+    // Moves the matching postings to the account. Rules on the payee or narration move the last
+    // posting, which is the counter posting of imported transactions.
+    SetAccount(Account),
+    AddTag(String),
+    // Adds a posting to the account, see `PostingAmount`.
+    AddPosting(Account, PostingAmount),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostingAmount {
+    Fixed(Amount),
+    // A multiple of the amount of each matching posting, like ledger's automated transactions.
+    // Rules on the payee or narration take the first posting.
+    Factor(Decimal),
+}
+
+/// Rewrites the transactions whose payee, narration or posting account matches `pattern`.
+/// Rules come from a JSON file, see `rules_from_json`, or from directives in the ledger:
+///
+/// ```text
+/// 2024-01-01 custom "rewrite" "payee" "^Migros" "account" Expenses:Groceries
+/// 2024-01-01 custom "rewrite" "narration" "(?i)flight" "tag" "travel"
+/// 2024-01-01 custom "rewrite" "account" "^Expenses:Food" "posting" Budget:Food -1
+/// ```
+#[derive(Debug, Clone)]
+pub struct RewriteRule {
+    pub field: RewriteField,
+    pub pattern: Regex,
+    pub action: RewriteAction,
+    // Rules declared in the ledger apply to the transactions from their date on.
+    pub from: Option<Date>,
+}
+
+impl RewriteRule {
+    /// Applies the rule to `transaction`. Returns whether it matched.
+    pub fn apply(&self, transaction: &mut Transaction) -> bool {
+        if self.from.is_some_and(|from| transaction.date < from) {
+            return false;
+        }
+        let text_matches = |text: &Option<String>| {
+            text.as_deref()
+                .is_some_and(|text| self.pattern.is_match(text))
+        };
+        // Indices of the postings the action applies to.
+        let matching: Vec<usize> = match self.field {
+            RewriteField::Payee if text_matches(&transaction.payee) => vec![],
+            RewriteField::Narration if text_matches(&transaction.narration) => vec![],
+            RewriteField::Account => {
+                let matching: Vec<usize> = (0..transaction.postings.len())
+                    .filter(|&i| self.pattern.is_match(&transaction.postings[i].account))
+                    .collect();
+                if matching.is_empty() {
+                    return false;
+                }
+                matching
+            }
+            _ => return false,
+        };
+        let postings = &mut transaction.postings;
+        match &self.action {
+            RewriteAction::SetAccount(account) => {
+                let targets = match self.field {
+                    RewriteField::Account => matching,
+                    _ => postings.len().checked_sub(1).into_iter().collect(),
+                };
+                for i in targets {
+                    postings[i].account = account.clone();
+                }
+            }
+            RewriteAction::AddTag(tag) => {
+                if !transaction.tags.contains(tag) {
+                    transaction.tags.push(tag.clone());
+                }
+            }
+            RewriteAction::AddPosting(account, PostingAmount::Fixed(amount)) => {
+                postings.push(posting(account.clone(), amount.clone()));
+            }
+            RewriteAction::AddPosting(account, PostingAmount::Factor(factor)) => {
+                let sources = match self.field {
+                    RewriteField::Account => matching,
+                    _ => (0..postings.len().min(1)).collect(),
+                };
+                for i in sources {
+                    let source = &postings[i].amount;
+                    let amount = Amount::new(source.number * factor, source.currency.clone());
+                    postings.push(posting(account.clone(), amount));
+                }
+            }
+        }
+        true
+    }
+}
+
+impl TryFrom<&Custom> for RewriteRule {
+    type Error = String;
+
+    fn try_from(custom: &Custom) -> Result<Self, Self::Error> {
+        let [
+            CustomValue::String(field),
+            CustomValue::String(pattern),
+            CustomValue::String(action),
+            args @ ..,
+        ] = custom.values.as_slice()
+        else {
+            return Err("Expected a field, a pattern and an action".to_string());
+        };
+        let action = match (action.as_str(), args) {
+            ("account", [CustomValue::Account(a)]) => RewriteAction::SetAccount(a.clone()),
+            ("tag", [CustomValue::String(t)]) => RewriteAction::AddTag(t.clone()),
+            ("posting", [CustomValue::Account(a), CustomValue::Amount(amount)]) => {
+                RewriteAction::AddPosting(a.clone(), PostingAmount::Fixed(amount.clone()))
+            }
+            ("posting", [CustomValue::Account(a), CustomValue::Number(factor)]) => {
+                RewriteAction::AddPosting(a.clone(), PostingAmount::Factor(*factor))
+            }
+            _ => return Err(format!("Invalid arguments for rewrite action `{action}`")),
+        };
+        Ok(RewriteRule {
+            field: parse_field(field)?,
+            pattern: parse_pattern(pattern)?,
+            action,
+            from: Some(custom.date),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    field: RewriteField,
+    pattern: String,
+    // Exactly one of the actions.
+    account: Option<String>,
+    tag: Option<String>,
+    posting: Option<PostingConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PostingConfig {
+    account: String,
+    // Either a fixed amount such as "5 CHF" or a factor.
+    amount: Option<String>,
+    factor: Option<Decimal>,
+}
+
+/// Reads rules from a JSON array such as
+/// `[{"field": "payee", "pattern": "^Migros", "account": "Expenses:Groceries"}]`. Besides
+/// `account`, the action can be `"tag": "travel"` or
+/// `"posting": {"account": "Budget:Food", "factor": "-1"}` with a factor or an amount.
+pub fn rules_from_json(json: &str) -> Result<Vec<RewriteRule>, String> {
+    let configs: Vec<RuleConfig> =
+        serde_json::from_str(json).map_err(|e| format!("Invalid rewrite rules: {e}"))?;
+    configs
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| {
+            let action = match (c.account, c.tag, c.posting) {
+                (Some(a), None, None) => RewriteAction::SetAccount(a.into()),
+                (None, Some(t), None) => RewriteAction::AddTag(t),
+                (None, None, Some(p)) => {
+                    let amount = match (p.amount, p.factor) {
+                        (Some(a), None) => PostingAmount::Fixed(Amount::try_from(a.as_str())?),
+                        (None, Some(f)) => PostingAmount::Factor(f),
+                        _ => return Err(format!("Rule {i}: expected an amount or a factor")),
+                    };
+                    RewriteAction::AddPosting(p.account.into(), amount)
+                }
+                _ => return Err(format!("Rule {i}: expected exactly one action")),
+            };
+            Ok(RewriteRule {
+                field: c.field,
+                pattern: parse_pattern(&c.pattern).map_err(|e| format!("Rule {i}: {e}"))?,
+                action,
+                from: None,
+            })
+        })
+        .collect()
+}
+
+/// Applies `rules` followed by the `custom "rewrite"` directives of the ledger to all
+/// transactions, each rule in turn. The directives are removed, the invalid ones reported.
+pub fn apply_rewrite_rules(entries: &mut ParsedEntries, rules: &[RewriteRule]) -> Vec<ParseError> {
+    let (directives, others) = std::mem::take(&mut entries.custom)
+        .into_iter()
+        .partition(|c| c.name == "rewrite");
+    entries.custom = others;
+    let mut errors = vec![];
+    let mut all_rules = rules.to_vec();
+    for custom in &directives {
+        match RewriteRule::try_from(custom) {
+            Ok(rule) => all_rules.push(rule),
+            Err(e) => errors.push(error_at(&custom.span, ErrorCode::InvalidRewriteRule, e)),
+        }
+    }
+    if all_rules.is_empty() {
+        return errors;
+    }
+    for t in &mut entries.transactions {
+        let mut rewritten = false;
+        for rule in &all_rules {
+            rewritten |= rule.apply(t);
+        }
+        if rewritten {
+            t.intern(&mut entries.symbols);
+        }
+    }
+    errors
+}
+
+fn parse_field(field: &str) -> Result<RewriteField, String> {
+    match field {
+        "payee" => Ok(RewriteField::Payee),
+        "narration" => Ok(RewriteField::Narration),
+        "account" => Ok(RewriteField::Account),
+        _ => Err(format!("Unknown rewrite field `{field}`")),
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Result<Regex, String> {
+    Regex::new(pattern).map_err(|e| format!("Invalid pattern `{pattern}`: {e}"))
+}
+
+fn posting(account: Account, amount: Amount) -> Posting {
+    Posting {
+        flag: None,
+        account,
+        amount,
+        price: None,
+        cost: None,
+        meta: Metadata::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::parser::parse_entries_from_string;
+    use crate::io::printer::print_transaction;
+    use std::path::Path;
+
+    #[test]
+    fn test_apply_rewrite_rules() {
+        let input = r#"2024-01-01 custom "rewrite" "narration" "(?i)flight" "tag" "travel"
+2024-01-01 custom "rewrite" "account" "^Expenses:Food" "posting" Budget:Food -1
+2024-01-01 custom "rewrite" "payee" "Migros" "spend" Expenses:Food
+2024-01-05 * "Migros" "Weekly shopping"
+  Assets:Bank -50 CHF
+  Expenses:Uncategorized 50 CHF
+2024-01-06 * "Swiss" "Flight to Rome"
+  Assets:Bank -200 CHF
+  Expenses:Uncategorized 200 CHF
+"#;
+        let mut entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        let rules = rules_from_json(
+            r#"[{"field": "payee", "pattern": "^Migros$", "account": "Expenses:Food:Groceries"}]"#,
+        )
+        .unwrap();
+        let errors = apply_rewrite_rules(&mut entries, &rules);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::InvalidRewriteRule);
+        assert_eq!(errors[0].line, Some(3));
+        assert!(entries.custom.is_empty());
+
+        let printed: Vec<String> = entries.transactions.iter().map(print_transaction).collect();
+        assert_eq!(
+            printed,
+            [
+                "2024-01-05 * \"Migros\" \"Weekly shopping\"
+    Assets:Bank -50 CHF
+    Expenses:Food:Groceries 50 CHF
+    Budget:Food -50 CHF",
+                "2024-01-06 * \"Swiss\" \"Flight to Rome\" #travel
+    Assets:Bank -200 CHF
+    Expenses:Uncategorized 200 CHF",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rules_from_json() {
+        let rules = rules_from_json(
+            r#"[{"field": "account", "pattern": "Food", "posting": {"account": "Budget:Food", "amount": "5 CHF"}}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules[0].action,
+            RewriteAction::AddPosting(
+                "Budget:Food".into(),
+                PostingAmount::Fixed(Amount::new(5.into(), "CHF"))
+            )
+        );
+        assert!(
+            rules_from_json(r#"[{"field": "payee", "pattern": "(", "tag": "x"}]"#)
+                .unwrap_err()
+                .starts_with("Rule 0: Invalid pattern `(`")
+        );
+        assert!(
+            rules_from_json(
+                r#"[{"field": "payee", "pattern": "x", "tag": "x", "account": "A:B"}]"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_rule_dates() {
+        let custom = Custom::try_from(
+            r#"2024-02-01 custom "rewrite" "payee" "Shop" "account" Expenses:Shopping"#,
+        )
+        .unwrap();
+        let rule = RewriteRule::try_from(&custom).unwrap();
+        let mut t = Transaction::try_from(
+            "2024-01-31 * \"Shop\" \"\"\n  Assets:Bank -1 CHF\n  Expenses:Misc 1 CHF",
+        )
+        .unwrap();
+        assert!(!rule.apply(&mut t));
+        t.date = custom.date;
+        assert!(rule.apply(&mut t));
+        assert_eq!(t.postings[1].account, "Expenses:Shopping");
+    }
+}
@@ -0,0 +1,233 @@
+use crate::core::ledger::Ledger;
+use crate::core::plugin::LedgerPlugin;
+use crate::core::types::*;
+use crate::core::validation::error_at;
+use crate::io::parser::error::{ErrorCode, ParseError};
+use rhai::{AST, Array, Dynamic, Engine, Map, Scope};
+use rust_decimal::prelude::ToPrimitive;
+use std::path::Path;
+
+// Operations a script may run per transaction, to stop endless loops.
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// A rhai script run on each transaction, for rules beyond what `RewriteRule` can express. The
+/// script sees the transaction as the variables `date` (a string), `payee` and `narration`
+/// (empty if missing), `tags` (an array of strings) and `postings` (an array of maps with
+/// `account`, `number` as a float and `currency`), and changes it by assigning to them. Of the
+/// postings, only the accounts can be changed. E.g.
+///
+/// ```text
+/// if payee.contains("MIGROS") && postings[1].number < 100 {
+///     postings[1].account = "Expenses:Groceries";
+///     tags.push("food");
+/// }
+/// ```
+pub struct TransactionScript {
+    name: String,
+    engine: Engine,
+    ast: AST,
+}
+
+impl TransactionScript {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+        TransactionScript::new(path.display().to_string(), &source)
+    }
+
+    pub fn new(name: String, source: &str) -> Result<Self, String> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine
+            .compile(source)
+            .map_err(|e| format!("Invalid script {name}: {e}"))?;
+        Ok(TransactionScript { name, engine, ast })
+    }
+
+    /// Runs the script on `transaction`. Returns whether it changed the transaction, which is
+    /// left as it was if the script fails.
+    pub fn apply(&self, transaction: &mut Transaction) -> Result<bool, String> {
+        let text = |text: &Option<String>| Dynamic::from(text.clone().unwrap_or_default());
+        let postings: Array = transaction
+            .postings
+            .iter()
+            .map(|p| {
+                let mut posting = Map::new();
+                posting.insert("account".into(), Dynamic::from(p.account.to_string()));
+                let number = p.amount.number.to_f64().unwrap_or_default();
+                posting.insert("number".into(), Dynamic::from_float(number));
+                posting.insert(
+                    "currency".into(),
+                    Dynamic::from(p.amount.currency.to_string()),
+                );
+                Dynamic::from_map(posting)
+            })
+            .collect();
+        let mut scope = Scope::new();
+        scope.push("date", transaction.date.to_string());
+        scope.push("payee", text(&transaction.payee));
+        scope.push("narration", text(&transaction.narration));
+        scope.push(
+            "tags",
+            transaction
+                .tags
+                .iter()
+                .cloned()
+                .map(Dynamic::from)
+                .collect::<Array>(),
+        );
+        scope.push("postings", postings);
+        self.engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| format!("Script {} failed: {e}", self.name))?;
+
+        let invalid = |variable| format!("Script {} set an invalid `{variable}`", self.name);
+        let text = |variable| -> Result<Option<String>, String> {
+            let value = scope
+                .get_value::<String>(variable)
+                .ok_or_else(|| invalid(variable))?;
+            Ok(Some(value).filter(|v| !v.is_empty()))
+        };
+        let (payee, narration) = (text("payee")?, text("narration")?);
+        let tags = scope
+            .get_value::<Array>("tags")
+            .and_then(|tags| tags.into_iter().map(|t| t.into_string().ok()).collect())
+            .ok_or_else(|| invalid("tags"))?;
+        let accounts: Vec<String> = scope
+            .get_value::<Array>("postings")
+            .filter(|postings| postings.len() == transaction.postings.len())
+            .and_then(|postings| {
+                postings
+                    .into_iter()
+                    .map(|p| {
+                        p.try_cast::<Map>()?
+                            .get("account")?
+                            .clone()
+                            .into_string()
+                            .ok()
+                    })
+                    .collect()
+            })
+            .ok_or_else(|| invalid("postings"))?;
+
+        let changed = payee != transaction.payee
+            || narration != transaction.narration
+            || tags != transaction.tags
+            || accounts
+                .iter()
+                .zip(&transaction.postings)
+                .any(|(a, p)| *a != *p.account);
+        transaction.payee = payee;
+        transaction.narration = narration;
+        transaction.tags = tags;
+        for (account, p) in accounts.into_iter().zip(&mut transaction.postings) {
+            if *account != *p.account {
+                p.account = account.into();
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// Runs `scripts` on all transactions, one after the other. Failures are reported at the
+/// transaction.
+pub struct Scripts {
+    pub scripts: Vec<TransactionScript>,
+}
+
+impl LedgerPlugin for Scripts {
+    fn name(&self) -> &str {
+        "scripts"
+    }
+
+    fn transform(&self, ledger: &mut Ledger) -> Vec<ParseError> {
+        if self.scripts.is_empty() {
+            return vec![];
+        }
+        ledger.modify(|entries| {
+            let mut errors = vec![];
+            for t in &mut entries.transactions {
+                let mut changed = false;
+                for script in &self.scripts {
+                    match script.apply(t) {
+                        Ok(c) => changed |= c,
+                        Err(e) => errors.push(error_at(&t.span, ErrorCode::PluginError, e)),
+                    }
+                }
+                if changed {
+                    t.intern(&mut entries.symbols);
+                }
+            }
+            errors
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::parser::parse_entries_from_string;
+
+    #[test]
+    fn test_transaction_script() {
+        let script = TransactionScript::new(
+            "test.rhai".to_string(),
+            r#"
+if payee.contains("MIGROS") && postings[0].number > -100 {
+    postings[1].account = "Expenses:Groceries";
+    if !tags.contains("food") { tags.push("food"); }
+}
+if narration == "Card" { narration += " " + date; }
+"#,
+        )
+        .unwrap();
+        let mut t = Transaction::try_from(
+            "2024-01-05 * \"MIGROS ZURICH\" \"Card\"\n  Assets:Bank -20.50 CHF\n  Expenses:Uncategorized 20.50 CHF",
+        )
+        .unwrap();
+        assert!(script.apply(&mut t).unwrap());
+        assert_eq!(
+            t.to_string(),
+            "2024-01-05 * \"MIGROS ZURICH\" \"Card 2024-01-05\" #food
+    Assets:Bank -20.50 CHF
+    Expenses:Groceries 20.50 CHF"
+        );
+        assert!(!script.apply(&mut t).unwrap());
+
+        let endless = TransactionScript::new("endless.rhai".to_string(), "loop {}").unwrap();
+        assert!(endless.apply(&mut t).unwrap_err().contains("endless.rhai"));
+        let removes = TransactionScript::new("x".to_string(), "postings.pop();").unwrap();
+        assert!(removes.apply(&mut t).is_err());
+        assert_eq!(t.postings.len(), 2);
+        assert!(TransactionScript::new("x".to_string(), "if {").is_err());
+    }
+
+    #[test]
+    fn test_scripts_plugin() {
+        let input = "2024-01-05 * \"Shop\"
+  Assets:Bank -10 CHF
+  Expenses:Food 10 CHF
+";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("main.bean")).unwrap();
+        let mut ledger = Ledger::new(entries);
+        let scripts = Scripts {
+            scripts: vec![
+                TransactionScript::new(
+                    "a".to_string(),
+                    r#"postings[1].account = "Expenses:Shop";"#,
+                )
+                .unwrap(),
+                TransactionScript::new("b".to_string(), "payee = 1;").unwrap(),
+            ],
+        };
+        let errors = scripts.transform(&mut ledger);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, Some(1));
+        assert_eq!(
+            ledger
+                .balance("Expenses:Shop", jiff::civil::Date::MAX)
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,869 @@
+use crate::core::ledger::is_within;
+use crate::core::types::*;
+use crate::core::validation::error_at;
+use crate::io::parser::ParsedEntries;
+use crate::io::parser::error::{ErrorCode, ParseError};
+use jiff::ToSpan;
+use jiff::civil::Date;
+use rust_decimal::Decimal;
+use smallvec::SmallVec;
+use std::collections::{BTreeMap, HashSet};
+
+pub const OPENING_BALANCES_ACCOUNT: &str = "Equity:Opening-Balances";
+pub const PREPAID_ACCOUNT: &str = "Assets:Prepaid";
+pub const DEPRECIATION_ACCOUNT: &str = "Expenses:Depreciation";
+// Prefix of the links added by `assign_ids`.
+pub const ID_LINK_PREFIX: &str = "id-";
+
+/// How the value of an asset is written off over its useful life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepreciationMethod {
+    // The same amount each month.
+    StraightLine,
+    // Double declining balance: each month writes off twice the straight-line rate of the
+    // remaining value, the last month the rest.
+    DecliningBalance,
+}
+
+impl std::str::FromStr for DepreciationMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "straight-line" => Ok(DepreciationMethod::StraightLine),
+            "declining-balance" => Ok(DepreciationMethod::DecliningBalance),
+            _ => Err(format!("Unknown depreciation method `{s}`")),
+        }
+    }
+}
+
+/// For ledgers started mid-life: adds a transaction on `start` for each account with balance
+/// assertions after `start`, so that the earliest assertion per currency holds. Assertions on
+/// `start` check the balance at the start of that day, before the added transaction, and are
+/// not used. The counter postings go to `Equity:Opening-Balances`, which is opened on `start`
+/// if needed. The assertions themselves are kept and check the result. Returns the number of
+/// added transactions.
+pub fn add_opening_balances(entries: &mut ParsedEntries, start: Date) -> usize {
+    // The earliest assertion per account and currency.
+    let mut earliest: BTreeMap<(&str, &str), &Balance> = BTreeMap::new();
+    for b in entries.balance.iter().filter(|b| b.date > start) {
+        let key = (&*b.account, &*b.amount.currency);
+        match earliest.get(&key) {
+            Some(e) if e.date <= b.date => {}
+            _ => {
+                earliest.insert(key, b);
+            }
+        }
+    }
+
+    let mut openings: BTreeMap<&Account, SmallVec<[Amount; 1]>> = BTreeMap::new();
+    for balance in earliest.values() {
+        let posted: Decimal = entries
+            .transactions
+            .iter()
+            .filter(|t| t.date < balance.date)
+            .flat_map(|t| &t.postings)
+            .filter(|p| {
+                p.account == balance.account && p.amount.currency == balance.amount.currency
+            })
+            .map(|p| p.amount.number)
+            .sum();
+        let missing = balance.amount.number - posted;
+        if !missing.is_zero() {
+            let amount = Amount::new(missing, balance.amount.currency.clone());
+            openings.entry(&balance.account).or_default().push(amount);
+        }
+    }
+
+    let transactions: Vec<Transaction> = openings
+        .into_iter()
+        .map(|(account, amounts)| Transaction {
+            date: start,
+            flag: TransactionFlag::OK,
+            payee: None,
+            narration: Some(format!("Opening balance for {account}")),
+            tags: vec![],
+            links: vec![],
+            meta: Metadata::default(),
+            postings: amounts
+                .into_iter()
+                .flat_map(|amount| {
+                    let counter = Amount::new(-amount.number, amount.currency.clone());
+                    [
+                        posting(account.clone(), amount),
+                        posting(OPENING_BALANCES_ACCOUNT.into(), counter),
+                    ]
+                })
+                .collect(),
+            span: Span::default(),
+        })
+        .collect();
+    let added = transactions.len();
+    let opened = entries
+        .open
+        .iter()
+        .any(|o| o.account == OPENING_BALANCES_ACCOUNT && o.date <= start);
+    if added > 0 && !opened {
+        entries.push(EntryVariant::Open(Open {
+            date: start,
+            account: OPENING_BALANCES_ACCOUNT.into(),
+            allowed_currencies: None,
+            meta: Metadata::default(),
+            span: Span::default(),
+        }));
+    }
+    for t in transactions {
+        entries.push(EntryVariant::Transaction(t));
+    }
+    added
+}
+
+/// Replaces each pad directive by the transactions it stands for: for every currency with a
+/// balance assertion on the padded account after the pad, and before its next pad, a transfer
+/// from the source account on the pad date that makes the first such assertion hold, flagged
+/// `P`. The result validates without the pad directives. Returns the number of added transactions.
+pub fn resolve_pads(entries: &mut ParsedEntries) -> usize {
+    let mut pads = std::mem::take(&mut entries.pad);
+    pads.sort_by_key(|p| p.date);
+    let mut added = 0;
+    for (i, pad) in pads.iter().enumerate() {
+        let until = pads[i + 1..]
+            .iter()
+            .find(|p| p.account == pad.account)
+            .map(|p| p.date);
+        let mut assertions: Vec<&Balance> = entries
+            .balance
+            .iter()
+            .filter(|b| b.account == pad.account && b.date > pad.date)
+            .filter(|b| until.is_none_or(|until| b.date <= until))
+            .collect();
+        assertions.sort_by_key(|b| b.date);
+        let mut amounts: Vec<(Amount, Amount)> = vec![];
+        for b in assertions {
+            if amounts.iter().any(|(a, _)| a.currency == b.amount.currency) {
+                continue;
+            }
+            let posted: Decimal = entries
+                .transactions
+                .iter()
+                .filter(|t| t.date < b.date)
+                .flat_map(|t| &t.postings)
+                .filter(|p| match b.recursive {
+                    true => is_within(&p.account, &pad.account),
+                    false => p.account == pad.account,
+                })
+                .filter(|p| p.amount.currency == b.amount.currency)
+                .map(|p| p.amount.number)
+                .sum();
+            let missing = Amount::new(b.amount.number - posted, b.amount.currency.clone());
+            amounts.push((missing, b.amount.clone()));
+        }
+        let mut span = pad.span.clone();
+        for (missing, asserted) in amounts {
+            if missing.number.is_zero() {
+                continue;
+            }
+            let counter = Amount::new(-missing.number, missing.currency.clone());
+            let narration =
+                format!("(Padding inserted for Balance of {asserted} for difference {missing})");
+            entries.push(EntryVariant::Transaction(Transaction {
+                date: pad.date,
+                flag: TransactionFlag::Padding,
+                payee: None,
+                narration: Some(narration),
+                tags: vec![],
+                links: vec![],
+                meta: Metadata::default(),
+                postings: [
+                    posting(pad.account.clone(), missing),
+                    posting(pad.source_account.clone(), counter),
+                ]
+                .into_iter()
+                .collect(),
+                span: span.clone(),
+            }));
+            // The comments of the pad go with its first transaction only.
+            span.comments = None;
+            added += 1;
+        }
+    }
+    added
+}
+
+/// Expands `custom "recur" "<frequency>" "<end>"` directives: the transaction following the
+/// directive on the same date is the template, and a copy of it is added for each daily,
+/// weekly, monthly or yearly step up to and including the end date. Months and years are
+/// counted from the template date, so a template on the 31st falls on the last day of shorter
+/// months. The directives are removed, the ones that cannot be expanded are reported.
+pub fn expand_recurring(entries: &mut ParsedEntries) -> Vec<ParseError> {
+    let (recurrences, others) = std::mem::take(&mut entries.custom)
+        .into_iter()
+        .partition(|c| c.name == "recur");
+    entries.custom = others;
+    let mut errors = vec![];
+    for recur in recurrences {
+        let copies = recurrence(&recur).and_then(|(step, end)| {
+            let template = template_of(entries, &recur)
+                .ok_or("No transaction on the same date follows the directive".to_string())?;
+            Ok((1..)
+                .map_while(|i| {
+                    let date = template.date.checked_add(step * i).ok()?;
+                    (date <= end).then_some(date)
+                })
+                .map(|date| Transaction {
+                    date,
+                    span: Span {
+                        comments: None,
+                        ..template.span.clone()
+                    },
+                    ..template.clone()
+                })
+                .collect::<Vec<_>>())
+        });
+        match copies {
+            Ok(copies) => {
+                for t in copies {
+                    entries.push(EntryVariant::Transaction(t));
+                }
+            }
+            Err(e) => errors.push(error_at(&recur.span, ErrorCode::InvalidRecurrence, e)),
+        }
+    }
+    errors
+}
+
+// The step and end date of a `recur` directive.
+fn recurrence(recur: &Custom) -> Result<(jiff::Span, Date), String> {
+    let [CustomValue::String(frequency), end] = recur.values.as_slice() else {
+        return Err("Expected a frequency and an end date".to_string());
+    };
+    let step = match frequency.as_str() {
+        "daily" => 1.day(),
+        "weekly" => 1.week(),
+        "monthly" => 1.month(),
+        "yearly" => 1.year(),
+        _ => return Err(format!("Unknown frequency `{frequency}`")),
+    };
+    let end = match end {
+        CustomValue::Date(d) => *d,
+        CustomValue::String(s) => s
+            .parse()
+            .map_err(|e| format!("Invalid end date `{s}`: {e}"))?,
+        _ => return Err(format!("Invalid end date `{end}`")),
+    };
+    Ok((step, end))
+}
+
+// The first transaction after `recur` in its file with the same date.
+fn template_of<'a>(entries: &'a ParsedEntries, recur: &Custom) -> Option<&'a Transaction> {
+    entries
+        .transactions
+        .iter()
+        .filter(|t| t.date == recur.date && t.span.file == recur.span.file)
+        .filter(|t| t.span.lines.start >= recur.span.lines.end)
+        .min_by_key(|t| t.span.lines.start)
+}
+
+/// Spreads the postings of `transaction` to `account` over `months` months, e.g. a yearly
+/// insurance premium. The returned transaction is `transaction` posting to `Assets:Prepaid`
+/// instead, followed by one transaction per month, starting on its date, that moves an equal
+/// share from `Assets:Prepaid` to `account`. Shares are rounded to the precision of the
+/// postings, the last one takes the remainder so the shares add up to the total.
+pub fn amortize(
+    transaction: &Transaction,
+    account: &str,
+    months: u32,
+) -> Result<Vec<Transaction>, String> {
+    if months == 0 {
+        return Err("Cannot amortize over 0 months".to_string());
+    }
+    let amortized: Vec<&Amount> = transaction
+        .postings
+        .iter()
+        .filter(|p| p.account == account)
+        .map(|p| &p.amount)
+        .collect();
+    if amortized.is_empty() {
+        return Err(format!("No posting to {account} in transaction"));
+    }
+    let scales: BTreeMap<&Currency, u32> =
+        amortized.iter().fold(BTreeMap::new(), |mut scales, a| {
+            let scale = scales.entry(&a.currency).or_default();
+            *scale = (*scale).max(a.number.scale());
+            scales
+        });
+    let totals = sum_by_currency(amortized.iter().copied());
+
+    let mut prepaid = transaction.clone();
+    for p in prepaid.postings.iter_mut().filter(|p| p.account == account) {
+        p.account = PREPAID_ACCOUNT.into();
+    }
+    let narration = transaction.narration.as_deref().unwrap_or("Amortization");
+    let mut result = vec![prepaid];
+    for month in 0..months {
+        let date = transaction
+            .date
+            .checked_add(i64::from(month).months())
+            .map_err(|e| format!("Unable to amortize until month {month}: {e}"))?;
+        let mut postings = Postings::new();
+        for (currency, total) in &totals {
+            let share = (total / Decimal::from(months)).round_dp(scales[currency]);
+            let number = if month + 1 < months {
+                share
+            } else {
+                total - share * Decimal::from(months - 1)
+            };
+            postings.push(posting(
+                account.into(),
+                Amount::new(number, currency.clone()),
+            ));
+            postings.push(posting(
+                PREPAID_ACCOUNT.into(),
+                Amount::new(-number, currency.clone()),
+            ));
+        }
+        result.push(Transaction {
+            date,
+            flag: TransactionFlag::OK,
+            payee: transaction.payee.clone(),
+            narration: Some(format!("{narration} ({}/{months})", month + 1)),
+            tags: transaction.tags.clone(),
+            links: transaction.links.clone(),
+            meta: Metadata::default(),
+            postings,
+            span: Span::default(),
+        });
+    }
+    Ok(result)
+}
+
+/// Writes off the value of the asset bought by `purchase` on `date` over `months` months: one
+/// transaction per month from the month after the purchase, moving the depreciation from the
+/// asset account to `Expenses:Depreciation`. The value is the weight of the posting, e.g. its
+/// cost, and is written off completely.
+pub fn depreciate(
+    date: Date,
+    purchase: &Posting,
+    months: u32,
+    method: DepreciationMethod,
+) -> Result<Vec<Transaction>, String> {
+    if months == 0 {
+        return Err("Cannot depreciate over 0 months".to_string());
+    }
+    let value = purchase
+        .weight()
+        .ok_or_else(|| format!("Unknown cost of {}", purchase.account))?;
+    let scale = value.number.scale();
+    let mut remaining = value.number;
+    let mut result = vec![];
+    for month in 1..=months {
+        let number = match method {
+            _ if month == months => remaining,
+            DepreciationMethod::StraightLine => {
+                (value.number / Decimal::from(months)).round_dp(scale)
+            }
+            DepreciationMethod::DecliningBalance => {
+                (remaining * Decimal::TWO / Decimal::from(months)).round_dp(scale)
+            }
+        };
+        remaining -= number;
+        let amount = Amount::new(number, value.currency.clone());
+        let counter = Amount::new(-number, value.currency.clone());
+        result.push(Transaction {
+            date: date
+                .checked_add(i64::from(month).months())
+                .map_err(|e| format!("Unable to depreciate until month {month}: {e}"))?,
+            flag: TransactionFlag::OK,
+            payee: None,
+            narration: Some(format!(
+                "Depreciation of {} ({month}/{months})",
+                purchase.account
+            )),
+            tags: vec![],
+            links: vec![],
+            meta: Metadata::default(),
+            postings: [
+                posting(DEPRECIATION_ACCOUNT.into(), amount),
+                posting(purchase.account.clone(), counter),
+            ]
+            .into_iter()
+            .collect(),
+            span: Span::default(),
+        });
+    }
+    Ok(result)
+}
+
+/// Renames `old` and its sub-accounts to `new` in all entries, e.g. `Assets:Old:Cash` to
+/// `Assets:New:Cash`. Returns the number of renamed references.
+pub fn rename_account(entries: &mut ParsedEntries, old: &str, new: &str) -> usize {
+    let mut renamed = 0;
+    for account in entries.accounts_mut() {
+        if is_within(account, old) {
+            *account = format!("{new}{}", &account[old.len()..]).into();
+            renamed += 1;
+        }
+    }
+    if renamed > 0 {
+        entries.intern_all();
+    }
+    renamed
+}
+
+/// Resolves `custom "alias" "<name>" <account>` directives: the name, which may be a short
+/// name like "checking" or an old account, is renamed to the account in all entries, see
+/// `rename_account`. The directives are removed, the invalid ones reported.
+pub fn resolve_aliases(entries: &mut ParsedEntries) -> Vec<ParseError> {
+    let (aliases, others) = std::mem::take(&mut entries.custom)
+        .into_iter()
+        .partition(|c| c.name == "alias");
+    entries.custom = others;
+    let mut errors = vec![];
+    for alias in aliases {
+        let name = match alias.values.first() {
+            Some(CustomValue::String(name)) => Some(name.as_str()),
+            Some(CustomValue::Account(name)) => Some(&**name),
+            _ => None,
+        };
+        match (name, alias.values.get(1..)) {
+            (Some(name), Some([CustomValue::Account(account)])) => {
+                rename_account(entries, name, account);
+            }
+            _ => errors.push(error_at(
+                &alias.span,
+                ErrorCode::InvalidAlias,
+                "Expected a name and an account".to_string(),
+            )),
+        }
+    }
+    errors
+}
+
+/// Gives each transaction without one a link like `^id-3f9a0c12d4e7` derived from its
+/// fingerprint, so that other systems can refer to it. The ID stays the same as long as the
+/// transaction is not edited before the IDs are assigned, and is kept afterwards. Identical
+/// transactions get a counter appended. Returns the number of assigned IDs.
+pub fn assign_ids(entries: &mut ParsedEntries) -> usize {
+    let is_id = |link: &String| link.starts_with(ID_LINK_PREFIX);
+    let mut used: HashSet<String> = entries
+        .transactions
+        .iter()
+        .flat_map(|t| t.links.iter().filter(|l| is_id(l)).cloned())
+        .collect();
+    let mut assigned = 0;
+    for t in &mut entries.transactions {
+        if t.links.iter().any(is_id) {
+            continue;
+        }
+        // 48 bits keep the links short while collisions stay unlikely.
+        let id = format!("{ID_LINK_PREFIX}{:012x}", t.fingerprint() >> 16);
+        let id = (1..)
+            .map(|n| match n {
+                1 => id.clone(),
+                n => format!("{id}-{n}"),
+            })
+            .find(|id| !used.contains(id))
+            .unwrap_or(id);
+        used.insert(id.clone());
+        t.links.push(id);
+        assigned += 1;
+    }
+    assigned
+}
+
+/// Adds a price directive for each posting with a price, or else a known cost, on the date of
+/// its transaction, as beancount's implicit_prices plugin does. Prices that are already in the
+/// ledger on that date are not repeated. Returns the number of added directives.
+pub fn add_implicit_prices(entries: &mut ParsedEntries) -> usize {
+    let mut known: HashSet<(Date, String, String)> = entries
+        .price
+        .iter()
+        .map(|p| (p.date, p.currency.to_string(), p.amount.to_string()))
+        .collect();
+    let mut prices = vec![];
+    for t in &entries.transactions {
+        for p in &t.postings {
+            let amount = match (&p.price, &p.cost) {
+                (Some(price), _) => &price.amount,
+                (None, Some(CostType::Known(cost))) => &cost.amount,
+                _ => continue,
+            };
+            if known.insert((t.date, p.amount.currency.to_string(), amount.to_string())) {
+                prices.push(PriceDirective {
+                    date: t.date,
+                    currency: p.amount.currency.clone(),
+                    amount: amount.clone(),
+                    meta: Metadata::default(),
+                    // The comments stay with the transaction.
+                    span: Span {
+                        comments: None,
+                        ..t.span.clone()
+                    },
+                });
+            }
+        }
+    }
+    let added = prices.len();
+    for p in prices {
+        entries.push(EntryVariant::Price(p));
+    }
+    added
+}
+
+fn posting(account: Account, amount: Amount) -> Posting {
+    Posting {
+        flag: None,
+        account,
+        amount,
+        price: None,
+        cost: None,
+        meta: Metadata::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ledger::Ledger;
+    use crate::io::parser::parse_entries_from_string;
+    use jiff::civil::date;
+    use std::path::Path;
+
+    #[test]
+    fn test_assign_ids() {
+        let input = "2024-01-05 * \"Shop\"
+  Assets:Cash -5 CHF
+  Expenses:Food 5 CHF
+2024-01-05 * \"Shop\"
+  Assets:Cash -5 CHF
+  Expenses:Food 5 CHF
+2024-01-06 * \"Rent\" ^id-1
+  Assets:Bank -900 CHF
+  Expenses:Rent 900 CHF
+";
+        let mut entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        let fingerprint = entries.transactions[0].fingerprint();
+        assert_eq!(assign_ids(&mut entries), 2);
+        let links: Vec<&[String]> = entries.transactions.iter().map(|t| &t.links[..]).collect();
+        let id = format!("id-{:012x}", fingerprint >> 16);
+        assert_eq!(
+            links,
+            [
+                &[id.clone()][..],
+                &[format!("{id}-2")],
+                &["id-1".to_string()]
+            ]
+        );
+        assert_eq!(assign_ids(&mut entries), 0);
+    }
+
+    #[test]
+    fn test_add_opening_balances() {
+        let input = "2024-01-01 open Assets:Cash
+2024-01-01 open Assets:Bank
+2024-01-01 open Expenses:Food
+2023-12-01 balance Assets:Bank 5 CHF
+2024-01-10 *
+  Assets:Cash -5 CHF
+  Expenses:Food 5 CHF
+2024-01-15 balance Assets:Cash 95 CHF
+2024-01-15 balance Assets:Cash 10 EUR
+2024-02-01 balance Assets:Cash 1000 CHF
+2024-01-15 balance Assets:Bank 0 CHF
+";
+        let mut entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(add_opening_balances(&mut entries, date(2024, 1, 1)), 1);
+        assert_eq!(
+            entries.open.last().unwrap().account,
+            OPENING_BALANCES_ACCOUNT
+        );
+        let opening = entries.transactions.last().unwrap();
+        assert_eq!(opening.date, date(2024, 1, 1));
+        let postings: Vec<String> = opening
+            .postings
+            .iter()
+            .map(|p| format!("{} {}", p.account, p.amount))
+            .collect();
+        assert_eq!(
+            postings,
+            [
+                "Assets:Cash 100 CHF",
+                "Equity:Opening-Balances -100 CHF",
+                "Assets:Cash 10 EUR",
+                "Equity:Opening-Balances -10 EUR",
+            ]
+        );
+
+        let ledger = Ledger::new(entries);
+        assert_eq!(
+            ledger.balance("Assets:Cash", date(2024, 1, 15)),
+            [Amount::new(95.into(), "CHF"), Amount::new(10.into(), "EUR")]
+        );
+
+        // An assertion on `start` holds before the opening transaction, so it can't size it.
+        let input = "2024-01-01 open Assets:Cash
+2024-01-01 balance Assets:Cash 100 CHF
+";
+        let mut entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(add_opening_balances(&mut entries, date(2024, 1, 1)), 0);
+        let input = "2024-01-01 open Assets:Cash
+2024-01-01 balance Assets:Cash 100 CHF
+2024-01-02 balance Assets:Cash 50 CHF
+";
+        let mut entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(add_opening_balances(&mut entries, date(2024, 1, 1)), 1);
+        let ledger = Ledger::new(entries);
+        assert_eq!(
+            ledger.balance("Assets:Cash", date(2024, 1, 2)),
+            [Amount::new(50.into(), "CHF")]
+        );
+    }
+
+    #[test]
+    fn test_expand_recurring() {
+        let input = r#"2024-01-31 custom "recur" "monthly" "2024-04-30"
+2024-01-31 * "Rent"
+  Expenses:Rent 1000 CHF
+  Assets:Bank -1000 CHF
+2024-01-31 * "Not a template"
+  Expenses:Food 10 CHF
+  Assets:Bank -10 CHF
+2024-02-01 custom "recur" "weekly" 2024-02-15
+2024-02-01 * "Cleaning"
+  Expenses:Cleaning 50 CHF
+  Assets:Bank -50 CHF
+2024-03-01 custom "recur" "fortnightly" "2024-12-31"
+2024-03-02 custom "recur" "daily" "2024-03-05"
+2024-03-03 * "Too late"
+  Expenses:Food 10 CHF
+  Assets:Bank -10 CHF
+2024-03-01 custom "budget" Expenses:Food 500 CHF
+"#;
+        let mut entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        let errors = expand_recurring(&mut entries);
+        let errors: Vec<(usize, String)> = errors
+            .into_iter()
+            .map(|e| (e.line.unwrap(), e.context))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (12, "Unknown frequency `fortnightly`".to_string()),
+                (
+                    13,
+                    "No transaction on the same date follows the directive".to_string()
+                ),
+            ]
+        );
+        assert_eq!(entries.custom.len(), 1);
+        let added: Vec<String> = entries.transactions[4..]
+            .iter()
+            .map(|t| format!("{} {}", t.date, t.narration.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            added,
+            [
+                "2024-02-29 Rent",
+                "2024-03-31 Rent",
+                "2024-04-30 Rent",
+                "2024-02-08 Cleaning",
+                "2024-02-15 Cleaning",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_amortize() {
+        let t = Transaction::try_from(
+            "2024-01-15 * \"Insurer\" \"Car insurance\"
+  Expenses:Insurance 1000.00 CHF
+  Assets:Bank -1000.00 CHF",
+        )
+        .unwrap();
+        let amortized = amortize(&t, "Expenses:Insurance", 3).unwrap();
+        let printed: Vec<String> = amortized.iter().map(|t| t.to_string()).collect();
+        assert_eq!(
+            printed,
+            [
+                "2024-01-15 * \"Insurer\" \"Car insurance\"
+    Assets:Prepaid 1000.00 CHF
+    Assets:Bank -1000.00 CHF",
+                "2024-01-15 * \"Insurer\" \"Car insurance (1/3)\"
+    Expenses:Insurance 333.33 CHF
+    Assets:Prepaid -333.33 CHF",
+                "2024-02-15 * \"Insurer\" \"Car insurance (2/3)\"
+    Expenses:Insurance 333.33 CHF
+    Assets:Prepaid -333.33 CHF",
+                "2024-03-15 * \"Insurer\" \"Car insurance (3/3)\"
+    Expenses:Insurance 333.34 CHF
+    Assets:Prepaid -333.34 CHF",
+            ]
+        );
+        assert!(amortized.iter().all(|t| t.check().is_ok()));
+
+        assert_eq!(
+            amortize(&t, "Expenses:Food", 3).unwrap_err(),
+            "No posting to Expenses:Food in transaction"
+        );
+        assert!(amortize(&t, "Expenses:Insurance", 0).is_err());
+    }
+
+    #[test]
+    fn test_depreciate() {
+        let laptop = Posting::try_from("Assets:Laptop 1 LAPTOP {1000.00 CHF}").unwrap();
+        let amounts = |method| -> Vec<String> {
+            depreciate(date(2024, 1, 31), &laptop, 4, method)
+                .unwrap()
+                .iter()
+                .map(|t| format!("{} {}", t.date, t.postings[0].amount))
+                .collect()
+        };
+        assert_eq!(
+            amounts(DepreciationMethod::StraightLine),
+            [
+                "2024-02-29 250.00 CHF",
+                "2024-03-31 250.00 CHF",
+                "2024-04-30 250.00 CHF",
+                "2024-05-31 250.00 CHF",
+            ]
+        );
+        assert_eq!(
+            amounts(DepreciationMethod::DecliningBalance),
+            [
+                "2024-02-29 500.00 CHF",
+                "2024-03-31 250.00 CHF",
+                "2024-04-30 125.00 CHF",
+                "2024-05-31 125.00 CHF",
+            ]
+        );
+        let t = &depreciate(
+            date(2024, 1, 31),
+            &laptop,
+            4,
+            DepreciationMethod::StraightLine,
+        )
+        .unwrap()[0];
+        assert_eq!(t.postings[0].account, DEPRECIATION_ACCOUNT);
+        assert_eq!(t.postings[1].account, "Assets:Laptop");
+        assert!(t.check().is_ok());
+        assert_eq!(
+            "declining-balance".parse(),
+            Ok(DepreciationMethod::DecliningBalance)
+        );
+
+        let automatic = Posting::try_from("Assets:Laptop 1 LAPTOP {}").unwrap();
+        assert!(
+            depreciate(
+                date(2024, 1, 31),
+                &automatic,
+                4,
+                DepreciationMethod::StraightLine
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_resolve_aliases() {
+        let input = r#"2024-01-01 custom "alias" "checking" Assets:Bank:Checking
+2024-01-01 custom "alias" Expenses:Eating Expenses:Food
+2024-01-01 custom "alias" "broken"
+2024-01-01 open Assets:Bank:Checking
+2024-01-02 * "Lunch"
+  checking -5 CHF
+  Expenses:Eating:Out 5 CHF
+2024-01-03 balance checking -5 CHF
+"#;
+        let mut entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        let errors = resolve_aliases(&mut entries);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, Some(3));
+        assert!(entries.custom.is_empty());
+        let accounts: Vec<&str> = entries.accounts_mut().map(|a| &**a).collect();
+        assert_eq!(
+            accounts,
+            [
+                "Assets:Bank:Checking",
+                "Assets:Bank:Checking",
+                "Assets:Bank:Checking",
+                "Expenses:Food:Out"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_pads() {
+        let input = "2024-01-01 pad Assets:Cash Equity:Opening
+2024-01-05 *
+  Assets:Cash:Wallet 10 CHF
+  Income:Gift -10 CHF
+2024-01-10 balance Assets:Cash:* 100 CHF
+2024-01-20 balance Assets:Cash:* 150 CHF
+2024-02-01 pad Assets:Cash Expenses:Unknown
+2024-03-01 balance Assets:Cash:* 90 CHF
+2024-03-01 balance Assets:Cash 5 EUR
+";
+        let mut entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(resolve_pads(&mut entries), 3);
+        assert!(entries.pad.is_empty());
+        let padding: Vec<String> = entries.transactions[1..]
+            .iter()
+            .map(|t| {
+                let p = &t.postings;
+                format!(
+                    "{} {} {} {}",
+                    t.date, p[0].amount, p[1].account, p[1].amount
+                )
+            })
+            .collect();
+        assert_eq!(
+            padding,
+            [
+                "2024-01-01 90 CHF Equity:Opening -90 CHF",
+                "2024-02-01 -10 CHF Expenses:Unknown 10 CHF",
+                "2024-02-01 5 EUR Expenses:Unknown -5 EUR",
+            ]
+        );
+        assert_eq!(
+            entries.transactions[1].narration.as_deref(),
+            Some("(Padding inserted for Balance of 100 CHF for difference 90 CHF)")
+        );
+        assert_eq!(entries.transactions[1].flag, TransactionFlag::Padding);
+        let ledger = Ledger::new(entries);
+        assert_eq!(
+            ledger.balance("Assets:Cash", date(2024, 3, 1)),
+            [Amount::new(90.into(), "CHF"), Amount::new(5.into(), "EUR")]
+        );
+    }
+
+    #[test]
+    fn test_add_implicit_prices() {
+        let input = "2024-01-05 price USD 0.9 CHF
+2024-01-05 *
+  Assets:Cash 100 USD @ 0.9 CHF
+  Assets:Bank -90 CHF
+2024-01-06 *
+  Assets:Broker 2 META {300 USD}
+  Assets:Cash -600 USD
+2024-01-07 *
+  Assets:Broker -1 META {300 USD} @ 350 USD
+  Assets:Cash 350 USD
+  Income:Gains -50 USD
+";
+        let mut entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(add_implicit_prices(&mut entries), 2);
+        let prices: Vec<String> = entries
+            .price
+            .iter()
+            .map(|p| format!("{} {} {}", p.date, p.currency, p.amount))
+            .collect();
+        assert_eq!(
+            prices,
+            [
+                "2024-01-05 USD 0.9 CHF",
+                "2024-01-06 META 300 USD",
+                "2024-01-07 META 350 USD",
+            ]
+        );
+    }
+}
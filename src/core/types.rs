@@ -1,22 +1,119 @@
+pub mod symbol;
 pub mod transaction;
 
-pub use transaction::{Cost, CostType, Posting, Price, Transaction, TransactionFlag};
+pub use symbol::{Intern, Interner, Symbol};
+pub use transaction::{
+    Cost, CostType, Posting, Postings, PriceAnnotation, TIME_KEY, Transaction, TransactionFlag,
+};
 
-use crate::io::printer::print_transaction;
+use crate::io::printer::{BeancountRenderer, print_transaction, render_entry};
 use jiff::civil::Date;
-use rust_decimal::Decimal;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::collections::BTreeMap;
 use std::fmt::Display;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
 
+// Transactions keep their postings inline and are much larger than the other entries. Entries
+// are moved into typed collections right after parsing, so boxing them would not pay off.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EntryVariant {
     Transaction(Transaction),
     Balance(Balance),
     Open(Open),
     Close(Close),
     Commodity(Commodity),
-    PriceEntry(PriceEntry),
+    Price(PriceDirective),
+    Note(Note),
+    Document(Document),
+    Pad(Pad),
+    Event(Event),
+    Custom(Custom),
+}
+
+pub type Account = Symbol;
+pub type Currency = Symbol;
+// Opens usually allow a single currency, which is then stored inline.
+pub type Currencies = SmallVec<[Currency; 1]>;
+
+/// Where an entry was read from. Entries that were not parsed, e.g. imported ones, have the
+/// default span without a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    // Shared by all entries of a file.
+    pub file: Option<Arc<Path>>,
+    // 1-based, the end is exclusive.
+    pub lines: Range<usize>,
+    // Comments around the entry in the source, boxed as most entries have none.
+    pub comments: Option<Box<Comments>>,
+}
+
+/// Comments attached to an entry, each starting with its comment character. Lines of an entry
+/// are counted as printed: 0 is the line with the date, 1 the first posting and so on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Comments {
+    // Comment lines between the previous entry and this one.
+    pub leading: Vec<String>,
+    // Comments at the end of a line of the entry, by line.
+    pub trailing: Vec<(usize, String)>,
+    // Comment lines within the entry, by the line they follow.
+    pub inner: Vec<(usize, String)>,
 }
 
 impl EntryVariant {
+    pub fn span(&self) -> &Span {
+        match self {
+            EntryVariant::Transaction(t) => &t.span,
+            EntryVariant::Balance(t) => &t.span,
+            EntryVariant::Open(t) => &t.span,
+            EntryVariant::Close(t) => &t.span,
+            EntryVariant::Commodity(c) => &c.span,
+            EntryVariant::Price(p) => &p.span,
+            EntryVariant::Note(n) => &n.span,
+            EntryVariant::Document(d) => &d.span,
+            EntryVariant::Pad(p) => &p.span,
+            EntryVariant::Event(e) => &e.span,
+            EntryVariant::Custom(c) => &c.span,
+        }
+    }
+
+    // Key/value lines below the entry.
+    pub fn meta_mut(&mut self) -> &mut Metadata {
+        match self {
+            EntryVariant::Transaction(t) => &mut t.meta,
+            EntryVariant::Balance(b) => &mut b.meta,
+            EntryVariant::Open(o) => &mut o.meta,
+            EntryVariant::Close(c) => &mut c.meta,
+            EntryVariant::Commodity(c) => &mut c.meta,
+            EntryVariant::Price(p) => &mut p.meta,
+            EntryVariant::Note(n) => &mut n.meta,
+            EntryVariant::Document(d) => &mut d.meta,
+            EntryVariant::Pad(p) => &mut p.meta,
+            EntryVariant::Event(e) => &mut e.meta,
+            EntryVariant::Custom(c) => &mut c.meta,
+        }
+    }
+
+    pub fn span_mut(&mut self) -> &mut Span {
+        match self {
+            EntryVariant::Transaction(t) => &mut t.span,
+            EntryVariant::Balance(t) => &mut t.span,
+            EntryVariant::Open(t) => &mut t.span,
+            EntryVariant::Close(t) => &mut t.span,
+            EntryVariant::Commodity(c) => &mut c.span,
+            EntryVariant::Price(p) => &mut p.span,
+            EntryVariant::Note(n) => &mut n.span,
+            EntryVariant::Document(d) => &mut d.span,
+            EntryVariant::Pad(p) => &mut p.span,
+            EntryVariant::Event(e) => &mut e.span,
+            EntryVariant::Custom(c) => &mut c.span,
+        }
+    }
+
     pub fn date(&self) -> Date {
         match self {
             EntryVariant::Transaction(t) => t.date,
@@ -24,19 +121,60 @@ impl EntryVariant {
             EntryVariant::Open(t) => t.date,
             EntryVariant::Close(t) => t.date,
             EntryVariant::Commodity(c) => c.date,
-            EntryVariant::PriceEntry(p) => p.date,
+            EntryVariant::Price(p) => p.date,
+            EntryVariant::Note(n) => n.date,
+            EntryVariant::Document(d) => d.date,
+            EntryVariant::Pad(p) => p.date,
+            EntryVariant::Event(e) => e.date,
+            EntryVariant::Custom(c) => c.date,
         }
     }
+
+    /// Hash of the entry as printed, without its comments and location. Entries that print
+    /// the same, e.g. a transaction imported twice, have the same fingerprint across files
+    /// and runs.
+    pub fn fingerprint(&self) -> u64 {
+        stable_hash(&render_entry(&BeancountRenderer::default(), self))
+    }
+}
+
+// FNV-1a. Unlike the std hashers, its results don't change between runs or Rust versions.
+pub(crate) fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
 }
-#[derive(PartialEq, Debug, Clone)]
+
+impl Intern for EntryVariant {
+    fn intern(&mut self, interner: &mut Interner) {
+        match self {
+            EntryVariant::Transaction(t) => t.intern(interner),
+            EntryVariant::Balance(b) => b.intern(interner),
+            EntryVariant::Open(o) => o.intern(interner),
+            EntryVariant::Close(c) => c.intern(interner),
+            EntryVariant::Commodity(c) => c.intern(interner),
+            EntryVariant::Price(p) => p.intern(interner),
+            EntryVariant::Note(n) => n.intern(interner),
+            EntryVariant::Document(d) => d.intern(interner),
+            EntryVariant::Pad(p) => p.intern(interner),
+            EntryVariant::Event(e) => e.intern(interner),
+            EntryVariant::Custom(c) => c.intern(interner),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct Amount {
     pub number: Decimal,
-    pub currency: String,
+    pub currency: Currency,
 }
 
 impl Amount {
-    pub fn new(number: Decimal, currency: String) -> Self {
-        Self { number, currency }
+    pub fn new(number: Decimal, currency: impl Into<Currency>) -> Self {
+        Self {
+            number,
+            currency: currency.into(),
+        }
     }
 }
 
@@ -51,33 +189,320 @@ impl std::ops::Div<Decimal> for Amount {
     }
 }
 
-pub struct PriceEntry {
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoundingMode {
+    // Ties to the even digit, e.g. 0.125 → 0.12.
+    #[default]
+    HalfEven,
+    // Ties away from zero, e.g. 0.125 → 0.13.
+    HalfUp,
+}
+
+impl std::str::FromStr for RoundingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half-even" => Ok(RoundingMode::HalfEven),
+            "half-up" => Ok(RoundingMode::HalfUp),
+            _ => Err(format!("Unknown rounding mode `{s}`")),
+        }
+    }
+}
+
+/// How per-unit values derived from totals, e.g. the price of `3 META @@ 100 CHF`, are rounded.
+/// Without rounding such divisions end in 28 digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rounding {
+    pub mode: RoundingMode,
+    // Decimal places of the result.
+    pub scale: u32,
+}
+
+impl Default for Rounding {
+    fn default() -> Self {
+        Rounding {
+            mode: RoundingMode::HalfEven,
+            scale: 12,
+        }
+    }
+}
+
+impl Rounding {
+    pub fn divide(self, number: Decimal, divisor: Decimal) -> Result<Decimal, String> {
+        let strategy = match self.mode {
+            RoundingMode::HalfEven => RoundingStrategy::MidpointNearestEven,
+            RoundingMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+        };
+        let quotient = number
+            .checked_div(divisor)
+            .ok_or_else(|| format!("Cannot divide {number} by {divisor}"))?;
+        Ok(quotient.round_dp_with_strategy(self.scale, strategy))
+    }
+}
+
+// The price of a currency on a date, e.g. `2024-01-01 price META 500 CHF`. Prices paid in a
+// posting are `PriceAnnotation`s.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceDirective {
     pub date: Date,
     // Price for currency
-    pub currency: String,
+    pub currency: Currency,
     // Price in amount
     pub amount: Amount,
+    pub meta: Metadata,
+    pub span: Span,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Balance {
     pub date: Date,
-    pub account: String,
+    pub account: Account,
     pub amount: Amount,
+    // Written `Assets:Broker:*`, asserts the total of the account and its sub-accounts instead
+    // of the account alone.
+    pub recursive: bool,
+    pub meta: Metadata,
+    pub span: Span,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Open {
     pub date: Date,
-    pub account: String,
-    pub allowed_currencies: Option<Vec<String>>,
+    pub account: Account,
+    pub allowed_currencies: Option<Currencies>,
+    pub meta: Metadata,
+    pub span: Span,
 }
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Close {
     pub date: Date,
-    pub account: String,
+    pub account: Account,
+    pub meta: Metadata,
+    pub span: Span,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Commodity {
     pub date: Date,
-    pub currency: String,
+    pub currency: Currency,
+    // E.g. `precision: 2` or `name: "Swiss Franc"`.
+    pub meta: Metadata,
+    pub span: Span,
+}
+
+impl Commodity {
+    // Decimal places to show in reports, from the `precision` metadata.
+    pub fn precision(&self) -> Option<u32> {
+        match self.meta.get("precision") {
+            Some(CustomValue::Number(n)) => {
+                u32::try_from(n.mantissa()).ok().filter(|_| n.scale() == 0)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        match self.meta.get("name") {
+            Some(CustomValue::String(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    // How reports show the currency, e.g. `symbol: "$"`.
+    pub fn symbol(&self) -> Option<&str> {
+        match self.meta.get("symbol") {
+            Some(CustomValue::String(symbol)) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    // Where to fetch prices from, e.g. `yahoo/AAPL`, from the `price-source` metadata.
+    pub fn price_source(&self) -> Option<&str> {
+        match self.meta.get("price-source") {
+            Some(CustomValue::String(source)) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Key/value lines below an entry, e.g. `  precision: 4`, in input order. Values are typed like
+/// those of custom directives.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata(pub Vec<(String, CustomValue)>);
+
+impl Metadata {
+    pub fn get(&self, key: &str) -> Option<&CustomValue> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+// A dated comment on an account, e.g. `2024-01-01 note Assets:Cash "Called the bank"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Note {
+    pub date: Date,
+    pub account: Account,
+    pub comment: String,
+    pub meta: Metadata,
+    pub span: Span,
+}
+
+// A file belonging to an account, e.g. a statement. The path is kept as written.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    pub date: Date,
+    pub account: Account,
+    pub path: String,
+    pub meta: Metadata,
+    pub span: Span,
+}
+
+// Fills `account` from `source_account` up to the amount of the next balance assertion.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pad {
+    pub date: Date,
+    pub account: Account,
+    pub source_account: Account,
+    pub meta: Metadata,
+    pub span: Span,
+}
+
+// The value of a named variable from `date` on, e.g. `2024-01-01 event "location" "Zurich"`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub date: Date,
+    pub name: String,
+    pub value: String,
+    pub meta: Metadata,
+    pub span: Span,
+}
+
+// A directive for plugins and tools, e.g. `2024-01-01 custom "budget" Expenses:Food 500 CHF`.
+// Its meaning depends on the name, the values are kept as typed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Custom {
+    pub date: Date,
+    pub name: String,
+    pub values: Vec<CustomValue>,
+    pub meta: Metadata,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CustomValue {
+    String(String),
+    Date(Date),
+    Bool(bool),
+    Amount(Amount),
+    Number(Decimal),
+    Account(Account),
+    Currency(Currency),
+}
+
+impl Intern for Amount {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.currency.intern(interner);
+    }
+}
+
+impl Intern for PriceDirective {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.currency.intern(interner);
+        self.amount.intern(interner);
+        self.meta.intern(interner);
+    }
+}
+
+impl Intern for Balance {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.account.intern(interner);
+        self.amount.intern(interner);
+        self.meta.intern(interner);
+    }
+}
+
+impl Intern for Open {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.account.intern(interner);
+        for c in self.allowed_currencies.iter_mut().flatten() {
+            c.intern(interner);
+        }
+        self.meta.intern(interner);
+    }
+}
+
+impl Intern for Close {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.account.intern(interner);
+        self.meta.intern(interner);
+    }
+}
+
+impl Intern for Commodity {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.currency.intern(interner);
+        self.meta.intern(interner);
+    }
+}
+
+impl Intern for Metadata {
+    fn intern(&mut self, interner: &mut Interner) {
+        for (_, v) in &mut self.0 {
+            v.intern(interner);
+        }
+    }
+}
+
+impl Intern for CustomValue {
+    fn intern(&mut self, interner: &mut Interner) {
+        match self {
+            CustomValue::Amount(a) => a.intern(interner),
+            CustomValue::Account(a) => a.intern(interner),
+            CustomValue::Currency(c) => c.intern(interner),
+            _ => {}
+        }
+    }
+}
+
+impl Intern for Note {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.account.intern(interner);
+        self.meta.intern(interner);
+    }
+}
+
+impl Intern for Document {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.account.intern(interner);
+        self.meta.intern(interner);
+    }
+}
+
+impl Intern for Custom {
+    fn intern(&mut self, interner: &mut Interner) {
+        for v in &mut self.values {
+            v.intern(interner);
+        }
+        self.meta.intern(interner);
+    }
+}
+
+impl Intern for Event {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.meta.intern(interner);
+    }
+}
+
+impl Intern for Pad {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.account.intern(interner);
+        self.source_account.intern(interner);
+        self.meta.intern(interner);
+    }
 }
 
 impl Display for Amount {
@@ -92,7 +517,7 @@ impl TryFrom<&str> for Amount {
     fn try_from(value: &str) -> Result<Self, Self::Error> {
         let currency_start = value
             .find(|c: char| c.is_alphabetic())
-            .ok_or(format!("No currency found in '{value}'"))?;
+            .ok_or_else(|| format!("No currency found in '{value}'"))?;
         let number_str = value[..currency_start].trim();
         let number: Decimal = number_str
             .try_into()
@@ -103,40 +528,46 @@ impl TryFrom<&str> for Amount {
         }
         Ok(Amount {
             number,
-            currency: currency.to_string(),
+            currency: currency.into(),
         })
     }
 }
 
+impl Display for CustomValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CustomValue::String(s) => write!(f, "\"{s}\""),
+            CustomValue::Date(d) => write!(f, "{d}"),
+            CustomValue::Bool(true) => f.write_str("TRUE"),
+            CustomValue::Bool(false) => f.write_str("FALSE"),
+            CustomValue::Amount(a) => write!(f, "{a}"),
+            CustomValue::Number(n) => write!(f, "{n}"),
+            CustomValue::Account(a) => write!(f, "{a}"),
+            CustomValue::Currency(c) => write!(f, "{c}"),
+        }
+    }
+}
+
 impl Display for Transaction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", print_transaction(self))
     }
 }
 
-fn sum_amounts_it<'a, It>(amounts: It) -> Result<Amount, String>
-where
-    It: Iterator<Item = &'a Amount>,
-{
-    let mut currency: Option<String> = None;
-    let mut total = Decimal::new(0, 0);
+/// The totals of `amounts` per currency. Currencies with no amounts are missing, not zero.
+pub fn sum_by_currency<'a>(
+    amounts: impl IntoIterator<Item = &'a Amount>,
+) -> BTreeMap<Currency, Decimal> {
+    let mut totals: BTreeMap<Currency, Decimal> = BTreeMap::new();
     for a in amounts {
-        if let Some(c) = &currency {
-            if c != &a.currency {
-                return Err(format!(
-                    "Multiple currencies in given amounts: {} and {}",
-                    c, a.currency
-                ));
+        match totals.get_mut(&a.currency) {
+            Some(total) => *total += a.number,
+            None => {
+                totals.insert(a.currency.clone(), a.number);
             }
-        } else {
-            currency = Some(a.currency.clone());
         }
-        total += a.number;
     }
-    Ok(Amount::new(
-        total,
-        currency.ok_or_else(|| "No amounts in transaction".to_string())?,
-    ))
+    totals
 }
 
 #[cfg(test)]
@@ -164,33 +595,46 @@ mod test {
     }
 
     #[test]
-    fn test_sum_amounts() {
-        assert!(sum_amounts_it([].iter()).is_err());
-        assert_eq!(
-            sum_amounts_it(
-                [
-                    Amount::new(100.into(), "USD".to_string()),
-                    Amount::new((-50).into(), "USD".to_string())
-                ]
-                .iter()
-            )
-            .unwrap(),
-            Amount::new(50.into(), "USD".to_string())
-        );
+    fn test_fingerprint() {
+        let t = Transaction::try_from(
+            "2024-01-05 * \"Shop\" \"Food\"\n  Assets:Cash -5 CHF ; paid cash\n  Expenses:Food 5 CHF",
+        )
+        .unwrap();
+        let mut moved = Transaction::try_from(
+            "2024-01-05   * \"Shop\"   \"Food\"\n    Assets:Cash  -5 CHF\n    Expenses:Food  5 CHF",
+        )
+        .unwrap();
+        moved.span.lines = 10..13;
+        assert_eq!(t.fingerprint(), moved.fingerprint());
         assert_eq!(
-            sum_amounts_it([Amount::new((-50).into(), "USD".to_string())].iter()).unwrap(),
-            Amount::new((-50).into(), "USD".to_string())
+            t.fingerprint(),
+            EntryVariant::Transaction(moved.clone()).fingerprint()
         );
+        moved.postings[1].account = "Expenses:Other".into();
+        assert_ne!(t.fingerprint(), moved.fingerprint());
+        // Fixed, as fingerprints may be stored outside of the ledger.
+        assert_eq!(stable_hash("beanrust"), 0x551690be84abb9a9);
+    }
 
-        assert!(
-            sum_amounts_it(
-                [
-                    Amount::new(100.into(), "USD".to_string()),
-                    Amount::new((-50).into(), "CHF".to_string())
-                ]
-                .iter()
-            )
-            .is_err()
+    #[test]
+    fn test_sum_by_currency() {
+        assert!(sum_by_currency(&[]).is_empty());
+        let amounts = [
+            Amount::new(100.into(), "USD".to_string()),
+            Amount::new((-50).into(), "CHF".to_string()),
+            Amount::new((-100).into(), "USD".to_string()),
+            Amount::new(Decimal::new(-25, 1), "CHF".to_string()),
+        ];
+        let totals: Vec<(String, Decimal)> = sum_by_currency(&amounts)
+            .into_iter()
+            .map(|(c, n)| (c.to_string(), n))
+            .collect();
+        assert_eq!(
+            totals,
+            [
+                ("CHF".to_string(), Decimal::new(-525, 1)),
+                ("USD".to_string(), Decimal::ZERO)
+            ]
         );
     }
 }
@@ -0,0 +1,184 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// An immutable, cheaply clonable string for names that repeat throughout a ledger, like
+/// accounts and currencies. Symbols handed out by the same `Interner` share their allocation,
+/// so comparing two equal interned symbols is a pointer comparison.
+#[derive(Clone, PartialOrd, Ord)]
+pub struct Symbol(Arc<str>);
+
+impl Symbol {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0) || self.0 == other.0
+    }
+}
+
+impl Eq for Symbol {}
+
+// Must hash like `str` so that `Borrow<str>` lookups in hash maps work.
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Symbol {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl std::ops::Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Symbol {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(value: &str) -> Self {
+        Symbol(value.into())
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(value: String) -> Self {
+        Symbol(value.into())
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+// Deserialized symbols are not interned, see `Interner::intern`.
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(String::deserialize(deserializer)?.into())
+    }
+}
+
+/// Implemented by everything holding symbols, to replace them with an interner's copies.
+pub trait Intern {
+    fn intern(&mut self, interner: &mut Interner);
+}
+
+impl Intern for Symbol {
+    fn intern(&mut self, interner: &mut Interner) {
+        interner.intern(self);
+    }
+}
+
+/// Symbol table deduplicating the symbols of a ledger.
+#[derive(Default)]
+pub struct Interner {
+    symbols: HashSet<Symbol>,
+}
+
+impl Interner {
+    /// Replaces `symbol` with the table's copy, adding it to the table if it is new.
+    pub fn intern(&mut self, symbol: &mut Symbol) {
+        match self.symbols.get(symbol.as_str()) {
+            Some(existing) => *symbol = existing.clone(),
+            None => {
+                self.symbols.insert(symbol.clone());
+            }
+        }
+    }
+
+    pub fn intern_str(&mut self, name: &str) -> Symbol {
+        if let Some(existing) = self.symbols.get(name) {
+            return existing.clone();
+        }
+        let symbol = Symbol::from(name);
+        self.symbols.insert(symbol.clone());
+        symbol
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_symbol_eq() {
+        let a = Symbol::from("CHF");
+        let b = Symbol::from("CHF".to_string());
+        assert_eq!(a, b);
+        assert!(!Arc::ptr_eq(&a.0, &b.0));
+        assert_eq!(a, "CHF");
+        assert_ne!(a, Symbol::from("USD"));
+        assert_eq!(format!("{a} {a:?}"), "CHF \"CHF\"");
+    }
+
+    #[test]
+    fn test_interner() {
+        let mut interner = Interner::default();
+        let mut a = Symbol::from("Assets:Cash");
+        let mut b = Symbol::from("Assets:Cash");
+        interner.intern(&mut a);
+        interner.intern(&mut b);
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+        assert!(Arc::ptr_eq(&a.0, &interner.intern_str("Assets:Cash").0));
+        assert_eq!(interner.len(), 1);
+        let c = interner.intern_str("Assets:Bank");
+        assert_eq!(c, "Assets:Bank");
+        assert_eq!(interner.len(), 2);
+    }
+}
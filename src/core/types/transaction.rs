@@ -1,78 +1,191 @@
-use super::{Amount, sum_amounts_it};
-use jiff::civil::Date;
+use super::{
+    Account, Amount, CustomValue, Intern, Interner, Metadata, Span, stable_hash, sum_by_currency,
+};
+use crate::io::printer::print_transaction;
+use jiff::civil::{Date, Time};
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum TransactionFlag {
     OK,
     Error,
+    // `P`, added by `resolve_pads` in place of a pad directive, as in beancount.
+    Padding,
 }
 
 // Cost represents the cost at which an asset was acquired.
 // E.g. 500 META {30 USD} means that 500 shares of META was acquired at a cost of 30 USD.
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Cost {
     pub amount: Amount,
+    // When the lot was acquired, e.g. `{100 CHF, 2024-01-15}`, to tell lots at the same cost apart.
+    pub date: Option<Date>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CostType {
     Known(Cost),
-    Automatic,
+    // `{}`, or `{2024-01-15}` to pick the lots acquired on that date.
+    Automatic(Option<Date>),
 }
 
 // Price paid or received for an asset. E.g. 500 USD @ 1.2CHF means that 500 USD was
 // bought or sold at a price of 1.2 CHF per USD.
 // 500 META {30 USD} @ 50 USD means that 500 shares of META with a cost of 30 USD was
 // bought or sold (very likely sold for that syntax) at a price of 50 USD per META share.
-#[derive(Debug)]
-pub struct Price {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceAnnotation {
     pub amount: Amount,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Posting {
-    pub account: String,
+    // A flag before the account, e.g. `! Assets:Receivable 100 CHF`, marking just this posting.
+    pub flag: Option<TransactionFlag>,
+    pub account: Account,
     pub amount: Amount,
-    pub price: Option<Price>,
+    pub price: Option<PriceAnnotation>,
     // If the cost type is automatic, then the cost will be determined once
     // all transactions were parsed. Then the appropriate lot will be found
     // to determine the actual cost.
     // TODO: Is an enum this deep really a good idea? Or should we have
     // different Transaction types before and after finishing parsing?
     pub cost: Option<CostType>,
+    // Key/value lines below the posting, indented deeper than it, e.g. `invoice: "2024-001"`.
+    pub meta: Metadata,
 }
 
-#[derive(Debug)]
+// Most transactions have two to four postings, which are then stored inline.
+pub type Postings = SmallVec<[Posting; 4]>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
     pub date: Date,
     pub flag: TransactionFlag,
     pub payee: Option<String>,
     pub narration: Option<String>,
-    pub postings: Vec<Posting>,
+    // Without the leading `#`.
+    pub tags: Vec<String>,
+    // Without the leading `^`, e.g. `invoice-42` to connect the transactions of an invoice.
+    pub links: Vec<String>,
+    // Key/value lines between the header and the postings, e.g. `  confidence: 0.8`.
+    pub meta: Metadata,
+    pub postings: Postings,
+    pub span: Span,
 }
 
+impl Intern for Posting {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.account.intern(interner);
+        self.amount.intern(interner);
+        if let Some(price) = &mut self.price {
+            price.amount.intern(interner);
+        }
+        if let Some(CostType::Known(cost)) = &mut self.cost {
+            cost.amount.intern(interner);
+        }
+        self.meta.intern(interner);
+    }
+}
+
+impl Intern for Transaction {
+    fn intern(&mut self, interner: &mut Interner) {
+        self.meta.intern(interner);
+        for p in &mut self.postings {
+            p.intern(interner);
+        }
+    }
+}
+
+/// Metadata key of the time of day of a transaction, e.g. `time: "14:30:05"`, which orders the
+/// transactions of a day, e.g. the fills of a broker.
+pub const TIME_KEY: &str = "time";
+
 impl Transaction {
-    // Verify that the sum of all amounts in postings is zero.
+    /// The time of day of the `time` metadata. None if there is none, Err if it is not a time.
+    pub fn time(&self) -> Option<Result<Time, String>> {
+        let value = self.meta.get(TIME_KEY)?;
+        let CustomValue::String(text) = value else {
+            return Some(Err(format!(
+                "Invalid time `{value}`, expected a quoted time"
+            )));
+        };
+        Some(
+            text.parse()
+                .map_err(|e| format!("Invalid time `{text}`: {e}")),
+        )
+    }
+
+    /// See `EntryVariant::fingerprint`.
+    pub fn fingerprint(&self) -> u64 {
+        stable_hash(&print_transaction(self))
+    }
+
+    // Verify that the weights of the postings sum up to zero in each currency, up to half a
+    // unit of the most precise amount without cost or price in that currency. Transactions
+    // with automatic costs pass, their weights are only known once lots are booked.
     pub fn check(&self) -> Result<(), String> {
-        if self.postings.is_empty() {
+        let Some(weights) = self
+            .postings
+            .iter()
+            .map(Posting::weight)
+            .collect::<Option<Vec<Amount>>>()
+        else {
             return Ok(());
-        }
-        let sum = sum_amounts_it(self.postings.iter().map(|p| &p.amount)).map_err(|x| {
-            format!("Invalid collection of amounts in postings: Error {x}. Transaction: {self}")
-        })?;
-        if sum.number != Decimal::new(0, 0) {
-            return Err(format!("Transaction not balanced: total is {sum}"));
+        };
+        let unbalanced: Vec<String> = sum_by_currency(&weights)
+            .into_iter()
+            .filter(|(currency, total)| total.abs() > self.tolerance(currency))
+            .map(|(currency, total)| Amount::new(total, currency).to_string())
+            .collect();
+        if !unbalanced.is_empty() {
+            return Err(format!(
+                "Transaction not balanced: total is {}",
+                unbalanced.join(", ")
+            ));
         }
         Ok(())
     }
+
+    fn tolerance(&self, currency: &str) -> Decimal {
+        let scale = self
+            .postings
+            .iter()
+            .filter(|p| p.price.is_none() && p.cost.is_none() && p.amount.currency == currency)
+            .map(|p| p.amount.number.scale())
+            .max();
+        match scale {
+            Some(scale) => Decimal::new(5, (scale + 1).min(28)),
+            None => Decimal::ZERO,
+        }
+    }
+}
+
+impl Posting {
+    // The amount the posting contributes to the balance of its transaction: its cost or
+    // price if it has one, else its amount. None if the cost is still to be determined.
+    pub fn weight(&self) -> Option<Amount> {
+        let per_unit = match (&self.cost, &self.price) {
+            (Some(CostType::Known(cost)), _) => &cost.amount,
+            (Some(CostType::Automatic(_)), _) => return None,
+            (None, Some(price)) => &price.amount,
+            (None, None) => return Some(self.amount.clone()),
+        };
+        Some(Amount::new(
+            per_unit.number * self.amount.number,
+            per_unit.currency.clone(),
+        ))
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use jiff::civil::date;
+    use smallvec::smallvec;
 
     #[test]
     fn test_transaction_check() {
@@ -81,23 +194,77 @@ mod test {
             flag: TransactionFlag::OK,
             payee: None,
             narration: None,
-            postings: vec![],
+            tags: vec![],
+            links: vec![],
+            meta: Metadata::default(),
+            postings: smallvec![],
+            span: Span::default(),
         };
         assert!(t.check().is_ok());
-        let account = "Assets:Cash".to_string();
+        let account: Account = "Assets:Cash".into();
         t.postings.push(Posting {
+            flag: None,
             account: account.clone(),
             amount: Amount::new(100.into(), "USD".to_string()),
             price: None,
             cost: None,
+            meta: Metadata::default(),
         });
         assert!(t.check().is_err());
         t.postings.push(Posting {
+            flag: None,
             account,
             amount: Amount::new((-100).into(), "USD".to_string()),
             price: None,
             cost: None,
+            meta: Metadata::default(),
         });
         assert!(t.check().is_ok());
+        t.postings.push(Posting {
+            flag: None,
+            account: "Assets:Bank".into(),
+            amount: Amount::new(5.into(), "CHF".to_string()),
+            price: None,
+            cost: None,
+            meta: Metadata::default(),
+        });
+        assert_eq!(
+            t.check(),
+            Err("Transaction not balanced: total is 5 CHF".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_weights() {
+        let check = |postings: &[&str]| {
+            let postings = postings
+                .iter()
+                .map(|p| Posting::try_from(*p).unwrap())
+                .collect();
+            Transaction {
+                date: date(2023, 1, 1),
+                flag: TransactionFlag::OK,
+                payee: None,
+                narration: None,
+                tags: vec![],
+                links: vec![],
+                meta: Metadata::default(),
+                postings,
+                span: Span::default(),
+            }
+            .check()
+        };
+        assert!(check(&["Assets:Depot 5 META {300 CHF}", "Assets:Cash -1500 CHF"]).is_ok());
+        assert!(check(&["Assets:Depot 2 META {{500 CHF}}", "Assets:Cash -500 CHF"]).is_ok());
+        assert!(check(&["Assets:Cash 100 USD @ 0.9 CHF", "Assets:Bank -90 CHF"]).is_ok());
+        assert!(check(&["Assets:Cash 3 USD @@ 100 CHF", "Assets:Bank -100.00 CHF"]).is_ok());
+        assert_eq!(
+            check(&["Assets:Cash 3 USD @ 33.3 CHF", "Assets:Bank -100.00 CHF"]),
+            Err("Transaction not balanced: total is -0.10 CHF".to_string())
+        );
+        // Within half a cent of the most precise CHF amount.
+        assert!(check(&["Assets:Cash 3 USD @ 33.332 CHF", "Assets:Bank -100.00 CHF"]).is_ok());
+        // The cost of lots is not known yet.
+        assert!(check(&["Assets:Depot -7 META {} @ 500 CHF", "Assets:Cash 10 CHF"]).is_ok());
     }
 }
@@ -0,0 +1,712 @@
+use crate::core::ledger::{EntryRef, Ledger};
+use crate::core::types::*;
+use crate::io::parser::ParsedEntries;
+use crate::io::parser::error::{ErrorCode, ParseError};
+use jiff::ToSpan;
+use jiff::civil::Date;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Checks the entries of `ledger` against each other. Each problem is reported once, at the
+/// first entry it occurs in:
+/// - accounts used without being opened, or before their open date.
+/// - currencies without a commodity directive, if the ledger declares any commodities.
+/// - balance assertions that don't hold, see `check_assertions`.
+/// - `time` metadata that is not a time of day.
+pub fn validate(ledger: &Ledger) -> Vec<ParseError> {
+    let entries = ledger.entries();
+    let mut opened: HashMap<&str, Date> = HashMap::new();
+    for o in &entries.open {
+        let date = opened.entry(&o.account).or_insert(o.date);
+        *date = o.date.min(*date);
+    }
+    let declared: HashSet<&str> = entries.commodity.iter().map(|c| &*c.currency).collect();
+
+    let mut errors = vec![];
+    let mut reported_accounts = HashSet::new();
+    let mut reported_currencies = HashSet::new();
+    for &entry in ledger.by_date() {
+        let date = ledger.date(entry);
+        if let EntryRef::Transaction(h) = entry
+            && let Some(Err(e)) = ledger[h].time()
+        {
+            errors.push(error(ledger, entry, ErrorCode::InvalidTime, e));
+        }
+        let (accounts, currencies) = symbols(ledger, entry);
+        for account in accounts {
+            let context = match opened.get(&**account) {
+                Some(open) if *open <= date => continue,
+                Some(open) => format!("Account `{account}` is used before it is opened on {open}"),
+                None => {
+                    let mut context = format!("Account `{account}` is not opened");
+                    if let Some(name) = closest(account, opened.keys().copied()) {
+                        context.push_str(&format!(", did you mean `{name}`?"));
+                    }
+                    context
+                }
+            };
+            if reported_accounts.insert(account) {
+                errors.push(error(ledger, entry, ErrorCode::UnopenedAccount, context));
+            }
+        }
+        if declared.is_empty() {
+            continue;
+        }
+        for currency in currencies {
+            if declared.contains(&**currency) || !reported_currencies.insert(currency) {
+                continue;
+            }
+            let mut context = format!("Currency `{currency}` is not declared");
+            if let Some(name) = closest(currency, declared.iter().copied()) {
+                context.push_str(&format!(", did you mean `{name}`?"));
+            }
+            errors.push(error(ledger, entry, ErrorCode::UndeclaredCurrency, context));
+        }
+    }
+    errors.extend(check_assertions(ledger));
+    errors
+}
+
+/// Checks each balance directive against the postings before its date, i.e. the balance at the
+/// start of the day, as in beancount. See `EntryRef::precedence`. The assertion covers
+/// the account alone, or with its sub-accounts if written `Assets:Broker:*`. The difference
+/// may be up to half a unit of the last digit of the asserted amount.
+pub fn check_assertions(ledger: &Ledger) -> Vec<ParseError> {
+    let mut errors = vec![];
+    for &entry in ledger.by_date() {
+        let EntryRef::Balance(h) = entry else {
+            continue;
+        };
+        let b = &ledger[h];
+        let currency = &b.amount.currency;
+        let actual: Decimal = if b.recursive {
+            ledger
+                .balance(&b.account, b.date)
+                .iter()
+                .filter(|a| a.currency == *currency)
+                .map(|a| a.number)
+                .sum()
+        } else {
+            ledger
+                .register(&b.account)
+                .take_while(|(t, _)| t.date < b.date)
+                .filter(|(_, p)| p.amount.currency == *currency)
+                .map(|(_, p)| p.amount.number)
+                .sum()
+        };
+        let difference = actual - b.amount.number;
+        let tolerance = Decimal::new(5, (b.amount.number.scale() + 1).min(28));
+        if difference.abs() > tolerance {
+            let wildcard = if b.recursive { ":*" } else { "" };
+            let context = format!(
+                "Balance of `{}{wildcard}` is {}, not {} (difference {})",
+                b.account,
+                Amount::new(actual, currency.clone()),
+                b.amount,
+                Amount::new(difference, currency.clone()),
+            );
+            errors.push(error(
+                ledger,
+                entry,
+                ErrorCode::FailedBalanceAssertion,
+                context,
+            ));
+        }
+    }
+    errors
+}
+
+/// Open directives for the accounts that are used but never opened, dated on their first use,
+/// each with a warning at that use. Accounts used before their open date are left to
+/// `validate`.
+pub fn missing_opens(ledger: &Ledger) -> (Vec<Open>, Vec<ParseError>) {
+    let opened: HashSet<&str> = ledger.entries().open.iter().map(|o| &*o.account).collect();
+    let mut opens = vec![];
+    let mut warnings = vec![];
+    let mut seen = HashSet::new();
+    for &entry in ledger.by_date() {
+        let date = ledger.date(entry);
+        for account in symbols(ledger, entry).0 {
+            if opened.contains(&**account) || !seen.insert(account) {
+                continue;
+            }
+            opens.push(Open {
+                date,
+                account: account.clone(),
+                allowed_currencies: None,
+                meta: Metadata::default(),
+                span: Span::default(),
+            });
+            let context = format!("Account `{account}` is not opened, opening it on {date}");
+            warnings.push(error(ledger, entry, ErrorCode::AutoOpenedAccount, context));
+        }
+    }
+    (opens, warnings)
+}
+
+/// The opt-in auto-open mode: adds the opens of `missing_opens` to the ledger, so that unopened
+/// accounts are reported as warnings instead of errors by `validate`.
+pub fn auto_open(ledger: Ledger) -> (Ledger, Vec<ParseError>) {
+    let (opens, warnings) = missing_opens(&ledger);
+    if opens.is_empty() {
+        return (ledger, warnings);
+    }
+    let mut entries = ledger.into_entries();
+    for o in opens {
+        entries.push(EntryVariant::Open(o));
+    }
+    (Ledger::new(entries), warnings)
+}
+
+/// Warns about transactions dated after `today`, e.g. typos in the year. Pass the date in the
+/// reporting time zone, see `Config::today`.
+pub fn check_future(ledger: &Ledger, today: Date) -> Vec<ParseError> {
+    ledger
+        .entries()
+        .transactions
+        .iter()
+        .filter(|t| t.date > today)
+        .map(|t| {
+            let context = format!("Transaction dated {}, after today {today}", t.date);
+            error_at(&t.span, ErrorCode::FutureTransaction, context)
+        })
+        .collect()
+}
+
+/// Warns about accounts that spent more than their budget in the current period, as of
+/// `today`. Budgets are `custom "budget" <account> "<period>" <amount>` directives as in fava,
+/// with a daily, weekly, monthly, quarterly or yearly period. The latest budget of an account
+/// dated on or before today applies. Spending is the sum of the postings to the account and
+/// its sub-accounts in the currency of the budget, from the start of the period up to and
+/// including today.
+pub fn check_budgets(ledger: &Ledger, today: Date) -> Vec<ParseError> {
+    let mut budgets: BTreeMap<&str, (&Custom, Date, &Amount)> = BTreeMap::new();
+    let mut problems = vec![];
+    for c in &ledger.entries().custom {
+        if c.name != "budget" || c.date > today {
+            continue;
+        }
+        match budget(c, today) {
+            Ok((account, start, amount)) => {
+                if budgets
+                    .get(account)
+                    .is_none_or(|(b, _, _)| b.date <= c.date)
+                {
+                    budgets.insert(account, (c, start, amount));
+                }
+            }
+            Err(e) => problems.push(error_at(&c.span, ErrorCode::InvalidBudget, e)),
+        }
+    }
+    let end = today.tomorrow().unwrap_or(Date::MAX);
+    for (account, (c, start, amount)) in budgets {
+        let spent: Decimal = ledger
+            .postings()
+            .within(account)
+            .currency(&amount.currency)
+            .between(start..end)
+            .map(|(_, p)| p.amount.number)
+            .sum();
+        if spent > amount.number {
+            let context = format!(
+                "{account} spent {spent} {} since {start}, above the budget of {amount}",
+                amount.currency
+            );
+            problems.push(error_at(&c.span, ErrorCode::BudgetExceeded, context));
+        }
+    }
+    problems
+}
+
+// The account, the start of the period containing `today` and the amount of a budget.
+fn budget(budget: &Custom, today: Date) -> Result<(&str, Date, &Amount), String> {
+    let [
+        CustomValue::Account(account),
+        CustomValue::String(period),
+        CustomValue::Amount(amount),
+    ] = budget.values.as_slice()
+    else {
+        return Err("Expected an account, a period and an amount".to_string());
+    };
+    let start = match period.as_str() {
+        "daily" => Ok(today),
+        "weekly" => today.checked_sub(i64::from(today.weekday().to_monday_zero_offset()).days()),
+        "monthly" => Ok(today.first_of_month()),
+        "quarterly" => Date::new(today.year(), (today.month() - 1) / 3 * 3 + 1, 1),
+        "yearly" => Ok(today.first_of_year()),
+        _ => return Err(format!("Unknown period `{period}`")),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok((account, start, amount))
+}
+
+/// Warns about computed amounts with more than `max_scale` decimal places: the per-unit prices
+/// and costs derived from totals, and the weights of postings with a price or cost. These
+/// usually point to a wrong conversion rate or a total written as a per-unit value. One warning
+/// per transaction.
+pub fn check_scale(ledger: &Ledger, max_scale: u32) -> Vec<ParseError> {
+    let mut warnings = vec![];
+    for t in &ledger.entries().transactions {
+        let computed = t.postings.iter().flat_map(|p| {
+            let per_unit = match (&p.cost, &p.price) {
+                (Some(CostType::Known(cost)), _) => Some(&cost.amount),
+                (_, Some(price)) => Some(&price.amount),
+                _ => None,
+            };
+            let weight = per_unit.and_then(|_| p.weight());
+            [per_unit.cloned(), weight]
+                .into_iter()
+                .flatten()
+                .map(move |a| (&p.account, a))
+        });
+        for (account, amount) in computed {
+            let scale = amount.number.normalize().scale();
+            if scale > max_scale {
+                let context = format!(
+                    "Amount {amount} computed for `{account}` has {scale} decimal places, more than {max_scale}"
+                );
+                warnings.push(error_at(&t.span, ErrorCode::ExcessiveScale, context));
+                break;
+            }
+        }
+    }
+    warnings
+}
+
+/// Checks that the file of each document directive exists, resolving relative paths against
+/// `root`. Also warns about the files below `root` that no document refers to, skipping hidden
+/// files and directories.
+pub fn check_documents(ledger: &Ledger, root: &Path) -> Vec<ParseError> {
+    let mut errors = vec![];
+    let mut referenced = HashSet::new();
+    for d in &ledger.entries().document {
+        match root.join(&d.path).canonicalize() {
+            Ok(path) => {
+                referenced.insert(path);
+            }
+            Err(e) => {
+                let context = format!("Document `{}` of `{}` not found: {e}", d.path, d.account);
+                errors.push(error_at(&d.span, ErrorCode::MissingDocument, context));
+            }
+        }
+    }
+    let mut files = vec![];
+    list_files(root, &mut files);
+    files.sort();
+    for file in files {
+        if file.canonicalize().is_ok_and(|f| referenced.contains(&f)) {
+            continue;
+        }
+        errors.push(ParseError {
+            code: ErrorCode::OrphanedDocument,
+            context: format!("Document `{}` is not referred to", file.display()),
+            failed_statement: String::new(),
+            file: Some(file),
+            line: None,
+            column: None,
+        });
+    }
+    errors
+}
+
+// The files below `dir`, recursively, other than hidden ones. Unreadable directories are skipped.
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            list_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+/// What to do with transactions whose postings don't balance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnbalancedTransactions {
+    // Drop them from the entries and report an error.
+    #[default]
+    Reject,
+    // Keep them with their flag set to `!` and report a warning, e.g. while cleaning up an
+    // imported ledger step by step.
+    Flag,
+}
+
+/// Checks that the postings of each transaction balance, see `Transaction::check`, and handles
+/// the transactions that don't according to `mode`. Run before building the `Ledger`.
+pub fn check_balances(
+    entries: &mut ParsedEntries,
+    mode: UnbalancedTransactions,
+) -> Vec<ParseError> {
+    let mut errors = vec![];
+    entries.transactions.retain_mut(|t| {
+        let Err(context) = t.check() else {
+            return true;
+        };
+        match mode {
+            UnbalancedTransactions::Reject => {
+                errors.push(error_at(&t.span, ErrorCode::UnbalancedTransaction, context));
+                false
+            }
+            UnbalancedTransactions::Flag => {
+                t.flag = TransactionFlag::Error;
+                let context = format!("{context}, flagged with `!`");
+                errors.push(error_at(
+                    &t.span,
+                    ErrorCode::FlaggedUnbalancedTransaction,
+                    context,
+                ));
+                true
+            }
+        }
+    });
+    errors
+}
+
+// Accounts referred to by `entry` other than by opening them, and its currencies.
+fn symbols(ledger: &Ledger, entry: EntryRef) -> (Vec<&Account>, Vec<&Currency>) {
+    match entry {
+        EntryRef::Open(h) => {
+            let o = &ledger[h];
+            (vec![], o.allowed_currencies.iter().flatten().collect())
+        }
+        EntryRef::Balance(h) => (vec![&ledger[h].account], vec![&ledger[h].amount.currency]),
+        EntryRef::Close(h) => (vec![&ledger[h].account], vec![]),
+        EntryRef::Note(h) => (vec![&ledger[h].account], vec![]),
+        EntryRef::Document(h) => (vec![&ledger[h].account], vec![]),
+        EntryRef::Pad(h) => (vec![&ledger[h].account, &ledger[h].source_account], vec![]),
+        EntryRef::Commodity(_) | EntryRef::Event(_) => (vec![], vec![]),
+        EntryRef::Custom(h) => {
+            let mut accounts = vec![];
+            let mut currencies = vec![];
+            for v in &ledger[h].values {
+                match v {
+                    CustomValue::Account(a) => accounts.push(a),
+                    CustomValue::Amount(a) => currencies.push(&a.currency),
+                    CustomValue::Currency(c) => currencies.push(c),
+                    _ => {}
+                }
+            }
+            (accounts, currencies)
+        }
+        EntryRef::Price(h) => (
+            vec![],
+            vec![&ledger[h].currency, &ledger[h].amount.currency],
+        ),
+        EntryRef::Transaction(h) => ledger[h]
+            .postings
+            .iter()
+            .map(|p| (&p.account, &p.amount.currency))
+            .unzip(),
+    }
+}
+
+fn error(ledger: &Ledger, entry: EntryRef, code: ErrorCode, context: String) -> ParseError {
+    error_at(ledger.span(entry), code, context)
+}
+
+pub(crate) fn error_at(span: &Span, code: ErrorCode, context: String) -> ParseError {
+    ParseError {
+        code,
+        context,
+        failed_statement: String::new(),
+        file: span.file.as_deref().map(Into::into),
+        line: Some(span.lines.start),
+        column: Some(1),
+    }
+}
+
+// The candidate closest to `name` by edit distance, if it is close enough to be a likely typo.
+fn closest<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(d, _)| *d <= max_distance)
+        .min()
+        .map(|(_, c)| c)
+}
+
+// Levenshtein distance, counting chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::parser::error::Severity;
+    use crate::io::parser::parse_entries_from_string;
+    use std::path::Path;
+
+    fn validate_str(input: &str) -> Vec<String> {
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert!(entries.errors.is_empty());
+        validate(&Ledger::new(entries))
+            .iter()
+            .map(|e| format!("{}:{} {}", e.line.unwrap(), e.code, e.context))
+            .collect()
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("CHF", ""), 3);
+        assert_eq!(edit_distance("Assets:Csh", "Assets:Cash"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(
+            closest(
+                "Expenses:Fod",
+                ["Expenses:Food", "Expenses:Fun"].into_iter()
+            ),
+            Some("Expenses:Food")
+        );
+        assert_eq!(
+            closest("Income:Salary", ["Expenses:Food"].into_iter()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let input = "2024-01-01 open Assets:Cash
+2024-01-01 commodity CHF
+2024-01-02 *
+  Assets:Cash -5 CHF
+  Expenses:Fod 5 CHF
+2024-01-03 *
+  Assets:Cash -5 CHf
+  Expenses:Fod 5 CHf
+2024-02-01 open Expenses:Food
+";
+        assert_eq!(
+            validate_str(input),
+            [
+                "3:B0002 Account `Expenses:Fod` is not opened, did you mean `Expenses:Food`?",
+                "6:B0003 Currency `CHf` is not declared, did you mean `CHF`?",
+            ]
+        );
+
+        let input = "2024-01-01 *\n  Assets:Cash -5 CHF\n  Expenses:Food 5 CHF\n2024-02-01 open Assets:Cash\n";
+        assert_eq!(
+            validate_str(input),
+            [
+                "1:B0002 Account `Assets:Cash` is used before it is opened on 2024-02-01",
+                "1:B0002 Account `Expenses:Food` is not opened",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_scale() {
+        let input = "2024-01-01 *\n  Assets:Depot 3 META @@ 100 CHF\n  Assets:Cash -100 CHF\n2024-01-02 *\n  Assets:Depot 4 META @@ 100.00 CHF\n  Assets:Cash -100 CHF\n";
+        let ledger =
+            Ledger::new(parse_entries_from_string(input.to_string(), Path::new("")).unwrap());
+        let warnings: Vec<String> = check_scale(&ledger, 4)
+            .iter()
+            .map(|e| format!("{}:{} {}", e.line.unwrap(), e.code, e.context))
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "1:B0009 Amount 33.333333333333 CHF computed for `Assets:Depot` has 12 decimal places, more than 4"
+            ]
+        );
+        assert!(check_scale(&ledger, 12).is_empty());
+    }
+
+    #[test]
+    fn test_check_future() {
+        let input = "2024-01-01 *\n  Assets:Cash 1 CHF\n  Income:Gift -1 CHF\n2204-01-02 *\n  Assets:Cash 1 CHF\n  Income:Gift -1 CHF\n";
+        let ledger =
+            Ledger::new(parse_entries_from_string(input.to_string(), Path::new("")).unwrap());
+        let warnings: Vec<String> = check_future(&ledger, jiff::civil::date(2024, 6, 1))
+            .iter()
+            .map(|e| format!("{}:{} {}", e.line.unwrap(), e.code, e.context))
+            .collect();
+        assert_eq!(
+            warnings,
+            ["4:B0015 Transaction dated 2204-01-02, after today 2024-06-01"]
+        );
+        assert_eq!(ErrorCode::FutureTransaction.severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn test_check_budgets() {
+        let input = "2024-01-01 custom \"budget\" Expenses:Food \"monthly\" 500 CHF
+2024-05-01 custom \"budget\" Expenses:Food \"weekly\" 100 CHF
+2024-01-01 custom \"budget\" Expenses:Fun \"yearly\" 1000 CHF
+2024-01-01 custom \"budget\" Expenses:Rent \"fortnightly\" 1000 CHF
+2024-05-31 *
+  Assets:Cash -80 CHF
+  Expenses:Food:Groceries 80 CHF
+2024-06-04 *
+  Assets:Cash -70 CHF
+  Expenses:Food 50 CHF
+  Expenses:Fun 20 CHF
+2024-06-06 *
+  Assets:Cash -30 USD
+  Expenses:Food 30 USD
+";
+        let problems = |input: &str, today| -> Vec<String> {
+            let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+            check_budgets(&Ledger::new(entries), today)
+                .iter()
+                .map(|e| format!("{}:{} {}", e.line.unwrap(), e.code, e.context))
+                .collect()
+        };
+        // A Wednesday, the weekly budget of Food replaced the monthly one.
+        let today = jiff::civil::date(2024, 6, 5);
+        let invalid = "4:B0017 Unknown period `fortnightly`";
+        assert_eq!(problems(input, today), [invalid]);
+        let tight = input.replace("\"weekly\" 100", "\"weekly\" 40");
+        assert_eq!(
+            problems(&tight, today),
+            [
+                invalid,
+                "2:B0016 Expenses:Food spent 50 CHF since 2024-06-03, above the budget of 40 CHF"
+            ]
+        );
+        let tight = input.replace("\"monthly\" 500", "\"monthly\" 50").replace(
+            "2024-05-01 custom \"budget\" Expenses:Food \"weekly\" 100 CHF\n",
+            "",
+        );
+        assert_eq!(
+            problems(&tight, jiff::civil::date(2024, 5, 31)),
+            [
+                "3:B0017 Unknown period `fortnightly`",
+                "1:B0016 Expenses:Food spent 80 CHF since 2024-05-01, above the budget of 50 CHF"
+            ]
+        );
+        assert_eq!(ErrorCode::BudgetExceeded.severity(), Severity::Warning);
+    }
+
+    #[test]
+    fn test_check_documents() {
+        let dir = std::env::temp_dir().join(format!("beanrust-documents-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("Assets/Bank")).unwrap();
+        std::fs::write(dir.join("Assets/Bank/statement.pdf"), "").unwrap();
+        std::fs::write(dir.join("Assets/Bank/receipt.pdf"), "").unwrap();
+        std::fs::write(dir.join(".DS_Store"), "").unwrap();
+        let input = "2024-01-31 document Assets:Bank \"Assets/Bank/statement.pdf\"
+2024-02-29 document Assets:Bank \"Assets/Bank/missing.pdf\"
+";
+        let ledger =
+            Ledger::new(parse_entries_from_string(input.to_string(), Path::new("")).unwrap());
+        let errors: Vec<_> = check_documents(&ledger, &dir)
+            .iter()
+            .map(|e| (e.code, e.file.clone(), e.line))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (ErrorCode::MissingDocument, None, Some(2)),
+                (
+                    ErrorCode::OrphanedDocument,
+                    Some(dir.join("Assets/Bank/receipt.pdf")),
+                    None
+                ),
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_check_assertions() {
+        let input = "2024-01-01 *
+  Assets:Broker:Cash 100 CHF
+  Assets:Broker:Stocks 50 CHF
+  Income:Salary -150 CHF
+2024-01-01 balance Assets:Broker:Cash 0 CHF
+2024-01-02 balance Assets:Broker:Cash 100 CHF
+2024-01-02 balance Assets:Broker 0 CHF
+2024-01-02 balance Assets:Broker:* 150.00 CHF
+2024-01-02 balance Assets:Broker:* 150.1 CHF
+";
+        let ledger =
+            Ledger::new(parse_entries_from_string(input.to_string(), Path::new("")).unwrap());
+        let errors: Vec<String> = check_assertions(&ledger)
+            .iter()
+            .map(|e| format!("{}:{} {}", e.line.unwrap(), e.code, e.context))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "9:B0010 Balance of `Assets:Broker:*` is 150 CHF, not 150.1 CHF (difference -0.1 CHF)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_auto_open() {
+        let input = "2024-01-01 *\n  Assets:Cash -5 CHF\n  Expenses:Food 5 CHF\n2024-01-05 balance Assets:Cash -5 CHF\n2024-02-01 open Expenses:Food\n";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        let (ledger, warnings) = auto_open(Ledger::new(entries));
+        let warnings: Vec<String> = warnings
+            .iter()
+            .map(|e| format!("{}:{} {}", e.line.unwrap(), e.code, e.context))
+            .collect();
+        assert_eq!(
+            warnings,
+            ["1:B0008 Account `Assets:Cash` is not opened, opening it on 2024-01-01"]
+        );
+        assert_eq!(ErrorCode::AutoOpenedAccount.severity(), Severity::Warning);
+        let errors: Vec<String> = validate(&ledger)
+            .iter()
+            .map(|e| e.context.clone())
+            .collect();
+        assert_eq!(
+            errors,
+            ["Account `Expenses:Food` is used before it is opened on 2024-02-01"]
+        );
+    }
+
+    #[test]
+    fn test_check_balances() {
+        let input = "2024-01-01 *
+  Assets:Cash -5 CHF
+  Expenses:Food 5 CHF
+2024-01-02 *
+  Assets:Cash -5 CHF
+  Expenses:Food 4 CHF
+";
+        let parse = || parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        let mut entries = parse();
+        let errors = check_balances(&mut entries, UnbalancedTransactions::Reject);
+        assert_eq!(entries.transactions.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::UnbalancedTransaction);
+        assert_eq!(errors[0].line, Some(4));
+        assert_eq!(
+            errors[0].context,
+            "Transaction not balanced: total is -1 CHF"
+        );
+
+        let mut entries = parse();
+        let errors = check_balances(&mut entries, UnbalancedTransactions::Flag);
+        assert_eq!(entries.transactions.len(), 2);
+        assert_eq!(entries.transactions[1].flag, TransactionFlag::Error);
+        assert_eq!(errors[0].code, ErrorCode::FlaggedUnbalancedTransaction);
+        assert_eq!(
+            errors[0].context,
+            "Transaction not balanced: total is -1 CHF, flagged with `!`"
+        );
+    }
+}
@@ -1,2 +1,11 @@
+pub mod cache;
+pub mod chart;
+pub mod compat;
+pub mod config;
+pub mod decode;
+pub mod format;
+pub mod html;
+pub mod import;
 pub mod parser;
 pub mod printer;
+pub mod report;
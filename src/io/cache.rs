@@ -0,0 +1,142 @@
+use crate::io::parser::{self, ParseOptions, ParsedEntries};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+// Bump whenever the serialized layout of the cached types changes, or the parser reads the
+// same input into different entries.
+const CACHE_FORMAT_VERSION: u32 = 33;
+
+// Identifies the state of a source file the cached entries were parsed from.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct SourceStamp {
+    path: PathBuf,
+    len: u64,
+    modified_nanos: u128,
+}
+
+impl SourceStamp {
+    fn of(path: &Path) -> std::io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified()?;
+        Ok(SourceStamp {
+            path: path.to_path_buf(),
+            len: metadata.len(),
+            modified_nanos: modified
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos()),
+        })
+    }
+}
+
+/// Parses `fpath` like `parser::parse_entries_from_file`, but stores the result in a binary
+/// cache at `cache_path`. Later calls load the cache instead of parsing, as long as the source
+/// file and the files it includes keep their size and modification time and the parse options
+/// stay the same.
+pub fn parse_entries_cached(
+    fpath: &Path,
+    cache_path: &Path,
+    options: &ParseOptions,
+) -> Result<ParsedEntries, Box<dyn Error>> {
+    if let Some(entries) = load_cache(fpath, cache_path, options) {
+        return Ok(entries);
+    }
+    // Stamp before parsing, so a change during parsing invalidates the cache.
+    let mut sources = vec![SourceStamp::of(fpath)?];
+    let entries = parser::parse_entries_from_file_with(fpath, options)?;
+    // Included files are only known after parsing.
+    for include in &entries.includes {
+        sources.push(SourceStamp::of(include)?);
+    }
+    // A cache that cannot be written only costs time on the next load.
+    let _ = write_cache(cache_path, &sources, options, &entries);
+    Ok(entries)
+}
+
+fn load_cache(fpath: &Path, cache_path: &Path, options: &ParseOptions) -> Option<ParsedEntries> {
+    let bytes = fs::read(cache_path).ok()?;
+    let (version, payload) = postcard::take_from_bytes::<u32>(&bytes).ok()?;
+    if version != CACHE_FORMAT_VERSION {
+        return None;
+    }
+    let (sources, cached_options, mut entries): (Vec<SourceStamp>, ParseOptions, ParsedEntries) =
+        postcard::from_bytes(payload).ok()?;
+    if sources.first()?.path != fpath || cached_options != *options {
+        return None;
+    }
+    for source in &sources {
+        if SourceStamp::of(&source.path).ok()? != *source {
+            return None;
+        }
+    }
+    entries.intern_all();
+    Some(entries)
+}
+
+fn write_cache(
+    cache_path: &Path,
+    sources: &[SourceStamp],
+    options: &ParseOptions,
+    entries: &ParsedEntries,
+) -> Result<(), Box<dyn Error>> {
+    let mut bytes = postcard::to_stdvec(&CACHE_FORMAT_VERSION)?;
+    bytes.extend(postcard::to_stdvec(&(sources, options, entries))?);
+    fs::write(cache_path, bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entries_cached() -> Result<(), Box<dyn Error>> {
+        let dir = std::env::temp_dir().join(format!("beanrust-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let ledger = dir.join("ledger.beancount");
+        let cache = dir.join("ledger.cache");
+        fs::write(
+            &ledger,
+            "2024-01-01 open Assets:Cash CHF\n2024-01-02 *\n  Assets:Cash 1 CHF\n  Income:Salary -1 CHF\n2024-01-03 foo\n",
+        )?;
+
+        let options = ParseOptions::default();
+        assert!(load_cache(&ledger, &cache, &options).is_none());
+        let parsed = parse_entries_cached(&ledger, &cache, &options)?;
+        let cached = load_cache(&ledger, &cache, &options).expect("cache should be valid");
+        assert_eq!(cached.open.len(), parsed.open.len());
+        assert_eq!(cached.transactions.len(), 1);
+        assert_eq!(cached.transactions[0].postings[1].account, "Income:Salary");
+        assert_eq!(cached.errors.len(), 1);
+        assert_eq!(cached.errors[0].line, Some(5));
+        assert_eq!(cached.symbols.len(), parsed.symbols.len());
+        assert!(load_cache(&dir.join("other.beancount"), &cache, &options).is_none());
+        let mut rounding = options.rounding;
+        rounding.scale = 2;
+        assert!(
+            load_cache(
+                &ledger,
+                &cache,
+                &ParseOptions {
+                    rounding,
+                    ..options
+                }
+            )
+            .is_none()
+        );
+
+        fs::write(&ledger, "2024-01-01 open Assets:Cash\n")?;
+        assert!(load_cache(&ledger, &cache, &options).is_none());
+        assert_eq!(
+            parse_entries_cached(&ledger, &cache, &options)?
+                .transactions
+                .len(),
+            0
+        );
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+use crate::core::ledger::Ledger;
+use jiff::ToSpan;
+use jiff::civil::Date;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use std::ops::Range;
+
+/// The accounts whose balance is the net worth.
+pub const NET_WORTH_ACCOUNTS: [&str; 2] = ["Assets", "Liabilities"];
+
+/// A value per date in date order, e.g. the balance of an account at the start of each month.
+pub type Series = Vec<(Date, Decimal)>;
+
+/// The balance in `currency` of `accounts` and their sub-accounts at the start of each month
+/// within `range`, beginning with `range.start` and ending with `range.end`. The range is
+/// limited to the dates of the entries, so that `Date::MIN..Date::MAX` covers the whole ledger.
+pub fn monthly_balances(
+    ledger: &Ledger,
+    accounts: &[&str],
+    currency: &str,
+    range: Range<Date>,
+) -> Series {
+    month_starts(ledger, range)
+        .into_iter()
+        .map(|date| {
+            let balance = accounts
+                .iter()
+                .flat_map(|a| ledger.balance(a, date))
+                .filter(|a| *a.currency == *currency)
+                .map(|a| a.number)
+                .sum();
+            (date, balance)
+        })
+        .collect()
+}
+
+/// `range.start`, the first day of each month after it and `range.end`, with the range
+/// limited to the dates of the entries. Empty if no entries are within the range.
+pub(crate) fn month_starts(ledger: &Ledger, range: Range<Date>) -> Vec<Date> {
+    let (Some(&first), Some(&last)) = (ledger.by_date().first(), ledger.by_date().last()) else {
+        return vec![];
+    };
+    let start = range.start.max(ledger.date(first));
+    let end = range
+        .end
+        .min(ledger.date(last).tomorrow().unwrap_or(Date::MAX));
+    if start >= end {
+        return vec![];
+    }
+    let mut dates = vec![start];
+    let mut month = start.first_of_month();
+    while let Ok(next) = month.checked_add(1.month()) {
+        if next >= end {
+            break;
+        }
+        dates.push(next);
+        month = next;
+    }
+    dates.push(end);
+    dates
+}
+
+/// The series as a JSON array of `[date, number]` pairs, the number as a string to keep its
+/// precision, e.g. `[["2024-01-01","100.50"]]`.
+pub fn series_to_json(series: &[(Date, Decimal)]) -> String {
+    serde_json::to_string(series).expect("dates and decimals serialize")
+}
+
+/// A minimal SVG line chart of `series`, without axes or labels, e.g. to show the trend of a
+/// balance next to it. Dates are spaced by the days between them.
+pub fn svg_sparkline(series: &[(Date, Decimal)], width: u32, height: u32) -> String {
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">"
+    );
+    if let (Some((first, _)), Some((last, _))) = (series.first(), series.last()) {
+        let days = |date: &Date| first.until(*date).map_or(0, |span| span.get_days()) as f64;
+        let values: Vec<f64> = series
+            .iter()
+            .map(|(_, v)| v.to_f64().unwrap_or(0.0))
+            .collect();
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let (width, height) = (f64::from(width), f64::from(height));
+        let points: Vec<String> = series
+            .iter()
+            .zip(&values)
+            .map(|((date, _), value)| {
+                let x = if first == last {
+                    width / 2.0
+                } else {
+                    days(date) / days(last) * width
+                };
+                // Flat series are drawn in the middle. SVG's y axis points down.
+                let y = if max == min {
+                    height / 2.0
+                } else {
+                    height - (value - min) / (max - min) * height
+                };
+                format!("{x:.1},{y:.1}")
+            })
+            .collect();
+        out.push_str(&format!(
+            "<polyline fill=\"none\" stroke=\"currentColor\" stroke-width=\"1.5\" points=\"{}\"/>",
+            points.join(" ")
+        ));
+    }
+    out.push_str("</svg>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::parser::parse_entries_from_string;
+    use jiff::civil::date;
+    use std::path::Path;
+
+    #[test]
+    fn test_monthly_balances() {
+        let input = "2024-01-15 *
+  Assets:Bank 100 CHF
+  Income:Salary -100 CHF
+2024-02-10 *
+  Liabilities:Card -30 CHF
+  Expenses:Food 30 CHF
+2024-03-20 *
+  Assets:Bank 5 EUR
+  Income:Salary -5 EUR
+";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        let ledger = Ledger::new(entries);
+        let series = monthly_balances(&ledger, &NET_WORTH_ACCOUNTS, "CHF", Date::MIN..Date::MAX);
+        let number = |n: i64| Decimal::from(n);
+        assert_eq!(
+            series,
+            [
+                (date(2024, 1, 15), number(0)),
+                (date(2024, 2, 1), number(100)),
+                (date(2024, 3, 1), number(70)),
+                (date(2024, 3, 21), number(70)),
+            ]
+        );
+        assert_eq!(
+            series_to_json(&series[..2]),
+            r#"[["2024-01-15","0"],["2024-02-01","100"]]"#
+        );
+        assert!(
+            monthly_balances(&ledger, &["Assets"], "CHF", date(2025, 1, 1)..Date::MAX).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_svg_sparkline() {
+        let series = vec![
+            (date(2024, 1, 1), Decimal::from(0)),
+            (date(2024, 1, 11), Decimal::from(10)),
+            (date(2024, 1, 21), Decimal::from(5)),
+        ];
+        assert_eq!(
+            svg_sparkline(&series, 100, 20),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"100\" height=\"20\" viewBox=\"0 0 100 20\"><polyline fill=\"none\" stroke=\"currentColor\" stroke-width=\"1.5\" points=\"0.0,20.0 50.0,0.0 100.0,10.0\"/></svg>"
+        );
+        assert!(!svg_sparkline(&[], 100, 20).contains("polyline"));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::core::types::*;
+use crate::io::parser::ParsedEntries;
+
+const BEANCOUNT_ROOT_ACCOUNTS: [&str; 5] =
+    ["Assets", "Liabilities", "Equity", "Income", "Expenses"];
+
+#[derive(Debug, PartialEq)]
+pub enum CompatDirection {
+    // beanrust accepts the construct, but beancount would reject the file.
+    RejectedByBeancount,
+    // beancount accepts the construct, but beanrust could not (fully) handle it.
+    RejectedByBeanrust,
+}
+
+#[derive(Debug)]
+pub struct CompatIssue {
+    pub direction: CompatDirection,
+    pub message: String,
+}
+
+impl std::fmt::Display for CompatIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tool = match self.direction {
+            CompatDirection::RejectedByBeancount => "beancount",
+            CompatDirection::RejectedByBeanrust => "beanrust",
+        };
+        write!(f, "rejected by {tool}: {}", self.message)
+    }
+}
+
+/// Collects the constructs in `entries` on which beanrust and beancount disagree, so a
+/// ledger can be kept loadable by both tools.
+pub fn check_beancount_compat(entries: &ParsedEntries) -> Vec<CompatIssue> {
+    let mut checker = CompatChecker { issues: vec![] };
+    for o in &entries.open {
+        checker.account(&o.account);
+        for c in o.allowed_currencies.iter().flatten() {
+            if c.contains(',') {
+                checker.push(
+                    CompatDirection::RejectedByBeanrust,
+                    format!(
+                        "currency list `{c}` of `{}` is read as a single currency",
+                        o.account
+                    ),
+                );
+            } else {
+                checker.currency(c);
+            }
+        }
+        if o.allowed_currencies.as_ref().is_some_and(|c| c.len() > 1) {
+            checker.push(
+                CompatDirection::RejectedByBeancount,
+                format!(
+                    "currencies of `{}` must be separated by commas, not whitespace",
+                    o.account
+                ),
+            );
+        }
+    }
+    for c in &entries.close {
+        checker.account(&c.account);
+    }
+    for n in &entries.note {
+        checker.account(&n.account);
+    }
+    for d in &entries.document {
+        checker.account(&d.account);
+    }
+    for p in &entries.pad {
+        checker.account(&p.account);
+        checker.account(&p.source_account);
+    }
+    for b in &entries.balance {
+        checker.account(&b.account);
+        checker.currency(&b.amount.currency);
+        if b.recursive {
+            checker.push(
+                CompatDirection::RejectedByBeancount,
+                format!("recursive balance assertion on `{}:*`", b.account),
+            );
+        }
+    }
+    for c in &entries.commodity {
+        checker.currency(&c.currency);
+    }
+    for p in &entries.price {
+        checker.currency(&p.currency);
+        checker.currency(&p.amount.currency);
+    }
+    for t in &entries.transactions {
+        for p in &t.postings {
+            checker.account(&p.account);
+            checker.currency(&p.amount.currency);
+            if let Some(price) = &p.price {
+                checker.currency(&price.amount.currency);
+            }
+            if let Some(CostType::Known(cost)) = &p.cost {
+                checker.currency(&cost.amount.currency);
+            }
+        }
+    }
+    for e in &entries.errors {
+        checker.push(CompatDirection::RejectedByBeanrust, e.to_string());
+    }
+    checker.issues
+}
+
+struct CompatChecker {
+    issues: Vec<CompatIssue>,
+}
+
+impl CompatChecker {
+    fn push(&mut self, direction: CompatDirection, message: String) {
+        self.issues.push(CompatIssue { direction, message });
+    }
+
+    fn account(&mut self, account: &str) {
+        if !is_beancount_account(account) {
+            self.push(
+                CompatDirection::RejectedByBeancount,
+                format!("invalid account name `{account}`"),
+            );
+        }
+    }
+
+    fn currency(&mut self, currency: &str) {
+        if !is_beancount_currency(currency) {
+            self.push(
+                CompatDirection::RejectedByBeancount,
+                format!("invalid currency `{currency}`"),
+            );
+        }
+    }
+}
+
+// Beancount accounts have one of the five default roots followed by at least one component.
+// Components start with an uppercase letter or a digit and contain letters, digits and dashes.
+fn is_beancount_account(account: &str) -> bool {
+    let mut components = account.split(':');
+    if !components
+        .next()
+        .is_some_and(|root| BEANCOUNT_ROOT_ACCOUNTS.contains(&root))
+    {
+        return false;
+    }
+    let mut has_child = false;
+    for component in components {
+        has_child = true;
+        let mut chars = component.chars();
+        match chars.next() {
+            Some(c) if c.is_uppercase() || c.is_ascii_digit() => {}
+            _ => return false,
+        }
+        if !chars.all(|c| c.is_alphanumeric() || c == '-') {
+            return false;
+        }
+    }
+    has_child
+}
+
+// Beancount currencies are up to 24 characters, start with an uppercase letter, end with an
+// uppercase letter or digit and may contain ' . _ - in between.
+fn is_beancount_currency(currency: &str) -> bool {
+    let bytes = currency.as_bytes();
+    let (Some(first), Some(last)) = (bytes.first(), bytes.last()) else {
+        return false;
+    };
+    bytes.len() <= 24
+        && first.is_ascii_uppercase()
+        && (last.is_ascii_uppercase() || last.is_ascii_digit())
+        && bytes
+            .iter()
+            .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b"'._-".contains(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::parser::error::{ErrorCode, ParseError};
+    use jiff::civil::date;
+    use smallvec::smallvec;
+
+    #[test]
+    fn test_is_beancount_account() {
+        assert!(is_beancount_account("Assets:Depot:Cash"));
+        assert!(is_beancount_account("Expenses:Food-Drinks"));
+        assert!(is_beancount_account("Liabilities:2024"));
+        assert!(!is_beancount_account("Assets"));
+        assert!(!is_beancount_account("Foo:Bar"));
+        assert!(!is_beancount_account("Assets:cash"));
+        assert!(!is_beancount_account("Assets::Cash"));
+        assert!(!is_beancount_account("Assets:Cash_Box"));
+    }
+
+    #[test]
+    fn test_is_beancount_currency() {
+        assert!(is_beancount_currency("CHF"));
+        assert!(is_beancount_currency("META"));
+        assert!(is_beancount_currency("VT.2"));
+        assert!(is_beancount_currency("X"));
+        assert!(!is_beancount_currency("usd"));
+        assert!(!is_beancount_currency("USD-"));
+        assert!(!is_beancount_currency(""));
+        assert!(!is_beancount_currency("ABCDEFGHIJKLMNOPQRSTUVWXYZ"));
+    }
+
+    #[test]
+    fn test_check_beancount_compat() {
+        let mut entries = ParsedEntries::default();
+        assert!(check_beancount_compat(&entries).is_empty());
+
+        entries.open.push(Open {
+            date: date(2024, 1, 1),
+            account: "Assets:Cash".into(),
+            allowed_currencies: Some(smallvec!["CHF".into(), "USD".into()]),
+            meta: Metadata::default(),
+            span: Span::default(),
+        });
+        entries.open.push(Open {
+            date: date(2024, 1, 1),
+            account: "Assets:Bank".into(),
+            allowed_currencies: Some(smallvec!["CHF,USD".into()]),
+            meta: Metadata::default(),
+            span: Span::default(),
+        });
+        entries.commodity.push(Commodity {
+            date: date(2024, 1, 1),
+            currency: "chf".into(),
+            meta: Metadata::default(),
+            span: Span::default(),
+        });
+        entries.errors.push(ParseError {
+            code: ErrorCode::UnknownDirective,
+            context: "Unknown command `foo` in entry".to_string(),
+            failed_statement: "2024-01-01 foo".to_string(),
+            file: None,
+            line: Some(3),
+            column: Some(1),
+        });
+
+        let issues = check_beancount_compat(&entries);
+        let directions: Vec<&CompatDirection> = issues.iter().map(|i| &i.direction).collect();
+        assert_eq!(
+            directions,
+            [
+                &CompatDirection::RejectedByBeancount,
+                &CompatDirection::RejectedByBeanrust,
+                &CompatDirection::RejectedByBeancount,
+                &CompatDirection::RejectedByBeanrust,
+            ]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use crate::io::import::csv::{self, PRESETS};
+use crate::io::import::json::JsonImportConfig;
+use crate::io::parser::Limits;
+use jiff::civil::Date;
+use jiff::{Timestamp, Zoned};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml_edit::{DocumentMut, Item};
+
+/// Name of the project configuration, looked up next to the root ledger.
+pub const CONFIG_FILE: &str = "beanrust.toml";
+
+/// Project-wide settings, e.g.
+/// ```toml
+/// operating-currency = "CHF"
+/// timezone = "Europe/Zurich"
+/// documents = "documents"
+/// plugins = ["auto-open"]
+/// scripts = ["rules.rhai"]
+///
+/// [lint]
+/// allow = ["B0003"]
+/// deny = ["warnings"]
+/// max-scale = 6
+///
+/// [limits]
+/// max-file-size = 104857600
+///
+/// [importers.bank]
+/// account = "Assets:Bank"
+/// counter_account = "Expenses:Uncategorized"
+/// ...
+///
+/// [importers.revolut]
+/// preset = "revolut"
+/// account = "Assets:Revolut"
+/// counter_account = "Expenses:Uncategorized"
+/// ```
+/// Command line flags take precedence: they replace single values and add to lists.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields, default)]
+pub struct Config {
+    // The currency reports value holdings in.
+    pub operating_currency: Option<String>,
+    // IANA name of the time zone that decides which day today is, the system's by default.
+    pub timezone: Option<String>,
+    // Root of the files of document directives, relative to the configuration.
+    pub documents: Option<PathBuf>,
+    // Names of the `PluginRegistry` plugins to run before the ledger is checked, in order.
+    pub plugins: Vec<String>,
+    // rhai scripts to run on each transaction when loading or importing, see
+    // `core::script::TransactionScript`. Relative to the configuration.
+    pub scripts: Vec<PathBuf>,
+    pub lint: LintConfig,
+    // Caps on the input when parsing, see `parser::Limits`.
+    pub limits: Limits,
+    // Import configurations by name, see `ImporterConfig`.
+    pub importers: BTreeMap<String, ImporterConfig>,
+}
+
+/// An importer of the configuration: a preset of `csv::preset` for the export of a common bank,
+/// or the fields of a `JsonImportConfig`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ImporterConfig {
+    Preset {
+        preset: String,
+        account: String,
+        counter_account: String,
+    },
+    Custom(JsonImportConfig),
+}
+
+impl ImporterConfig {
+    pub fn into_config(self) -> Result<JsonImportConfig, String> {
+        match self {
+            ImporterConfig::Preset {
+                preset,
+                account,
+                counter_account,
+            } => csv::preset(&preset, &account, &counter_account).ok_or_else(|| {
+                format!(
+                    "Unknown preset `{preset}`, expected one of {}",
+                    PRESETS.join(", ")
+                )
+            }),
+            ImporterConfig::Custom(config) => Ok(config),
+        }
+    }
+}
+
+/// Defaults of the `bean check` flags of the same names.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields, default)]
+pub struct LintConfig {
+    // Error codes.
+    pub allow: Vec<String>,
+    // Error codes, or `warnings`.
+    pub deny: Vec<String>,
+    pub max_scale: Option<u32>,
+    // Warn about transactions dated after today.
+    pub no_future: bool,
+    // Warn about accounts above their budget this period, see `validation::check_budgets`.
+    pub budgets: bool,
+}
+
+impl Config {
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        let document: DocumentMut = text.parse().map_err(|e| format!("Invalid TOML: {e}"))?;
+        let json = to_json(document.as_item());
+        serde_json::from_value(json).map_err(|e| format!("Invalid configuration: {e}"))
+    }
+
+    /// The `beanrust.toml` in `dir`, or the defaults if there is none. Paths in it are made
+    /// relative to `dir`.
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let path = dir.join(CONFIG_FILE);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(format!("Unable to read {}: {e}", path.display())),
+        };
+        let mut config =
+            Config::from_toml(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        config.documents = config.documents.map(|d| dir.join(d));
+        config.scripts = config.scripts.iter().map(|s| dir.join(s)).collect();
+        Ok(config)
+    }
+
+    /// Today in the configured time zone, e.g. the default end of reports. Dates in the ledger
+    /// have no time zone, so this is the only place it matters.
+    pub fn today(&self) -> Result<Date, String> {
+        let now = Timestamp::now();
+        match &self.timezone {
+            Some(name) => now
+                .in_tz(name)
+                .map(|now| now.date())
+                .map_err(|e| format!("Invalid timezone `{name}`: {e}")),
+            None => Ok(Zoned::now().date()),
+        }
+    }
+
+    /// The configuration next to `ledger`.
+    pub fn for_ledger(ledger: &Path) -> Result<Self, String> {
+        Config::load(ledger.parent().unwrap_or(Path::new("")))
+    }
+}
+
+// TOML maps onto JSON except for dates, which become strings.
+fn to_json(item: &Item) -> Value {
+    match item {
+        Item::None => Value::Null,
+        Item::Value(value) => value_to_json(value),
+        Item::Table(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, item)| (key.to_string(), to_json(item)))
+                .collect::<Map<_, _>>(),
+        ),
+        Item::ArrayOfTables(tables) => Value::Array(
+            tables
+                .iter()
+                .map(|t| to_json(&Item::Table(t.clone())))
+                .collect(),
+        ),
+    }
+}
+
+fn value_to_json(value: &toml_edit::Value) -> Value {
+    use toml_edit::Value as Toml;
+    match value {
+        Toml::String(s) => Value::String(s.value().clone()),
+        Toml::Integer(i) => Value::from(*i.value()),
+        Toml::Float(f) => Value::from(*f.value()),
+        Toml::Boolean(b) => Value::Bool(*b.value()),
+        Toml::Datetime(d) => Value::String(d.value().to_string()),
+        Toml::Array(array) => Value::Array(array.iter().map(value_to_json).collect()),
+        Toml::InlineTable(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.to_string(), value_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_from_toml() {
+        let config = Config::from_toml(
+            r#"operating-currency = "CHF"
+documents = "docs"
+plugins = ["auto-open"]
+timezone = "Pacific/Kiritimati"
+
+[lint]
+deny = ["warnings", "B0003"]
+max-scale = 6
+
+[limits]
+max-postings = 100
+
+[importers.bank]
+account = "Assets:Bank"
+counter_account = "Expenses:Uncategorized"
+records = "$.transactions"
+date = "bookingDate"
+amount = "amount"
+currency = "currency"
+payee = ["creditorName", "debtorName"]
+
+[importers.revolut]
+preset = "revolut"
+account = "Assets:Revolut"
+counter_account = "Expenses:Uncategorized"
+
+[importers.unknown]
+preset = "unknown"
+account = "Assets:Bank"
+counter_account = "Expenses:Uncategorized"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.operating_currency.as_deref(), Some("CHF"));
+        assert_eq!(config.plugins, ["auto-open"]);
+        assert_eq!(config.lint.deny, ["warnings", "B0003"]);
+        assert_eq!(config.lint.max_scale, Some(6));
+        assert!(config.lint.allow.is_empty());
+        assert_eq!(config.limits.max_postings, 100);
+        assert_eq!(
+            config.limits.max_line_length,
+            Limits::default().max_line_length
+        );
+        // Kiritimati is the first to start a new day.
+        assert!(config.today().unwrap() >= Config::default().today().unwrap());
+        let invalid = Config {
+            timezone: Some("Mars/Olympus".to_string()),
+            ..Config::default()
+        };
+        assert!(invalid.today().is_err());
+        let mut importers = config.importers;
+        let bank = importers.remove("bank").unwrap().into_config().unwrap();
+        assert_eq!(bank.payee.len(), 2);
+        let revolut = importers.remove("revolut").unwrap().into_config().unwrap();
+        assert_eq!(revolut.account, "Assets:Revolut");
+        assert_eq!(revolut.date, "Completed Date");
+        assert_eq!(
+            importers
+                .remove("unknown")
+                .unwrap()
+                .into_config()
+                .unwrap_err(),
+            "Unknown preset `unknown`, expected one of revolut, wise, n26, ibkr"
+        );
+
+        assert!(Config::from_toml("operating_currency = \"CHF\"").is_err());
+        assert!(Config::from_toml("[lint\n").is_err());
+        assert!(Config::load(Path::new("/nonexistent")).is_ok());
+    }
+}
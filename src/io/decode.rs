@@ -0,0 +1,104 @@
+use crate::io::parser::error::{ErrorCode, ParseError};
+use std::error::Error;
+use std::path::Path;
+
+/// What to do with input that is not valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidUtf8 {
+    // Fail, naming the position of the first invalid byte.
+    #[default]
+    Fail,
+    // Replace invalid bytes with U+FFFD and warn.
+    Replace,
+    // Decode the whole input as Latin-1 and warn, for e.g. bank exports.
+    Latin1,
+}
+
+/// Reads `fpath` as text. Unless `mode` is `Fail`, invalid UTF-8 doesn't fail the load but
+/// yields a warning at the first invalid byte.
+pub fn read_source(
+    fpath: &Path,
+    mode: InvalidUtf8,
+) -> Result<(String, Option<ParseError>), Box<dyn Error>> {
+    let bytes = std::fs::read(fpath)?;
+    let (text, warning) = decode(bytes, mode).map_err(|e| format!("{}: {e}", fpath.display()))?;
+    let warning = warning.map(|mut w| {
+        w.file = (!fpath.as_os_str().is_empty()).then(|| fpath.to_path_buf());
+        w
+    });
+    Ok((text, warning))
+}
+
+pub fn decode(bytes: Vec<u8>, mode: InvalidUtf8) -> Result<(String, Option<ParseError>), String> {
+    let error = match String::from_utf8(bytes) {
+        Ok(text) => return Ok((text, None)),
+        Err(e) => e,
+    };
+    let position = error.utf8_error().valid_up_to();
+    let bytes = error.into_bytes();
+    let line = bytes[..position].iter().filter(|b| **b == b'\n').count() + 1;
+    let line_start = bytes[..position]
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map_or(0, |i| i + 1);
+    let (text, context) = match mode {
+        InvalidUtf8::Fail => {
+            return Err(format!(
+                "Invalid UTF-8 at byte {position} (line {line}, column {})",
+                position - line_start + 1
+            ));
+        }
+        InvalidUtf8::Replace => (
+            String::from_utf8_lossy(&bytes).into_owned(),
+            format!("Invalid UTF-8 at byte {position}, invalid bytes were replaced"),
+        ),
+        InvalidUtf8::Latin1 => (
+            bytes.iter().map(|&b| char::from(b)).collect(),
+            format!("Invalid UTF-8 at byte {position}, the input was read as Latin-1"),
+        ),
+    };
+    let warning = ParseError {
+        code: ErrorCode::InvalidEncoding,
+        context,
+        failed_statement: String::new(),
+        file: None,
+        line: Some(line),
+        // Chars of the line before the invalid byte, plus one.
+        column: Some(
+            String::from_utf8_lossy(&bytes[line_start..position])
+                .chars()
+                .count()
+                + 1,
+        ),
+    };
+    Ok((text, Some(warning)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let valid = "2024-01-01 open Assets:Café\n".as_bytes().to_vec();
+        assert_eq!(
+            decode(valid, InvalidUtf8::Fail).unwrap().0,
+            "2024-01-01 open Assets:Café\n"
+        );
+
+        let latin1 = b"2024-01-01 open Assets:Cash\n2024-01-02 open Assets:Caf\xe9\n".to_vec();
+        assert_eq!(
+            decode(latin1.clone(), InvalidUtf8::Fail).unwrap_err(),
+            "Invalid UTF-8 at byte 54 (line 2, column 27)"
+        );
+
+        let (text, warning) = decode(latin1.clone(), InvalidUtf8::Replace).unwrap();
+        assert!(text.ends_with("Assets:Caf\u{FFFD}\n"));
+        let warning = warning.unwrap();
+        assert_eq!((warning.line, warning.column), (Some(2), Some(27)));
+        assert_eq!(warning.code, ErrorCode::InvalidEncoding);
+
+        let (text, _) = decode(latin1, InvalidUtf8::Latin1).unwrap();
+        assert!(text.ends_with("Assets:Café\n"));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::core::ledger::is_within;
+use crate::io::parser::{Lexer, TokenKind};
+
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    // Spaces in front of postings.
+    pub indent_width: usize,
+    // Column (0-based) at which the numbers of posting amounts end, so currencies line up.
+    pub amount_column: usize,
+    // Whether to align amounts at `amount_column`. Otherwise accounts and amounts are
+    // separated by two spaces.
+    pub align_currencies: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent_width: 2,
+            amount_column: 50,
+            align_currencies: true,
+        }
+    }
+}
+
+/// Formats ledger text: postings are re-indented and their amounts aligned, trailing whitespace
+/// is removed. Everything else, including comments and lines that fail to parse, is kept as is.
+pub fn format_ledger(input: &str, options: &FormatOptions) -> String {
+    let mut out = String::with_capacity(input.len());
+    for line in input.lines() {
+        match format_posting(line, options) {
+            Some(posting) => out.push_str(&posting),
+            None => out.push_str(line.trim_end()),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Renames `old` and its sub-accounts to `new` wherever they appear in `input` as accounts.
+/// Comments, strings and the formatting are kept, so the result can replace the source file.
+pub fn rename_account_in_text(input: &str, old: &str, new: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut copied = 0;
+    for token in Lexer::new(input).filter(|t| t.kind == TokenKind::Account) {
+        let account = &input[token.span.clone()];
+        if is_within(account, old) {
+            out.push_str(&input[copied..token.span.start]);
+            out.push_str(new);
+            out.push_str(&account[old.len()..]);
+            copied = token.span.end;
+        }
+    }
+    out.push_str(&input[copied..]);
+    out
+}
+
+// None if `line` is not a posting.
+fn format_posting(line: &str, options: &FormatOptions) -> Option<String> {
+    if !line.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let mut tokens = Lexer::new(line).peekable();
+    let start = tokens.peek()?.span.start;
+    let mut account = tokens.next()?;
+    if account.kind == TokenKind::Flag {
+        account = tokens.next()?;
+    }
+    if account.kind != TokenKind::Account {
+        return None;
+    }
+    let mut out = " ".repeat(options.indent_width);
+    // Keep a flag and the account, normalizing the space between them.
+    let prefix: Vec<&str> = line[start..account.span.end].split_whitespace().collect();
+    out.push_str(&prefix.join(" "));
+    let rest = line[account.span.end..].trim();
+    let number = tokens.next().filter(|t| t.kind == TokenKind::Number);
+    let Some(number) = number else {
+        if !rest.is_empty() {
+            out.push_str("  ");
+            out.push_str(rest);
+        }
+        return Some(out);
+    };
+    let number_text = &line[number.span.clone()];
+    let width = out.chars().count() + 2 + number_text.chars().count();
+    let padding = match options.align_currencies {
+        true => 2 + options.amount_column.saturating_sub(width),
+        false => 2,
+    };
+    out.push_str(&" ".repeat(padding));
+    out.push_str(number_text);
+    let tail = line[number.span.end..].trim();
+    if !tail.is_empty() {
+        out.push(' ');
+        out.push_str(tail);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rename_account_in_text() {
+        let input = "2024-01-01 open Assets:Old ; Assets:Old\n2024-01-02 * \"Assets:Old\"\n  Assets:Old:Cash   -5 CHF\n  Assets:Older 5 CHF\n";
+        assert_eq!(
+            rename_account_in_text(input, "Assets:Old", "Assets:New"),
+            "2024-01-01 open Assets:New ; Assets:Old\n2024-01-02 * \"Assets:Old\"\n  Assets:New:Cash   -5 CHF\n  Assets:Older 5 CHF\n"
+        );
+    }
+
+    #[test]
+    fn test_format_ledger() {
+        let input = "2024-01-01 * \"Shop\"   \n    Expenses:Food   5.50 CHF ; lunch\n\t! Assets:Cash -5.50   CHF @ 1 USD\n  Assets:Other\n; comment\n";
+        let options = FormatOptions {
+            amount_column: 30,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_ledger(input, &options),
+            "2024-01-01 * \"Shop\"
+  Expenses:Food           5.50 CHF ; lunch
+  ! Assets:Cash          -5.50 CHF @ 1 USD
+  Assets:Other
+; comment
+"
+        );
+
+        let options = FormatOptions {
+            indent_width: 4,
+            align_currencies: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_ledger(input, &options),
+            "2024-01-01 * \"Shop\"
+    Expenses:Food  5.50 CHF ; lunch
+    ! Assets:Cash  -5.50 CHF @ 1 USD
+    Assets:Other
+; comment
+"
+        );
+
+        // Accounts longer than the amount column still get two spaces.
+        let options = FormatOptions {
+            amount_column: 5,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_ledger("  Assets:Cash 1 CHF", &options),
+            "  Assets:Cash  1 CHF\n"
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use crate::core::ledger::{Ledger, is_within};
+use crate::core::types::*;
+use crate::io::chart::{NET_WORTH_ACCOUNTS, monthly_balances, svg_sparkline};
+use crate::io::report::{AmountFormatter, ReportOptions};
+use jiff::civil::Date;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+// Inline, so that the page can be sent by email or opened from disk as a single file.
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 2em; min-width: 30em; }
+th, td { padding: 0.2em 0.8em; border-bottom: 1px solid #ddd; text-align: left; }
+th { cursor: pointer; background: #f4f4f4; }
+svg { vertical-align: middle; margin-right: 1em; }
+td.amount { text-align: right; white-space: nowrap; font-variant-numeric: tabular-nums; }
+tr.total td { font-weight: bold; }
+";
+
+// Sorts a table by the clicked column, by the `data-sort` numbers if all cells have one and
+// by text otherwise. Clicking again reverses the order. The total row stays last.
+const SCRIPT: &str = "document.querySelectorAll('th').forEach(th => {
+  th.addEventListener('click', () => {
+    const body = th.closest('table').tBodies[0];
+    const rows = Array.from(body.rows);
+    const key = row => row.cells[th.cellIndex];
+    const numeric = rows.every(row => key(row).dataset.sort !== undefined);
+    const descending = th.dataset.order !== 'descending';
+    th.dataset.order = descending ? 'descending' : 'ascending';
+    rows.sort((a, b) => {
+      const order = numeric
+        ? key(a).dataset.sort - key(b).dataset.sort
+        : key(a).textContent.localeCompare(key(b).textContent);
+      return descending ? -order : order;
+    });
+    rows.forEach(row => body.appendChild(row));
+  });
+});
+";
+
+/// A standalone HTML page with the net worth at the end of `range` and its trend within it,
+/// the balance sheet at the end of `range` and the income statement of the postings within it,
+/// e.g. as a monthly summary. Each table can be sorted by clicking its headers.
+pub fn html_report(ledger: &Ledger, range: Range<Date>, options: &ReportOptions) -> String {
+    let last = range.end.yesterday().unwrap_or(range.end);
+    let title = match (range.start != Date::MIN, range.end != Date::MAX) {
+        (true, true) => format!("Report {} to {last}", range.start),
+        (false, true) => format!("Report until {last}"),
+        (true, false) => format!("Report from {}", range.start),
+        (false, false) => "Report".to_string(),
+    };
+    let formatter = AmountFormatter::new(ledger, options);
+    let balances = ledger.balances_at(range.end);
+    let changes = ledger.changes_between(range.clone());
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+    let net_worth: Vec<Amount> = NET_WORTH_ACCOUNTS
+        .iter()
+        .flat_map(|a| ledger.balance(a, range.end))
+        .collect();
+    if !net_worth.is_empty() {
+        out.push_str("<h2>Net worth</h2>\n");
+    }
+    for (currency, number) in sum_by_currency(&net_worth) {
+        let series = monthly_balances(ledger, &NET_WORTH_ACCOUNTS, &currency, range.clone());
+        out.push_str(&format!(
+            "<p>{}{}</p>\n",
+            svg_sparkline(&series, 200, 30),
+            escape(&formatter.format(&Amount::new(number, currency)))
+        ));
+    }
+    out.push_str(&table(
+        "Balance sheet",
+        &balances,
+        &["Assets", "Liabilities", "Equity"],
+        &formatter,
+    ));
+    out.push_str(&table(
+        "Income statement",
+        &changes,
+        &["Income", "Expenses"],
+        &formatter,
+    ));
+    out.push_str(&format!("<script>\n{SCRIPT}</script>\n</body>\n</html>\n"));
+    out
+}
+
+// The accounts within `roots` with their amounts, followed by the total of the roots.
+fn table(
+    heading: &str,
+    totals: &BTreeMap<String, Vec<Amount>>,
+    roots: &[&str],
+    formatter: &AmountFormatter,
+) -> String {
+    let mut out = format!(
+        "<h2>{heading}</h2>\n<table>\n<thead><tr><th>Account</th><th>Amount</th></tr></thead>\n<tbody>\n"
+    );
+    let mut total: BTreeMap<&Currency, Decimal> = BTreeMap::new();
+    for (account, amounts) in totals {
+        if !roots.iter().any(|r| is_within(account, r)) {
+            continue;
+        }
+        if roots.contains(&account.as_str()) {
+            for a in amounts {
+                *total.entry(&a.currency).or_default() += a.number;
+            }
+        }
+        out.push_str(&format!(
+            "<tr><td>{}</td>{}</tr>\n",
+            escape(account),
+            amounts_cell(amounts, formatter)
+        ));
+    }
+    let total: Vec<Amount> = total
+        .into_iter()
+        .map(|(currency, number)| Amount::new(number, currency.clone()))
+        .collect();
+    out.push_str(&format!(
+        "</tbody>\n<tfoot><tr class=\"total\"><td>Total</td>{}</tr></tfoot>\n</table>\n",
+        amounts_cell(&total, formatter)
+    ));
+    out
+}
+
+// One line per currency, sorted by the first amount.
+fn amounts_cell(amounts: &[Amount], formatter: &AmountFormatter) -> String {
+    let text: Vec<String> = amounts
+        .iter()
+        .map(|a| escape(&formatter.format(a)))
+        .collect();
+    let sort = amounts.first().map(|a| a.number).unwrap_or_default();
+    format!(
+        "<td class=\"amount\" data-sort=\"{sort}\">{}</td>",
+        text.join("<br>")
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::parser::parse_entries_from_string;
+    use jiff::civil::date;
+    use std::path::Path;
+
+    #[test]
+    fn test_html_report() {
+        let input = "2024-01-01 *
+  Assets:Bank 1000 CHF
+  Income:Salary -1000 CHF
+2024-02-03 * \"Shop\"
+  Assets:Bank -50 CHF
+  Expenses:Food 50 CHF
+2024-03-01 *
+  Assets:Bank -10 CHF
+  Expenses:Food 10 CHF
+";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        let ledger = Ledger::new(entries);
+        let html = html_report(
+            &ledger,
+            date(2024, 2, 1)..date(2024, 3, 1),
+            &ReportOptions::default(),
+        );
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Report 2024-02-01 to 2024-02-29</title>"));
+        assert!(html.contains(
+            "<tr><td>Assets:Bank</td><td class=\"amount\" data-sort=\"950\">950 CHF</td></tr>"
+        ));
+        assert!(html.contains(
+            "<tr><td>Expenses:Food</td><td class=\"amount\" data-sort=\"50\">50 CHF</td></tr>"
+        ));
+        assert!(!html.contains("Income:Salary"));
+        assert!(html.contains("<h2>Net worth</h2>\n<p><svg"));
+        assert!(html.contains("</svg>950 CHF</p>"));
+        assert_eq!(
+            escape("<Café & \"Bar\">"),
+            "&lt;Café &amp; &quot;Bar&quot;&gt;"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+pub mod categorize;
+pub mod csv;
+pub mod json;
+pub mod routing;
+pub mod state;
+
+use crate::core::types::*;
+use jiff::civil::Date;
+use smallvec::smallvec;
+use std::collections::HashSet;
+
+/// Drops the transactions that are already in `known`, recognized by their fingerprint, e.g.
+/// when a bank export overlaps the previously imported one. Returns the number of dropped
+/// transactions.
+pub fn remove_known(transactions: &mut Vec<Transaction>, known: &[Transaction]) -> usize {
+    let known: HashSet<u64> = known.iter().map(Transaction::fingerprint).collect();
+    let before = transactions.len();
+    transactions.retain(|t| !known.contains(&t.fingerprint()));
+    before - transactions.len()
+}
+
+// An imported record: `amount` moves into `account` and is balanced against `counter_account`,
+// which usually is a placeholder like Expenses:Uncategorized until the entry gets categorized.
+struct ImportedRecord {
+    date: Date,
+    payee: Option<String>,
+    narration: Option<String>,
+    amount: Amount,
+}
+
+impl ImportedRecord {
+    fn into_transaction(self, account: &str, counter_account: &str) -> Transaction {
+        let counter_amount = Amount::new(-self.amount.number, self.amount.currency.clone());
+        Transaction {
+            date: self.date,
+            flag: TransactionFlag::OK,
+            payee: self.payee,
+            narration: self.narration,
+            tags: vec![],
+            links: vec![],
+            meta: Metadata::default(),
+            postings: smallvec![
+                Posting {
+                    flag: None,
+                    account: account.into(),
+                    amount: self.amount,
+                    price: None,
+                    cost: None,
+                    meta: Metadata::default(),
+                },
+                Posting {
+                    flag: None,
+                    account: counter_account.into(),
+                    amount: counter_amount,
+                    price: None,
+                    cost: None,
+                    meta: Metadata::default(),
+                },
+            ],
+            span: Span::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::civil::date;
+
+    #[test]
+    fn test_remove_known() {
+        let record = |day, number: i64| ImportedRecord {
+            date: date(2024, 1, day),
+            payee: Some("Shop".to_string()),
+            narration: None,
+            amount: Amount::new((-number).into(), "CHF"),
+        };
+        let known = vec![record(1, 5).into_transaction("Assets:Bank", "Expenses:Uncategorized")];
+        let mut imported: Vec<Transaction> = [record(1, 5), record(2, 7)]
+            .into_iter()
+            .map(|r| r.into_transaction("Assets:Bank", "Expenses:Uncategorized"))
+            .collect();
+        assert_eq!(remove_known(&mut imported, &known), 1);
+        assert_eq!(imported[0].date, date(2024, 1, 2));
+    }
+}
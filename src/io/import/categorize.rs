@@ -0,0 +1,175 @@
+use crate::core::ledger::is_within;
+use crate::core::types::*;
+use rust_decimal::Decimal;
+use std::collections::{BTreeSet, HashMap};
+
+// The roots of the accounts to suggest.
+const CATEGORY_ROOTS: [&str; 2] = ["Expenses", "Income"];
+
+/// Metadata key of the similarity of a categorized transaction to the past transaction its
+/// account was taken from, from 0 to 1.
+pub const CONFIDENCE_KEY: &str = "confidence";
+
+/// Suggests the expense or income account of a transaction from the past transactions with a
+/// similar payee and narration.
+pub struct Categorizer {
+    // The words of each past transaction and the accounts it was categorized to.
+    history: Vec<(BTreeSet<String>, Vec<Account>)>,
+}
+
+impl Categorizer {
+    /// Learns from the postings of `transactions` to accounts below Expenses or Income, other
+    /// than to `placeholder`, e.g. Expenses:Uncategorized.
+    pub fn new(transactions: &[Transaction], placeholder: &str) -> Self {
+        let history = transactions
+            .iter()
+            .filter_map(|t| {
+                let accounts: Vec<Account> = t
+                    .postings
+                    .iter()
+                    .map(|p| &p.account)
+                    .filter(|a| CATEGORY_ROOTS.iter().any(|r| is_within(a, r)))
+                    .filter(|a| !is_within(a, placeholder))
+                    .cloned()
+                    .collect();
+                let words = words(t);
+                (!accounts.is_empty() && !words.is_empty()).then_some((words, accounts))
+            })
+            .collect();
+        Categorizer { history }
+    }
+
+    /// The account of the past transaction most similar to `transaction` and the similarity,
+    /// the share of words they have in common. None below `min_confidence`. Of several equally
+    /// similar transactions, the account used most often wins.
+    pub fn suggest(
+        &self,
+        transaction: &Transaction,
+        min_confidence: Decimal,
+    ) -> Option<(Account, Decimal)> {
+        let words = words(transaction);
+        let mut best = Decimal::ZERO;
+        let mut votes: HashMap<&Account, usize> = HashMap::new();
+        for (past, accounts) in &self.history {
+            let common = words.intersection(past).count();
+            if common == 0 {
+                continue;
+            }
+            let all = words.union(past).count();
+            let similarity = (Decimal::from(common) / Decimal::from(all)).round_dp(2);
+            if similarity > best {
+                best = similarity;
+                votes.clear();
+            }
+            if similarity == best {
+                for account in accounts {
+                    *votes.entry(account).or_default() += 1;
+                }
+            }
+        }
+        if best.is_zero() || best < min_confidence {
+            return None;
+        }
+        votes
+            .into_iter()
+            .max_by(|(a1, n1), (a2, n2)| n1.cmp(n2).then(a2.cmp(a1)))
+            .map(|(account, _)| (account.clone(), best))
+    }
+}
+
+/// Replaces the postings to `placeholder` with the account suggested by `categorizer`, adding
+/// the confidence as metadata. Returns the number of categorized transactions.
+pub fn categorize(
+    transactions: &mut [Transaction],
+    placeholder: &str,
+    categorizer: &Categorizer,
+    min_confidence: Decimal,
+) -> usize {
+    let mut categorized = 0;
+    for t in transactions {
+        if !t.postings.iter().any(|p| *p.account == *placeholder) {
+            continue;
+        }
+        let Some((account, confidence)) = categorizer.suggest(t, min_confidence) else {
+            continue;
+        };
+        for p in &mut t.postings {
+            if *p.account == *placeholder {
+                p.account = account.clone();
+            }
+        }
+        t.meta
+            .0
+            .push((CONFIDENCE_KEY.to_string(), CustomValue::Number(confidence)));
+        categorized += 1;
+    }
+    categorized
+}
+
+// The lowercase words of the payee and narration, without numbers such as dates, card numbers
+// or references, which rarely repeat.
+fn words(t: &Transaction) -> BTreeSet<String> {
+    [&t.payee, &t.narration]
+        .into_iter()
+        .flatten()
+        .flat_map(|text| text.split(|c: char| !c.is_alphanumeric()))
+        .filter(|w| w.chars().count() > 1 && !w.chars().all(|c| c.is_ascii_digit()))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transaction(input: &str) -> Transaction {
+        Transaction::try_from(input).unwrap()
+    }
+
+    #[test]
+    fn test_categorize() {
+        let history = [
+            transaction(
+                "2024-01-03 * \"MIGROS ZURICH\" \"Card 1234\"\n  Assets:Bank -20 CHF\n  Expenses:Groceries 20 CHF",
+            ),
+            transaction(
+                "2024-01-10 * \"Migros Basel\"\n  Assets:Bank -30 CHF\n  Expenses:Groceries 30 CHF",
+            ),
+            transaction(
+                "2024-01-12 * \"SBB Zurich\"\n  Assets:Bank -5 CHF\n  Expenses:Travel 5 CHF",
+            ),
+            transaction(
+                "2024-01-15 * \"Unknown shop\"\n  Assets:Bank -9 CHF\n  Expenses:Uncategorized 9 CHF",
+            ),
+        ];
+        let categorizer = Categorizer::new(&history, "Expenses:Uncategorized");
+        let mut imported = vec![
+            transaction(
+                "2024-02-02 * \"Migros Zurich\" \"Card 5678\"\n  Assets:Bank -25 CHF\n  Expenses:Uncategorized 25 CHF",
+            ),
+            transaction(
+                "2024-02-03 * \"Unknown shop\"\n  Assets:Bank -8 CHF\n  Expenses:Uncategorized 8 CHF",
+            ),
+        ];
+        let min = Decimal::new(5, 1);
+        assert_eq!(
+            categorize(&mut imported, "Expenses:Uncategorized", &categorizer, min),
+            1
+        );
+        assert_eq!(
+            imported[0].to_string(),
+            "2024-02-02 * \"Migros Zurich\" \"Card 5678\"
+    confidence: 1
+    Assets:Bank -25 CHF
+    Expenses:Groceries 25 CHF"
+        );
+        assert_eq!(&*imported[1].postings[1].account, "Expenses:Uncategorized");
+
+        let shop = transaction("2024-02-04 * \"Migros Bern\"\n  Assets:Bank -1 CHF");
+        assert_eq!(
+            categorizer.suggest(&shop, Decimal::ZERO),
+            Some(("Expenses:Groceries".into(), Decimal::new(33, 2)))
+        );
+        assert_eq!(categorizer.suggest(&shop, min), None);
+    }
+}
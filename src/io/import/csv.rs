@@ -0,0 +1,263 @@
+use super::json::{InputFormat, JsonImportConfig};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Names of the built-in presets of `preset`.
+pub const PRESETS: [&str; 4] = ["revolut", "wise", "n26", "ibkr"];
+
+/// The import configuration of a common bank export, selected by name:
+/// - `revolut`: the account statement CSV, without pending and reverted transactions.
+/// - `wise`: the statement CSV of a balance.
+/// - `n26`: the transactions CSV of the classic export, in EUR.
+/// - `ibkr`: the deposits and withdrawals of an Interactive Brokers activity statement CSV.
+pub fn preset(name: &str, account: &str, counter_account: &str) -> Option<JsonImportConfig> {
+    let paths = |paths: &[&str]| paths.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    let mut config = JsonImportConfig {
+        account: account.to_string(),
+        counter_account: counter_account.to_string(),
+        format: InputFormat::Csv,
+        delimiter: None,
+        records: "$".to_string(),
+        date: "Date".to_string(),
+        date_format: None,
+        amount: "Amount".to_string(),
+        currency: Some("Currency".to_string()),
+        default_currency: None,
+        payee: vec![],
+        narration: vec![],
+        require: BTreeMap::new(),
+    };
+    match name {
+        "revolut" => {
+            // Pending transactions have no completion date yet.
+            config.date = "Completed Date".to_string();
+            config.payee = paths(&["Description"]);
+            config.require = BTreeMap::from([("State".to_string(), "COMPLETED".to_string())]);
+        }
+        "wise" => {
+            config.date_format = Some("%d-%m-%Y".to_string());
+            config.payee = paths(&["Payee Name", "Payer Name", "Merchant"]);
+            config.narration = paths(&["Description"]);
+        }
+        "n26" => {
+            config.amount = "Amount (EUR)".to_string();
+            config.currency = None;
+            config.default_currency = Some("EUR".to_string());
+            config.payee = paths(&["Payee"]);
+            config.narration = paths(&["Payment reference"]);
+        }
+        "ibkr" => {
+            config.format = InputFormat::CsvSections;
+            config.records = "$.Deposits & Withdrawals".to_string();
+            config.date = "Settle Date".to_string();
+            config.narration = paths(&["Description"]);
+        }
+        _ => return None,
+    }
+    Some(config)
+}
+
+/// The rows of a CSV export with a header row as an array of objects keyed by column. Empty
+/// cells are left out, like fields missing from a JSON record.
+pub fn to_records(input: &str, delimiter: char) -> Result<Value, String> {
+    let mut rows = parse_rows(input, delimiter)?.into_iter();
+    let header = rows.next().unwrap_or_default();
+    Ok(Value::Array(
+        rows.map(|row| to_record(&header, row)).collect(),
+    ))
+}
+
+/// The rows of a CSV export made of sections, like the activity statements of Interactive
+/// Brokers, as an object of the records of each section. Each row starts with the name of its
+/// section and `Header` or `Data`. A `Header` row names the columns of the `Data` rows after
+/// it, other rows such as `Total` are left out.
+pub fn to_section_records(input: &str, delimiter: char) -> Result<Value, String> {
+    let mut headers: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut sections = Map::new();
+    for mut row in parse_rows(input, delimiter)? {
+        if row.len() < 2 {
+            continue;
+        }
+        let fields = row.split_off(2);
+        let [section, kind] = [row[0].clone(), row[1].clone()];
+        match kind.as_str() {
+            "Header" => {
+                headers.insert(section, fields);
+            }
+            "Data" => {
+                let header = headers
+                    .get(&section)
+                    .ok_or_else(|| format!("No header for the data of section `{section}`"))?;
+                let records = sections
+                    .entry(section)
+                    .or_insert_with(|| Value::Array(vec![]));
+                if let Value::Array(records) = records {
+                    records.push(to_record(header, fields));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(Value::Object(sections))
+}
+
+fn to_record(header: &[String], row: Vec<String>) -> Value {
+    Value::Object(
+        header
+            .iter()
+            .zip(row)
+            .filter(|(_, cell)| !cell.is_empty())
+            .map(|(column, cell)| (column.clone(), Value::String(cell)))
+            .collect(),
+    )
+}
+
+// Splits CSV input into rows of cells. Cells may be quoted with `"`, doubling quotes inside, to
+// hold the delimiter, quotes or line breaks. Empty lines are skipped.
+fn parse_rows(input: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut quote_line = 0;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if cell.is_empty() => {
+                quoted = true;
+                quote_line = line;
+            }
+            '\n' if !quoted => {
+                line += 1;
+                row.push(std::mem::take(&mut cell));
+                if row.len() > 1 || !row[0].is_empty() {
+                    rows.push(std::mem::take(&mut row));
+                }
+                row.clear();
+            }
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            c if c == delimiter && !quoted => row.push(std::mem::take(&mut cell)),
+            c => {
+                line += usize::from(c == '\n');
+                cell.push(c);
+            }
+        }
+    }
+    if quoted {
+        return Err(format!("Unterminated quoted cell at line {quote_line}"));
+    }
+    row.push(cell);
+    if row.len() > 1 || !row[0].is_empty() {
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::import::json::import_transactions;
+    use jiff::civil::date;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_rows() {
+        let input = "\u{feff}a,\"b, c\",\"say \"\"hi\"\"\"\r\n\n1,\"two\nlines\",\n";
+        assert_eq!(
+            parse_rows(input, ',').unwrap(),
+            [vec!["a", "b, c", "say \"hi\""], vec!["1", "two\nlines", ""]]
+        );
+        assert_eq!(parse_rows("a;b", ';').unwrap(), [vec!["a", "b"]]);
+        assert_eq!(
+            parse_rows("a\n\"b\nc", ',').unwrap_err(),
+            "Unterminated quoted cell at line 2"
+        );
+    }
+
+    #[test]
+    fn test_to_section_records() {
+        let input = "Statement,Header,Field Name,Field Value
+Statement,Data,Period,2024
+Deposits & Withdrawals,Header,Currency,Settle Date,Description,Amount
+Deposits & Withdrawals,Data,CHF,2024-02-01,Cash Transfer,5000
+Deposits & Withdrawals,Data,Total,,,5000
+Deposits & Withdrawals,Total,,,,5000
+";
+        let records = to_section_records(input, ',').unwrap();
+        assert_eq!(
+            records["Deposits & Withdrawals"],
+            json!([
+                {"Currency": "CHF", "Settle Date": "2024-02-01",
+                 "Description": "Cash Transfer", "Amount": "5000"},
+                {"Currency": "Total", "Amount": "5000"},
+            ])
+        );
+        assert_eq!(records["Statement"][0]["Field Value"], "2024");
+        assert!(to_section_records("Trades,Data,1\n", ',').is_err());
+    }
+
+    #[test]
+    fn test_presets() {
+        let import = |name: &str, input: &str| {
+            let config = preset(name, "Assets:Bank", "Expenses:Uncategorized").unwrap();
+            import_transactions(input, &config).unwrap()
+        };
+
+        let revolut = import(
+            "revolut",
+            "Type,Product,Started Date,Completed Date,Description,Amount,Fee,Currency,State,Balance
+CARD_PAYMENT,Current,2024-03-01 12:00:00,2024-03-02 09:00:00,Migros,-12.50,0.00,CHF,COMPLETED,87.50
+CARD_PAYMENT,Current,2024-03-03 12:00:00,,Coop,-3.00,0.00,CHF,PENDING,
+CARD_PAYMENT,Current,2024-03-04 12:00:00,2024-03-04 13:00:00,Coop,-3.00,0.00,CHF,REVERTED,87.50
+",
+        );
+        assert_eq!(revolut.len(), 1);
+        assert_eq!(revolut[0].date, date(2024, 3, 2));
+        assert_eq!(revolut[0].payee.as_deref(), Some("Migros"));
+        assert_eq!(
+            revolut[0].postings[0].amount,
+            "-12.50 CHF".try_into().unwrap()
+        );
+
+        let wise = import(
+            "wise",
+            "\"TransferWise ID\",Date,Amount,Currency,Description,\"Payer Name\",\"Payee Name\",Merchant
+TRANSFER-1,15-01-2024,-100.00,EUR,\"Sent money to Anna\",,\"Anna Muster\",
+CARD-2,16-01-2024,-4.20,EUR,\"Card transaction\",,,\"Cafe Zentral\"
+",
+        );
+        assert_eq!(wise[0].date, date(2024, 1, 15));
+        assert_eq!(wise[0].payee.as_deref(), Some("Anna Muster"));
+        assert_eq!(wise[0].narration.as_deref(), Some("Sent money to Anna"));
+        assert_eq!(wise[1].payee.as_deref(), Some("Cafe Zentral"));
+
+        let n26 = import(
+            "n26",
+            "\"Date\",\"Payee\",\"Account number\",\"Transaction type\",\"Payment reference\",\"Amount (EUR)\"
+\"2024-02-01\",\"ACME GmbH\",\"DE89370400440532013000\",\"Income\",\"Salary\",\"3000.0\"
+",
+        );
+        assert_eq!(n26[0].payee.as_deref(), Some("ACME GmbH"));
+        assert_eq!(n26[0].postings[0].amount, "3000.0 EUR".try_into().unwrap());
+
+        let ibkr = import(
+            "ibkr",
+            "Deposits & Withdrawals,Header,Currency,Settle Date,Description,Amount
+Deposits & Withdrawals,Data,CHF,2024-02-01,Electronic Fund Transfer,5000
+Deposits & Withdrawals,Data,Total,,,5000
+",
+        );
+        assert_eq!(ibkr.len(), 1);
+        assert_eq!(ibkr[0].date, date(2024, 2, 1));
+        assert_eq!(ibkr[0].postings[0].amount, "5000 CHF".try_into().unwrap());
+
+        assert!(preset("unknown", "Assets:Bank", "Expenses:Uncategorized").is_none());
+    }
+}
@@ -0,0 +1,231 @@
+use super::{ImportedRecord, csv};
+use crate::core::types::*;
+use jiff::civil::Date;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Maps the fields of a JSON dump (e.g. from an open-banking API) to transactions.
+/// Fields are addressed with a JSONPath-like syntax such as `$.transactions.booked`,
+/// `transactionAmount.amount` or `entries[0].date`. CSV exports are read as JSON records keyed
+/// by column, see `csv::to_records`, with the built-in presets of `csv::preset`.
+#[derive(Debug, Deserialize)]
+pub struct JsonImportConfig {
+    // Ledger account the imported records belong to, e.g. Assets:Bank:Checking.
+    pub account: String,
+    // Account balancing each imported record, e.g. Expenses:Uncategorized.
+    pub counter_account: String,
+    #[serde(default)]
+    pub format: InputFormat,
+    // Of CSV input, `,` by default.
+    #[serde(default)]
+    pub delimiter: Option<char>,
+    // Path to the array of records in the dump, `$` for the rows of a CSV export.
+    pub records: String,
+    // The remaining paths are relative to a single record.
+    pub date: String,
+    // strftime-like format of the date, e.g. `%d.%m.%Y`. By default the date is ISO 8601, and
+    // only the date part of timestamps is used.
+    #[serde(default)]
+    pub date_format: Option<String>,
+    pub amount: String,
+    #[serde(default)]
+    pub currency: Option<String>,
+    // Of records without a currency, e.g. in the export of an account in a single currency.
+    #[serde(default)]
+    pub default_currency: Option<String>,
+    // Candidate paths for the counterparty and the reference. The first one present in a
+    // record is used, e.g. the creditor for outgoing and the debtor for incoming payments.
+    #[serde(default)]
+    pub payee: Vec<String>,
+    #[serde(default)]
+    pub narration: Vec<String>,
+    // Paths and the value a record must have there to be imported, e.g. `State` = `COMPLETED`.
+    #[serde(default)]
+    pub require: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputFormat {
+    #[default]
+    Json,
+    // With a header row, see `csv::to_records`.
+    Csv,
+    // With a section name in front of each row, see `csv::to_section_records`.
+    CsvSections,
+}
+
+impl JsonImportConfig {
+    pub fn from_json(config: &str) -> Result<Self, String> {
+        serde_json::from_str(config).map_err(|e| format!("Invalid JSON import config: {e}"))
+    }
+}
+
+pub fn import_transactions(
+    input: &str,
+    config: &JsonImportConfig,
+) -> Result<Vec<Transaction>, String> {
+    let delimiter = config.delimiter.unwrap_or(',');
+    let dump = match config.format {
+        InputFormat::Json => {
+            serde_json::from_str(input).map_err(|e| format!("Invalid JSON input: {e}"))?
+        }
+        InputFormat::Csv => csv::to_records(input, delimiter)?,
+        InputFormat::CsvSections => csv::to_section_records(input, delimiter)?,
+    };
+    let records = select(&dump, &config.records)
+        .and_then(Value::as_array)
+        .ok_or_else(|| format!("No array of records at `{}`", config.records))?;
+    records
+        .iter()
+        .enumerate()
+        // Rows of CSV exports without a date are totals and the like.
+        .filter(|(_, record)| {
+            config.format == InputFormat::Json || select(record, &config.date).is_some()
+        })
+        .filter(|(_, record)| {
+            config.require.iter().all(|(path, value)| {
+                select(record, path).and_then(Value::as_str) == Some(value.as_str())
+            })
+        })
+        .map(|(i, record)| {
+            parse_record(record, config)
+                .map(|r| r.into_transaction(&config.account, &config.counter_account))
+                .map_err(|e| format!("Unable to import record {i}: {e}"))
+        })
+        .collect()
+}
+
+fn parse_record(record: &Value, config: &JsonImportConfig) -> Result<ImportedRecord, String> {
+    let date_str = select_str(record, &config.date)?;
+    let date = match &config.date_format {
+        Some(format) => Date::strptime(format, date_str),
+        // Only the date part of timestamps like 2024-01-15T10:00:00Z is relevant.
+        None => Date::from_str(date_str.get(..10).unwrap_or(date_str)),
+    }
+    .map_err(|e| format!("Invalid date `{date_str}`: {e}"))?;
+    let number = match select(record, &config.amount) {
+        Some(Value::String(s)) => Decimal::from_str_exact(s.trim()),
+        Some(Value::Number(n)) => Decimal::from_str_exact(&n.to_string()),
+        _ => return Err(format!("No amount at `{}`", config.amount)),
+    }
+    .map_err(|e| format!("Invalid amount at `{}`: {e}", config.amount))?;
+    let currency = match (&config.currency, &config.default_currency) {
+        (Some(path), default) => select(record, path)
+            .and_then(Value::as_str)
+            .or(default.as_deref())
+            .ok_or_else(|| format!("No string at `{path}`"))?,
+        (None, Some(default)) => default,
+        (None, None) => return Err("No `currency` or `default_currency` configured".to_string()),
+    };
+    Ok(ImportedRecord {
+        date,
+        payee: first_str(record, &config.payee),
+        narration: first_str(record, &config.narration),
+        amount: Amount::new(number, currency.to_string()),
+    })
+}
+
+fn select_str<'a>(value: &'a Value, path: &str) -> Result<&'a str, String> {
+    select(value, path)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("No string at `{path}`"))
+}
+
+fn first_str(value: &Value, paths: &[String]) -> Option<String> {
+    paths
+        .iter()
+        .find_map(|p| select(value, p).and_then(Value::as_str))
+        .map(|s| s.to_string())
+}
+
+// Resolves a path like `$.a.b[1].c` in `value`. The leading `$` is optional.
+fn select<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let mut current = value;
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        // A segment is a key, optionally followed by one or more `[index]`.
+        let (key, mut indices) = segment.split_at(segment.find('[').unwrap_or(segment.len()));
+        if !key.is_empty() {
+            current = current.get(key)?;
+        }
+        while let Some(rest) = indices.strip_prefix('[') {
+            let (index, remain) = rest.split_once(']')?;
+            current = current.get(index.parse::<usize>().ok()?)?;
+            indices = remain;
+        }
+        if !indices.is_empty() {
+            return None;
+        }
+    }
+    Some(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::civil::date;
+    use serde_json::json;
+
+    #[test]
+    fn test_select() {
+        let value = json!({"a": {"b": [{"c": 1}, {"c": 2}]}, "d": [[3, 4]]});
+        assert_eq!(select(&value, "$.a.b[1].c"), Some(&json!(2)));
+        assert_eq!(select(&value, "a.b[0]"), Some(&json!({"c": 1})));
+        assert_eq!(select(&value, "d[0][1]"), Some(&json!(4)));
+        assert_eq!(select(&value, "$"), Some(&value));
+        assert_eq!(select(&value, "a.x"), None);
+        assert_eq!(select(&value, "a.b[5]"), None);
+        assert_eq!(select(&value, "a.b[x]"), None);
+        assert_eq!(select(&value, "a.b[0]c"), None);
+    }
+
+    #[test]
+    fn test_import_transactions() {
+        let config = JsonImportConfig::from_json(
+            r#"{
+                "account": "Assets:Bank",
+                "counter_account": "Expenses:Uncategorized",
+                "records": "$.transactions.booked",
+                "date": "bookingDate",
+                "amount": "transactionAmount.amount",
+                "currency": "transactionAmount.currency",
+                "payee": ["creditorName", "debtorName"],
+                "narration": ["remittanceInformationUnstructured"]
+            }"#,
+        )
+        .unwrap();
+        let input = r#"{"transactions": {"booked": [
+            {"bookingDate": "2024-03-01", "creditorName": "Migros",
+             "transactionAmount": {"amount": "-12.50", "currency": "CHF"},
+             "remittanceInformationUnstructured": "groceries"},
+            {"bookingDate": "2024-03-25T08:00:00Z", "debtorName": "ACME",
+             "transactionAmount": {"amount": 5000, "currency": "CHF"}}
+        ]}}"#;
+
+        let transactions = import_transactions(input, &config).unwrap();
+        assert_eq!(transactions.len(), 2);
+        let t = &transactions[0];
+        assert_eq!(t.date, date(2024, 3, 1));
+        assert_eq!(t.payee.as_deref(), Some("Migros"));
+        assert_eq!(t.narration.as_deref(), Some("groceries"));
+        assert_eq!(t.postings[0].account, "Assets:Bank");
+        assert_eq!(t.postings[0].amount, "-12.50 CHF".try_into().unwrap());
+        assert_eq!(t.postings[1].account, "Expenses:Uncategorized");
+        assert_eq!(t.postings[1].amount, "12.50 CHF".try_into().unwrap());
+        assert!(t.check().is_ok());
+
+        let t = &transactions[1];
+        assert_eq!(t.date, date(2024, 3, 25));
+        assert_eq!(t.payee.as_deref(), Some("ACME"));
+        assert_eq!(t.narration, None);
+        assert_eq!(t.postings[0].amount, "5000 CHF".try_into().unwrap());
+
+        assert!(import_transactions(r#"{"transactions": {}}"#, &config).is_err());
+        let missing_amount = r#"{"transactions": {"booked": [{"bookingDate": "2024-03-01"}]}}"#;
+        assert!(import_transactions(missing_amount, &config).is_err());
+    }
+}
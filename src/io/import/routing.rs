@@ -0,0 +1,123 @@
+use crate::core::types::*;
+use crate::io::parser::IncrementalParser;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Where imported transactions go in a multi-file ledger, given as a path template such as
+/// `ledger/{account}/{year}.beancount`. `{account}` is the account of the first posting, i.e.
+/// the imported account, with one directory per component. `{year}` is the year of the
+/// transaction.
+#[derive(Debug, Clone)]
+pub struct Routing {
+    pub template: String,
+}
+
+impl Routing {
+    pub fn new(template: impl Into<String>) -> Self {
+        Routing {
+            template: template.into(),
+        }
+    }
+
+    pub fn destination(&self, transaction: &Transaction) -> PathBuf {
+        let account = transaction
+            .postings
+            .first()
+            .map_or(String::new(), |p| p.account.replace(':', "/"));
+        self.template
+            .replace("{account}", &account)
+            .replace("{year}", &transaction.date.year().to_string())
+            .into()
+    }
+}
+
+/// Appends each transaction to its destination file, after the last entry of its month. The
+/// rest of the files is left as it is, missing files and directories are created. Returns the
+/// files written.
+pub fn append_routed(
+    routing: &Routing,
+    transactions: Vec<Transaction>,
+) -> Result<Vec<PathBuf>, String> {
+    let mut by_file: BTreeMap<PathBuf, Vec<Transaction>> = BTreeMap::new();
+    for t in transactions {
+        by_file.entry(routing.destination(&t)).or_default().push(t);
+    }
+    for (path, transactions) in &by_file {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Unable to read {}: {e}", path.display())),
+        };
+        let mut parser = IncrementalParser::new(text);
+        for t in transactions {
+            parser.insert_entry_in_month(&EntryVariant::Transaction(t.clone()));
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Unable to create {}: {e}", dir.display()))?;
+        }
+        std::fs::write(path, parser.text())
+            .map_err(|e| format!("Unable to write {}: {e}", path.display()))?;
+    }
+    Ok(by_file.into_keys().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn transaction(input: &str) -> Transaction {
+        Transaction::try_from(input).unwrap()
+    }
+
+    #[test]
+    fn test_destination() {
+        let t = transaction("2024-03-01 *\n  Assets:Bank:Checking -5 CHF\n  Expenses:Food 5 CHF");
+        assert_eq!(
+            Routing::new("ledger/{account}/{year}.beancount").destination(&t),
+            PathBuf::from("ledger/Assets/Bank/Checking/2024.beancount")
+        );
+        assert_eq!(
+            Routing::new("imported.beancount").destination(&t),
+            PathBuf::from("imported.beancount")
+        );
+    }
+
+    #[test]
+    fn test_append_routed() {
+        let dir = std::env::temp_dir().join(format!("beanrust-routing-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("2024.beancount");
+        fs::write(
+            &existing,
+            "2024-01-10 * \"A\"\n  Assets:Bank -1 CHF\n  Expenses:Food 1 CHF\n\n2024-02-01 * \"B\"\n  Assets:Bank -2 CHF\n  Expenses:Food 2 CHF\n",
+        )
+        .unwrap();
+        let routing = Routing::new(dir.join("{year}.beancount").to_str().unwrap());
+        let written = append_routed(
+            &routing,
+            vec![
+                transaction("2024-01-05 * \"C\"\n  Assets:Bank -3 CHF\n  Expenses:Food 3 CHF"),
+                transaction("2023-12-31 * \"D\"\n  Assets:Bank -4 CHF\n  Expenses:Food 4 CHF"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(written, [dir.join("2023.beancount"), existing.clone()]);
+
+        let text = fs::read_to_string(&existing).unwrap();
+        let narrations: Vec<&str> = text
+            .lines()
+            .filter(|l| l.starts_with("2024"))
+            .map(|l| &l[13..])
+            .collect();
+        assert_eq!(narrations, ["\"A\"", "\"C\"", "\"B\""]);
+        assert!(
+            fs::read_to_string(dir.join("2023.beancount"))
+                .unwrap()
+                .starts_with("2023-12-31 * \"D\"")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use crate::core::types::*;
+use jiff::civil::Date;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// What earlier imports produced, kept in a JSON file next to the downloads, so that importing
+/// the same or overlapping statements again only yields the transactions not seen before.
+/// Transactions are recognized by the fingerprint they had when imported, before scripts,
+/// categorization or review changed them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImportState {
+    // The date of the latest imported transaction per account.
+    #[serde(default)]
+    pub last_dates: BTreeMap<String, Date>,
+    #[serde(default)]
+    pub fingerprints: BTreeSet<u64>,
+}
+
+impl ImportState {
+    /// The state in `path`, empty if the file does not exist yet.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| format!("Invalid import state {}: {e}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ImportState::default()),
+            Err(e) => Err(format!("Unable to read {}: {e}", path.display())),
+        }
+    }
+
+    /// Writes the state to a temporary file first, so an interrupted run leaves the previous
+    /// state intact.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).expect("import state serializes");
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, json + "\n")
+            .and_then(|()| std::fs::rename(&tmp, path))
+            .map_err(|e| format!("Unable to write {}: {e}", path.display()))
+    }
+
+    /// Drops the transactions imported before. Returns the number of dropped transactions.
+    pub fn remove_seen(&self, transactions: &mut Vec<Transaction>) -> usize {
+        let before = transactions.len();
+        transactions.retain(|t| !self.fingerprints.contains(&t.fingerprint()));
+        before - transactions.len()
+    }
+
+    /// Remembers `transactions`, freshly imported into `account`.
+    pub fn record(&mut self, account: &str, transactions: &[Transaction]) {
+        self.fingerprints
+            .extend(transactions.iter().map(Transaction::fingerprint));
+        if let Some(last) = transactions.iter().map(|t| t.date).max() {
+            let date = self.last_dates.entry(account.to_string()).or_insert(last);
+            *date = last.max(*date);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_state() {
+        let dir = std::env::temp_dir().join(format!("beanrust-state-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("import-state.json");
+        let _ = std::fs::remove_file(&path);
+        let transaction = |day| {
+            Transaction::try_from(
+                format!(
+                    "2024-01-{day:02} * \"Shop\"\n  Assets:Bank -5 CHF\n  Expenses:Uncategorized 5 CHF"
+                )
+                .as_str(),
+            )
+            .unwrap()
+        };
+
+        let mut state = ImportState::load(&path).unwrap();
+        assert_eq!(state, ImportState::default());
+        state.record("Assets:Bank", &[transaction(3), transaction(1)]);
+        state.save(&path).unwrap();
+
+        let state = ImportState::load(&path).unwrap();
+        assert_eq!(
+            state.last_dates["Assets:Bank"],
+            jiff::civil::date(2024, 1, 3)
+        );
+        let mut again = vec![transaction(1), transaction(2), transaction(3)];
+        assert_eq!(state.remove_seen(&mut again), 2);
+        assert_eq!(again[0].date, jiff::civil::date(2024, 1, 2));
+
+        std::fs::write(&path, "{").unwrap();
+        assert!(ImportState::load(&path).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
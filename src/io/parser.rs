@@ -1,98 +1,771 @@
+mod incremental;
+mod lexer;
 mod statement_iterator;
 mod transaction_parsing;
 
+use crate::core::ledger::Ledger;
 use crate::core::types::*;
-use error::ParseError;
+use crate::core::validation::error_at;
+use crate::io::decode::{InvalidUtf8, read_source};
+use error::{ErrorCode, ParseError};
+pub use incremental::{IncrementalParser, ParsedStatement, StatementEdit};
 use jiff::civil::Date;
+pub use lexer::{Lexer, Token, TokenKind};
 use rust_decimal::Decimal;
-pub use statement_iterator::TokenIterator;
+use serde::{Deserialize, Serialize};
+pub use statement_iterator::{StatementIterator, TokenIterator};
 use std::error::Error;
-use std::{fs, path::Path, str::FromStr, vec};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::{path::Path, str::FromStr};
 
+#[derive(Default, Serialize, Deserialize)]
 pub struct ParsedEntries {
     pub open: Vec<Open>,
     pub balance: Vec<Balance>,
     pub close: Vec<Close>,
     pub commodity: Vec<Commodity>,
-    pub price: Vec<PriceEntry>,
+    pub price: Vec<PriceDirective>,
     pub transactions: Vec<Transaction>,
-    // temporry until impl complete
-    pub unhandled_entries: Vec<String>,
+    pub note: Vec<Note>,
+    pub document: Vec<Document>,
+    pub pad: Vec<Pad>,
+    pub event: Vec<Event>,
+    pub custom: Vec<Custom>,
+    // Statements that failed to parse, in input order, with context and location.
+    pub errors: Vec<ParseError>,
+    // Files loaded through `include` directives, recursively.
+    pub includes: Vec<PathBuf>,
+    // The `plugin` directives of all files, in input order.
+    pub plugins: Vec<PluginDirective>,
+    // The `option` directives of all files.
+    pub options: Options,
+    // Accounts and currencies of all entries, each stored once.
+    #[serde(skip)]
+    pub symbols: Interner,
+    // Include directives of the file being parsed, loaded once it is complete.
+    #[serde(skip)]
+    pending_includes: Vec<(String, Span)>,
+    // Tags pushed by `pushtag` in the file being parsed and not popped yet.
+    #[serde(skip)]
+    pushed_tags: Vec<(String, Span)>,
 }
 
 impl ParsedEntries {
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
+    // Number of entries of all kinds, not counting errors.
     pub fn len(&self) -> usize {
-        self.open.len() + self.balance.len() + self.close.len()
+        self.open.len()
+            + self.balance.len()
+            + self.close.len()
+            + self.commodity.len()
+            + self.price.len()
+            + self.transactions.len()
+            + self.note.len()
+            + self.document.len()
+            + self.pad.len()
+            + self.event.len()
+            + self.custom.len()
     }
-    pub fn push(&mut self, entry: EntryVariant) {
+    pub fn push(&mut self, mut entry: EntryVariant) {
+        entry.intern(&mut self.symbols);
         match entry {
             EntryVariant::Open(o) => self.open.push(o),
             EntryVariant::Balance(b) => self.balance.push(b),
             EntryVariant::Close(c) => self.close.push(c),
             EntryVariant::Commodity(c) => self.commodity.push(c),
-            EntryVariant::PriceEntry(p) => self.price.push(p),
+            EntryVariant::Price(p) => self.price.push(p),
             EntryVariant::Transaction(t) => self.transactions.push(t),
+            EntryVariant::Note(n) => self.note.push(n),
+            EntryVariant::Document(d) => self.document.push(d),
+            EntryVariant::Pad(p) => self.pad.push(p),
+            EntryVariant::Event(e) => self.event.push(e),
+            EntryVariant::Custom(c) => self.custom.push(c),
         }
     }
-    pub fn push_result(&mut self, entry: Result<EntryVariant, Box<error::ParseError>>) {
-        match entry {
-            Ok(e) => self.push(e),
-            Err(e) => {
-                self.unhandled_entries.push(e.failed_statement);
+    /// Interns the symbols of all entries, e.g. after they were deserialized.
+    pub fn intern_all(&mut self) {
+        let symbols = &mut self.symbols;
+        self.open.iter_mut().for_each(|e| e.intern(symbols));
+        self.balance.iter_mut().for_each(|e| e.intern(symbols));
+        self.close.iter_mut().for_each(|e| e.intern(symbols));
+        self.commodity.iter_mut().for_each(|e| e.intern(symbols));
+        self.price.iter_mut().for_each(|e| e.intern(symbols));
+        self.transactions.iter_mut().for_each(|e| e.intern(symbols));
+        self.note.iter_mut().for_each(|e| e.intern(symbols));
+        self.document.iter_mut().for_each(|e| e.intern(symbols));
+        self.pad.iter_mut().for_each(|e| e.intern(symbols));
+        self.custom.iter_mut().for_each(|e| e.intern(symbols));
+    }
+
+    /// The accounts referred to by all entries, one item per reference.
+    pub fn accounts_mut(&mut self) -> impl Iterator<Item = &mut Account> {
+        self.open
+            .iter_mut()
+            .map(|e| &mut e.account)
+            .chain(self.balance.iter_mut().map(|e| &mut e.account))
+            .chain(self.close.iter_mut().map(|e| &mut e.account))
+            .chain(
+                self.transactions
+                    .iter_mut()
+                    .flat_map(|t| t.postings.iter_mut().map(|p| &mut p.account)),
+            )
+            .chain(self.note.iter_mut().map(|e| &mut e.account))
+            .chain(self.document.iter_mut().map(|e| &mut e.account))
+            .chain(
+                self.pad
+                    .iter_mut()
+                    .flat_map(|p| [&mut p.account, &mut p.source_account]),
+            )
+            .chain(self.custom.iter_mut().flat_map(|c| {
+                c.values.iter_mut().filter_map(|v| match v {
+                    CustomValue::Account(a) => Some(a),
+                    _ => None,
+                })
+            }))
+    }
+
+    /// Indexes the entries for queries, see `Ledger`.
+    pub fn into_ledger(self) -> Ledger {
+        Ledger::new(self)
+    }
+    /// Moves the entries and errors of `other` to the end of these.
+    pub fn append(&mut self, mut other: ParsedEntries) {
+        other.symbols = std::mem::take(&mut self.symbols);
+        other.intern_all();
+        self.symbols = std::mem::take(&mut other.symbols);
+        self.open.append(&mut other.open);
+        self.balance.append(&mut other.balance);
+        self.close.append(&mut other.close);
+        self.commodity.append(&mut other.commodity);
+        self.price.append(&mut other.price);
+        self.transactions.append(&mut other.transactions);
+        self.note.append(&mut other.note);
+        self.document.append(&mut other.document);
+        self.pad.append(&mut other.pad);
+        self.event.append(&mut other.event);
+        self.custom.append(&mut other.custom);
+        self.errors.append(&mut other.errors);
+        self.includes.append(&mut other.includes);
+        self.plugins.append(&mut other.plugins);
+        self.options.values.append(&mut other.options.values);
+    }
+    pub fn push_result(&mut self, result: StatementResult) {
+        if let Some(mut entry) = result.entry {
+            if let EntryVariant::Transaction(t) = &mut entry {
+                for (tag, _) in &self.pushed_tags {
+                    if !t.tags.contains(tag) {
+                        t.tags.push(tag.clone());
+                    }
+                }
+            }
+            self.push(entry);
+        }
+        self.errors.extend(result.errors);
+        match result.directive {
+            Some((FileDirective::Include(path), span)) => self.pending_includes.push((path, span)),
+            Some((FileDirective::PushTag(tag), span)) => self.pushed_tags.push((tag, span)),
+            Some((FileDirective::Plugin(name, config), span)) => {
+                self.plugins.push(PluginDirective { name, config, span })
             }
+            Some((FileDirective::Option(name, value), _)) => self.options.insert(name, value),
+            Some((FileDirective::PopTag(tag), span)) => {
+                match self.pushed_tags.iter().rposition(|(t, _)| *t == tag) {
+                    Some(i) => {
+                        self.pushed_tags.remove(i);
+                    }
+                    None => self.errors.push(error_at(
+                        &span,
+                        ErrorCode::UnbalancedTag,
+                        format!("Tag `#{tag}` is popped but was not pushed"),
+                    )),
+                }
+            }
+            None => {}
         }
     }
-}
-
-impl Default for ParsedEntries {
-    fn default() -> Self {
-        ParsedEntries {
-            open: vec![],
-            balance: vec![],
-            close: vec![],
-            commodity: vec![],
-            price: vec![],
-            unhandled_entries: vec![],
-            transactions: vec![],
+    // Reports the tags still pushed at the end of a file. As in beancount, they don't carry
+    // over to other files.
+    pub(crate) fn end_of_file(&mut self) {
+        for (tag, span) in std::mem::take(&mut self.pushed_tags) {
+            self.errors.push(error_at(
+                &span,
+                ErrorCode::UnbalancedTag,
+                format!("Tag `#{tag}` is pushed but never popped"),
+            ));
         }
     }
 }
 
+/// Parses all statements of `fpath`. Statements that fail to parse are collected in
+/// `ParsedEntries::errors`, an `Err` is only returned if the file cannot be read.
 pub fn parse_entries_from_file(fpath: &Path) -> Result<ParsedEntries, Box<dyn Error>> {
-    parse_entries_from_string(fs::read_to_string(fpath)?, fpath)
+    parse_entries_from_file_with(fpath, &ParseOptions::default())
+}
+
+pub fn parse_entries_from_file_with(
+    fpath: &Path,
+    options: &ParseOptions,
+) -> Result<ParsedEntries, Box<dyn Error>> {
+    options
+        .limits
+        .check_file_size(std::fs::metadata(fpath)?.len(), fpath)?;
+    let (input, _) = read_source(fpath, InvalidUtf8::Fail)?;
+    parse_entries_from_string_with(input, fpath, options)
 }
 
 pub fn parse_entries_from_string(
     input: String,
-    _cur_fpath: &Path,
+    cur_fpath: &Path,
+) -> Result<ParsedEntries, Box<dyn Error>> {
+    parse_entries_from_string_with(input, cur_fpath, &ParseOptions::default())
+}
+
+/// Included files are loaded relative to `cur_fpath`, their entries follow the entries of
+/// the including file. Tags pushed in a file don't apply to the files it includes.
+pub fn parse_entries_from_string_with(
+    input: String,
+    cur_fpath: &Path,
+    options: &ParseOptions,
 ) -> Result<ParsedEntries, Box<dyn Error>> {
-    // TODO: Handle imports of other files.
+    options
+        .limits
+        .check_file_size(input.len() as u64, cur_fpath)?;
+    let mut parsed_entries = parse_file(&input, cur_fpath, options);
+    load_included_files(&mut parsed_entries, cur_fpath, options);
+    Ok(parsed_entries)
+}
+
+// The entries of a single file, without loading its includes.
+fn parse_file(input: &str, fpath: &Path, options: &ParseOptions) -> ParsedEntries {
     let mut parsed_entries: ParsedEntries = ParsedEntries::default();
+    let locator = Locator::new(input, fpath);
+    StatementIterator::new(input)
+        .map(|s| parse_statement(&locator, s, options))
+        .for_each(|r| parsed_entries.push_result(r));
+    parsed_entries.end_of_file();
+    parsed_entries
+}
 
-    statement_iterator::StatementIterator::new(&input)
-        .map(|s| StatementParser::new(s).parse_entry())
-        .for_each(|r| {
-            // todo: don't swallow errors here.
-            parsed_entries.push_result(r);
-        });
+// Loads the files included by `entries`, the entries of `fpath`.
+pub(crate) fn load_included_files(
+    entries: &mut ParsedEntries,
+    fpath: &Path,
+    options: &ParseOptions,
+) {
+    load_includes(entries, options, &mut vec![canonical(fpath)]);
+}
+
+fn canonical(fpath: &Path) -> PathBuf {
+    fpath.canonicalize().unwrap_or_else(|_| fpath.to_path_buf())
+}
+
+// Parses the files included by `entries` and appends their entries, recursively. `loading`
+// holds the files being loaded, to report include cycles instead of following them.
+fn load_includes(entries: &mut ParsedEntries, options: &ParseOptions, loading: &mut Vec<PathBuf>) {
+    for (path, span) in std::mem::take(&mut entries.pending_includes) {
+        let dir = span.file.as_deref().and_then(Path::parent);
+        let fpath = dir.map_or_else(|| PathBuf::from(&path), |dir| dir.join(&path));
+        let key = canonical(&fpath);
+        if loading.contains(&key) {
+            entries.errors.push(error_at(
+                &span,
+                ErrorCode::InvalidInclude,
+                format!("Include cycle: `{path}` is already being loaded"),
+            ));
+            continue;
+        }
+        // `loading` holds the root file as well.
+        let limits = &options.limits;
+        let limit = if loading.len() > limits.max_include_depth {
+            Err(format!(
+                "Cannot include `{path}`: more than {} nested includes",
+                limits.max_include_depth
+            ))
+        } else {
+            std::fs::metadata(&fpath)
+                .map_or(Ok(()), |m| limits.check_file_size(m.len(), &fpath))
+                .map_err(|e| format!("Cannot include `{path}`: {e}"))
+        };
+        if let Err(e) = limit {
+            entries
+                .errors
+                .push(error_at(&span, ErrorCode::LimitExceeded, e));
+            continue;
+        }
+        let input = match read_source(&fpath, InvalidUtf8::Fail) {
+            Ok((input, _)) => input,
+            Err(e) => {
+                entries.errors.push(error_at(
+                    &span,
+                    ErrorCode::InvalidInclude,
+                    format!("Cannot include `{path}`: {e}"),
+                ));
+                continue;
+            }
+        };
+        let mut included = parse_file(&input, &fpath, options);
+        loading.push(key);
+        load_includes(&mut included, options, loading);
+        loading.pop();
+        entries.includes.push(fpath);
+        entries.append(included);
+    }
+}
+
+#[cfg(feature = "parallel")]
+pub fn parse_entries_from_file_parallel(
+    fpath: &Path,
+    options: &ParseOptions,
+) -> Result<ParsedEntries, Box<dyn Error>> {
+    options
+        .limits
+        .check_file_size(std::fs::metadata(fpath)?.len(), fpath)?;
+    let (input, _) = read_source(fpath, InvalidUtf8::Fail)?;
+    parse_entries_from_string_parallel(input, fpath, options)
+}
 
+/// Same as `parse_entries_from_string`, but parses the statements on the rayon thread pool.
+/// The file is segmented into statements first, the results are merged in input order.
+#[cfg(feature = "parallel")]
+pub fn parse_entries_from_string_parallel(
+    input: String,
+    cur_fpath: &Path,
+    options: &ParseOptions,
+) -> Result<ParsedEntries, Box<dyn Error>> {
+    use rayon::prelude::*;
+
+    options
+        .limits
+        .check_file_size(input.len() as u64, cur_fpath)?;
+    let locator = Locator::new(&input, cur_fpath);
+    let statements: Vec<&str> = StatementIterator::new(&input).collect();
+    let results: Vec<_> = statements
+        .par_iter()
+        .map(|s| parse_statement(&locator, s, options))
+        .collect();
+
+    let mut parsed_entries: ParsedEntries = ParsedEntries::default();
+    for r in results {
+        parsed_entries.push_result(r);
+    }
+    parsed_entries.end_of_file();
+    // Included files are usually few, they are parsed sequentially.
+    load_included_files(&mut parsed_entries, cur_fpath, options);
     Ok(parsed_entries)
 }
 
+/// Settings of the parser that change the parsed entries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParseOptions {
+    pub rounding: Rounding,
+    pub limits: Limits,
+}
+
+/// Caps on the input, so that a corrupted or malicious file is reported instead of exhausting
+/// memory, e.g. in the language server. The defaults are far beyond what real ledgers need.
+/// Statements and includes beyond them are skipped with a `LimitExceeded` error, a file given
+/// directly that is too large is not read at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields, default)]
+pub struct Limits {
+    // Bytes, of each file.
+    pub max_file_size: u64,
+    // Bytes, of each line.
+    pub max_line_length: usize,
+    pub max_postings: usize,
+    // Nested includes below the root file.
+    pub max_include_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_file_size: 1 << 30,
+            max_line_length: 1 << 16,
+            max_postings: 10_000,
+            max_include_depth: 64,
+        }
+    }
+}
+
+impl Limits {
+    pub(crate) fn check_file_size(&self, size: u64, fpath: &Path) -> Result<(), String> {
+        match size > self.max_file_size {
+            true => Err(format!(
+                "{} has {size} bytes, more than the limit of {}",
+                fpath.display(),
+                self.max_file_size
+            )),
+            false => Ok(()),
+        }
+    }
+
+    // The problem with `statement` or its parsed `entry`, if any.
+    fn check_statement(&self, statement: &str, entry: Option<&EntryVariant>) -> Option<String> {
+        if let Some((i, line)) = statement
+            .lines()
+            .enumerate()
+            .find(|(_, l)| l.len() > self.max_line_length)
+        {
+            return Some(format!(
+                "Line {} of the entry has {} bytes, more than the limit of {}",
+                i + 1,
+                line.len(),
+                self.max_line_length
+            ));
+        }
+        match entry {
+            Some(EntryVariant::Transaction(t)) if t.postings.len() > self.max_postings => {
+                Some(format!(
+                    "Transaction with {} postings, more than the limit of {}",
+                    t.postings.len(),
+                    self.max_postings
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The outcome of parsing one statement. There can be an entry and errors at the same time,
+/// if the parser recovered from errors within the statement.
+#[derive(Debug, Clone)]
+pub struct StatementResult {
+    pub entry: Option<EntryVariant>,
+    pub errors: Vec<ParseError>,
+    pub directive: Option<(FileDirective, Span)>,
+}
+
+impl StatementResult {
+    fn limit_exceeded(locator: &Locator, statement: &str, context: String) -> Self {
+        let span = locator.span(statement);
+        StatementResult {
+            entry: None,
+            errors: vec![error_at(&span, ErrorCode::LimitExceeded, context)],
+            directive: None,
+        }
+    }
+}
+
+/// An undated directive, which changes how the file is loaded rather than being an entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileDirective {
+    // `include "other.beancount"`, relative to the including file.
+    Include(String),
+    // `pushtag #trip` adds the tag to the following transactions of the file, until
+    // `poptag #trip`.
+    PushTag(String),
+    PopTag(String),
+    // `plugin "name"` or `plugin "name" "config"`.
+    Plugin(String, Option<String>),
+    // `option "name" "value"`.
+    Option(String, String),
+}
+
+/// A `plugin` directive: the name of a plugin to run on the loaded ledger, either one of the
+/// `core::plugin::PluginRegistry` or a `.wasm` file relative to the file of the directive,
+/// and the configuration to pass to it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginDirective {
+    pub name: String,
+    pub config: Option<String>,
+    pub span: Span,
+}
+
+/// The `option "name" "value"` directives of a ledger, in input order, e.g.
+/// `option "operating_currency" "CHF"`. Beancount reads some options several times, like the
+/// operating currencies, and keeps the last value of the others.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Options {
+    values: Vec<(String, String)>,
+}
+
+impl Options {
+    pub fn insert(&mut self, name: String, value: String) {
+        self.values.push((name, value));
+    }
+
+    /// The last value of `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.iter().rfind(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// All values of `name`, in input order.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.iter().filter(move |(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&str, &str)> {
+        self.values.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.get("title")
+    }
+
+    pub fn operating_currencies(&self) -> Vec<&str> {
+        self.get_all("operating_currency").collect()
+    }
+
+    /// Directories of documents, relative to the ledger.
+    pub fn documents(&self) -> Vec<&str> {
+        self.get_all("documents").collect()
+    }
+}
+
+// `statement` must be a slice of the input of `locator`.
+pub(crate) fn parse_statement(
+    locator: &Locator,
+    statement: &str,
+    options: &ParseOptions,
+) -> StatementResult {
+    if let Some(e) = options.limits.check_statement(statement, None) {
+        return StatementResult::limit_exceeded(locator, statement, e);
+    }
+    let mut parser = StatementParser::new(statement);
+    parser.rounding = options.rounding;
+    if statement_iterator::starts_with_undated_directive(statement) {
+        return match parser.parse_file_directive() {
+            Ok(directive) => StatementResult {
+                entry: None,
+                errors: vec![],
+                directive: Some((directive, locator.span(statement))),
+            },
+            Err(mut e) => {
+                locator.locate(&mut e, statement);
+                StatementResult {
+                    entry: None,
+                    errors: vec![*e],
+                    directive: None,
+                }
+            }
+        };
+    }
+    let entry = match parser.parse_entry() {
+        Ok(mut entry) => {
+            if let Some(e) = options.limits.check_statement(statement, Some(&entry)) {
+                return StatementResult::limit_exceeded(locator, statement, e);
+            }
+            *entry.span_mut() = locator.span(statement);
+            Some(entry)
+        }
+        Err(e) => {
+            parser.recovered.push(*e);
+            None
+        }
+    };
+    let mut errors = parser.recovered;
+    for e in &mut errors {
+        locator.locate(e, statement);
+    }
+    StatementResult {
+        entry,
+        errors,
+        directive: None,
+    }
+}
+
+// Converts positions in an input to file, line and column for spans and error messages.
+pub(crate) struct Locator<'a> {
+    input: &'a str,
+    file: Option<Arc<Path>>,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> Locator<'a> {
+    pub(crate) fn new(input: &'a str, file: &Path) -> Self {
+        Locator {
+            input,
+            file: (!file.as_os_str().is_empty()).then(|| file.into()),
+            line_starts: std::iter::once(0)
+                .chain(input.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+        }
+    }
+
+    // 1-based line and column at which `statement`, a slice of the input, starts.
+    fn position(&self, statement: &str) -> (usize, usize) {
+        let offset = statement.as_ptr() as usize - self.input.as_ptr() as usize;
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let column = self.input[self.line_starts[line - 1]..offset]
+            .chars()
+            .count()
+            + 1;
+        (line, column)
+    }
+
+    fn span(&self, statement: &str) -> Span {
+        let (line, _) = self.position(statement);
+        Span {
+            file: self.file.clone(),
+            lines: line..line + statement.matches('\n').count() + 1,
+            comments: self.comments(statement, line),
+        }
+    }
+
+    // The comments of the statement starting at `line`, including the comment lines above it.
+    fn comments(&self, statement: &str, line: usize) -> Option<Box<Comments>> {
+        let mut comments = Comments::default();
+        for above in (1..line).rev() {
+            let text = self.line(above).trim();
+            if text.starts_with(is_comment_char) {
+                comments.leading.push(text.to_string());
+            } else if !text.is_empty() {
+                break;
+            }
+        }
+        comments.leading.reverse();
+        let mut printed_line = 0;
+        for (i, text) in statement.lines().map(str::trim).enumerate() {
+            if text.starts_with(is_comment_char) {
+                comments.inner.push((printed_line, text.to_string()));
+                continue;
+            } else if text.is_empty() {
+                continue;
+            }
+            printed_line += usize::from(i > 0);
+            if let Some(comment) = line_comment(text) {
+                comments.trailing.push((printed_line, comment.to_string()));
+            }
+        }
+        (comments != Comments::default()).then(|| Box::new(comments))
+    }
+
+    // The text of a 1-based line, without its newline.
+    fn line(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.input.len(), |&i| i - 1);
+        &self.input[start..end]
+    }
+
+    fn locate(&self, e: &mut ParseError, statement: &str) {
+        let (mut line, mut column) = self.position(statement);
+        if let Some(offset) = e.line {
+            line += offset;
+            let text = &self.input[self.line_starts[line - 1]..];
+            column = text.chars().take_while(|c| *c == ' ' || *c == '\t').count() + 1;
+        }
+        e.file = self.file.as_deref().map(Path::to_path_buf);
+        e.line = Some(line);
+        e.column = Some(column);
+    }
+}
+
+// Currencies start with an uppercase letter, unlike numbers and dates, and have no colon,
+// unlike accounts. The booleans of custom entries look like currencies.
+fn is_currency_token(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_uppercase())
+        && !token.contains(':')
+        && !matches!(token, "TRUE" | "FALSE")
+}
+
 pub fn is_comment_char(c: char) -> bool {
     c == ';' || c == '#'
 }
 
+// Whether a comment starts at the front of `rest`. A `#` followed by a tag character starts a
+// tag like `#trip-2024` instead.
+pub(crate) fn starts_comment(rest: &str) -> bool {
+    let mut chars = rest.chars();
+    match chars.next() {
+        Some('#') => !chars.next().is_some_and(is_tag_char),
+        Some(c) => is_comment_char(c),
+        None => false,
+    }
+}
+
+pub(crate) fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || "-_/.".contains(c)
+}
+
+// The comment at the end of `line`, if any. Comment characters in quoted strings are kept.
+fn line_comment(line: &str) -> Option<&str> {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if !quoted && starts_comment(&line[i..]) {
+            return Some(line[i..].trim_end());
+        }
+    }
+    None
+}
+
+// Metadata keys start with a lowercase letter, e.g. `price-source`.
+fn is_metadata_key(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_lowercase())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// A `key: value` metadata line without its indent and comment, None if `line` is something
+// else, e.g. a posting. Used for the metadata of transactions, postings and other entries.
+fn parse_metadata_line(line: &str) -> Option<Result<(String, CustomValue), String>> {
+    let (key, value) = line
+        .split_once(':')
+        .filter(|(key, _)| is_metadata_key(key))?;
+    let mut it = TokenIterator::new(value);
+    let parsed = match parse_value(&mut it) {
+        Ok(Some(_)) if !it.remainder().is_empty() => Err(format!(
+            "Unexpected remaining input in metadata parsing: `{}`",
+            it.remainder()
+        )),
+        Ok(Some(value)) => Ok((key.to_string(), value)),
+        Ok(None) => Err(format!("No value for metadata `{key}`")),
+        Err(e) => Err(e),
+    };
+    Some(parsed.map_err(|e| format!("Unable to parse metadata `{line}`: {e}")))
+}
+
+// A value of a custom entry or of metadata, None at the end of the input.
+fn parse_value(it: &mut TokenIterator) -> Result<Option<CustomValue>, String> {
+    if let Some(s) = it.next_string() {
+        return Ok(Some(CustomValue::String(s.to_string())));
+    }
+    let Some(token) = it.next() else {
+        return Ok(None);
+    };
+    let value = match token {
+        "TRUE" => CustomValue::Bool(true),
+        "FALSE" => CustomValue::Bool(false),
+        _ if token.contains(':') => CustomValue::Account(token.into()),
+        _ if statement_iterator::starts_with_date(token) => CustomValue::Date(
+            token
+                .parse()
+                .map_err(|e| format!("invalid date `{token}`: {e}"))?,
+        ),
+        _ if is_currency_token(token) => CustomValue::Currency(token.into()),
+        _ => {
+            let number = Decimal::from_str_exact(token)
+                .map_err(|e| format!("invalid value `{token}`: {e}"))?;
+            // A number followed by a currency is an amount.
+            let mut peek = TokenIterator::new(it.remainder());
+            match peek.next() {
+                Some(c) if is_currency_token(c) => {
+                    *it = peek;
+                    CustomValue::Amount(Amount::new(number, c))
+                }
+                _ => CustomValue::Number(number),
+            }
+        }
+    };
+    Ok(Some(value))
+}
+
 fn trim_comment_at_end(data: &str) -> &str {
     for (i, c) in data.char_indices().rev() {
         // if we find a newline, then we are done. We can only trim comments on the last line.
         if c == '\n' {
             break;
         }
-        if is_comment_char(c) {
+        if starts_comment(&data[i..]) {
             // found a comment char, trim the string here.
             return &data[..i];
         }
@@ -100,11 +773,11 @@ fn trim_comment_at_end(data: &str) -> &str {
     data
 }
 
-fn date_and_cmd<'a>(statement: &'a str) -> Result<(Date, &'a str, &'a str), String> {
+fn date_and_cmd(statement: &str) -> Result<(Date, &str, &str), String> {
     let (date, remain) = statement
         .trim_start()
         .split_once(' ')
-        .ok_or(format!("No date in entry: {statement}"))?;
+        .ok_or_else(|| format!("No date in entry: {statement}"))?;
     let date: Date = Date::from_str(date).map_err(|e| e.to_string())?;
 
     let cmd;
@@ -132,7 +805,7 @@ fn consume_amount(input: &str) -> Result<(Amount, &str), String> {
     // The number definitely won't contain a letter...
     let currency_start = input
         .find(|c: char| c.is_alphabetic())
-        .ok_or(format!("No currency found: {input}"))?;
+        .ok_or_else(|| format!("No currency found: {input}"))?;
     let currency_end = currency_start
         + input[currency_start..]
             .find(|c: char| !c.is_alphabetic())
@@ -144,16 +817,140 @@ fn consume_amount(input: &str) -> Result<(Amount, &str), String> {
 /// input is a complete entry as a string, it can be multiple lines for eg transactions.
 struct StatementParser<'a> {
     statement: &'a str, // complete statement, can be multiline
+    // Errors the parser recovered from, e.g. a bad posting in an otherwise valid transaction.
+    recovered: Vec<ParseError>,
+    rounding: Rounding,
+}
+
+// Parses one complete statement, e.g. `2024-01-01 open Assets:Cash`, for the `TryFrom<&str>`
+// implementations of the entry types. Errors a posting was dropped for are errors here.
+fn parse_single_statement(statement: &str) -> Result<EntryVariant, String> {
+    let mut parser = StatementParser::new(statement.trim());
+    let entry = parser.parse_entry().map_err(|e| e.context)?;
+    match parser.recovered.into_iter().next() {
+        Some(e) => Err(e.context),
+        None => Ok(entry),
+    }
+}
+
+impl TryFrom<&str> for Open {
+    type Error = String;
+    fn try_from(statement: &str) -> Result<Self, Self::Error> {
+        match parse_single_statement(statement)? {
+            EntryVariant::Open(e) => Ok(e),
+            _ => Err(format!("Expected `open` entry: {statement}")),
+        }
+    }
+}
+
+impl TryFrom<&str> for Close {
+    type Error = String;
+    fn try_from(statement: &str) -> Result<Self, Self::Error> {
+        match parse_single_statement(statement)? {
+            EntryVariant::Close(e) => Ok(e),
+            _ => Err(format!("Expected `close` entry: {statement}")),
+        }
+    }
+}
+
+impl TryFrom<&str> for Balance {
+    type Error = String;
+    fn try_from(statement: &str) -> Result<Self, Self::Error> {
+        match parse_single_statement(statement)? {
+            EntryVariant::Balance(e) => Ok(e),
+            _ => Err(format!("Expected `balance` entry: {statement}")),
+        }
+    }
+}
+
+impl TryFrom<&str> for Commodity {
+    type Error = String;
+    fn try_from(statement: &str) -> Result<Self, Self::Error> {
+        match parse_single_statement(statement)? {
+            EntryVariant::Commodity(e) => Ok(e),
+            _ => Err(format!("Expected `commodity` entry: {statement}")),
+        }
+    }
+}
+
+impl TryFrom<&str> for PriceDirective {
+    type Error = String;
+    fn try_from(statement: &str) -> Result<Self, Self::Error> {
+        match parse_single_statement(statement)? {
+            EntryVariant::Price(e) => Ok(e),
+            _ => Err(format!("Expected `price` entry: {statement}")),
+        }
+    }
+}
+
+impl TryFrom<&str> for Note {
+    type Error = String;
+    fn try_from(statement: &str) -> Result<Self, Self::Error> {
+        match parse_single_statement(statement)? {
+            EntryVariant::Note(e) => Ok(e),
+            _ => Err(format!("Expected `note` entry: {statement}")),
+        }
+    }
+}
+
+impl TryFrom<&str> for Document {
+    type Error = String;
+    fn try_from(statement: &str) -> Result<Self, Self::Error> {
+        match parse_single_statement(statement)? {
+            EntryVariant::Document(e) => Ok(e),
+            _ => Err(format!("Expected `document` entry: {statement}")),
+        }
+    }
+}
+
+impl TryFrom<&str> for Pad {
+    type Error = String;
+    fn try_from(statement: &str) -> Result<Self, Self::Error> {
+        match parse_single_statement(statement)? {
+            EntryVariant::Pad(e) => Ok(e),
+            _ => Err(format!("Expected `pad` entry: {statement}")),
+        }
+    }
+}
+
+impl TryFrom<&str> for Event {
+    type Error = String;
+    fn try_from(statement: &str) -> Result<Self, Self::Error> {
+        match parse_single_statement(statement)? {
+            EntryVariant::Event(e) => Ok(e),
+            _ => Err(format!("Expected `event` entry: {statement}")),
+        }
+    }
+}
+
+impl TryFrom<&str> for Custom {
+    type Error = String;
+    fn try_from(statement: &str) -> Result<Self, Self::Error> {
+        match parse_single_statement(statement)? {
+            EntryVariant::Custom(c) => Ok(c),
+            _ => Err(format!("Expected `custom` entry: {statement}")),
+        }
+    }
 }
 
 impl<'a> StatementParser<'a> {
     pub fn new(statement: &'a str) -> Self {
-        StatementParser { statement }
+        StatementParser {
+            statement,
+            recovered: vec![],
+            rounding: Rounding::default(),
+        }
     }
 
     pub fn parse_entry(&mut self) -> Result<EntryVariant, Box<ParseError>> {
-        let (date, cmd, remain) =
-            date_and_cmd(self.statement).map_err(|e| self.new_parse_err(e))?;
+        if !statement_iterator::starts_with_date(self.statement) {
+            return Err(self.new_parse_err(
+                ErrorCode::StrayText,
+                "Line does not start with a date".to_string(),
+            ));
+        }
+        let (date, cmd, remain) = date_and_cmd(self.statement)
+            .map_err(|e| self.new_parse_err(ErrorCode::InvalidHeader, e))?;
         let remaining = trim_comment_at_end(remain);
         if let Some(flag) = transaction_parsing::parse_flag(cmd) {
             // This is a transaction entry, the rest of the statement is the complete transaction.
@@ -161,24 +958,100 @@ impl<'a> StatementParser<'a> {
                 self.parse_transaction(date, flag, remaining)?,
             ));
         }
-        match cmd {
-            // TODO: Change all of these to use TryFrom instead of parse_xxx functions.
-            "open" => Ok(EntryVariant::Open(self.parse_open(date, remaining)?)),
-            "close" => Ok(EntryVariant::Close(self.parse_close(date, remaining)?)),
-            "balance" => Ok(EntryVariant::Balance(self.parse_balance(date, remaining)?)),
-            "commodity" => Ok(EntryVariant::Commodity(
-                self.parse_commodity(date, remaining)?,
-            )),
-            "price" => Ok(EntryVariant::PriceEntry(self.parse_price(date, remaining)?)),
+        let (remaining, meta_lines) = match remain.split_once('\n') {
+            Some((first, rest)) => (trim_comment_at_end(first), Some(rest)),
+            None => (remaining, None),
+        };
+        // Besides transactions, entries only span several lines with their metadata.
+        let meta = match meta_lines {
+            Some(lines) => self.parse_metadata(lines),
+            None => Metadata::default(),
+        };
+        let mut entry = match cmd {
+            "open" => EntryVariant::Open(self.parse_open(date, remaining)?),
+            "close" => EntryVariant::Close(self.parse_close(date, remaining)?),
+            "balance" => EntryVariant::Balance(self.parse_balance(date, remaining)?),
+            "commodity" => EntryVariant::Commodity(self.parse_commodity(date, remaining)?),
+            "price" => EntryVariant::Price(self.parse_price(date, remaining)?),
+            "note" => EntryVariant::Note(self.parse_note(date, remaining)?),
+            "document" => EntryVariant::Document(self.parse_document(date, remaining)?),
+            "pad" => EntryVariant::Pad(self.parse_pad(date, remaining)?),
+            "event" => EntryVariant::Event(self.parse_event(date, remaining)?),
+            "custom" => EntryVariant::Custom(self.parse_custom(date, remaining)?),
+
+            &_ => {
+                return Err(self.new_parse_err(
+                    ErrorCode::UnknownDirective,
+                    format!("Unknown command `{}` in entry", cmd),
+                ));
+            }
+        };
+        *entry.meta_mut() = meta;
+        Ok(entry)
+    }
 
-            &_ => Err(self.new_parse_err(format!("Unknown command `{}` in entry", cmd))),
+    fn parse_file_directive(&self) -> Result<FileDirective, Box<ParseError>> {
+        if self.statement.contains('\n') {
+            let mut e = self.new_parse_err(
+                ErrorCode::UnexpectedInput,
+                "Unexpected lines after directive".to_string(),
+            );
+            e.line = Some(1);
+            return Err(e);
         }
+        let mut it = TokenIterator::new(self.statement);
+        let keyword = self.get_next_token(&mut it, "directive")?;
+        let directive = if keyword == "include" {
+            let path = it.next_string().ok_or_else(|| {
+                self.new_parse_err(
+                    ErrorCode::MissingToken,
+                    "No quoted file name found".to_string(),
+                )
+            })?;
+            FileDirective::Include(path.to_string())
+        } else if keyword == "plugin" {
+            let name = it.next_string().ok_or_else(|| {
+                self.new_parse_err(
+                    ErrorCode::MissingToken,
+                    "No quoted plugin name found".to_string(),
+                )
+            })?;
+            let config = it.next_string().map(str::to_string);
+            FileDirective::Plugin(name.to_string(), config)
+        } else if keyword == "option" {
+            let mut string = |what| {
+                it.next_string().ok_or_else(|| {
+                    self.new_parse_err(ErrorCode::MissingToken, format!("No quoted {what} found"))
+                })
+            };
+            let name = string("option name")?.to_string();
+            let value = string("option value")?.to_string();
+            FileDirective::Option(name, value)
+        } else {
+            let token = self.get_next_token(&mut it, "tag")?;
+            let tag = token
+                .strip_prefix('#')
+                .filter(|t| !t.is_empty() && t.chars().all(is_tag_char))
+                .ok_or_else(|| {
+                    self.new_parse_err(ErrorCode::InvalidHeader, format!("Invalid tag `{token}`"))
+                })?
+                .to_string();
+            match keyword {
+                "pushtag" => FileDirective::PushTag(tag),
+                _ => FileDirective::PopTag(tag),
+            }
+        };
+        self.err_if_more_tokens(&it, keyword)?;
+        Ok(directive)
     }
-
-    fn new_parse_err(&self, context: String) -> Box<ParseError> {
+    fn new_parse_err(&self, code: ErrorCode, context: String) -> Box<ParseError> {
         Box::new(ParseError {
+            code,
             context,
             failed_statement: self.statement.to_string(),
+            file: None,
+            line: None,
+            column: None,
         })
     }
 
@@ -187,21 +1060,22 @@ impl<'a> StatementParser<'a> {
         token_it: &mut TokenIterator<'a>,
         token_type: &str,
     ) -> Result<&'a str, Box<ParseError>> {
-        let next = token_it
-            .next()
-            .ok_or(self.new_parse_err(format!("No {token_type} found")))?;
+        let next = token_it.next().ok_or_else(|| {
+            self.new_parse_err(ErrorCode::MissingToken, format!("No {token_type} found"))
+        })?;
         Ok(next)
     }
     fn err_if_more_tokens(
         &self,
-        token_it: &mut TokenIterator<'a>,
+        token_it: &TokenIterator<'a>,
         token_type: &str,
     ) -> Result<(), Box<ParseError>> {
-        if let Some(_) = token_it.next() {
-            return Err(self.new_parse_err(format!(
-                "Unexpected remaining input in {token_type} parsing: `{}`",
-                token_it.collect::<Vec<&str>>().join(" ")
-            )));
+        let remainder = token_it.remainder();
+        if !remainder.is_empty() {
+            return Err(self.new_parse_err(
+                ErrorCode::UnexpectedInput,
+                format!("Unexpected remaining input in {token_type} parsing: `{remainder}`"),
+            ));
         }
         Ok(())
     }
@@ -209,8 +1083,8 @@ impl<'a> StatementParser<'a> {
     /// The parse functions returning entry types do not have to update self.remaining, as the parser is done after this.
     fn parse_open(&self, date: Date, remaining: &str) -> Result<Open, Box<ParseError>> {
         let mut it = TokenIterator::new(remaining);
-        let account = self.get_next_token(&mut it, "account")?.to_string();
-        let allowed_currencies: Vec<String> = it.map(|s| s.to_string()).collect();
+        let account = self.get_next_token(&mut it, "account")?.into();
+        let allowed_currencies: Currencies = it.map(|s| s.into()).collect();
 
         Ok(Open {
             date,
@@ -220,107 +1094,504 @@ impl<'a> StatementParser<'a> {
             } else {
                 Some(allowed_currencies)
             },
+            meta: Metadata::default(),
+            span: Span::default(),
         })
     }
 
     fn parse_close(&self, date: Date, remaining: &str) -> Result<Close, Box<ParseError>> {
         let mut it = TokenIterator::new(remaining);
-        let account = self.get_next_token(&mut it, "close")?.to_string();
-        self.err_if_more_tokens(&mut it, "close")?;
-        Ok(Close { date, account })
+        let account = self.get_next_token(&mut it, "close")?.into();
+        self.err_if_more_tokens(&it, "close")?;
+        Ok(Close {
+            date,
+            account,
+            meta: Metadata::default(),
+            span: Span::default(),
+        })
     }
 
     fn parse_commodity(&self, date: Date, remaining: &str) -> Result<Commodity, Box<ParseError>> {
         let commodity = remaining.trim();
         if commodity.is_empty() {
-            return Err(self.new_parse_err("No commodity specified in entry".to_string()));
+            return Err(self.new_parse_err(
+                ErrorCode::MissingToken,
+                "No commodity specified in entry".to_string(),
+            ));
         }
         if commodity.contains(' ') {
-            return Err(self.new_parse_err(format!(
-                "unexpected remaining input in commodity parsing: `{}`",
-                commodity
-            )));
+            return Err(self.new_parse_err(
+                ErrorCode::UnexpectedInput,
+                format!("unexpected remaining input in commodity parsing: `{commodity}`"),
+            ));
         }
         Ok(Commodity {
             date,
-            currency: commodity.to_string(),
+            currency: commodity.into(),
+            meta: Metadata::default(),
+            span: Span::default(),
         })
     }
 
+    // Lines like `  precision: 4` below an entry. Comment lines are skipped.
+    // Lines that fail to parse are reported and skipped, the entry keeps the other keys.
+    fn parse_metadata(&mut self, lines: &'a str) -> Metadata {
+        let mut meta = Metadata::default();
+        for (i, line) in lines.lines().enumerate() {
+            let line = trim_comment_at_end(line).trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let parsed = parse_metadata_line(line)
+                .unwrap_or_else(|| Err(format!("expected `key: value` metadata, found `{line}`")));
+            match parsed {
+                Ok(entry) => meta.0.push(entry),
+                Err(e) => {
+                    let mut e = self.new_parse_err(ErrorCode::UnexpectedInput, e);
+                    e.line = Some(i + 1);
+                    self.recovered.push(*e);
+                }
+            }
+        }
+        meta
+    }
+
     // e.g. a statement like "Assets:Depot:META 1.23 CHF" or "META 1.23 USD"
     fn parse_str_and_price(
         &self,
         remaining: &str,
         token_type: &str,
-    ) -> Result<(String, Amount), Box<ParseError>> {
+    ) -> Result<(Symbol, Amount), Box<ParseError>> {
         let mut it = TokenIterator::new(remaining);
-        let out_str = self.get_next_token(&mut it, token_type)?.to_string();
+        let out_str = self.get_next_token(&mut it, token_type)?.into();
         let amnt_string = self.get_next_token(&mut it, "amount")?;
         let currency = self.get_next_token(&mut it, "currency")?;
-        self.err_if_more_tokens(&mut it, token_type)?;
+        self.err_if_more_tokens(&it, token_type)?;
 
         let number = Decimal::from_str_exact(amnt_string).map_err(|e| {
-            self.new_parse_err(format!(
-                "unable to parse amount number in {token_type} entry: {e}"
-            ))
+            self.new_parse_err(
+                ErrorCode::InvalidAmount,
+                format!("unable to parse amount number in {token_type} entry: {e}"),
+            )
         })?;
 
-        Ok((out_str, Amount::new(number, currency.to_string())))
+        Ok((out_str, Amount::new(number, currency)))
     }
 
     fn parse_balance(&self, date: Date, remaining: &str) -> Result<Balance, Box<ParseError>> {
         let (account, amount) = self.parse_str_and_price(remaining, "balance")?;
+        let (account, recursive) = match account.strip_suffix(":*") {
+            Some(parent) => (parent.into(), true),
+            None => (account, false),
+        };
         Ok(Balance {
             date,
             account,
             amount,
+            recursive,
+            meta: Metadata::default(),
+            span: Span::default(),
         })
     }
 
     // 2024-10-03 price META 1.23 CHF
-    fn parse_price(&self, date: Date, remaining: &str) -> Result<PriceEntry, Box<ParseError>> {
+    fn parse_price(&self, date: Date, remaining: &str) -> Result<PriceDirective, Box<ParseError>> {
         let (currency, amount) = self.parse_str_and_price(remaining, "price")?;
-        Ok(PriceEntry {
+        Ok(PriceDirective {
             date,
             currency,
             amount,
+            meta: Metadata::default(),
+            span: Span::default(),
         })
     }
 
-    fn parse_transaction(
+    fn get_next_string(
         &self,
+        token_it: &mut TokenIterator<'a>,
+        token_type: &str,
+    ) -> Result<&'a str, Box<ParseError>> {
+        token_it.next_string().ok_or_else(|| {
+            self.new_parse_err(
+                ErrorCode::MissingToken,
+                format!("No quoted {token_type} found"),
+            )
+        })
+    }
+
+    fn parse_note(&self, date: Date, remaining: &'a str) -> Result<Note, Box<ParseError>> {
+        let mut it = TokenIterator::new(remaining);
+        let account = self.get_next_token(&mut it, "account")?.into();
+        let comment = self.get_next_string(&mut it, "comment")?.to_string();
+        self.err_if_more_tokens(&it, "note")?;
+        Ok(Note {
+            date,
+            account,
+            comment,
+            meta: Metadata::default(),
+            span: Span::default(),
+        })
+    }
+
+    fn parse_document(&self, date: Date, remaining: &'a str) -> Result<Document, Box<ParseError>> {
+        let mut it = TokenIterator::new(remaining);
+        let account = self.get_next_token(&mut it, "account")?.into();
+        let path = self.get_next_string(&mut it, "path")?.to_string();
+        self.err_if_more_tokens(&it, "document")?;
+        Ok(Document {
+            date,
+            account,
+            path,
+            meta: Metadata::default(),
+            span: Span::default(),
+        })
+    }
+
+    fn parse_pad(&self, date: Date, remaining: &'a str) -> Result<Pad, Box<ParseError>> {
+        let mut it = TokenIterator::new(remaining);
+        let account = self.get_next_token(&mut it, "account")?.into();
+        let source_account = self.get_next_token(&mut it, "source account")?.into();
+        self.err_if_more_tokens(&it, "pad")?;
+        Ok(Pad {
+            date,
+            account,
+            source_account,
+            meta: Metadata::default(),
+            span: Span::default(),
+        })
+    }
+
+    fn parse_event(&self, date: Date, remaining: &'a str) -> Result<Event, Box<ParseError>> {
+        let mut it = TokenIterator::new(remaining);
+        let name = self.get_next_string(&mut it, "event name")?.to_string();
+        let value = self.get_next_string(&mut it, "event value")?.to_string();
+        self.err_if_more_tokens(&it, "event")?;
+        Ok(Event {
+            date,
+            name,
+            value,
+            meta: Metadata::default(),
+            span: Span::default(),
+        })
+    }
+
+    // 2024-01-01 custom "budget" Expenses:Food "monthly" 500 CHF
+    fn parse_custom(&self, date: Date, remaining: &'a str) -> Result<Custom, Box<ParseError>> {
+        let mut it = TokenIterator::new(remaining);
+        let name = self.get_next_string(&mut it, "custom name")?.to_string();
+        let mut values = vec![];
+        while let Some(value) = self.parse_value(&mut it)? {
+            values.push(value);
+        }
+        Ok(Custom {
+            date,
+            name,
+            values,
+            meta: Metadata::default(),
+            span: Span::default(),
+        })
+    }
+
+    fn parse_value(
+        &self,
+        it: &mut TokenIterator<'a>,
+    ) -> Result<Option<CustomValue>, Box<ParseError>> {
+        parse_value(it).map_err(|e| self.new_parse_err(ErrorCode::UnexpectedInput, e))
+    }
+
+    // Postings that fail to parse are reported and dropped, the transaction is kept but flagged,
+    // so a typo doesn't hide the rest of the entry from reports. Metadata lines that fail to
+    // parse are only reported, the postings and the flag stay as they are.
+    fn parse_transaction(
+        &mut self,
         date: Date,
         flag: TransactionFlag,
         statement: &str,
     ) -> Result<Transaction, Box<ParseError>> {
-        Transaction::try_from((date, flag, statement))
-            .map_err(|e| self.new_parse_err(format!("unable to parse transaction: {e}")))
+        let (mut transaction, bad_lines) =
+            transaction_parsing::parse_transaction_recovering(date, flag, statement, self.rounding)
+                .map_err(|e| {
+                    self.new_parse_err(
+                        ErrorCode::InvalidTransaction,
+                        format!("unable to parse transaction: {e}"),
+                    )
+                })?;
+        if bad_lines
+            .iter()
+            .any(|(_, code, _)| *code == ErrorCode::InvalidPosting)
+        {
+            transaction.flag = TransactionFlag::Error;
+        }
+        for (line, code, e) in bad_lines {
+            let mut e = self.new_parse_err(code, e);
+            e.line = Some(line);
+            self.recovered.push(*e);
+        }
+        Ok(transaction)
     }
 }
 
 pub mod error {
-    #[derive(Debug)]
+    use std::path::PathBuf;
+
+    /// Stable identifier of a kind of error, for suppressing it and for referring to it in
+    /// tooling. B00xx are validation errors, B01xx parse errors. Codes are never reused.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+    pub enum ErrorCode {
+        // Postings of a transaction do not sum up to zero.
+        UnbalancedTransaction,
+        // An account used without an open directive, or before it.
+        UnopenedAccount,
+        // A currency without commodity directive, in a ledger that declares commodities.
+        UndeclaredCurrency,
+        // An unbalanced transaction that was kept, with its flag set to `!`.
+        FlaggedUnbalancedTransaction,
+        // A `custom "recur"` directive that cannot be expanded.
+        InvalidRecurrence,
+        // A `custom "rewrite"` directive that is not a valid rule.
+        InvalidRewriteRule,
+        // A `custom "alias"` directive without a name and an account.
+        InvalidAlias,
+        // An account used without an open directive, opened by the auto-open mode.
+        AutoOpenedAccount,
+        // A per-unit value or weight with more decimal places than allowed by the scale guard.
+        ExcessiveScale,
+        // A balance directive that does not match the postings before its date.
+        FailedBalanceAssertion,
+        // A document directive whose file does not exist.
+        MissingDocument,
+        // A file in the documents directory that no document directive refers to.
+        OrphanedDocument,
+        // A plugin that cannot be loaded or failed, or a problem reported by a plugin.
+        PluginError,
+        // A `time` metadata value that is not a time of day.
+        InvalidTime,
+        // A transaction dated after today, reported on request.
+        FutureTransaction,
+        // Spending of the current period above the budget of an account, reported on request.
+        BudgetExceeded,
+        // A `custom "budget"` directive that cannot be understood.
+        InvalidBudget,
+        // Missing or invalid date or command.
+        InvalidHeader,
+        UnknownDirective,
+        // Tokens or lines after a complete entry.
+        UnexpectedInput,
+        // E.g. an account or amount missing from an entry.
+        MissingToken,
+        InvalidAmount,
+        InvalidTransaction,
+        // Text outside of any entry, e.g. before the first dated line.
+        StrayText,
+        // A posting that failed to parse. Its transaction is kept without it.
+        InvalidPosting,
+        // Input that is not valid UTF-8 and was decoded leniently.
+        InvalidEncoding,
+        // A `poptag` without `pushtag`, or a tag still pushed at the end of its file.
+        UnbalancedTag,
+        // An include of a missing file, or of a file that is already being loaded.
+        InvalidInclude,
+        // Input beyond one of the `Limits` of the parser, e.g. an overly long line.
+        LimitExceeded,
+    }
+
+    impl ErrorCode {
+        pub const ALL: [ErrorCode; 29] = [
+            ErrorCode::UnbalancedTransaction,
+            ErrorCode::UnopenedAccount,
+            ErrorCode::UndeclaredCurrency,
+            ErrorCode::FlaggedUnbalancedTransaction,
+            ErrorCode::InvalidRecurrence,
+            ErrorCode::InvalidRewriteRule,
+            ErrorCode::InvalidAlias,
+            ErrorCode::AutoOpenedAccount,
+            ErrorCode::ExcessiveScale,
+            ErrorCode::FailedBalanceAssertion,
+            ErrorCode::MissingDocument,
+            ErrorCode::OrphanedDocument,
+            ErrorCode::PluginError,
+            ErrorCode::InvalidTime,
+            ErrorCode::FutureTransaction,
+            ErrorCode::BudgetExceeded,
+            ErrorCode::InvalidBudget,
+            ErrorCode::InvalidHeader,
+            ErrorCode::UnknownDirective,
+            ErrorCode::UnexpectedInput,
+            ErrorCode::MissingToken,
+            ErrorCode::InvalidAmount,
+            ErrorCode::InvalidTransaction,
+            ErrorCode::StrayText,
+            ErrorCode::InvalidPosting,
+            ErrorCode::InvalidEncoding,
+            ErrorCode::UnbalancedTag,
+            ErrorCode::InvalidInclude,
+            ErrorCode::LimitExceeded,
+        ];
+
+        pub fn as_str(self) -> &'static str {
+            match self {
+                ErrorCode::UnbalancedTransaction => "B0001",
+                ErrorCode::UnopenedAccount => "B0002",
+                ErrorCode::UndeclaredCurrency => "B0003",
+                ErrorCode::FlaggedUnbalancedTransaction => "B0004",
+                ErrorCode::InvalidRecurrence => "B0005",
+                ErrorCode::InvalidRewriteRule => "B0006",
+                ErrorCode::InvalidAlias => "B0007",
+                ErrorCode::AutoOpenedAccount => "B0008",
+                ErrorCode::ExcessiveScale => "B0009",
+                ErrorCode::FailedBalanceAssertion => "B0010",
+                ErrorCode::MissingDocument => "B0011",
+                ErrorCode::OrphanedDocument => "B0012",
+                ErrorCode::PluginError => "B0013",
+                ErrorCode::InvalidTime => "B0014",
+                ErrorCode::FutureTransaction => "B0015",
+                ErrorCode::BudgetExceeded => "B0016",
+                ErrorCode::InvalidBudget => "B0017",
+                ErrorCode::InvalidHeader => "B0101",
+                ErrorCode::UnknownDirective => "B0102",
+                ErrorCode::UnexpectedInput => "B0103",
+                ErrorCode::MissingToken => "B0104",
+                ErrorCode::InvalidAmount => "B0105",
+                ErrorCode::InvalidTransaction => "B0106",
+                ErrorCode::StrayText => "B0107",
+                ErrorCode::InvalidPosting => "B0108",
+                ErrorCode::InvalidEncoding => "B0109",
+                ErrorCode::UnbalancedTag => "B0110",
+                ErrorCode::InvalidInclude => "B0111",
+                ErrorCode::LimitExceeded => "B0112",
+            }
+        }
+
+        pub fn severity(self) -> Severity {
+            match self {
+                ErrorCode::UndeclaredCurrency
+                | ErrorCode::FlaggedUnbalancedTransaction
+                | ErrorCode::AutoOpenedAccount
+                | ErrorCode::ExcessiveScale
+                | ErrorCode::OrphanedDocument
+                | ErrorCode::FutureTransaction
+                | ErrorCode::BudgetExceeded
+                | ErrorCode::InvalidEncoding => Severity::Warning,
+                ErrorCode::UnbalancedTransaction
+                | ErrorCode::UnopenedAccount
+                | ErrorCode::InvalidRecurrence
+                | ErrorCode::InvalidRewriteRule
+                | ErrorCode::InvalidAlias
+                | ErrorCode::FailedBalanceAssertion
+                | ErrorCode::MissingDocument
+                | ErrorCode::PluginError
+                | ErrorCode::InvalidTime
+                | ErrorCode::InvalidBudget
+                | ErrorCode::InvalidHeader
+                | ErrorCode::UnknownDirective
+                | ErrorCode::UnexpectedInput
+                | ErrorCode::MissingToken
+                | ErrorCode::InvalidAmount
+                | ErrorCode::InvalidTransaction
+                | ErrorCode::StrayText
+                | ErrorCode::InvalidPosting
+                | ErrorCode::UnbalancedTag
+                | ErrorCode::InvalidInclude
+                | ErrorCode::LimitExceeded => Severity::Error,
+            }
+        }
+    }
+
+    /// How serious a diagnostic is. Only errors make a ledger fail to check by default.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Severity {
+        Info,
+        Warning,
+        Error,
+    }
+
+    impl std::fmt::Display for Severity {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(match self {
+                Severity::Info => "info",
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            })
+        }
+    }
+
+    impl std::fmt::Display for ErrorCode {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(self.as_str())
+        }
+    }
+
+    impl std::str::FromStr for ErrorCode {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            ErrorCode::ALL
+                .into_iter()
+                .find(|c| c.as_str() == s)
+                .ok_or_else(|| format!("Unknown error code `{s}`"))
+        }
+    }
+
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
     pub struct ParseError {
+        pub code: ErrorCode,
         pub context: String,
         pub failed_statement: String,
+        // Where the error is in its source, if known. Line and column are 1-based, the column
+        // counts characters. Before the error is located, the parser may set `line` to the
+        // offset of the offending line within the statement.
+        pub file: Option<PathBuf>,
+        pub line: Option<usize>,
+        pub column: Option<usize>,
     }
 
     impl std::fmt::Display for ParseError {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-            return write!(
+            match (&self.file, self.line, self.column) {
+                (Some(file), Some(line), Some(column)) => {
+                    write!(f, "{}:{line}:{column}: ", file.display())?
+                }
+                (None, Some(line), Some(column)) => write!(f, "{line}:{column}: ")?,
+                _ => {}
+            }
+            write!(f, "{} ", self.code)?;
+            // Validation errors are about parsed entries, there is no statement to quote.
+            if self.failed_statement.is_empty() {
+                return f.write_str(&self.context);
+            }
+            write!(
                 f,
                 "Failed to parse ({}): `{}`",
                 self.context, self.failed_statement
-            );
+            )
+        }
+    }
+    impl ParseError {
+        /// The error as a JSON object for editors and review tooling, one per line of output.
+        /// `severity` is passed in since callers may promote or demote codes.
+        pub fn to_json(&self, severity: Severity) -> serde_json::Value {
+            serde_json::json!({
+                "code": self.code.as_str(),
+                "severity": severity,
+                "message": self.context,
+                "file": self.file,
+                "line": self.line,
+                "column": self.column,
+                "statement": self.failed_statement,
+            })
         }
     }
+
     impl std::error::Error for ParseError {}
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::io::printer::{print_custom, print_open};
     use jiff::civil::date;
+    use smallvec::smallvec;
 
     #[test]
     fn test_consume_amount() {
@@ -362,7 +1633,7 @@ mod tests {
         .unwrap();
         assert_eq!(entry.date, date(2024, 1, 1));
         assert_eq!(entry.account, "Assets:Depot:META");
-        assert_eq!(entry.allowed_currencies, Some(vec!["META".to_string()]));
+        assert_eq!(entry.allowed_currencies, Some(smallvec!["META".into()]));
         Ok(())
     }
 
@@ -383,14 +1654,14 @@ mod tests {
 
     #[test]
     fn test_parse_open() -> Result<(), String> {
-        let entry = StatementParser { statement: "" }
+        let entry = StatementParser::new("")
             .parse_open(date(2022, 1, 1), "Assets:Depot:META META")
             .unwrap();
         assert_eq!(entry.date, date(2022, 1, 1));
         assert_eq!(entry.account, "Assets:Depot:META");
-        assert_eq!(entry.allowed_currencies, Some(vec!["META".to_string()]));
+        assert_eq!(entry.allowed_currencies, Some(smallvec!["META".into()]));
 
-        let entry = StatementParser { statement: "" }
+        let entry = StatementParser::new("")
             .parse_open(date(2022, 2, 1), "Assets:Depot:Cash")
             .unwrap();
 
@@ -403,19 +1674,27 @@ mod tests {
 
     #[test]
     fn test_parse_close() -> Result<(), String> {
-        let entry = StatementParser { statement: "" }
+        let entry = StatementParser::new("")
             .parse_close(date(2022, 1, 1), "Assets:Depot:META  ")
             .unwrap();
 
         assert_eq!(entry.date, date(2022, 1, 1));
         assert_eq!(entry.account, "Assets:Depot:META");
 
+        let err = StatementParser::new("")
+            .parse_close(date(2022, 1, 1), "Assets:Depot:META  foo bar ; comment")
+            .unwrap_err();
+        assert_eq!(
+            err.context,
+            "Unexpected remaining input in close parsing: `foo bar`"
+        );
+
         Ok(())
     }
 
     #[test]
     fn test_parse_balance() -> Result<(), String> {
-        let entry = StatementParser { statement: "" }
+        let entry = StatementParser::new("")
             .parse_balance(date(2022, 1, 1), "Assets:Depot:META 5 CHF ")
             .unwrap();
 
@@ -424,7 +1703,7 @@ mod tests {
         assert_eq!(entry.amount.number, Decimal::new(5, 0));
         assert_eq!(entry.amount.currency, "CHF");
 
-        let entry = StatementParser { statement: "" }
+        let entry = StatementParser::new("")
             .parse_balance(date(2022, 1, 1), "Assets:Depot -5.123456 CHF")
             .unwrap();
 
@@ -433,16 +1712,14 @@ mod tests {
         assert_eq!(entry.amount.number, Decimal::new(-5123456, 6));
         assert_eq!(entry.amount.currency, "CHF");
 
-        let entry =
-            StatementParser { statement: "" }.parse_balance(date(2022, 1, 1), "Assets:Depot  ");
+        let entry = StatementParser::new("").parse_balance(date(2022, 1, 1), "Assets:Depot  ");
         assert!(entry.is_err());
 
-        let entry =
-            StatementParser { statement: "" }.parse_balance(date(2022, 1, 1), "Assets:Depot 3 ");
+        let entry = StatementParser::new("").parse_balance(date(2022, 1, 1), "Assets:Depot 3 ");
         assert!(entry.is_err());
 
-        let entry = StatementParser { statement: "" }
-            .parse_balance(date(2022, 1, 1), "Assets:Depot usd chf ");
+        let entry =
+            StatementParser::new("").parse_balance(date(2022, 1, 1), "Assets:Depot usd chf ");
         assert!(entry.is_err());
 
         let entry = StatementParser::new("2024-10-03   balance Assets:Depot:Cash 0 CHF")
@@ -468,14 +1745,512 @@ mod tests {
         assert_eq!(entries.len(), 0);
         entries.open.push(Open {
             date: date(2024, 1, 1),
-            account: "Assets:Cash".to_string(),
+            account: "Assets:Cash".into(),
             allowed_currencies: None,
+            meta: Metadata::default(),
+            span: Span::default(),
         });
         assert!(!entries.is_empty());
         assert_eq!(entries.len(), 1);
         Ok(())
     }
 
+    #[test]
+    fn test_parse_error_line() {
+        let input = "2024-01-01 open Assets:Cash\n\n2024-01-02 foo\n2024-01-03 *\n  bar\n  2024-01-04 close Äsßets:Cash X\n";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(entries.open.len(), 1);
+        let lines: Vec<Option<usize>> = entries.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [Some(3), Some(5), Some(6)]);
+        let columns: Vec<Option<usize>> = entries.errors.iter().map(|e| e.column).collect();
+        assert_eq!(columns, [Some(1), Some(3), Some(3)]);
+        // The transaction is kept, flagged, without the bad posting.
+        assert_eq!(entries.transactions.len(), 1);
+        assert_eq!(entries.transactions[0].flag, TransactionFlag::Error);
+        assert_eq!(entries.errors[1].code, ErrorCode::InvalidPosting);
+        assert!(entries.errors[0].file.is_none());
+        assert_eq!(
+            entries.errors[0].to_string(),
+            "3:1: B0102 Failed to parse (Unknown command `foo` in entry): `2024-01-02 foo`"
+        );
+
+        let entries = parse_entries_from_string(input.to_string(), Path::new("main.bean")).unwrap();
+        assert_eq!(
+            entries.errors[2].file.as_deref(),
+            Some(Path::new("main.bean"))
+        );
+        assert!(entries.errors[2].to_string().starts_with("main.bean:6:3: "));
+    }
+
+    #[test]
+    fn test_parse_posting_metadata_errors() {
+        let input = "2024-01-05 * \"Shop\"
+  Assets:Bank -10 CHF
+    settled-in: CHF
+    invoice: 2024 001
+  Expenses:Food 10 CHF
+2024-01-06 open Assets:Cash
+  invoice: 2024 001
+";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        // A bad metadata line neither drops the posting nor flags the transaction.
+        let transaction = &entries.transactions[0];
+        assert_eq!(transaction.flag, TransactionFlag::OK);
+        assert_eq!(transaction.postings.len(), 2);
+        assert_eq!(
+            transaction.postings[0].meta.0,
+            [(
+                "settled-in".to_string(),
+                CustomValue::Currency("CHF".into())
+            )]
+        );
+        let errors: Vec<_> = entries.errors.iter().map(|e| (e.code, e.line)).collect();
+        assert_eq!(
+            errors,
+            [
+                (ErrorCode::UnexpectedInput, Some(4)),
+                (ErrorCode::UnexpectedInput, Some(7)),
+            ]
+        );
+        // Metadata of transactions and other entries is read the same way.
+        assert_eq!(
+            entries.errors[0].context,
+            "Unable to parse metadata `invoice: 2024 001`: \
+             Unexpected remaining input in metadata parsing: `001`"
+        );
+        assert_eq!(entries.errors[1].context, entries.errors[0].context);
+    }
+
+    #[test]
+    fn test_error_codes() {
+        for code in ErrorCode::ALL {
+            assert_eq!(code.as_str().parse::<ErrorCode>(), Ok(code));
+        }
+        assert!("B9999".parse::<ErrorCode>().is_err());
+
+        let input =
+            "2024-01-01 foo\n2024-01-02 close A B\n2024-13-01 open A\n2024-01-03 balance A x CHF\n";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        let codes: Vec<&str> = entries.errors.iter().map(|e| e.code.as_str()).collect();
+        assert_eq!(codes, ["B0102", "B0103", "B0101", "B0105"]);
+    }
+
+    #[test]
+    fn test_error_to_json() {
+        let entries =
+            parse_entries_from_string("2024-01-01 foo\n".to_string(), Path::new("a.bean")).unwrap();
+        assert_eq!(
+            entries.errors[0].to_json(error::Severity::Warning),
+            serde_json::json!({
+                "code": "B0102",
+                "severity": "warning",
+                "message": "Unknown command `foo` in entry",
+                "file": "a.bean",
+                "line": 1,
+                "column": 1,
+                "statement": "2024-01-01 foo",
+            })
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "; Header\n\n# cash\n2024-01-01 open Assets:Cash ; opened\n2024-01-03 * \"Invoice #3\" ; paid\n  Assets:Cash 1 CHF\n  ; inner\n  Income:Salary -1 CHF ; salary\n";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(
+            entries.open[0].span.comments.as_deref(),
+            Some(&Comments {
+                leading: vec!["; Header".into(), "# cash".into()],
+                trailing: vec![(0, "; opened".into())],
+                inner: vec![],
+            })
+        );
+        assert_eq!(
+            entries.transactions[0].span.comments.as_deref(),
+            Some(&Comments {
+                leading: vec![],
+                trailing: vec![(0, "; paid".into()), (2, "; salary".into())],
+                inner: vec![(1, "; inner".into())],
+            })
+        );
+        assert_eq!(
+            entries.transactions[0].narration.as_deref(),
+            Some("Invoice #3")
+        );
+
+        let entries = parse_entries_from_string("2024-01-01 open A:B".into(), Path::new(""));
+        assert_eq!(entries.unwrap().open[0].span.comments, None);
+    }
+
+    #[test]
+    fn test_entry_spans() {
+        let input = "2024-01-01 open Assets:Cash\n\n2024-01-03 *\n  Assets:Cash 1 CHF\n  ; comment\n  Income:Salary -1 CHF\n\n  2024-01-04 close Assets:Cash\n";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("main.bean")).unwrap();
+        let file: Arc<Path> = Path::new("main.bean").into();
+        assert_eq!(
+            entries.open[0].span,
+            Span {
+                file: Some(file.clone()),
+                lines: 1..2,
+                comments: None,
+            }
+        );
+        assert_eq!(entries.transactions[0].span.lines, 3..7);
+        assert_eq!(entries.close[0].span.lines, 8..9);
+        assert!(Arc::ptr_eq(
+            entries.open[0].span.file.as_ref().unwrap(),
+            entries.close[0].span.file.as_ref().unwrap()
+        ));
+
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(entries.open[0].span.file, None);
+    }
+
+    #[test]
+    fn test_parse_other_directives() {
+        let input = r#"2024-01-01 open Assets:Checking
+2024-01-01 pad Assets:Checking Equity:Opening-Balances
+2024-01-02 note Assets:Checking "Called the bank" ; comment
+2024-01-03 document Assets:Checking "statements/2024 01.pdf"
+2024-01-04 event "location" "New York"
+2024-01-05 event "location"
+2024-01-06 note Assets:Checking unquoted
+"#;
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries.pad[0].account, "Assets:Checking");
+        assert_eq!(entries.pad[0].source_account, "Equity:Opening-Balances");
+        assert_eq!(entries.note[0].comment, "Called the bank");
+        assert_eq!(entries.document[0].path, "statements/2024 01.pdf");
+        assert_eq!(
+            (
+                entries.event[0].name.as_str(),
+                entries.event[0].value.as_str()
+            ),
+            ("location", "New York")
+        );
+        let codes: Vec<ErrorCode> = entries.errors.iter().map(|e| e.code).collect();
+        assert_eq!(codes, [ErrorCode::MissingToken, ErrorCode::MissingToken]);
+
+        let ledger = entries.into_ledger();
+        assert_eq!(ledger.for_account("Assets:Checking").len(), 4);
+        assert_eq!(ledger.for_account("Equity:Opening-Balances").len(), 1);
+    }
+
+    #[test]
+    fn test_parse_custom() {
+        let custom = Custom::try_from(
+            "2024-01-01 custom \"budget\" Expenses:Food \"monthly\" 500 CHF TRUE 2024-12-31 12",
+        )
+        .unwrap();
+        assert_eq!(custom.name, "budget");
+        assert_eq!(
+            custom.values,
+            [
+                CustomValue::Account("Expenses:Food".into()),
+                CustomValue::String("monthly".to_string()),
+                CustomValue::Amount(Amount::new(500.into(), "CHF")),
+                CustomValue::Bool(true),
+                CustomValue::Date(date(2024, 12, 31)),
+                CustomValue::Number(12.into()),
+            ]
+        );
+        let err = Custom::try_from("2024-01-01 custom \"budget\" 5,00").unwrap_err();
+        assert!(err.contains("invalid value `5,00`"), "{err}");
+        assert!(Custom::try_from("2024-01-01 custom budget").is_err());
+    }
+
+    #[test]
+    fn test_parse_commodity_metadata() {
+        let commodity = Commodity::try_from(
+            "2024-01-01 commodity AAPL ; stock\n  ; listed in the US\n  precision: 4\n  name: \"Apple Inc.\"\n  price-source: \"yahoo/AAPL\"",
+        )
+        .unwrap();
+        assert_eq!(commodity.currency, "AAPL");
+        assert_eq!(commodity.precision(), Some(4));
+        assert_eq!(commodity.name(), Some("Apple Inc."));
+        assert_eq!(commodity.price_source(), Some("yahoo/AAPL"));
+
+        let mut parser =
+            StatementParser::new("2024-01-01 commodity CHF\n  precision: 2\n  Precision 2");
+        let mut entry = parser.parse_entry().unwrap();
+        assert_eq!(entry.meta_mut().0.len(), 1);
+        let err = &parser.recovered[0];
+        assert_eq!(err.code, ErrorCode::UnexpectedInput);
+        assert_eq!(err.line, Some(2));
+        assert!(Commodity::try_from("2024-01-01 commodity CHF\n  precision:").is_err());
+        assert!(Commodity::try_from("2024-01-01 commodity CHF\n  precision: 2 3").is_err());
+    }
+
+    #[test]
+    fn test_parse_entry_metadata() {
+        let input = "2024-01-01 open Assets:Bank CHF
+  iban: \"CH93 0076 2011 6238 5295 7\"
+  opened-by: Assets:Cash
+2024-01-31 balance Assets:Bank 0 CHF
+  statement: 2024-01-31 ; from the PDF
+2024-01-01 custom \"budget\" Expenses:Food \"monthly\" 500 CHF
+  shared: TRUE
+";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert!(entries.errors.is_empty());
+        let open = &entries.open[0];
+        assert_eq!(
+            open.meta.get("opened-by"),
+            Some(&CustomValue::Account("Assets:Cash".into()))
+        );
+        assert_eq!(
+            print_open(open),
+            "2024-01-01 open Assets:Bank CHF\n    iban: \"CH93 0076 2011 6238 5295 7\"\n    opened-by: Assets:Cash"
+        );
+        assert_eq!(
+            entries.balance[0].meta.get("statement"),
+            Some(&CustomValue::Date(jiff::civil::date(2024, 1, 31)))
+        );
+        assert_eq!(
+            print_custom(&entries.custom[0]),
+            "2024-01-01 custom \"budget\" Expenses:Food \"monthly\" 500 CHF\n    shared: TRUE"
+        );
+        assert!(Pad::try_from("2024-01-01 pad Assets:Bank Equity:Opening\n  source").is_err());
+
+        // A bad line is reported at its line, the entry keeps the valid keys.
+        let input = "2024-01-01 commodity USD
+  quote: CHF
+  name: US Dollar
+  precision: 2
+";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        let commodity = &entries.commodity[0];
+        assert_eq!(
+            commodity.meta.get("quote"),
+            Some(&CustomValue::Currency("CHF".into()))
+        );
+        assert_eq!(commodity.meta.0.len(), 2);
+        let errors: Vec<_> = entries.errors.iter().map(|e| (e.code, e.line)).collect();
+        assert_eq!(errors, [(ErrorCode::UnexpectedInput, Some(3))]);
+    }
+
+    #[test]
+    fn test_pushtag() {
+        let input = "pushtag #trip\n2024-01-01 * \"Hotel\" #trip #paris\n  Assets:Cash -1 CHF\n  Expenses:Hotel 1 CHF\npoptag #trip\n2024-01-02 * \"Lunch\"\n  Assets:Cash -1 CHF\n  Expenses:Food 1 CHF\npoptag #trip\npushtag #work\npushtag bad\n";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(entries.transactions[0].tags, ["trip", "paris"]);
+        assert!(entries.transactions[1].tags.is_empty());
+        let errors: Vec<_> = entries
+            .errors
+            .iter()
+            .map(|e| (e.code, e.line.unwrap()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (ErrorCode::UnbalancedTag, 9),
+                (ErrorCode::InvalidHeader, 11),
+                (ErrorCode::UnbalancedTag, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_options() {
+        let input = "option \"title\" \"Household\"
+option \"operating_currency\" \"CHF\"
+option \"operating_currency\" \"EUR\"
+option \"title\" \"Family\" ; renamed
+option \"documents\"
+2024-01-01 open Assets:Cash
+";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(entries.open.len(), 1);
+        assert_eq!(entries.options.title(), Some("Family"));
+        assert_eq!(entries.options.operating_currencies(), ["CHF", "EUR"]);
+        assert!(entries.options.documents().is_empty());
+        assert_eq!(entries.options.get("booking_method"), None);
+        assert_eq!(entries.options.iter().count(), 4);
+        assert_eq!(entries.errors.len(), 1);
+        assert_eq!(entries.errors[0].code, ErrorCode::MissingToken);
+        assert_eq!(entries.errors[0].line, Some(5));
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("beanrust-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(
+            dir.join("main.beancount"),
+            "pushtag #trip\ninclude \"sub/a.beancount\"\n2024-01-02 * \"Main\"\n  Assets:Cash -1 CHF\n  Expenses:Food 1 CHF\npoptag #trip\ninclude \"missing.beancount\"\n",
+        )
+        .unwrap();
+        // Relative to the including file, and not pushed by the include site.
+        std::fs::write(
+            dir.join("sub/a.beancount"),
+            "include \"../main.beancount\"\n2024-01-01 * \"Included\"\n  Assets:Cash -1 CHF\n  Expenses:Food 1 CHF\n",
+        )
+        .unwrap();
+
+        let entries = parse_entries_from_file(&dir.join("main.beancount")).unwrap();
+        let narrations: Vec<_> = entries
+            .transactions
+            .iter()
+            .map(|t| (t.narration.as_deref().unwrap(), t.tags.clone()))
+            .collect();
+        assert_eq!(
+            narrations,
+            [("Main", vec!["trip".to_string()]), ("Included", vec![])]
+        );
+        assert_eq!(entries.includes, [dir.join("sub/a.beancount")]);
+        let errors: Vec<_> = entries
+            .errors
+            .iter()
+            .map(|e| (e.code, e.file.clone().unwrap(), e.line.unwrap()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (ErrorCode::InvalidInclude, dir.join("sub/a.beancount"), 1),
+                (ErrorCode::InvalidInclude, dir.join("main.beancount"), 7),
+            ]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_limits() {
+        let dir = std::env::temp_dir().join(format!("beanrust-limits-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // `b.beancount` includes `a.beancount` two levels below the root, one more than allowed.
+        std::fs::write(dir.join("a.beancount"), "include \"b.beancount\"\n").unwrap();
+        std::fs::write(dir.join("b.beancount"), "include \"a.beancount\"\n").unwrap();
+        let options = ParseOptions {
+            limits: Limits {
+                max_line_length: 40,
+                max_postings: 2,
+                max_include_depth: 1,
+                ..Limits::default()
+            },
+            ..ParseOptions::default()
+        };
+        let input = format!(
+            "2024-01-01 * \"{}\"
+  Assets:Cash -1 CHF
+  Expenses:Food 1 CHF
+2024-01-02 *
+  Assets:Cash -2 CHF
+  Expenses:Food 1 CHF
+  Expenses:Drinks 1 CHF
+2024-01-03 *
+  Assets:Cash -1 CHF
+  Expenses:Food 1 CHF
+include \"b.beancount\"
+",
+            "x".repeat(40)
+        );
+        let entries =
+            parse_entries_from_string_with(input, &dir.join("main.beancount"), &options).unwrap();
+        assert_eq!(entries.transactions.len(), 1);
+        let errors: Vec<_> = entries.errors.iter().map(|e| (e.code, e.line)).collect();
+        assert_eq!(
+            errors,
+            [
+                (ErrorCode::LimitExceeded, Some(1)),
+                (ErrorCode::LimitExceeded, Some(4)),
+                (ErrorCode::LimitExceeded, Some(1)),
+            ]
+        );
+        let nested = &entries.errors[2];
+        assert_eq!(nested.file, Some(dir.join("b.beancount")));
+        assert_eq!(
+            nested.context,
+            "Cannot include `a.beancount`: more than 1 nested includes"
+        );
+        assert_eq!(entries.includes, [dir.join("b.beancount")]);
+
+        let small = ParseOptions {
+            limits: Limits {
+                max_file_size: 10,
+                ..Limits::default()
+            },
+            ..ParseOptions::default()
+        };
+        let Err(e) = parse_entries_from_file_with(&dir.join("a.beancount"), &small) else {
+            panic!("expected the file to be too large");
+        };
+        assert!(e.to_string().contains("more than the limit of 10"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_str() {
+        let open = Open::try_from("2024-01-01 open Assets:Cash CHF").unwrap();
+        assert_eq!(open.account, "Assets:Cash");
+        let close = Close::try_from("2024-01-02 close Assets:Cash").unwrap();
+        assert_eq!(close.date, jiff::civil::date(2024, 1, 2));
+        let balance = Balance::try_from("2024-01-02 balance Assets:Cash 5 CHF").unwrap();
+        assert_eq!(balance.amount, Amount::new(5.into(), "CHF"));
+        assert!(!balance.recursive);
+        let balance = Balance::try_from("2024-01-02 balance Assets:Broker:* 5 CHF").unwrap();
+        assert_eq!(
+            (&*balance.account, balance.recursive),
+            ("Assets:Broker", true)
+        );
+        assert_eq!(
+            crate::io::printer::print_balance(&balance),
+            "2024-01-02 balance Assets:Broker:* 5 CHF"
+        );
+        assert!(Commodity::try_from("2024-01-01 commodity CHF").is_ok());
+        let price = PriceDirective::try_from("2024-01-01 price META 500 CHF").unwrap();
+        assert_eq!(price.currency, "META");
+        assert!(Note::try_from("2024-01-01 note Assets:Cash \"x\"").is_ok());
+        assert!(Document::try_from("2024-01-01 document Assets:Cash \"a.pdf\"").is_ok());
+        assert!(Pad::try_from("2024-01-01 pad Assets:Cash Equity:Opening").is_ok());
+        assert!(Event::try_from("2024-01-01 event \"a\" \"b\"").is_ok());
+        assert!(Custom::try_from("2024-01-01 custom \"a\"").is_ok());
+
+        assert_eq!(
+            Open::try_from("2024-01-01 close Assets:Cash").unwrap_err(),
+            "Expected `open` entry: 2024-01-01 close Assets:Cash"
+        );
+        assert_eq!(
+            Close::try_from("2024-01-01 close").unwrap_err(),
+            "No close found"
+        );
+    }
+
+    #[test]
+    fn test_parse_stray_lines() {
+        let input =
+            "title \"Test\"\n2024-01-01 open Assets:Cash\n  foo\n2024-01-02 close Assets:Cash\n";
+        let entries = parse_entries_from_string(input.to_string(), Path::new("")).unwrap();
+        assert_eq!(entries.close.len(), 1);
+        let lines: Vec<Option<usize>> = entries.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [Some(1), Some(3)]);
+        let codes: Vec<ErrorCode> = entries.errors.iter().map(|e| e.code).collect();
+        assert_eq!(codes, [ErrorCode::StrayText, ErrorCode::UnexpectedInput]);
+        assert_eq!(entries.errors[0].context, "Line does not start with a date");
+        assert_eq!(
+            entries.errors[1].context,
+            "expected `key: value` metadata, found `foo`"
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parse_entries_parallel() {
+        let input = "2024-01-01 open Assets:Cash\n2024-01-02 foo\n2024-01-03 *\n  Assets:Cash 1 CHF\n  Income:Salary -1 CHF\n2024-01-04 close Assets:Cash\n2024-01-05 *\n  Assets:Cash 2 CHF\n  Income:Salary -2 CHF\n".repeat(50);
+        let sequential = parse_entries_from_string(input.clone(), Path::new("")).unwrap();
+        let parallel =
+            parse_entries_from_string_parallel(input, Path::new(""), &ParseOptions::default())
+                .unwrap();
+        assert_eq!(parallel.open.len(), sequential.open.len());
+        assert_eq!(parallel.close.len(), sequential.close.len());
+        assert_eq!(parallel.transactions.len(), 100);
+        let dates = |e: &ParsedEntries| e.transactions.iter().map(|t| t.date).collect::<Vec<_>>();
+        assert_eq!(dates(&parallel), dates(&sequential));
+        let lines = |e: &ParsedEntries| e.errors.iter().map(|e| e.line).collect::<Vec<_>>();
+        assert_eq!(lines(&parallel), lines(&sequential));
+        assert_eq!(parallel.symbols.len(), sequential.symbols.len());
+    }
+
     #[test]
     fn test_date_and_cmd() {
         let (d, cmd, remain) = date_and_cmd("2024-01-01 open Assets:Cash").unwrap();
@@ -0,0 +1,424 @@
+use super::statement_iterator::StatementIterator;
+use super::{
+    Locator, ParseOptions, ParsedEntries, StatementResult, load_included_files, parse_statement,
+};
+use crate::core::types::{Comments, EntryVariant};
+use crate::io::printer;
+use jiff::civil::Date;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+pub struct ParsedStatement {
+    // Byte range of the statement in the source text.
+    pub range: Range<usize>,
+    pub result: StatementResult,
+}
+
+/// What an edit of an `IncrementalParser` re-parsed: the statements at `start..start + added`
+/// replaced `removed`, which covered `lines` of the text before the edit. The statements after
+/// them moved by `line_delta` lines.
+pub struct StatementEdit {
+    pub start: usize,
+    pub removed: Vec<ParsedStatement>,
+    pub added: usize,
+    pub lines: Range<usize>,
+    pub line_delta: isize,
+}
+
+/// Keeps a source text together with the parse result of each of its statements, so that an
+/// edit only re-parses the statements around the edited byte range. Meant for watch and
+/// editor modes, where the text changes in small steps.
+pub struct IncrementalParser {
+    text: String,
+    // The file of the text, which its spans and errors refer to, empty if there is none.
+    path: PathBuf,
+    statements: Vec<ParsedStatement>,
+}
+
+impl IncrementalParser {
+    pub fn new(text: String) -> Self {
+        IncrementalParser::for_file(text, Path::new(""))
+    }
+
+    /// Like `new`, for the text of the file at `path`, e.g. a document open in an editor.
+    pub fn for_file(text: String, path: &Path) -> Self {
+        let statements = parse_window(&text, 0..text.len(), path);
+        IncrementalParser {
+            text,
+            path: path.to_path_buf(),
+            statements,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn statements(&self) -> &[ParsedStatement] {
+        &self.statements
+    }
+
+    /// Replaces `range` of the text with `replacement` and re-parses the affected statements.
+    /// Returns the number of statements that were parsed again.
+    ///
+    /// Panics if `range` is out of bounds or not on char boundaries, like `String::replace_range`.
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> usize {
+        self.edit_statements(range, replacement).added
+    }
+
+    /// Like `edit`, but returns the statements it replaced and where, e.g. to update a ledger
+    /// built from the previous statements.
+    pub fn edit_statements(&mut self, range: Range<usize>, replacement: &str) -> StatementEdit {
+        // The statements directly before and after the edit are always re-parsed as well: the
+        // edit may add continuation lines to the previous statement, or join the next one.
+        let first_touched = self
+            .statements
+            .partition_point(|s| s.range.end < range.start);
+        let lo = first_touched.saturating_sub(1);
+        let after_edit = self
+            .statements
+            .partition_point(|s| s.range.start <= range.end);
+        let hi = (after_edit + 1).min(self.statements.len());
+
+        let window_start = match self.statements.get(lo) {
+            Some(s) if lo > 0 => line_start(&self.text, s.range.start),
+            _ => 0,
+        };
+        let window_end = if hi < self.statements.len() {
+            self.statements[hi - 1].range.end
+        } else {
+            self.text.len()
+        };
+
+        // 1-based, the line after the window is the first one not re-parsed.
+        let first_line = self.text[..window_start].matches('\n').count() + 1;
+        let lines = first_line..self.text[..window_end].matches('\n').count() + 2;
+        let removed_lines = self.text[range.clone()].matches('\n').count();
+        let added_lines = replacement.matches('\n').count();
+        let delta = replacement.len() as isize - range.len() as isize;
+        self.text.replace_range(range, replacement);
+
+        let new_window_end = (window_end as isize + delta) as usize;
+        let reparsed = parse_window(&self.text, window_start..new_window_end, &self.path);
+        let added = reparsed.len();
+
+        let shift_line = |l: usize| l + added_lines - removed_lines;
+        for s in &mut self.statements[hi..] {
+            s.range.start = (s.range.start as isize + delta) as usize;
+            s.range.end = (s.range.end as isize + delta) as usize;
+            if let Some(entry) = &mut s.result.entry {
+                let lines = &mut entry.span_mut().lines;
+                *lines = shift_line(lines.start)..shift_line(lines.end);
+            }
+            for e in &mut s.result.errors {
+                e.line = e.line.map(shift_line);
+            }
+        }
+        StatementEdit {
+            start: lo,
+            removed: self.statements.splice(lo..hi, reparsed).collect(),
+            added,
+            lines,
+            line_delta: added_lines as isize - removed_lines as isize,
+        }
+    }
+
+    /// Inserts the printed `entry` after the last entry dated on or before it, as a paragraph
+    /// of its own. The rest of the text is left untouched. Returns the byte range of the
+    /// printed entry.
+    pub fn insert_entry(&mut self, entry: &EntryVariant) -> Range<usize> {
+        self.insert_entry_after(entry, entry.date())
+    }
+
+    /// Like `insert_entry`, but after the last entry of the month of `entry`, so that entries
+    /// added later in a month don't end up between the ones added before.
+    pub fn insert_entry_in_month(&mut self, entry: &EntryVariant) -> Range<usize> {
+        self.insert_entry_after(entry, entry.date().last_of_month())
+    }
+
+    // Inserts `entry` after the last entry dated on or before `until`.
+    fn insert_entry_after(&mut self, entry: &EntryVariant, until: Date) -> Range<usize> {
+        let printed = printer::print_entry(entry);
+        let previous = self
+            .statements
+            .iter()
+            .rfind(|s| s.result.entry.as_ref().is_some_and(|e| e.date() <= until));
+        let (pos, before, after) = match (previous, self.statements.first()) {
+            (Some(s), _) => {
+                let pos = line_end(&self.text, s.range.end);
+                match self.text[..pos].ends_with('\n') {
+                    true => (pos, "\n", "\n"),
+                    false => (pos, "\n\n", "\n"),
+                }
+            }
+            (None, Some(s)) => (line_start(&self.text, s.range.start), "", "\n\n"),
+            (None, None) => (self.text.len(), "", "\n"),
+        };
+        self.edit(pos..pos, &format!("{before}{printed}{after}"));
+        let start = pos + before.len();
+        start..start + printed.len()
+    }
+
+    /// Replaces the statement at `index` with the printed `entry`. Comment lines above the
+    /// statement are not part of it and stay as they are.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn replace_entry(&mut self, index: usize, entry: &EntryVariant) -> Range<usize> {
+        let range = self.statements[index].range.clone();
+        let mut entry = entry.clone();
+        if let Some(comments) = &mut entry.span_mut().comments {
+            **comments = Comments {
+                leading: vec![],
+                ..std::mem::take(comments)
+            };
+        }
+        let printed = printer::print_entry(&entry);
+        self.edit(range.clone(), &printed);
+        range.start..range.start + printed.len()
+    }
+
+    /// Removes the lines of the statement at `index`. Comment lines above it are kept.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove_entry(&mut self, index: usize) {
+        let range = self.statements[index].range.clone();
+        let end = line_end(&self.text, range.end);
+        self.edit(line_start(&self.text, range.start)..end, "");
+    }
+
+    pub fn to_parsed_entries(&self) -> ParsedEntries {
+        let mut entries = ParsedEntries::default();
+        for s in &self.statements {
+            entries.push_result(s.result.clone());
+        }
+        entries.end_of_file();
+        entries
+    }
+
+    /// Like `to_parsed_entries`, followed by the entries of the files the text includes, which
+    /// are parsed in full every time.
+    pub fn to_parsed_entries_with_includes(&self) -> ParsedEntries {
+        let mut entries = self.to_parsed_entries();
+        load_included_files(&mut entries, &self.path, &ParseOptions::default());
+        entries
+    }
+}
+
+fn parse_window(text: &str, window: Range<usize>, path: &Path) -> Vec<ParsedStatement> {
+    let locator = Locator::new(text, path);
+    StatementIterator::new(&text[window])
+        .map(|s| {
+            let start = s.as_ptr() as usize - text.as_ptr() as usize;
+            ParsedStatement {
+                range: start..start + s.len(),
+                result: parse_statement(&locator, s, &ParseOptions::default()),
+            }
+        })
+        .collect()
+}
+
+fn line_start(text: &str, pos: usize) -> usize {
+    text[..pos].rfind('\n').map_or(0, |i| i + 1)
+}
+
+// Position after the newline ending the line at `pos`, or the end of the text.
+fn line_end(text: &str, pos: usize) -> usize {
+    text[pos..].find('\n').map_or(text.len(), |i| pos + i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEDGER: &str = "2024-01-01 open Assets:Cash
+; comment
+
+2024-01-02 *
+  Assets:Cash 5 CHF
+  Income:Salary -5 CHF
+2024-01-03 close Assets:Cash
+  2024-01-04 commodity CHF
+";
+
+    // Asserts that the incremental state matches parsing the current text from scratch.
+    fn assert_matches_full_parse(parser: &IncrementalParser) {
+        let full = IncrementalParser::new(parser.text().to_string());
+        let summary = |p: &IncrementalParser| {
+            p.statements()
+                .iter()
+                .map(|s| {
+                    let lines = s.result.entry.as_ref().map(|e| e.span().lines.clone());
+                    let errors: Vec<_> = s.result.errors.iter().map(|e| e.line).collect();
+                    (s.range.clone(), lines, errors)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summary(parser), summary(&full), "text: {:?}", parser.text());
+    }
+
+    #[test]
+    fn test_new() {
+        let parser = IncrementalParser::new(LEDGER.to_string());
+        let ranges: Vec<&str> = parser
+            .statements()
+            .iter()
+            .map(|s| &LEDGER[s.range.clone()])
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                "2024-01-01 open Assets:Cash",
+                "2024-01-02 *\n  Assets:Cash 5 CHF\n  Income:Salary -5 CHF",
+                "2024-01-03 close Assets:Cash",
+                "2024-01-04 commodity CHF",
+            ]
+        );
+        assert_eq!(parser.to_parsed_entries().transactions.len(), 1);
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut parser = IncrementalParser::new(LEDGER.to_string());
+
+        // Change an amount inside the transaction.
+        let pos = parser.text().find("5 CHF").unwrap();
+        assert_eq!(parser.edit(pos..pos + 1, "7"), 3);
+        assert_matches_full_parse(&parser);
+        let entries = parser.to_parsed_entries();
+        assert_eq!(entries.transactions[0].postings[0].amount.number, 7.into());
+
+        // Break a posting, the error line must be right.
+        let pos = parser.text().find("Income").unwrap();
+        parser.edit(pos..pos, "foo ");
+        assert_matches_full_parse(&parser);
+        assert_eq!(parser.statements()[1].result.errors[0].line, Some(6));
+
+        // Break the last statement, then insert a statement at the top: the error line of
+        // the untouched last statement moves down.
+        let pos = parser.text().find("commodity CHF").unwrap() + "commodity CHF".len();
+        parser.edit(pos..pos, " USD");
+        assert_eq!(parser.statements()[3].result.errors[0].line, Some(8));
+        assert_eq!(parser.edit(0..0, "2024-01-01 open Income:Salary\n"), 3);
+        assert_matches_full_parse(&parser);
+        assert_eq!(parser.statements().len(), 5);
+        assert_eq!(parser.statements()[4].result.errors[0].line, Some(9));
+
+        // Insert a statement into the comment gap.
+        let pos = parser.text().find("; comment").unwrap();
+        parser.edit(pos..pos + 1, "2024-01-01 open Expenses:Food\n;");
+        assert_matches_full_parse(&parser);
+        assert_eq!(parser.statements().len(), 6);
+
+        // Append a transaction at the end.
+        let end = parser.text().len();
+        parser.edit(end..end, "2024-02-01 *\n  Assets:Cash 1 CHF\n");
+        assert_matches_full_parse(&parser);
+
+        // Delete everything up to the transaction.
+        let pos = parser.text().find("2024-01-02 *").unwrap();
+        parser.edit(0..pos, "");
+        assert_matches_full_parse(&parser);
+
+        parser.edit(0..parser.text().len(), "");
+        assert!(parser.statements().is_empty());
+    }
+
+    #[test]
+    fn test_edit_statements() {
+        let mut parser = IncrementalParser::new(LEDGER.to_string());
+        // A posting added to the transaction re-parses it with its neighbours, on lines 1 to 7.
+        let pos = parser.text().find("  Income").unwrap();
+        let edit = parser.edit_statements(pos..pos, "  Assets:Cash 0 CHF\n");
+        assert_eq!((edit.start, edit.removed.len(), edit.added), (0, 3, 3));
+        assert_eq!((edit.lines, edit.line_delta), (1..8, 1));
+        let removed = edit.removed[1].result.entry.as_ref().unwrap();
+        assert_eq!(removed.span().lines, 4..7);
+        assert_matches_full_parse(&parser);
+    }
+
+    #[test]
+    fn test_entry_edits() {
+        let input = "; Accounts\n2024-01-01   open Assets:Cash ; cash\n\n2024-01-05 *  \"Shop\"\n  Assets:Cash   -5 CHF\n  Expenses:Food  5 CHF\n";
+        let mut parser = IncrementalParser::new(input.to_string());
+        let close = EntryVariant::Close("2024-01-03 close Assets:Cash".try_into().unwrap());
+        let range = parser.insert_entry(&close);
+        assert_eq!(&parser.text()[range], "2024-01-03 close Assets:Cash");
+        assert_eq!(
+            parser.text(),
+            "; Accounts\n2024-01-01   open Assets:Cash ; cash\n\n2024-01-03 close Assets:Cash\n\n2024-01-05 *  \"Shop\"\n  Assets:Cash   -5 CHF\n  Expenses:Food  5 CHF\n"
+        );
+        assert_matches_full_parse(&parser);
+
+        // Entries before all others go to the top, after the leading comments.
+        let open = EntryVariant::Open("2023-12-01 open Expenses:Food".try_into().unwrap());
+        parser.insert_entry(&open);
+        assert!(
+            parser
+                .text()
+                .starts_with("; Accounts\n2023-12-01 open Expenses:Food\n\n2024-01-01   open")
+        );
+        assert_matches_full_parse(&parser);
+
+        // Replacing keeps the comments, and the formatting of all other entries.
+        let mut entry = parser.statements()[1].result.entry.clone().unwrap();
+        if let EntryVariant::Open(open) = &mut entry {
+            open.allowed_currencies = Some(["CHF".into()].into_iter().collect());
+        }
+        parser.replace_entry(1, &entry);
+        assert!(parser.text().starts_with("; Accounts\n2023-12-01 open Expenses:Food\n\n2024-01-01 open Assets:Cash CHF ; cash\n\n2024-01-03 close"));
+        assert!(
+            parser.text().ends_with(
+                "2024-01-05 *  \"Shop\"\n  Assets:Cash   -5 CHF\n  Expenses:Food  5 CHF\n"
+            )
+        );
+        assert_matches_full_parse(&parser);
+
+        parser.remove_entry(2);
+        assert!(!parser.text().contains("close"));
+        assert_matches_full_parse(&parser);
+
+        let mut parser = IncrementalParser::new(String::new());
+        parser.insert_entry(&close);
+        assert_eq!(parser.text(), "2024-01-03 close Assets:Cash\n");
+
+        let note = EntryVariant::Note(
+            "2024-01-02 note Assets:Cash \"Counted\""
+                .try_into()
+                .unwrap(),
+        );
+        parser.insert_entry_in_month(&note);
+        assert!(
+            parser
+                .text()
+                .contains("close Assets:Cash\n\n2024-01-02 note")
+        );
+        assert_matches_full_parse(&parser);
+    }
+
+    #[test]
+    fn test_for_file() {
+        let dir = std::env::temp_dir().join(format!("beanrust-incremental-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("accounts.beancount"),
+            "2024-01-01 open Assets:Bank\n",
+        )
+        .unwrap();
+        let path = dir.join("main.beancount");
+        let mut parser = IncrementalParser::for_file(LEDGER.to_string(), &path);
+        let pos = parser.text().find("; comment").unwrap();
+        parser.edit(pos..pos, "include \"accounts.beancount\"\n");
+        let entries = parser.to_parsed_entries_with_includes();
+        assert!(entries.errors.is_empty(), "{:?}", entries.errors);
+        let files: Vec<_> = entries
+            .open
+            .iter()
+            .map(|o| o.span.file.as_deref())
+            .collect();
+        assert_eq!(
+            files,
+            [Some(path.as_path()), Some(&*dir.join("accounts.beancount"))]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+use super::statement_iterator::starts_with_date;
+use super::{is_comment_char, starts_comment};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Date,
+    // Lowercase words such as `open` or `balance`.
+    Keyword,
+    // Transaction and posting flags, `*` and `!`.
+    Flag,
+    Account,
+    Currency,
+    Number,
+    // A double quoted string, including its quotes.
+    String,
+    // A comment, from its comment character up to the end of the line.
+    Comment,
+    // A tag such as `#trip`, including the `#`.
+    Tag,
+    // A link such as `^invoice-42`, including the `^`.
+    Link,
+    // `@`, `@@`, `{`, `}`, `{{`, `}}` and `,`.
+    Punctuation,
+    Newline,
+    // Anything else, e.g. a misspelled account or an unterminated string.
+    Other,
+}
+
+/// A token and its position, as byte offsets into the lexed input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Range<usize>,
+}
+
+/// Splits input into tokens for syntax highlighting and precise edits. Works on single lines as
+/// well as whole files, never fails and skips whitespace other than newlines.
+pub struct Lexer<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Lexer { input, position: 0 }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let rest = &self.input[self.position..];
+        let rest_trimmed = rest.trim_start_matches(|c: char| c.is_whitespace() && c != '\n');
+        let start = self.position + rest.len() - rest_trimmed.len();
+        let first = rest_trimmed.chars().next()?;
+        let (kind, len) = match first {
+            '\n' => (TokenKind::Newline, 1),
+            _ if starts_comment(rest_trimmed) => (
+                TokenKind::Comment,
+                rest_trimmed.find('\n').unwrap_or(rest_trimmed.len()),
+            ),
+            '#' => (TokenKind::Tag, word_len(rest_trimmed)),
+            '^' => (TokenKind::Link, word_len(rest_trimmed)),
+            '"' => match rest_trimmed[1..].find(['"', '\n']) {
+                Some(i) if rest_trimmed.as_bytes()[i + 1] == b'"' => (TokenKind::String, i + 2),
+                _ => (TokenKind::Other, word_len(rest_trimmed)),
+            },
+            _ => {
+                let punctuation = ["@@", "{{", "}}", "@", "{", "}", ","]
+                    .into_iter()
+                    .find(|p| rest_trimmed.starts_with(p));
+                match punctuation {
+                    Some(p) => (TokenKind::Punctuation, p.len()),
+                    None => {
+                        let len = word_len(rest_trimmed);
+                        (classify(&rest_trimmed[..len]), len)
+                    }
+                }
+            }
+        };
+        self.position = start + len;
+        Some(Token {
+            kind,
+            span: start..self.position,
+        })
+    }
+}
+
+// Length of the word at the start of `s`. Words end at whitespace, comments and punctuation.
+fn word_len(s: &str) -> usize {
+    s.char_indices()
+        .skip(1)
+        .find(|&(_, c)| c.is_whitespace() || is_comment_char(c) || "@{},".contains(c))
+        .map_or(s.len(), |(i, _)| i)
+}
+
+fn classify(word: &str) -> TokenKind {
+    let first = word.chars().next().unwrap_or_default();
+    if word.len() == 10 && starts_with_date(word) {
+        TokenKind::Date
+    } else if word == "*" || word == "!" {
+        TokenKind::Flag
+    } else if word.chars().all(|c| c.is_ascii_lowercase()) {
+        TokenKind::Keyword
+    } else if (first.is_ascii_digit() || "+-.".contains(first))
+        && word.chars().any(|c| c.is_ascii_digit())
+        && word[1..]
+            .chars()
+            .all(|c| c.is_ascii_digit() || ".,".contains(c))
+    {
+        TokenKind::Number
+    } else if first.is_uppercase() && word.contains(':') && !word.split(':').any(str::is_empty) {
+        TokenKind::Account
+    } else if first.is_ascii_uppercase()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || "'._-".contains(c))
+    {
+        TokenKind::Currency
+    } else {
+        TokenKind::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lex(input: &str) -> Vec<(TokenKind, &str)> {
+        Lexer::new(input)
+            .map(|t| (t.kind, &input[t.span]))
+            .collect()
+    }
+
+    #[test]
+    fn test_lexer() {
+        use TokenKind::*;
+        assert_eq!(
+            lex("2024-01-01 * \"Some shop\" \"Food\" ; lunch\n  Assets:Cash  -5.50 CHF @@ 6 USD\n"),
+            [
+                (Date, "2024-01-01"),
+                (Flag, "*"),
+                (String, "\"Some shop\""),
+                (String, "\"Food\""),
+                (Comment, "; lunch"),
+                (Newline, "\n"),
+                (Account, "Assets:Cash"),
+                (Number, "-5.50"),
+                (Currency, "CHF"),
+                (Punctuation, "@@"),
+                (Number, "6"),
+                (Currency, "USD"),
+                (Newline, "\n"),
+            ]
+        );
+        assert_eq!(
+            lex("2024-01-01 open Assets:Cash CHF,USD"),
+            [
+                (Date, "2024-01-01"),
+                (Keyword, "open"),
+                (Account, "Assets:Cash"),
+                (Currency, "CHF"),
+                (Punctuation, ","),
+                (Currency, "USD"),
+            ]
+        );
+        assert_eq!(
+            lex("  Assets:Depot 1 AMD {10 CHF}"),
+            [
+                (Account, "Assets:Depot"),
+                (Number, "1"),
+                (Currency, "AMD"),
+                (Punctuation, "{"),
+                (Number, "10"),
+                (Currency, "CHF"),
+                (Punctuation, "}"),
+            ]
+        );
+        assert_eq!(
+            lex("\"unterminated X:"),
+            [(Other, "\"unterminated"), (Other, "X:")]
+        );
+        assert_eq!(
+            lex("\"Trip\" #trip-2024 ^hotel # note"),
+            [
+                (String, "\"Trip\""),
+                (Tag, "#trip-2024"),
+                (Link, "^hotel"),
+                (Comment, "# note")
+            ]
+        );
+        assert_eq!(lex(" \t "), []);
+    }
+}
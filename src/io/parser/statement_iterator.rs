@@ -1,39 +1,29 @@
 use super::trim_comment_at_end;
-use regex::Regex;
 
+/// Splits a ledger into statements in a single pass over its lines. A statement starts at a
+/// line beginning with a date or an undated directive like `include`, and spans the following
+/// lines up to the next such line, leaving out blank and comment lines at its end. Lines before
+/// the first statement line are returned as a statement of their own, which then fails to parse.
 pub struct StatementIterator<'a> {
     data: &'a str,
 
     line_iterator: LineIterator<'a>,
 
-    new_statement_matcher: Regex,
-    new_multiline_statement_matcher: Regex,
-
-    state: IteratorState,
+    // Starting line found while reading the previous statement, it starts the next one.
+    pending: Option<(usize, usize)>,
 }
 
+/// Splits a single line into whitespace separated tokens, ignoring a trailing comment.
 pub struct TokenIterator<'a> {
-    inner: std::iter::Filter<std::str::SplitWhitespace<'a>, fn(&'_ &str) -> bool>,
-}
-
-enum IteratorState {
-    SearchingNextStart,
-    ReadingMultiline(usize), // position of the start of the multiline entry
-    FinishedMultilineFoundSingle((usize, usize)), // (start, end) of the next single/multiline entry
+    remaining: &'a str,
 }
 
 impl<'a> StatementIterator<'a> {
     pub fn new(data: &'a str) -> Self {
-        let new_statement_matcher = regex::Regex::new(r"^\d{4}-\d{2}-\d{2}.*").unwrap();
-        let new_multiline_statement_matcher =
-            regex::Regex::new(r"^\d{4}-\d{2}-\d{2} +\*.*").unwrap();
-
         StatementIterator {
             data,
             line_iterator: LineIterator::new(data),
-            new_statement_matcher,
-            new_multiline_statement_matcher,
-            state: IteratorState::SearchingNextStart,
+            pending: None,
         }
     }
 }
@@ -42,71 +32,26 @@ impl<'a> Iterator for StatementIterator<'a> {
     type Item = &'a str;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // first match only handles searching next start. In case its a multiline, we need
-        // to do more work later on.
-        match self.state {
-            IteratorState::SearchingNextStart => {
-                loop {
-                    let (start, end) = self.line_iterator.next()?;
-                    let line = &self.data[start..end].trim();
-                    if skip_line(line) {
-                        continue;
-                    }
-                    if self.new_multiline_statement_matcher.is_match(line) {
-                        self.state = IteratorState::ReadingMultiline(start);
-                        // Break out of loop & goto multiline handling after this if statement.
-                        break;
-                    }
-
-                    if self.new_statement_matcher.is_match(line) {
-                        // state remains SearchingNextStart
-                        return Some(line);
-                    } else {
-                        panic!("Unhandled line: {}", line);
-                    }
-                }
+        // (start, end) of the statement read so far, without surrounding whitespace.
+        let mut statement = self.pending.take();
+        for (line_start, line_end) in self.line_iterator.by_ref() {
+            let raw = &self.data[line_start..line_end];
+            let line = raw.trim_ascii();
+            if skip_line(line) {
+                continue;
             }
-            _ => {}
-        }
-
-        match self.state {
-            IteratorState::SearchingNextStart => unreachable!(),
-            IteratorState::ReadingMultiline(start_pos) => {
-                let mut end_pos = start_pos;
-                loop {
-                    let (line_start, line_end) = match self.line_iterator.next() {
-                        Some(l) => l,
-                        None => {
-                            // End of data reached, return the multiline entry.
-                            self.state = IteratorState::SearchingNextStart;
-                            return Some(&self.data[start_pos..self.data.len()]);
-                        }
-                    };
-                    let line = &self.data[line_start..line_end].trim();
-                    if skip_line(line) {
-                        continue;
-                    }
-                    // if we find either a new single, or a multi line entry, then we are finished with the current entry
-                    if self.new_multiline_statement_matcher.is_match(line) {
-                        self.state = IteratorState::ReadingMultiline(line_start);
-                        return Some(&self.data[start_pos..end_pos]);
-                    }
-
-                    if self.new_statement_matcher.is_match(line) {
-                        self.state =
-                            IteratorState::FinishedMultilineFoundSingle((line_start, line_end));
-                        return Some(&self.data[start_pos..end_pos]);
-                    }
-
-                    // Continue reading the multiline entry.
-                    end_pos = line_end;
+            let start = line.as_ptr() as usize - self.data.as_ptr() as usize;
+            let end = start + line.len();
+            match statement {
+                Some(_) if starts_with_date(line) || starts_with_undated_directive(raw) => {
+                    self.pending = Some((start, end));
+                    break;
                 }
-            }
-            IteratorState::FinishedMultilineFoundSingle((start, end)) => {
-                self.state = IteratorState::SearchingNextStart;
-                return Some(&self.data[start..end]);
+                Some((statement_start, _)) => statement = Some((statement_start, end)),
+                None => statement = Some((start, end)),
             }
         }
+        statement.map(|(start, end)| &self.data[start..end])
     }
 }
 
@@ -124,19 +69,46 @@ impl<'a> LineIterator<'a> {
 impl<'a> TokenIterator<'a> {
     pub fn new(data: &'a str) -> Self {
         Self {
-            inner: trim_comment_at_end(data)
-                .split_whitespace()
-                .filter(|s| !s.is_empty()),
+            remaining: trim_comment_at_end(data),
         }
     }
+
+    // The input not consumed yet, without surrounding whitespace. Borrowed from the input, so
+    // error messages can quote it without collecting the remaining tokens.
+    pub fn remainder(&self) -> &'a str {
+        self.remaining.trim()
+    }
+
+    // The contents of the double quoted string at the front, which may contain whitespace.
+    // None, without consuming anything, if the next token is not a complete quoted string.
+    pub fn next_string(&mut self) -> Option<&'a str> {
+        let rest = self.remaining.trim_start().strip_prefix('"')?;
+        let end = rest.find('"')?;
+        if rest[end + 1..].starts_with(|c: char| !c.is_whitespace()) {
+            return None;
+        }
+        self.remaining = &rest[end + 1..];
+        Some(&rest[..end])
+    }
 }
 
 impl<'a> Iterator for TokenIterator<'a> {
     type Item = &'a str;
 
-    // TODO: handle comments at end of line
+    // Newlines count as whitespace, a comment runs until the end of its line.
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next()
+        let mut rest = self.remaining.trim_start();
+        while super::starts_comment(rest) {
+            rest = rest.find('\n').map_or("", |i| rest[i..].trim_start());
+        }
+        if rest.is_empty() {
+            self.remaining = rest;
+            return None;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let (token, rest) = rest.split_at(end);
+        self.remaining = rest;
+        Some(token)
     }
 }
 
@@ -166,7 +138,32 @@ impl<'a> Iterator for LineIterator<'a> {
 }
 
 fn skip_line(line: &str) -> bool {
-    line.is_empty() || super::is_comment_char(line.chars().next().unwrap()) || line.starts_with('*')
+    match line.as_bytes().first() {
+        None => true,
+        Some(&c) => super::is_comment_char(c as char) || c == b'*',
+    }
+}
+
+// Whether `line` starts with a date like 2024-01-31.
+pub(super) fn starts_with_date(line: &str) -> bool {
+    let bytes = line.as_bytes();
+    bytes.len() >= 10
+        && bytes[4] == b'-'
+        && bytes[7] == b'-'
+        && [0, 1, 2, 3, 5, 6, 8, 9]
+            .iter()
+            .all(|&i| bytes[i].is_ascii_digit())
+}
+
+// Whether `line` starts with a directive without date, like `include "other.beancount"`.
+// Unlike dated lines, these must not be indented.
+pub(super) fn starts_with_undated_directive(line: &str) -> bool {
+    ["include", "option", "plugin", "pushtag", "poptag"]
+        .iter()
+        .any(|keyword| {
+            line.strip_prefix(keyword)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        })
 }
 
 #[cfg(test)]
@@ -213,7 +210,7 @@ foo bar3
         assert_eq!(iterator.next(), Some("2024-10-04 *\nfoo bar3"));
         assert_eq!(
             iterator.next(),
-            Some("  2024-01-01 close Assets:Depot ; some comment here * * ")
+            Some("2024-01-01 close Assets:Depot ; some comment here * *")
         );
 
         assert_eq!(iterator.next(), None);
@@ -222,37 +219,54 @@ foo bar3
     }
 
     #[test]
-    fn test_regex_multiline() -> Result<(), String> {
-        let multi_positive = vec![
-            "2024-10-04 *",
-            "2024-10-04 * \"some text\"",
-            "2024-10-04   * \"some text\" ; comments",
-            "2024-10-04   *\"some text\"   \"some text\" #comments",
-        ];
-        let multi_negative = vec![
-            "2024-10-04 close Foo:Bar",
-            "; 2024-10-04 * ",
-            "# 2024-10-04 * ",
-            "2024-10-04 close Foo:Bar ; comments * important *",
-            "****2024-10-04 close Foo:Bar ; comments * important *",
-        ];
-        let it = StatementIterator::new("");
-        for line in multi_positive {
-            assert!(
-                it.new_multiline_statement_matcher.is_match(line),
-                "line should match: `{}`",
-                line
-            );
-        }
-        for line in multi_negative {
-            assert!(
-                !it.new_multiline_statement_matcher.is_match(line),
-                "line should NOT match: `{}`",
-                line
-            );
-        }
+    fn test_statement_iterator_stray_lines() {
+        let data = "foo\nbar\n2024-01-01 open Assets:Cash\n  baz\n; comment\n\n2024-01-02 *\n";
+        let statements: Vec<&str> = StatementIterator::new(data).collect();
+        assert_eq!(
+            statements,
+            [
+                "foo\nbar",
+                "2024-01-01 open Assets:Cash\n  baz",
+                "2024-01-02 *"
+            ]
+        );
 
-        Ok(())
+        // A transaction without postings must not swallow the next statement.
+        let statements: Vec<&str> =
+            StatementIterator::new("2024-01-01 *\n2024-01-02 close A").collect();
+        assert_eq!(statements, ["2024-01-01 *", "2024-01-02 close A"]);
+
+        assert_eq!(StatementIterator::new(" \n; only comments\n").next(), None);
+    }
+
+    #[test]
+    fn test_statement_iterator_undated_directives() {
+        let data = "pushtag #trip\n2024-01-01 *\n  Assets:Cash 1 CHF\npoptag #trip\ninclude \"a.bean\"\n  includes\n";
+        let statements: Vec<&str> = StatementIterator::new(data).collect();
+        assert_eq!(
+            statements,
+            [
+                "pushtag #trip",
+                "2024-01-01 *\n  Assets:Cash 1 CHF",
+                "poptag #trip",
+                "include \"a.bean\"\n  includes"
+            ]
+        );
+        assert!(starts_with_undated_directive("include"));
+        assert!(!starts_with_undated_directive("  include \"a.bean\""));
+        assert!(!starts_with_undated_directive("includes"));
+    }
+
+    #[test]
+    fn test_starts_with_date() {
+        assert!(starts_with_date("2024-10-04 *"));
+        assert!(starts_with_date("2024-10-04 close Foo:Bar"));
+        assert!(starts_with_date("2024-10-04"));
+        assert!(!starts_with_date("; 2024-10-04 * "));
+        assert!(!starts_with_date("2024-1-04 close Foo:Bar"));
+        assert!(!starts_with_date("2024/10/04 close Foo:Bar"));
+        assert!(!starts_with_date("Assets:Cash 5 CHF"));
+        assert!(!starts_with_date(""));
     }
 
     #[test]
@@ -299,6 +313,23 @@ foo bar3
             vec!["5.123478", "USD"]
         );
 
+        let mut iterator = TokenIterator::new(" foo  bar baz ; comment");
+        assert_eq!(iterator.next(), Some("foo"));
+        assert_eq!(iterator.remainder(), "bar baz");
+        assert_eq!(iterator.by_ref().count(), 2);
+        assert_eq!(iterator.remainder(), "");
+
+        let mut iterator = TokenIterator::new("\"location\" \"New York\" \"a\"b");
+        assert_eq!(iterator.next_string(), Some("location"));
+        assert_eq!(iterator.next_string(), Some("New York"));
+        assert_eq!(iterator.next_string(), None);
+        assert_eq!(iterator.remainder(), "\"a\"b");
+
+        assert_eq!(
+            TokenIterator::new("foo ; comment\n\tbar\r\n ; more\n  baz\n").collect::<Vec<_>>(),
+            vec!["foo", "bar", "baz"]
+        );
+
         Ok(())
     }
 }
@@ -1,10 +1,13 @@
-use super::{consume_amount, date_and_cmd};
+use super::{
+    consume_amount, date_and_cmd, error::ErrorCode, parse_metadata_line,
+    statement_iterator::starts_with_date,
+};
 use crate::{
     core::types::*,
     io::parser::{TokenIterator, trim_comment_at_end},
 };
 use jiff::civil::Date;
-use regex::Regex;
+use rust_decimal::Decimal;
 
 impl TryFrom<&str> for Transaction {
     type Error = String;
@@ -13,7 +16,7 @@ impl TryFrom<&str> for Transaction {
 
         Transaction::try_from((
             date,
-            parse_flag(flag).ok_or(format!("Invalid flag: {flag}"))?,
+            parse_flag(flag).ok_or_else(|| format!("Invalid flag: {flag}"))?,
             remain,
         ))
     }
@@ -22,44 +25,53 @@ impl TryFrom<&str> for Transaction {
 impl TryFrom<&str> for Posting {
     type Error = String;
     fn try_from(input: &str) -> Result<Self, Self::Error> {
+        Posting::try_from((input, Rounding::default()))
+    }
+}
+
+// Total prices and costs (`@@`, `{{}}`) are divided into per-unit values with `rounding`.
+impl TryFrom<(&str, Rounding)> for Posting {
+    type Error = String;
+    fn try_from((input, rounding): (&str, Rounding)) -> Result<Self, Self::Error> {
         // we assume comments were trimmed at call site.
-        // Format is <account> <amount> [@|@@ <price>] [{<cost>}|{{<cost>}}]
+        // Format is [<flag>] <account> <amount> [@|@@ <price>] [{<cost>}|{{<cost>}}]
+        let flagged = input
+            .split_once(' ')
+            .and_then(|(flag, rest)| Some((parse_flag(flag)?, rest.trim_start())));
+        let (flag, input) = match flagged {
+            Some((flag, rest)) => (Some(flag), rest),
+            None => (None, input),
+        };
         let (acc, remain) = input
             .split_once(' ')
-            .ok_or(format!("No account in posting: {input}"))?;
+            .ok_or_else(|| format!("No amount in posting: {input}"))?;
         let (amount, remain) = consume_amount(remain)?;
         let (price, cost) = parse_price_and_cost(remain)?;
-        let price = price.map(|p| {
-            if p.per_unit {
-                p.data
-            } else {
-                Price {
-                    amount: p.data.amount / amount.number.abs(),
-                    ..p.data
-                }
-            }
-        });
-        let cost = cost.map(|c| {
-            if c.per_unit {
-                c.data
-            } else {
-                let c = match c.data {
-                    CostType::Known(c) => Some(c),
-                    _ => None,
-                }
-                .unwrap();
-                CostType::Known(Cost {
-                    amount: c.amount / amount.number.abs(),
-                    ..c
-                })
-            }
-        });
+        let per_unit = |total: Amount| -> Result<Amount, String> {
+            let number = rounding.divide(total.number, amount.number.abs())?;
+            Ok(Amount::new(number, total.currency))
+        };
+        let price = match price {
+            Some(p) if !p.per_unit => Some(PriceAnnotation {
+                amount: per_unit(p.data.amount)?,
+            }),
+            p => p.map(|p| p.data),
+        };
+        let cost = match cost.map(|c| (c.per_unit, c.data)) {
+            Some((false, CostType::Known(cost))) => Some(CostType::Known(Cost {
+                amount: per_unit(cost.amount)?,
+                ..cost
+            })),
+            c => c.map(|(_, data)| data),
+        };
 
         Ok(Posting {
-            account: acc.to_string(),
+            flag,
+            account: acc.into(),
             amount,
             price,
             cost,
+            meta: Metadata::default(),
         })
     }
 }
@@ -68,52 +80,200 @@ impl TryFrom<(Date, TransactionFlag, &str)> for Transaction {
     type Error = String;
     fn try_from(input: (Date, TransactionFlag, &str)) -> Result<Self, Self::Error> {
         let (date, flag, statement) = input;
-        let (header, postings_str) = statement.split_once('\n').unwrap_or((statement, ""));
-        let (payee, narration) = parse_narration_and_payee(header.trim())?;
-
-        // Parse postings:
-        let mut postings: Vec<Posting> = vec![];
-        for line in postings_str.lines() {
-            let sanitized = trim_comment_at_end(line).trim();
-            if !sanitized.is_empty() {
-                let posting = Posting::try_from(sanitized)
-                    .map_err(|e| format!("Unable to parse posting '{line}': {e}"))?;
-                postings.push(posting);
+        let (transaction, bad_lines) =
+            parse_transaction_recovering(date, flag, statement, Rounding::default())?;
+        match bad_lines.into_iter().next() {
+            Some((_, _, e)) => Err(e),
+            None => Ok(transaction),
+        }
+    }
+}
+
+// A line of a transaction that failed to parse: the offset of the line in the statement,
+// `ErrorCode::InvalidPosting` for postings or `ErrorCode::UnexpectedInput` for metadata, and
+// the error.
+pub(super) type BadLine = (usize, ErrorCode, String);
+
+// Parses a transaction, skipping postings and metadata lines that fail to parse, which are
+// returned as well. Only an invalid header fails the transaction. A posting without an amount
+// gets the amount that balances the others, one posting per currency if there are several,
+// as in beancount.
+pub(super) fn parse_transaction_recovering(
+    date: Date,
+    flag: TransactionFlag,
+    statement: &str,
+    rounding: Rounding,
+) -> Result<(Transaction, Vec<BadLine>), String> {
+    let (header, postings_str) = statement.split_once('\n').unwrap_or((statement, ""));
+    let (payee, narration, tags, links) = parse_header(header.trim())?;
+
+    let mut meta = Metadata::default();
+    let mut postings = Postings::new();
+    let mut bad_lines = vec![];
+    // Indentation of the last posting line and whether it parsed. Metadata indented deeper
+    // belongs to that posting, and is dropped with it if it failed.
+    let mut last_posting = None;
+    // The index, line offset and line of the posting without an amount.
+    let mut elided = None;
+    for (i, line) in postings_str.lines().enumerate() {
+        let sanitized = trim_comment_at_end(line).trim();
+        let indent = line.len() - line.trim_start().len();
+        if let Some(entry) = parse_metadata_line(sanitized) {
+            let owner = match last_posting {
+                Some((posting_indent, parsed)) if indent > posting_indent => {
+                    postings.last_mut().filter(|_| parsed).map(|p| &mut p.meta)
+                }
+                _ => Some(&mut meta),
+            };
+            match entry {
+                Ok(entry) => {
+                    if let Some(owner) = owner {
+                        owner.0.push(entry)
+                    }
+                }
+                Err(e) => bad_lines.push((i + 1, ErrorCode::UnexpectedInput, e)),
+            }
+        } else if !sanitized.is_empty() {
+            let posting = match elided_posting(sanitized) {
+                Some(_) if elided.is_some() => Err("only one posting can omit its amount".into()),
+                Some((flag, account)) => {
+                    elided = Some((postings.len(), i + 1, line));
+                    // The amount is filled in once all postings are known.
+                    Ok(Posting {
+                        flag,
+                        account: account.into(),
+                        amount: Amount::new(0.into(), ""),
+                        price: None,
+                        cost: None,
+                        meta: Metadata::default(),
+                    })
+                }
+                None => Posting::try_from((sanitized, rounding)),
+            };
+            last_posting = Some((indent, posting.is_ok()));
+            match posting {
+                Ok(posting) => postings.push(posting),
+                Err(e) => bad_lines.push((
+                    i + 1,
+                    ErrorCode::InvalidPosting,
+                    format!("Unable to parse posting '{line}': {e}"),
+                )),
             }
         }
+    }
 
-        Ok(Transaction {
-            date,
-            flag,
-            payee,
-            narration,
-            postings,
-        })
+    if let Some((index, line_offset, line)) = elided {
+        let posting = postings.remove(index);
+        match residual(&postings) {
+            Ok(amounts) => {
+                for (i, amount) in amounts.into_iter().enumerate() {
+                    let posting = Posting {
+                        amount,
+                        ..posting.clone()
+                    };
+                    postings.insert(index + i, posting);
+                }
+            }
+            Err(e) => bad_lines.push((
+                line_offset,
+                ErrorCode::InvalidPosting,
+                format!("Unable to infer the amount of posting '{line}': {e}"),
+            )),
+        }
+        bad_lines.sort_by_key(|(line, _, _)| *line);
     }
+
+    let transaction = Transaction {
+        date,
+        flag,
+        payee,
+        narration,
+        tags,
+        links,
+        meta,
+        postings,
+        span: Span::default(),
+    };
+    Ok((transaction, bad_lines))
+}
+
+// A posting line without an amount, e.g. `! Assets:Cash`: its flag and account.
+fn elided_posting(input: &str) -> Option<(Option<TransactionFlag>, &str)> {
+    let mut tokens = input.split_whitespace();
+    let (flag, account) = match (tokens.next()?, tokens.next()) {
+        (flag, Some(account)) => (Some(parse_flag(flag)?), account),
+        (account, None) => (None, account),
+    };
+    let is_account = account.starts_with(|c: char| c.is_ascii_uppercase()) && account.contains(':');
+    (tokens.next().is_none() && is_account).then_some((flag, account))
+}
+
+// The amounts that balance `postings`, leaving out currencies that already balance unless all
+// of them do.
+fn residual(postings: &[Posting]) -> Result<Vec<Amount>, String> {
+    let weights = postings
+        .iter()
+        .map(Posting::weight)
+        .collect::<Option<Vec<_>>>()
+        .ok_or("the cost of another posting is not known yet")?;
+    let totals = sum_by_currency(&weights);
+    if totals.is_empty() {
+        return Err("no other posting to balance".to_string());
+    }
+    let unbalanced = totals.values().any(|n| !n.is_zero());
+    Ok(totals
+        .into_iter()
+        .filter(|(_, number)| !unbalanced || !number.is_zero())
+        .map(|(currency, number)| Amount::new(Decimal::ZERO - number, currency))
+        .collect())
 }
 
 pub fn parse_flag(s: &str) -> Option<TransactionFlag> {
     match s {
         "*" => Some(TransactionFlag::OK),
         "!" => Some(TransactionFlag::Error),
+        "P" => Some(TransactionFlag::Padding),
         _ => None,
     }
 }
 
-fn parse_narration_and_payee(header: &str) -> Result<(Option<String>, Option<String>), String> {
+// Payee, narration, tags and links of a transaction header, e.g.
+// `"Shop" "Food" #trip ^receipt-12`.
+type Header = (Option<String>, Option<String>, Vec<String>, Vec<String>);
+
+fn parse_header(header: &str) -> Result<Header, String> {
+    let mut tokens = TokenIterator::new(header);
     let mut first = None;
     let mut second = None;
-    for token in TokenIterator::new(header) {
-        if !token.starts_with('"') && !token.ends_with('"') {
+    let mut tags = vec![];
+    let mut links = vec![];
+    while !tokens.remainder().is_empty() {
+        let rest = tokens.remainder();
+        if rest.starts_with(['#', '^']) {
+            let token = tokens.next().unwrap_or_default();
+            let name = token[1..].to_string();
+            let names = match rest.starts_with('#') {
+                true => &mut tags,
+                false => &mut links,
+            };
+            // Repeated tags and links are kept once.
+            if !names.contains(&name) {
+                names.push(name);
+            }
+            continue;
+        }
+        if !tags.is_empty() || !links.is_empty() {
             return Err(format!(
-                "Invalid transaction header: {header}. Narration/payee must be quoted"
+                "Invalid transaction header: {header}. Tags and links must follow narration and payee"
             ));
         }
-        let trimmed = token.trim_matches('"');
+        let s = tokens.next_string().ok_or_else(|| {
+            format!("Invalid transaction header: {header}. Narration/payee must be quoted")
+        })?;
         if first.is_none() {
-            first = Some(trimmed.to_string());
+            first = Some(s.to_string());
         } else if second.is_none() {
-            second = Some(trimmed.to_string());
+            second = Some(s.to_string());
         } else {
             return Err(format!(
                 "Too many quoted strings in transaction header: {header}"
@@ -121,9 +281,9 @@ fn parse_narration_and_payee(header: &str) -> Result<(Option<String>, Option<Str
         }
     }
     if second.is_some() {
-        Ok((first, second))
+        Ok((first, second, tags, links))
     } else {
-        Ok((None, first))
+        Ok((None, first, tags, links))
     }
 }
 
@@ -133,56 +293,167 @@ struct Parsed<T> {
     per_unit: bool,
 }
 
-fn parse_price_and_cost(
-    input: &str,
-) -> Result<(Option<Parsed<Price>>, Option<Parsed<CostType>>), String> {
-    let input = input.trim();
-    if input.is_empty() {
-        return Ok((None, None));
+type ParsedPriceAndCost = (Option<Parsed<PriceAnnotation>>, Option<Parsed<CostType>>);
+
+// Parses the optional price and cost following the amount of a posting, in either order:
+//   [@ <amount> | @@ <amount>] [{ [<amount>] } | {{ [<amount>] }}]
+// Empty braces leave the cost to be determined from the lots of the account.
+fn parse_price_and_cost(input: &str) -> Result<ParsedPriceAndCost, String> {
+    let mut parser = PriceCostParser {
+        input,
+        tokens: PriceCostTokens { remaining: input }.peekable(),
+    };
+    let mut price = parser.price()?;
+    let cost = parser.cost()?;
+    if price.is_none() {
+        price = parser.price()?;
     }
-    let amnt_regex = r"(\d+.*\w+)";
-    let reg = Regex::new(
-        &format!(r"^((\@ *(?P<unitpr>{amnt_regex}))|(\@\@ *(?P<totpr>{amnt_regex})))? *((\{{ *(?P<unitcost>{amnt_regex})\}})|(\{{\{{ *(?P<totcost>{amnt_regex} *)\}}\}}))?$",
-    )).unwrap();
-
-    let mut price = None;
-    let mut cost = None;
-    for capture in reg.captures_iter(input) {
-        if let Some(unit_price) = capture.name("unitpr") {
-            price = Some(Parsed::<Price> {
-                data: Price {
-                    amount: unit_price.as_str().try_into()?,
-                },
-                per_unit: true,
-            });
-        } else if let Some(tot_price) = capture.name("totpr") {
-            price = Some(Parsed::<Price> {
-                data: Price {
-                    amount: tot_price.as_str().try_into()?,
-                },
-                per_unit: false,
-            });
+    if let Some(token) = parser.tokens.next() {
+        return Err(format!(
+            "unable to parse `{}`: unexpected {token:?}",
+            input.trim()
+        ));
+    }
+    Ok((price, cost))
+}
+
+#[derive(Debug, PartialEq)]
+enum PriceCostToken<'a> {
+    At,
+    AtAt,
+    OpenBrace,
+    OpenDoubleBrace,
+    CloseBrace,
+    CloseDoubleBrace,
+    Comma,
+    // Anything else, e.g. a number, a currency or both without whitespace in between.
+    Word(&'a str),
+}
+
+struct PriceCostTokens<'a> {
+    remaining: &'a str,
+}
+
+impl<'a> Iterator for PriceCostTokens<'a> {
+    type Item = PriceCostToken<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.remaining = self.remaining.trim_start();
+        let mut chars = self.remaining.chars();
+        let first = chars.next()?;
+        let doubled = chars.next() == Some(first);
+        let (token, len) = match (first, doubled) {
+            ('@', true) => (PriceCostToken::AtAt, 2),
+            ('@', false) => (PriceCostToken::At, 1),
+            ('{', true) => (PriceCostToken::OpenDoubleBrace, 2),
+            ('{', false) => (PriceCostToken::OpenBrace, 1),
+            ('}', true) => (PriceCostToken::CloseDoubleBrace, 2),
+            ('}', false) => (PriceCostToken::CloseBrace, 1),
+            (',', _) => (PriceCostToken::Comma, 1),
+            _ => {
+                let len = self
+                    .remaining
+                    .find(|c: char| c.is_whitespace() || "@{},".contains(c))
+                    .unwrap_or(self.remaining.len());
+                (PriceCostToken::Word(&self.remaining[..len]), len)
+            }
+        };
+        self.remaining = &self.remaining[len..];
+        Some(token)
+    }
+}
+
+struct PriceCostParser<'a> {
+    input: &'a str,
+    tokens: std::iter::Peekable<PriceCostTokens<'a>>,
+}
+
+impl PriceCostParser<'_> {
+    fn price(&mut self) -> Result<Option<Parsed<PriceAnnotation>>, String> {
+        let per_unit = match self.tokens.peek() {
+            Some(PriceCostToken::At) => true,
+            Some(PriceCostToken::AtAt) => false,
+            _ => return Ok(None),
+        };
+        self.tokens.next();
+        Ok(Some(Parsed {
+            data: PriceAnnotation {
+                amount: self.amount("price")?,
+            },
+            per_unit,
+        }))
+    }
+
+    fn cost(&mut self) -> Result<Option<Parsed<CostType>>, String> {
+        let (per_unit, close) = match self.tokens.peek() {
+            Some(PriceCostToken::OpenBrace) => (true, PriceCostToken::CloseBrace),
+            Some(PriceCostToken::OpenDoubleBrace) => (false, PriceCostToken::CloseDoubleBrace),
+            _ => return Ok(None),
+        };
+        self.tokens.next();
+        let (mut amount, mut date) = (None, None);
+        // Comma-separated parts in any order, e.g. `{2024-01-15, 100 CHF}`.
+        if self.tokens.peek() != Some(&close) {
+            loop {
+                match self.tokens.peek() {
+                    Some(&PriceCostToken::Word(w)) if starts_with_date(w) => {
+                        if date.is_some() {
+                            return Err(format!("two cost dates in `{}`", self.input.trim()));
+                        }
+                        self.tokens.next();
+                        date = Some(w.parse().map_err(|e| {
+                            format!("invalid cost date `{w}` in `{}`: {e}", self.input.trim())
+                        })?);
+                    }
+                    Some(PriceCostToken::Word(w))
+                        if w.starts_with(|c: char| c.is_ascii_digit()) =>
+                    {
+                        if amount.is_some() {
+                            return Err(format!("two cost amounts in `{}`", self.input.trim()));
+                        }
+                        amount = Some(self.amount("cost")?);
+                    }
+                    Some(PriceCostToken::Word(w)) => {
+                        return Err(format!(
+                            "unsupported cost component `{w}` in `{}`",
+                            self.input.trim()
+                        ));
+                    }
+                    _ => return Err(format!("empty cost component in `{}`", self.input.trim())),
+                }
+                if self.tokens.peek() != Some(&PriceCostToken::Comma) {
+                    break;
+                }
+                self.tokens.next();
+            }
         }
-        if let Some(unit_cost) = capture.name("unitcost") {
-            cost = Some(Parsed::<CostType> {
-                data: CostType::Known(Cost {
-                    amount: unit_cost.as_str().try_into()?,
-                }),
-                per_unit: true,
-            });
-        } else if let Some(tot_cost) = capture.name("totcost") {
-            cost = Some(Parsed::<CostType> {
-                data: CostType::Known(Cost {
-                    amount: tot_cost.as_str().try_into()?,
-                }),
-                per_unit: false,
-            });
+        if self.tokens.next() != Some(close) {
+            return Err(format!("unterminated cost in `{}`", self.input.trim()));
         }
+        let data = match amount {
+            Some(amount) => CostType::Known(Cost { amount, date }),
+            None => CostType::Automatic(date),
+        };
+        Ok(Some(Parsed { data, per_unit }))
     }
-    if price.is_none() && cost.is_none() {
-        Err(format!("unable to parse `{input}`"))
-    } else {
-        Ok((price, cost))
+
+    // An amount is made of the words up to the next delimiter, e.g. `5 USD` or `5USD`.
+    fn amount(&mut self, token_type: &str) -> Result<Amount, String> {
+        // Words are slices of the input, so the amount is the input between the first and the
+        // end of the last word.
+        let mut span: Option<(&str, &str)> = None;
+        while let Some(PriceCostToken::Word(w)) = self.tokens.peek() {
+            span = Some((span.map_or(*w, |(first, _)| first), *w));
+            self.tokens.next();
+        }
+        let Some((first, last)) =
+            span.filter(|(first, _)| first.starts_with(|c: char| c.is_ascii_digit()))
+        else {
+            return Err(format!("no {token_type} amount in `{}`", self.input.trim()));
+        };
+        let start = first.as_ptr() as usize - self.input.as_ptr() as usize;
+        let end = last.as_ptr() as usize - self.input.as_ptr() as usize + last.len();
+        Amount::try_from(&self.input[start..end])
     }
 }
 
@@ -254,16 +525,167 @@ mod test {
         );
         assert!(result.is_ok());
 
+        let result = Transaction::try_from("2022-05-03 * \"Some Shop\" \"Lunch, with tip\"")?;
+        assert_eq!(result.payee.as_deref(), Some("Some Shop"));
+        assert_eq!(result.narration.as_deref(), Some("Lunch, with tip"));
+        assert!(Transaction::try_from("2022-05-03 * \"Shop\" lunch").is_err());
+
+        let result = Transaction::try_from("2022-05-03 * \"Lunch\" #trip #work-2022 # note")?;
+        assert_eq!(result.narration.as_deref(), Some("Lunch"));
+        assert_eq!(result.tags, ["trip", "work-2022"]);
+        assert!(result.links.is_empty());
+        let result = Transaction::try_from(
+            "2022-05-03 * \"Hotel\" #trip ^booking-7 #work ^booking-7 #trip",
+        )?;
+        assert_eq!(result.tags, ["trip", "work"]);
+        assert_eq!(result.links, ["booking-7"]);
+        assert!(Transaction::try_from("2022-05-03 * #trip \"Lunch\"").is_err());
+
+        let result = Transaction::try_from(
+            "2024-01-05 * \"Shop\"
+  confidence: 0.75 ; suggested
+  source: \"import\"
+  Assets:Bank -5 CHF
+  Expenses:Food 5 CHF",
+        )?;
+        assert_eq!(
+            result.meta.get("confidence"),
+            Some(&CustomValue::Number(Decimal::new(75, 2)))
+        );
+        assert_eq!(result.meta.0.len(), 2);
+        assert_eq!(result.postings.len(), 2);
+        assert!(
+            Transaction::try_from("2024-01-05 *\n  confidence:\n  Assets:Bank -5 CHF").is_err()
+        );
+
+        let result = Transaction::try_from(
+            "2024-01-05 * \"Shop\"
+  note: \"x\"
+  Assets:Bank -10 CHF
+    invoice: \"2024-001\"
+  Expenses:Food 10 CHF",
+        )?;
+        assert_eq!(result.meta.0.len(), 1);
+        assert_eq!(
+            result.postings[0].meta.get("invoice"),
+            Some(&CustomValue::String("2024-001".to_string()))
+        );
+        assert!(result.postings[1].meta.0.is_empty());
+        let printed = crate::io::printer::print_transaction(&result);
+        assert!(printed.contains("\n        invoice: \"2024-001\"\n"));
+        assert_eq!(
+            Transaction::try_from(printed.as_str())?.postings[0].meta,
+            result.postings[0].meta
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_elided_amount() -> Result<(), String> {
+        let amounts = |t: &Transaction| -> Vec<String> {
+            t.postings
+                .iter()
+                .map(|p| format!("{} {}", p.account, p.amount))
+                .collect()
+        };
+        let result = Transaction::try_from(
+            "2024-01-05 *
+  Assets:Cash 100 USD @ 0.9 CHF
+  Expenses:Food 5 EUR
+  ! Assets:Bank ; rest
+    note: \"split\"
+  Expenses:Fees 1 CHF",
+        )?;
+        assert_eq!(
+            amounts(&result),
+            [
+                "Assets:Cash 100 USD",
+                "Expenses:Food 5 EUR",
+                "Assets:Bank -91.0 CHF",
+                "Assets:Bank -5 EUR",
+                "Expenses:Fees 1 CHF"
+            ]
+        );
+        assert!(result.postings[3].meta.get("note").is_some());
+        assert_eq!(result.postings[3].flag, Some(TransactionFlag::Error));
+        assert!(result.check().is_ok());
+
+        let result = Transaction::try_from(
+            "2024-01-05 *\n  Assets:Cash 5 CHF\n  Assets:Bank -5 CHF\n  Assets:Foo",
+        )?;
+        assert_eq!(amounts(&result)[2], "Assets:Foo 0 CHF");
+
+        let (result, bad_lines) = parse_transaction_recovering(
+            date(2024, 1, 5),
+            TransactionFlag::OK,
+            "\n  Assets:Cash\n  Assets:Bank\n  Expenses:Food 5 CHF",
+            Rounding::default(),
+        )?;
+        assert_eq!(
+            amounts(&result),
+            ["Assets:Cash -5 CHF", "Expenses:Food 5 CHF"]
+        );
+        assert_eq!(bad_lines[0].0, 2);
+        for statement in [
+            "\n  Assets:Depot -1 META {}\n  Assets:Cash",
+            "\n  Assets:Cash",
+        ] {
+            let (result, bad_lines) = parse_transaction_recovering(
+                date(2024, 1, 5),
+                TransactionFlag::OK,
+                statement,
+                Rounding::default(),
+            )?;
+            assert!(result.postings.iter().all(|p| &*p.account != "Assets:Cash"));
+            assert_eq!(bad_lines[0].1, ErrorCode::InvalidPosting);
+        }
         Ok(())
     }
 
+    #[test]
+    fn test_posting_flag() {
+        let posting = Posting::try_from("!  Assets:Receivable 100 CHF").unwrap();
+        assert_eq!(posting.flag, Some(TransactionFlag::Error));
+        assert_eq!(&*posting.account, "Assets:Receivable");
+        assert_eq!(
+            crate::io::printer::print_posting(&posting),
+            "    ! Assets:Receivable 100 CHF"
+        );
+        assert_eq!(Posting::try_from("Assets:Bank 5 CHF").unwrap().flag, None);
+        assert!(Posting::try_from("! 5 CHF").is_err());
+    }
+
     #[test]
     fn test_parse_flag() {
         assert_eq!(parse_flag("*"), Some(TransactionFlag::OK));
         assert_eq!(parse_flag("!"), Some(TransactionFlag::Error));
+        assert_eq!(parse_flag("P"), Some(TransactionFlag::Padding));
         assert_eq!(parse_flag("x"), None);
     }
 
+    #[test]
+    fn test_price_cost_tokens() {
+        use PriceCostToken::*;
+        assert_eq!(
+            PriceCostTokens {
+                remaining: " @@3USD {{ 6.3CHF}} }@{ "
+            }
+            .collect::<Vec<_>>(),
+            [
+                AtAt,
+                Word("3USD"),
+                OpenDoubleBrace,
+                Word("6.3CHF"),
+                CloseDoubleBrace,
+                CloseBrace,
+                At,
+                OpenBrace
+            ]
+        );
+        assert_eq!(PriceCostTokens { remaining: "  " }.next(), None);
+    }
+
     #[test]
     fn test_parse_price_and_cost() -> Result<(), String> {
         let success = vec![
@@ -287,6 +709,11 @@ mod test {
                 Some((3.0, "USD", false)),
                 Some((60.0, "CHF", false)),
             ),
+            (
+                "{3 CHF  } @@ 5 USD",
+                Some((5.0, "USD", false)),
+                Some((3.0, "CHF", true)),
+            ),
         ];
         let errors = vec![
             "5 USD",
@@ -300,7 +727,9 @@ mod test {
             "@@ 50 CHF @ 3 usd",
             "{5 USD} {{ 20 CHF}}",
             "@ 5 USD {30 USD} {{3 chf}}",
-            "{3 CHF  } @@ 5 USD",
+            "{100 CHF, }",
+            "{100 CHF, 2024-13-01}",
+            "{100 CHF 2024-01-15}",
         ];
         for (inp, expected_price, expected_cost) in success {
             let (price, cost) = parse_price_and_cost(inp)?;
@@ -357,6 +786,81 @@ mod test {
         for inp in errors {
             assert!(parse_price_and_cost(inp).is_err());
         }
+        for inp in ["{}", " {  } @ 3 USD"] {
+            let (_, cost) = parse_price_and_cost(inp)?;
+            assert!(
+                matches!(cost.map(|c| c.data), Some(CostType::Automatic(None))),
+                "{inp}"
+            );
+        }
+        for inp in [
+            "{100 CHF, 2024-01-15}",
+            " {{ 300CHF,2024-01-15 }} @ 120 CHF",
+            "{2024-01-15, 100 CHF}",
+        ] {
+            let (_, cost) = parse_price_and_cost(inp)?;
+            let Some(CostType::Known(cost)) = cost.map(|c| c.data) else {
+                panic!("{inp}");
+            };
+            assert_eq!(cost.date, Some(date(2024, 1, 15)), "{inp}");
+        }
+        let (_, cost) = parse_price_and_cost("{ 2024-01-15 }")?;
+        assert!(matches!(
+            cost.map(|c| c.data),
+            Some(CostType::Automatic(Some(d))) if d == date(2024, 1, 15)
+        ));
+        for (inp, error) in [
+            (
+                "{100 CHF, \"lot1\"}",
+                "unsupported cost component `\"lot1\"`",
+            ),
+            ("{100 CHF, 2024-13-01}", "invalid cost date `2024-13-01`"),
+            ("{2024-01-15, 2024-01-16}", "two cost dates"),
+            ("{1 CHF, 2 CHF}", "two cost amounts"),
+        ] {
+            let Err(e) = parse_price_and_cost(inp) else {
+                panic!("{inp}");
+            };
+            assert!(e.starts_with(error), "{e}");
+        }
+        let posting = Posting::try_from("Assets:Depot 3 AMD {{300 CHF, 2024-01-15}}")?;
+        assert_eq!(
+            crate::io::printer::print_posting(&posting),
+            "    Assets:Depot 3 AMD { 100 CHF, 2024-01-15 }"
+        );
+        let posting = Posting::try_from("Assets:Depot -1 AMD {2024-01-15}")?;
+        assert_eq!(
+            crate::io::printer::print_posting(&posting),
+            "    Assets:Depot -1 AMD { 2024-01-15 }"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_per_unit_rounding() -> Result<(), String> {
+        let posting = Posting::try_from("Assets:Depot 3 META @@ 100 CHF")?;
+        assert_eq!(
+            posting.price.unwrap().amount.number.to_string(),
+            "33.333333333333"
+        );
+
+        let half_up = Rounding {
+            mode: RoundingMode::HalfUp,
+            scale: 2,
+        };
+        let posting = Posting::try_from(("Assets:Depot 8 META {{1 CHF}}", half_up))?;
+        let Some(CostType::Known(cost)) = posting.cost else {
+            panic!("expected a known cost");
+        };
+        assert_eq!(cost.amount.number.to_string(), "0.13");
+        let half_even = Rounding {
+            mode: RoundingMode::HalfEven,
+            ..half_up
+        };
+        let posting = Posting::try_from(("Assets:Depot 8 META @@ 1 CHF", half_even))?;
+        assert_eq!(posting.price.unwrap().amount.number.to_string(), "0.12");
+
+        assert!(Posting::try_from("Assets:Depot 0 META @@ 1 CHF").is_err());
         Ok(())
     }
 }
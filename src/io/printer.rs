@@ -1,32 +1,104 @@
 use crate::core::types::*;
 
+/// Layout of printed postings. The default is the plain style: four spaces of indent, a single
+/// space between account and amount.
+#[derive(Debug, Clone)]
+pub struct PrintOptions {
+    // Spaces in front of postings.
+    pub indent_width: usize,
+    // Column (0-based) at which the numbers of posting amounts end, or their decimal points
+    // with `align_decimals`. None separates accounts and amounts by a single space.
+    pub amount_column: Option<usize>,
+    pub align_decimals: bool,
+    // Spaces inside the braces of costs, `{ 30 USD }` rather than `{30 USD}`.
+    pub spaced_braces: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            indent_width: 4,
+            amount_column: None,
+            align_decimals: false,
+            spaced_braces: true,
+        }
+    }
+}
+
+impl PrintOptions {
+    /// Amounts aligned at column 50 with two spaces of indent, like `bean format` does.
+    pub fn aligned() -> Self {
+        PrintOptions {
+            indent_width: 2,
+            amount_column: Some(50),
+            align_decimals: false,
+            spaced_braces: false,
+        }
+    }
+}
+
 pub fn print_posting(posting: &Posting) -> String {
-    let mut out = format!("    {} {}", posting.account, posting.amount);
-    if let Some(price) = &posting.price {
-        out.push_str(&format!(" @ {} ", price.amount));
+    print_posting_with(posting, &PrintOptions::default())
+}
+
+pub fn print_posting_with(posting: &Posting, options: &PrintOptions) -> String {
+    let mut out = " ".repeat(options.indent_width);
+    if let Some(flag) = &posting.flag {
+        out.push_str(&format!("{} ", flag_str(flag)));
     }
+    out.push_str(&posting.account);
+    let number = posting.amount.number.to_string();
+    let padding = match options.amount_column {
+        Some(column) => {
+            let aligned = match options.align_decimals {
+                true => number.find('.').unwrap_or(number.len()),
+                false => number.len(),
+            };
+            let width = out.chars().count() + 2 + aligned;
+            2 + column.saturating_sub(width)
+        }
+        None => 1,
+    };
+    out.push_str(&" ".repeat(padding));
+    out.push_str(&format!("{number} {}", posting.amount.currency));
+    // Cost before price, in the order beancount expects them.
     if let Some(cost) = &posting.cost {
-        match cost {
-            CostType::Known(c) => {
-                out.push_str(&format!(" {{ {} }} ", c.amount));
-            }
-            CostType::Automatic => {
-                out.push_str(" { } ");
-            }
+        let spec = |c: &Cost| match c.date {
+            Some(date) => format!("{}, {date}", c.amount),
+            None => c.amount.to_string(),
+        };
+        match (cost, options.spaced_braces) {
+            (CostType::Known(c), true) => out.push_str(&format!(" {{ {} }}", spec(c))),
+            (CostType::Known(c), false) => out.push_str(&format!(" {{{}}}", spec(c))),
+            (CostType::Automatic(None), _) => out.push_str(" {}"),
+            (CostType::Automatic(Some(date)), true) => out.push_str(&format!(" {{ {date} }}")),
+            (CostType::Automatic(Some(date)), false) => out.push_str(&format!(" {{{date}}}")),
         }
     }
-    out.trim_end().to_string()
+    if let Some(price) = &posting.price {
+        out.push_str(&format!(" @ {}", price.amount));
+    }
+    let indent = " ".repeat(options.indent_width * 2);
+    for (key, value) in &posting.meta.0 {
+        out.push_str(&format!("\n{indent}{key}: {value}"));
+    }
+    out
+}
+
+fn flag_str(flag: &TransactionFlag) -> &'static str {
+    match flag {
+        TransactionFlag::OK => "*",
+        TransactionFlag::Error => "!",
+        TransactionFlag::Padding => "P",
+    }
 }
 
 pub fn print_transaction(tx: &Transaction) -> String {
-    let mut out = format!(
-        "{} {}",
-        tx.date,
-        match tx.flag {
-            TransactionFlag::OK => "*",
-            TransactionFlag::Error => "!",
-        }
-    );
+    print_transaction_with(tx, &PrintOptions::default())
+}
+
+pub fn print_transaction_with(tx: &Transaction, options: &PrintOptions) -> String {
+    let mut out = format!("{} {}", tx.date, flag_str(&tx.flag));
     if let Some(payee) = &tx.payee {
         out.push_str(&format!(" \"{}\"", payee));
     }
@@ -35,27 +107,317 @@ pub fn print_transaction(tx: &Transaction) -> String {
     } else if tx.payee.is_some() {
         out.push_str(" \"\"");
     }
+    for tag in &tx.tags {
+        out.push_str(&format!(" #{tag}"));
+    }
+    for link in &tx.links {
+        out.push_str(&format!(" ^{link}"));
+    }
+    for (key, value) in &tx.meta.0 {
+        let indent = " ".repeat(options.indent_width);
+        out.push_str(&format!("\n{indent}{key}: {value}"));
+    }
     for p in &tx.postings {
         out.push('\n');
-        out.push_str(&print_posting(&p));
+        out.push_str(&print_posting_with(p, options));
+    }
+    out
+}
+
+pub fn print_open(open: &Open) -> String {
+    let mut out = format!("{} open {}", open.date, open.account);
+    if let Some(currencies) = &open.allowed_currencies {
+        let currencies: Vec<&str> = currencies.iter().map(|c| &**c).collect();
+        out.push(' ');
+        out.push_str(&currencies.join(","));
+    }
+    with_meta(out, &open.meta)
+}
+
+pub fn print_close(close: &Close) -> String {
+    with_meta(
+        format!("{} close {}", close.date, close.account),
+        &close.meta,
+    )
+}
+
+pub fn print_balance(balance: &Balance) -> String {
+    let wildcard = if balance.recursive { ":*" } else { "" };
+    with_meta(
+        format!(
+            "{} balance {}{wildcard} {}",
+            balance.date, balance.account, balance.amount
+        ),
+        &balance.meta,
+    )
+}
+
+pub fn print_commodity(commodity: &Commodity) -> String {
+    with_meta(
+        format!("{} commodity {}", commodity.date, commodity.currency),
+        &commodity.meta,
+    )
+}
+
+pub fn print_price(price: &PriceDirective) -> String {
+    with_meta(
+        format!("{} price {} {}", price.date, price.currency, price.amount),
+        &price.meta,
+    )
+}
+
+pub fn print_note(note: &Note) -> String {
+    with_meta(
+        format!("{} note {} \"{}\"", note.date, note.account, note.comment),
+        &note.meta,
+    )
+}
+
+pub fn print_document(document: &Document) -> String {
+    with_meta(
+        format!(
+            "{} document {} \"{}\"",
+            document.date, document.account, document.path
+        ),
+        &document.meta,
+    )
+}
+
+pub fn print_pad(pad: &Pad) -> String {
+    with_meta(
+        format!("{} pad {} {}", pad.date, pad.account, pad.source_account),
+        &pad.meta,
+    )
+}
+
+pub fn print_event(event: &Event) -> String {
+    with_meta(
+        format!(
+            "{} event \"{}\" \"{}\"",
+            event.date, event.name, event.value
+        ),
+        &event.meta,
+    )
+}
+
+pub fn print_custom(custom: &Custom) -> String {
+    let mut printed = format!("{} custom \"{}\"", custom.date, custom.name);
+    for v in &custom.values {
+        printed += &format!(" {v}");
+    }
+    with_meta(printed, &custom.meta)
+}
+
+// Appends the metadata lines of an entry other than a transaction to its first line.
+fn with_meta(mut out: String, meta: &Metadata) -> String {
+    for (key, value) in &meta.0 {
+        out.push_str(&format!("\n    {key}: {value}"));
+    }
+    out
+}
+
+/// Adds the comments of `span` to the printed entry `printed`.
+pub fn print_comments(printed: &str, span: &Span) -> String {
+    let Some(comments) = &span.comments else {
+        return printed.to_string();
+    };
+    // Comments within the entry are indented like its postings.
+    let indent = printed
+        .lines()
+        .nth(1)
+        .map_or("    ", |l| &l[..l.len() - l.trim_start().len()]);
+    let mut out = String::with_capacity(printed.len());
+    for comment in &comments.leading {
+        out.push_str(comment);
+        out.push('\n');
     }
+    for (i, line) in printed.lines().enumerate() {
+        out.push_str(line);
+        for (_, comment) in comments.trailing.iter().filter(|(l, _)| *l == i) {
+            out.push(' ');
+            out.push_str(comment);
+        }
+        out.push('\n');
+        for (_, comment) in comments.inner.iter().filter(|(l, _)| *l == i) {
+            out.push_str(indent);
+            out.push_str(comment);
+            out.push('\n');
+        }
+    }
+    out.pop();
     out
 }
 
+/// Renders entries into some output format, one method per kind of entry. The traversal of
+/// entries and their comments is shared by all formats, see `render_entry` and
+/// `Ledger::render`.
+pub trait EntryRenderer {
+    fn open(&self, open: &Open) -> String;
+    fn close(&self, close: &Close) -> String;
+    fn balance(&self, balance: &Balance) -> String;
+    fn commodity(&self, commodity: &Commodity) -> String;
+    fn price(&self, price: &PriceDirective) -> String;
+    fn transaction(&self, transaction: &Transaction) -> String;
+    fn note(&self, note: &Note) -> String;
+    fn document(&self, document: &Document) -> String;
+    fn pad(&self, pad: &Pad) -> String;
+    fn event(&self, event: &Event) -> String;
+    fn custom(&self, custom: &Custom) -> String;
+
+    // Adds the comments of `span` to a rendered entry. Formats without comments drop them.
+    fn comments(&self, rendered: String, _span: &Span) -> String {
+        rendered
+    }
+
+    // Combines the rendered entries into the complete output.
+    fn join(&self, rendered: Vec<String>) -> String {
+        rendered.join("\n\n") + "\n"
+    }
+}
+
+/// Renders an entry together with its comments.
+pub fn render_entry(renderer: &impl EntryRenderer, entry: &EntryVariant) -> String {
+    let rendered = match entry {
+        EntryVariant::Transaction(t) => renderer.transaction(t),
+        EntryVariant::Balance(b) => renderer.balance(b),
+        EntryVariant::Open(o) => renderer.open(o),
+        EntryVariant::Close(c) => renderer.close(c),
+        EntryVariant::Commodity(c) => renderer.commodity(c),
+        EntryVariant::Price(p) => renderer.price(p),
+        EntryVariant::Note(n) => renderer.note(n),
+        EntryVariant::Document(d) => renderer.document(d),
+        EntryVariant::Pad(p) => renderer.pad(p),
+        EntryVariant::Event(e) => renderer.event(e),
+        EntryVariant::Custom(c) => renderer.custom(c),
+    };
+    renderer.comments(rendered, entry.span())
+}
+
+/// Beancount text, the output of the `print_*` functions.
+#[derive(Debug, Clone, Default)]
+pub struct BeancountRenderer {
+    pub options: PrintOptions,
+}
+
+impl EntryRenderer for BeancountRenderer {
+    fn open(&self, open: &Open) -> String {
+        print_open(open)
+    }
+    fn close(&self, close: &Close) -> String {
+        print_close(close)
+    }
+    fn balance(&self, balance: &Balance) -> String {
+        print_balance(balance)
+    }
+    fn commodity(&self, commodity: &Commodity) -> String {
+        print_commodity(commodity)
+    }
+    fn price(&self, price: &PriceDirective) -> String {
+        print_price(price)
+    }
+    fn transaction(&self, transaction: &Transaction) -> String {
+        print_transaction_with(transaction, &self.options)
+    }
+    fn note(&self, note: &Note) -> String {
+        print_note(note)
+    }
+    fn document(&self, document: &Document) -> String {
+        print_document(document)
+    }
+    fn pad(&self, pad: &Pad) -> String {
+        print_pad(pad)
+    }
+    fn event(&self, event: &Event) -> String {
+        print_event(event)
+    }
+    fn custom(&self, custom: &Custom) -> String {
+        print_custom(custom)
+    }
+
+    fn comments(&self, rendered: String, span: &Span) -> String {
+        print_comments(&rendered, span)
+    }
+}
+
+/// JSON lines, one serialized entry per line, tagged with its kind.
+#[derive(Debug, Clone, Default)]
+pub struct JsonRenderer;
+
+impl JsonRenderer {
+    fn render(kind: &str, entry: &impl serde::Serialize) -> String {
+        serde_json::json!({ "kind": kind, "entry": entry }).to_string()
+    }
+}
+
+impl EntryRenderer for JsonRenderer {
+    fn open(&self, open: &Open) -> String {
+        Self::render("open", open)
+    }
+    fn close(&self, close: &Close) -> String {
+        Self::render("close", close)
+    }
+    fn balance(&self, balance: &Balance) -> String {
+        Self::render("balance", balance)
+    }
+    fn commodity(&self, commodity: &Commodity) -> String {
+        Self::render("commodity", commodity)
+    }
+    fn price(&self, price: &PriceDirective) -> String {
+        Self::render("price", price)
+    }
+    fn transaction(&self, transaction: &Transaction) -> String {
+        Self::render("transaction", transaction)
+    }
+    fn note(&self, note: &Note) -> String {
+        Self::render("note", note)
+    }
+    fn document(&self, document: &Document) -> String {
+        Self::render("document", document)
+    }
+    fn pad(&self, pad: &Pad) -> String {
+        Self::render("pad", pad)
+    }
+    fn event(&self, event: &Event) -> String {
+        Self::render("event", event)
+    }
+    fn custom(&self, custom: &Custom) -> String {
+        Self::render("custom", custom)
+    }
+
+    fn join(&self, rendered: Vec<String>) -> String {
+        rendered.into_iter().map(|r| r + "\n").collect()
+    }
+}
+
+/// Prints an entry together with its comments.
+pub fn print_entry(entry: &EntryVariant) -> String {
+    print_entry_with(entry, &PrintOptions::default())
+}
+
+pub fn print_entry_with(entry: &EntryVariant, options: &PrintOptions) -> String {
+    let renderer = BeancountRenderer {
+        options: options.clone(),
+    };
+    render_entry(&renderer, entry)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use jiff::civil::date;
+    use smallvec::smallvec;
 
     #[test]
     fn test_print_posting() {
-        let acc = "Assets:Cash".to_string();
+        let acc: Account = "Assets:Cash".into();
         let am = Amount::new(100.into(), "USD".to_string());
         let posting = Posting {
+            flag: None,
             account: acc.clone(),
             amount: am.clone(),
             price: None,
             cost: None,
+            meta: Metadata::default(),
         };
         assert_eq!(
             print_posting(&posting)
@@ -66,12 +428,14 @@ mod test {
         );
 
         let posting = Posting {
+            flag: None,
             account: acc.clone(),
             amount: am.clone(),
-            price: Some(Price {
+            price: Some(PriceAnnotation {
                 amount: "50 CHF".try_into().unwrap(),
             }),
             cost: None,
+            meta: Metadata::default(),
         };
         assert_eq!(
             print_posting(&posting)
@@ -82,12 +446,15 @@ mod test {
         );
 
         let posting = Posting {
+            flag: None,
             account: acc.clone(),
             amount: am.clone(),
             price: None,
             cost: Some(CostType::Known(Cost {
                 amount: "50 CHF".try_into().unwrap(),
+                date: None,
             })),
+            meta: Metadata::default(),
         };
         assert_eq!(
             print_posting(&posting)
@@ -98,14 +465,17 @@ mod test {
         );
 
         let posting = Posting {
+            flag: None,
             account: acc.clone(),
             amount: am.clone(),
-            price: Some(Price {
+            price: Some(PriceAnnotation {
                 amount: "75 CHF".try_into().unwrap(),
             }),
             cost: Some(CostType::Known(Cost {
                 amount: "50 CHF".try_into().unwrap(),
+                date: None,
             })),
+            meta: Metadata::default(),
         };
         assert_eq!(
             print_posting(&posting)
@@ -116,13 +486,13 @@ mod test {
                 "Assets:Cash",
                 "100",
                 "USD",
-                "@",
-                "75",
-                "CHF",
                 "{",
                 "50",
                 "CHF",
-                "}"
+                "}",
+                "@",
+                "75",
+                "CHF"
             ]
         );
     }
@@ -134,7 +504,11 @@ mod test {
             flag: TransactionFlag::OK,
             payee: None,
             narration: None,
-            postings: vec![],
+            tags: vec![],
+            links: vec![],
+            meta: Metadata::default(),
+            postings: smallvec![],
+            span: Span::default(),
         };
         assert_eq!(print_transaction(&t), "2022-05-03 *");
         let t = Transaction {
@@ -142,7 +516,11 @@ mod test {
             flag: TransactionFlag::OK,
             payee: None,
             narration: Some("foo".to_string()),
-            postings: vec![],
+            tags: vec![],
+            links: vec![],
+            meta: Metadata::default(),
+            postings: smallvec![],
+            span: Span::default(),
         };
         assert_eq!(print_transaction(&t), "2022-05-03 * \"foo\"");
         let t = Transaction {
@@ -150,7 +528,11 @@ mod test {
             flag: TransactionFlag::OK,
             payee: Some("foo".to_string()),
             narration: None,
-            postings: vec![],
+            tags: vec![],
+            links: vec![],
+            meta: Metadata::default(),
+            postings: smallvec![],
+            span: Span::default(),
         };
         assert_eq!(print_transaction(&t), "2022-05-03 * \"foo\" \"\"");
         let t = Transaction {
@@ -158,7 +540,11 @@ mod test {
             flag: TransactionFlag::OK,
             payee: Some("bar".to_string()),
             narration: Some("foo".to_string()),
-            postings: vec![],
+            tags: vec![],
+            links: vec![],
+            meta: Metadata::default(),
+            postings: smallvec![],
+            span: Span::default(),
         };
         assert_eq!(print_transaction(&t), "2022-05-03 * \"bar\" \"foo\"");
 
@@ -167,7 +553,11 @@ mod test {
             flag: TransactionFlag::Error,
             payee: None,
             narration: Some("foo".to_string()),
-            postings: vec![],
+            tags: vec![],
+            links: vec![],
+            meta: Metadata::default(),
+            postings: smallvec![],
+            span: Span::default(),
         };
         assert_eq!(print_transaction(&t), "2022-05-03 ! \"foo\"");
 
@@ -176,24 +566,116 @@ mod test {
             flag: TransactionFlag::OK,
             payee: None,
             narration: None,
-            postings: vec![
+            tags: vec![],
+            links: vec![],
+            meta: Metadata::default(),
+            postings: smallvec![
                 Posting {
-                    account: "Assets:Cash".to_string(),
+                    flag: None,
+                    account: "Assets:Cash".into(),
                     amount: "5 CHF".try_into().unwrap(),
                     price: None,
                     cost: None,
+                    meta: Metadata::default(),
                 },
                 Posting {
-                    account: "Assets:Cash2".to_string(),
+                    flag: None,
+                    account: "Assets:Cash2".into(),
                     amount: "5 USD".try_into().unwrap(),
                     price: None,
                     cost: None,
+                    meta: Metadata::default(),
                 },
             ],
+            span: Span::default(),
         };
         assert_eq!(
             print_transaction(&t),
             "2022-05-03 *\n    Assets:Cash 5 CHF\n    Assets:Cash2 5 USD"
         );
+        let mut t = t;
+        t.meta
+            .0
+            .push(("confidence".to_string(), CustomValue::Number(5.into())));
+        assert_eq!(
+            print_transaction(&t),
+            "2022-05-03 *\n    confidence: 5\n    Assets:Cash 5 CHF\n    Assets:Cash2 5 USD"
+        );
+    }
+
+    #[test]
+    fn test_print_options() {
+        let posting = |amount: &str, cost: Option<&str>| Posting {
+            flag: None,
+            account: "Assets:Depot".into(),
+            amount: amount.try_into().unwrap(),
+            price: None,
+            cost: cost.map(|c| {
+                CostType::Known(Cost {
+                    amount: c.try_into().unwrap(),
+                    date: None,
+                })
+            }),
+            meta: Metadata::default(),
+        };
+        let options = PrintOptions {
+            amount_column: Some(25),
+            ..Default::default()
+        };
+        assert_eq!(
+            print_posting_with(&posting("-12.5 CHF", None), &options),
+            "    Assets:Depot    -12.5 CHF"
+        );
+        let options = PrintOptions {
+            indent_width: 2,
+            amount_column: Some(22),
+            align_decimals: true,
+            spaced_braces: false,
+        };
+        assert_eq!(
+            [
+                print_posting_with(&posting("-12.5 CHF", None), &options),
+                print_posting_with(&posting("100 CHF", None), &options),
+                print_posting_with(&posting("2 META", Some("30.25 USD")), &options),
+            ],
+            [
+                "  Assets:Depot     -12.5 CHF",
+                "  Assets:Depot     100 CHF",
+                "  Assets:Depot       2 META {30.25 USD}",
+            ]
+        );
+        // Accounts longer than the amount column still get two spaces.
+        let options = PrintOptions {
+            amount_column: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(
+            print_posting_with(&posting("1 CHF", None), &options),
+            "    Assets:Depot  1 CHF"
+        );
+    }
+
+    #[test]
+    fn test_renderers() {
+        let open = EntryVariant::Open("2024-01-01 open Assets:Cash CHF".try_into().unwrap());
+        let json: serde_json::Value =
+            serde_json::from_str(&render_entry(&JsonRenderer, &open)).unwrap();
+        assert_eq!(json["kind"], "open");
+        assert_eq!(json["entry"]["account"], "Assets:Cash");
+        assert_eq!(
+            JsonRenderer.join(vec!["{}".into(), "{}".into()]),
+            "{}\n{}\n"
+        );
+        assert_eq!(
+            render_entry(&BeancountRenderer::default(), &open),
+            "2024-01-01 open Assets:Cash CHF"
+        );
+    }
+
+    #[test]
+    fn test_print_commodity() {
+        let input = "2024-01-01 commodity AAPL\n    precision: 4\n    name: \"Apple Inc.\"";
+        let commodity = Commodity::try_from(input).unwrap();
+        assert_eq!(print_commodity(&commodity), input);
     }
 }
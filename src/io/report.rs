@@ -0,0 +1,679 @@
+use crate::core::ledger::{Ledger, is_within};
+use crate::core::ledger_set::LedgerSet;
+use crate::core::prices::PriceDb;
+use crate::core::types::*;
+use crate::io::chart::{NET_WORTH_ACCOUNTS, month_starts};
+use jiff::ToSpan;
+use jiff::civil::Date;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// How reports show amounts. Neither the ledger syntax nor `bean print` is affected.
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    // Display symbols by currency, e.g. USD → "$". They take precedence over the `symbol`
+    // metadata of commodities.
+    pub symbols: HashMap<String, String>,
+    pub numbers: NumberFormat,
+}
+
+/// Digit grouping and decimal separator of numbers in reports, see `FromStr` for the supported
+/// locales. The default is the locale-independent form of the ledger syntax, e.g. `1234.56`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub group_separator: Option<char>,
+    pub decimal_separator: char,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            group_separator: None,
+            decimal_separator: '.',
+        }
+    }
+}
+
+impl FromStr for NumberFormat {
+    type Err = String;
+
+    // A locale such as `de-CH` or a language such as `de`.
+    fn from_str(locale: &str) -> Result<Self, Self::Err> {
+        let (group, decimal) = match locale.replace('_', "-").as_str() {
+            "de-CH" | "fr-CH" | "it-CH" | "de-LI" => ('\'', '.'),
+            "en" | "en-US" | "en-GB" | "en-AU" | "en-CA" | "ja" | "zh" => (',', '.'),
+            "de" | "de-DE" | "de-AT" | "es" | "it" | "nl" | "pt" | "pt-BR" => ('.', ','),
+            "fr" | "fr-FR" | "fr-CA" | "sv" | "nb" | "fi" | "pl" | "cs" => ('\u{202f}', ','),
+            _ => return Err(format!("Unsupported locale `{locale}`")),
+        };
+        Ok(NumberFormat {
+            group_separator: Some(group),
+            decimal_separator: decimal,
+        })
+    }
+}
+
+impl NumberFormat {
+    pub fn format(&self, number: Decimal) -> String {
+        let plain = number.abs().to_string();
+        let (int, frac) = plain.split_once('.').unwrap_or((&plain, ""));
+        let mut out = String::new();
+        if number.is_sign_negative() {
+            out.push('-');
+        }
+        for (i, digit) in int.chars().enumerate() {
+            if i > 0
+                && (int.len() - i) % 3 == 0
+                && let Some(group) = self.group_separator
+            {
+                out.push(group);
+            }
+            out.push(digit);
+        }
+        if !frac.is_empty() {
+            out.push(self.decimal_separator);
+            out.push_str(frac);
+        }
+        out
+    }
+}
+
+/// Formats amounts with the `precision` and `symbol` of their commodity and the report options.
+pub struct AmountFormatter<'a> {
+    commodities: Commodities<'a>,
+    options: &'a ReportOptions,
+}
+
+enum Commodities<'a> {
+    Ledger(&'a Ledger),
+    Shared(&'a PriceDb),
+}
+
+impl<'a> AmountFormatter<'a> {
+    pub fn new(ledger: &'a Ledger, options: &'a ReportOptions) -> Self {
+        AmountFormatter {
+            commodities: Commodities::Ledger(ledger),
+            options,
+        }
+    }
+
+    /// With the commodities shared by several ledgers.
+    pub fn with_prices(prices: &'a PriceDb, options: &'a ReportOptions) -> Self {
+        AmountFormatter {
+            commodities: Commodities::Shared(prices),
+            options,
+        }
+    }
+
+    pub fn format(&self, amount: &Amount) -> String {
+        let commodity = match self.commodities {
+            Commodities::Ledger(ledger) => ledger.commodity(&amount.currency),
+            Commodities::Shared(prices) => prices.commodity(&amount.currency),
+        };
+        let mut number = amount.number;
+        if let Some(precision) = commodity.and_then(Commodity::precision) {
+            number = number.round_dp(precision);
+            number.rescale(precision);
+        }
+        let symbol = self
+            .options
+            .symbols
+            .get(&*amount.currency)
+            .map(String::as_str)
+            .or_else(|| commodity.and_then(Commodity::symbol))
+            .unwrap_or(&amount.currency);
+        let numbers = &self.options.numbers;
+        // Symbols such as "$" or "€" go before the number, codes such as "CHF" after it.
+        if symbol.chars().all(char::is_alphanumeric) {
+            format!("{} {symbol}", numbers.format(number))
+        } else if number.is_sign_negative() {
+            format!("-{symbol}{}", numbers.format(number.abs()))
+        } else {
+            format!("{symbol}{}", numbers.format(number))
+        }
+    }
+}
+
+/// The balance of each account at the start of `date`, one line per account with its amounts.
+pub fn balances_report(ledger: &Ledger, date: Date, options: &ReportOptions) -> String {
+    let formatter = AmountFormatter::new(ledger, options);
+    let balances = ledger.balances_at(date);
+    let width = balances.keys().map(|a| a.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (account, amounts) in balances {
+        let amounts: Vec<String> = amounts.iter().map(|a| formatter.format(a)).collect();
+        out.push_str(&format!("{account:width$}  {}\n", amounts.join(", ")));
+    }
+    out
+}
+
+/// The net worth of each ledger of `set` at the start of `date` in `quote`, valued at the
+/// shared prices of the day before, and their total. Holdings without a price are listed in
+/// their own currency after the value.
+pub fn consolidated_report(
+    set: &LedgerSet,
+    quote: &str,
+    date: Date,
+    options: &ReportOptions,
+) -> String {
+    let formatter = AmountFormatter::with_prices(set.prices(), options);
+    let priced_at = date.yesterday().unwrap_or(date);
+    let mut lines = vec![];
+    let mut holdings = vec![];
+    for (name, ledger) in set.ledgers() {
+        let amounts: Vec<Amount> = NET_WORTH_ACCOUNTS
+            .iter()
+            .flat_map(|a| ledger.balance(a, date))
+            .collect();
+        lines.push((name, set.prices().value(&amounts, quote, priced_at)));
+        holdings.extend(amounts);
+    }
+    lines.push(("Total", set.prices().value(&holdings, quote, priced_at)));
+    let width = lines.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (name, (value, unpriced)) in lines {
+        let amounts: Vec<String> = [Amount::new(value, quote.to_string())]
+            .iter()
+            .chain(unpriced.iter().filter(|a| !a.number.is_zero()))
+            .map(|a| formatter.format(a))
+            .collect();
+        out.push_str(&format!("{name:width$}  {}\n", amounts.join(", ")));
+    }
+    out
+}
+
+/// The postings to `account` or its sub-accounts in date order, one line per posting with its
+/// date, a marker, its account, amount and the running balance in its currency, and the payee
+/// and narration. The marker is `P` for the transactions added in place of pad directives and
+/// `!` for pending ones, which `exclude_pending` leaves out.
+pub fn register_report(
+    ledger: &Ledger,
+    account: &str,
+    exclude_pending: bool,
+    options: &ReportOptions,
+) -> String {
+    let formatter = AmountFormatter::new(ledger, options);
+    let mut balances: HashMap<&Currency, Decimal> = HashMap::new();
+    let mut rows: Vec<[String; 6]> = vec![];
+    for (t, p) in ledger.postings().within(account) {
+        let marker = match t.flag {
+            TransactionFlag::OK => " ",
+            TransactionFlag::Error if exclude_pending => continue,
+            TransactionFlag::Error => "!",
+            TransactionFlag::Padding => "P",
+        };
+        let balance = balances.entry(&p.amount.currency).or_default();
+        *balance += p.amount.number;
+        let text = [&t.payee, &t.narration].into_iter().flatten();
+        rows.push([
+            t.date.to_string(),
+            marker.to_string(),
+            p.account.to_string(),
+            formatter.format(&p.amount),
+            formatter.format(&Amount::new(*balance, p.amount.currency.clone())),
+            text.map(String::as_str).collect::<Vec<_>>().join(" | "),
+        ]);
+    }
+    let width = |i: usize| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0);
+    let (account_width, amount_width, balance_width) = (width(2), width(3), width(4));
+    let mut out = String::new();
+    for [date, marker, account, amount, balance, text] in rows {
+        let line = format!(
+            "{date} {marker} {account:account_width$}  {amount:>amount_width$}  {balance:>balance_width$}  {text}"
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Placeholder accounts of postings still to be categorized, e.g. the counter postings of
+/// imported transactions.
+pub const TODO_ACCOUNTS: [&str; 2] = ["Expenses:Uncategorized", "Assets:Unknown"];
+
+/// The postings to `accounts` or their sub-accounts, largest amounts first regardless of their
+/// sign and currency, one line per posting with its date, account, amount and the payee and
+/// narration of its transaction.
+pub fn uncategorized_report(ledger: &Ledger, accounts: &[&str], options: &ReportOptions) -> String {
+    let formatter = AmountFormatter::new(ledger, options);
+    let mut postings: Vec<_> = ledger
+        .postings()
+        .filter(|(_, p)| accounts.iter().any(|a| is_within(&p.account, a)))
+        .collect();
+    postings.sort_by(|(t1, p1), (t2, p2)| {
+        let size = |p: &Posting| p.amount.number.abs();
+        size(p2).cmp(&size(p1)).then(t1.date.cmp(&t2.date))
+    });
+    let rows: Vec<[String; 4]> = postings
+        .into_iter()
+        .map(|(t, p)| {
+            let text = [&t.payee, &t.narration].into_iter().flatten();
+            [
+                t.date.to_string(),
+                p.account.to_string(),
+                formatter.format(&p.amount),
+                text.map(String::as_str).collect::<Vec<_>>().join(" | "),
+            ]
+        })
+        .collect();
+    let width = |i: usize| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0);
+    let (account_width, amount_width) = (width(1), width(2));
+    let mut out = String::new();
+    for [date, account, amount, text] in rows {
+        let line = format!("{date}  {account:account_width$}  {amount:>amount_width$}  {text}");
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// A value of an event, from the day it was set until the day before it changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventPeriod {
+    pub value: String,
+    pub start: Date,
+    // Exclusive.
+    pub end: Date,
+}
+
+impl EventPeriod {
+    pub fn days(&self) -> i32 {
+        self.start.until(self.end).map_or(0, |span| span.get_days())
+    }
+}
+
+/// The values of the events called `name` within `range`, e.g. the places lived in for
+/// `location`. A value lasts until the next event of the name, the last one until the end of
+/// `range`. Events on the same day replace each other, in input order.
+pub fn event_timeline(ledger: &Ledger, name: &str, range: Range<Date>) -> Vec<EventPeriod> {
+    let mut events: Vec<&Event> = ledger
+        .entries()
+        .event
+        .iter()
+        .filter(|e| e.name == name)
+        .collect();
+    events.sort_by_key(|e| e.date);
+    let mut periods: Vec<EventPeriod> = vec![];
+    for (i, event) in events.iter().enumerate() {
+        let next = events.get(i + 1).map_or(range.end, |e| e.date);
+        let start = event.date.max(range.start);
+        let end = next.min(range.end);
+        if start >= end {
+            continue;
+        }
+        match periods.last_mut() {
+            Some(last) if last.value == event.value && last.end == start => last.end = end,
+            _ => periods.push(EventPeriod {
+                value: event.value.clone(),
+                start,
+                end,
+            }),
+        }
+    }
+    periods
+}
+
+/// The timeline of each of the event `names`, one line per period with its first and last day,
+/// its number of days and the value, followed by the total days per value, most days first.
+pub fn events_report(ledger: &Ledger, names: &[&str], range: Range<Date>) -> String {
+    let mut out = String::new();
+    for name in names {
+        let periods = event_timeline(ledger, name, range.clone());
+        let mut totals: Vec<(&str, i32)> = vec![];
+        for period in &periods {
+            match totals.iter_mut().find(|(value, _)| *value == period.value) {
+                Some((_, days)) => *days += period.days(),
+                None => totals.push((&period.value, period.days())),
+            }
+        }
+        totals.sort_by(|(v1, d1), (v2, d2)| d2.cmp(d1).then(v1.cmp(v2)));
+        let width = totals
+            .iter()
+            .map(|(_, d)| d.to_string().len())
+            .max()
+            .unwrap_or(0);
+        out.push_str(&format!("{name}\n"));
+        for period in &periods {
+            let last = period.end.yesterday().unwrap_or(period.end);
+            let (start, days, value) = (period.start, period.days(), &period.value);
+            out.push_str(&format!("  {start}  {last}  {days:>width$}  {value}\n"));
+        }
+        for (value, days) in totals {
+            out.push_str(&format!("  {:22}  {days:>width$}  {value}\n", "total"));
+        }
+    }
+    out
+}
+
+/// A valuation without a recent enough price: the holdings of `currency` at the end of `date`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingPrice {
+    pub currency: Currency,
+    pub date: Date,
+    // The date of the latest price before, if any.
+    pub last: Option<Date>,
+}
+
+/// The valuations of the net worth in `quote` at the end of each month within `range`, and at
+/// the end of the range, for which the price of a held currency is missing or older than
+/// `max_age` days. These are the price directives to add.
+pub fn missing_prices(
+    ledger: &Ledger,
+    quote: &str,
+    max_age: i32,
+    range: Range<Date>,
+) -> Vec<MissingPrice> {
+    let mut missing = vec![];
+    // The holdings at the start of each day are valued at the end of the day before.
+    for day in month_starts(ledger, range).into_iter().skip(1) {
+        let date = day.yesterday().unwrap_or(day);
+        let holdings: Vec<Amount> = NET_WORTH_ACCOUNTS
+            .iter()
+            .flat_map(|a| ledger.balance(a, day))
+            .collect();
+        for (currency, number) in sum_by_currency(&holdings) {
+            if *currency == *quote || number.is_zero() {
+                continue;
+            }
+            let last = ledger.price(&currency, quote, date).map(|p| p.date);
+            let oldest = date.checked_sub(max_age.days()).unwrap_or(Date::MIN);
+            if last.is_none_or(|last| last < oldest) {
+                missing.push(MissingPrice {
+                    currency,
+                    date,
+                    last,
+                });
+            }
+        }
+    }
+    missing
+}
+
+/// `missing_prices` one per line, with the date of the latest price before if any.
+pub fn missing_prices_report(
+    ledger: &Ledger,
+    quote: &str,
+    max_age: i32,
+    range: Range<Date>,
+) -> String {
+    let mut out = String::new();
+    for m in missing_prices(ledger, quote, max_age, range) {
+        let last = m
+            .last
+            .map_or("no price".to_string(), |d| format!("last price {d}"));
+        out.push_str(&format!("{}  {}  {last}\n", m.date, m.currency));
+    }
+    out
+}
+
+/// The open Assets and Liabilities accounts at `date` whose last balance assertion is more than
+/// `max_age` days old, or that were never asserted, with the date of the last assertion. An
+/// assertion written `Account:*` covers the sub-accounts as well. Longest unasserted first.
+pub fn stale_assertions(ledger: &Ledger, max_age: i32, date: Date) -> Vec<(String, Option<Date>)> {
+    let entries = ledger.entries();
+    let closed: Vec<&str> = entries
+        .close
+        .iter()
+        .filter(|c| c.date <= date)
+        .map(|c| &*c.account)
+        .collect();
+    let oldest = date.checked_sub(max_age.days()).unwrap_or(Date::MIN);
+    let mut stale: Vec<(String, Option<Date>)> = entries
+        .open
+        .iter()
+        .filter(|o| o.date <= date && !closed.contains(&&*o.account))
+        .filter(|o| NET_WORTH_ACCOUNTS.iter().any(|r| is_within(&o.account, r)))
+        .filter_map(|o| {
+            let last = entries
+                .balance
+                .iter()
+                .filter(|b| b.date <= date)
+                .filter(|b| match b.recursive {
+                    true => is_within(&o.account, &b.account),
+                    false => b.account == o.account,
+                })
+                .map(|b| b.date)
+                .max();
+            match last {
+                Some(last) if last >= oldest => None,
+                last => Some((o.account.to_string(), last)),
+            }
+        })
+        .collect();
+    stale.sort_by(|(a1, d1), (a2, d2)| d1.cmp(d2).then(a1.cmp(a2)));
+    stale.dedup();
+    stale
+}
+
+/// `stale_assertions` one per line, with the date of the last assertion and its age in days.
+pub fn stale_assertions_report(ledger: &Ledger, max_age: i32, date: Date) -> String {
+    let stale = stale_assertions(ledger, max_age, date);
+    let width = stale.iter().map(|(a, _)| a.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (account, last) in stale {
+        let line = match last {
+            Some(last) => {
+                let days = last.until(date).map_or(0, |span| span.get_days());
+                format!("{account:width$}  {last}  {days} days ago")
+            }
+            None => format!("{account:width$}  never"),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::parser::parse_entries_from_string;
+    use std::path::Path;
+
+    fn ledger(input: &str) -> Ledger {
+        Ledger::new(parse_entries_from_string(input.to_string(), Path::new("")).unwrap())
+    }
+
+    #[test]
+    fn test_format_amount() {
+        let ledger = ledger(
+            "2024-01-01 commodity AAPL\n  precision: 2\n2024-01-01 commodity EUR\n  symbol: \"€\"\n",
+        );
+        let options = ReportOptions {
+            symbols: HashMap::from([("USD".to_string(), "$".to_string())]),
+            ..ReportOptions::default()
+        };
+        let formatter = AmountFormatter::new(&ledger, &options);
+        let amount = |s: &str| formatter.format(&Amount::try_from(s).unwrap());
+        assert_eq!(amount("1.239 AAPL"), "1.24 AAPL");
+        assert_eq!(amount("5 AAPL"), "5.00 AAPL");
+        assert_eq!(amount("1.239 CHF"), "1.239 CHF");
+        assert_eq!(amount("-12.50 USD"), "-$12.50");
+        assert_eq!(amount("3 EUR"), "€3");
+    }
+
+    #[test]
+    fn test_number_format() {
+        let number = |s: &str| Decimal::from_str_exact(s).unwrap();
+        let swiss: NumberFormat = "de-CH".parse().unwrap();
+        assert_eq!(swiss.format(number("1234.56")), "1'234.56");
+        assert_eq!(swiss.format(number("-1234567")), "-1'234'567");
+        assert_eq!(swiss.format(number("123")), "123");
+        let german: NumberFormat = "de_DE".parse().unwrap();
+        assert_eq!(german.format(number("1234.56")), "1.234,56");
+        assert_eq!(NumberFormat::default().format(number("1234.56")), "1234.56");
+        assert!("xx".parse::<NumberFormat>().is_err());
+    }
+
+    #[test]
+    fn test_uncategorized_report() {
+        let ledger = ledger(
+            "2024-01-05 * \"Shop\" \"Groceries\"
+  Assets:Bank -5 CHF
+  Expenses:Uncategorized 5 CHF
+2024-01-06 * \"Refund\"
+  Assets:Bank 20 CHF
+  Expenses:Uncategorized:Card -20 CHF
+2024-01-07 *
+  Assets:Bank -12.50 CHF
+  Expenses:Food 12.50 CHF
+",
+        );
+        assert_eq!(
+            uncategorized_report(&ledger, &TODO_ACCOUNTS, &ReportOptions::default()),
+            "2024-01-06  Expenses:Uncategorized:Card  -20 CHF  Refund
+2024-01-05  Expenses:Uncategorized         5 CHF  Shop | Groceries
+"
+        );
+    }
+
+    #[test]
+    fn test_events_report() {
+        let ledger = ledger(
+            "2024-03-15 event \"location\" \"Berlin\"
+2023-12-01 event \"location\" \"Zurich\"
+2024-05-01 event \"location\" \"Berlin\"
+2024-06-01 event \"employer\" \"ACME\"
+2024-06-10 event \"location\" \"Paris\"
+2024-06-10 event \"location\" \"Zurich\"
+",
+        );
+        let range = jiff::civil::date(2024, 1, 1)..jiff::civil::date(2025, 1, 1);
+        let timeline = event_timeline(&ledger, "location", range.clone());
+        assert_eq!(
+            timeline.iter().map(|p| p.days()).collect::<Vec<_>>(),
+            [74, 87, 205]
+        );
+        assert_eq!(
+            events_report(&ledger, &["location"], range),
+            "location
+  2024-01-01  2024-03-14   74  Zurich
+  2024-03-15  2024-06-09   87  Berlin
+  2024-06-10  2024-12-31  205  Zurich
+  total                   279  Zurich
+  total                    87  Berlin
+"
+        );
+    }
+
+    #[test]
+    fn test_missing_prices_report() {
+        let ledger = ledger(
+            "2024-01-10 *
+  Assets:Broker 2 META
+  Assets:Bank -1000 CHF
+  Equity:Opening 1000 CHF
+  Equity:Opening -2 META
+2024-01-31 price META 500 CHF
+2024-02-05 *
+  Assets:Bank 10 EUR
+  Income:Salary -10 EUR
+2024-02-20 price CHF 1.05 EUR
+2024-03-15 *
+  Assets:Broker -2 META
+  Assets:Bank 1000 CHF
+  Income:Gains -1000 CHF
+",
+        );
+        assert_eq!(
+            missing_prices_report(&ledger, "CHF", 7, Date::MIN..Date::MAX),
+            "2024-02-29  EUR  last price 2024-02-20
+2024-02-29  META  last price 2024-01-31
+2024-03-15  EUR  last price 2024-02-20
+"
+        );
+    }
+
+    #[test]
+    fn test_stale_assertions_report() {
+        let ledger = ledger(
+            "2024-01-01 open Assets:Bank
+2024-01-01 open Assets:Broker:Cash
+2024-01-01 open Assets:Wallet
+2024-01-01 open Assets:Old
+2024-01-01 open Expenses:Food
+2024-02-01 close Assets:Old
+2024-01-31 balance Assets:Bank 0 CHF
+2024-05-31 balance Assets:Broker:* 0 CHF
+2024-06-30 balance Assets:Bank 0 CHF
+",
+        );
+        assert_eq!(
+            stale_assertions_report(&ledger, 30, jiff::civil::date(2024, 7, 15)),
+            "Assets:Wallet       never
+Assets:Broker:Cash  2024-05-31  45 days ago
+"
+        );
+    }
+
+    #[test]
+    fn test_register_report() {
+        let mut entries = parse_entries_from_string(
+            "2024-01-01 pad Assets:Bank Equity:Opening
+2024-01-05 * \"Shop\"
+  Assets:Bank:Card -5 CHF
+  Expenses:Food 5 CHF
+2024-01-06 ! \"Refund\"
+  Assets:Bank 2 CHF
+  Expenses:Food -2 CHF
+2024-01-10 balance Assets:Bank:* 95 CHF
+"
+            .to_string(),
+            Path::new(""),
+        )
+        .unwrap();
+        crate::core::transform::resolve_pads(&mut entries);
+        let ledger = Ledger::new(entries);
+        let options = ReportOptions::default();
+        assert_eq!(
+            register_report(&ledger, "Assets:Bank", false, &options),
+            "2024-01-01 P Assets:Bank       98 CHF  98 CHF  (Padding inserted for Balance of 95 CHF for difference 98 CHF)
+2024-01-05   Assets:Bank:Card  -5 CHF  93 CHF  Shop
+2024-01-06 ! Assets:Bank        2 CHF  95 CHF  Refund
+"
+        );
+        assert_eq!(
+            register_report(&ledger, "Assets:Bank", true, &options),
+            "2024-01-01 P Assets:Bank       98 CHF  98 CHF  (Padding inserted for Balance of 95 CHF for difference 98 CHF)
+2024-01-05   Assets:Bank:Card  -5 CHF  93 CHF  Shop
+"
+        );
+    }
+
+    #[test]
+    fn test_balances_report() {
+        let ledger = ledger("2024-01-05 * \"Shop\"\n  Assets:Bank -5 USD\n  Expenses:Food 5 USD\n");
+        let options = ReportOptions {
+            symbols: HashMap::from([("USD".to_string(), "$".to_string())]),
+            ..ReportOptions::default()
+        };
+        assert_eq!(
+            balances_report(&ledger, Date::MAX, &options),
+            "Assets         -$5\nAssets:Bank    -$5\nExpenses       $5\nExpenses:Food  $5\n"
+        );
+    }
+
+    #[test]
+    fn test_consolidated_report() {
+        let personal = ledger(
+            "2024-01-01 commodity CHF\n  precision: 2\n2024-01-01 price USD 0.90 CHF
+2024-01-05 *\n  Assets:Bank 100 CHF\n  Income:Salary -100 CHF\n",
+        );
+        let business = ledger(
+            "2024-01-10 *\n  Assets:Bank 50 USD\n  Assets:Gold 1 XAU\n  Income:Sales -50 USD
+  Income:Sales -1 XAU\n",
+        );
+        let set = LedgerSet::new(vec![
+            ("personal".to_string(), personal),
+            ("business".to_string(), business),
+        ])
+        .unwrap();
+        let date = "2024-02-01".parse().unwrap();
+        assert_eq!(
+            consolidated_report(&set, "CHF", date, &ReportOptions::default()),
+            "personal  100.00 CHF\nbusiness  45.00 CHF, 1 XAU\nTotal     145.00 CHF, 1 XAU\n"
+        );
+    }
+}
@@ -1,10 +1,11 @@
+use beanrust::core::ledger::Ledger;
 use beanrust::io::parser;
-use std::{env, path};
+use std::{env, fs, path};
 
 #[test]
 fn parse_file() -> Result<(), String> {
     let manifest_dir = env!("CARGO_MANIFEST_DIR");
-    let ledger_path: path::PathBuf = [&manifest_dir, "tests/test_ledger.beancount"]
+    let ledger_path: path::PathBuf = [manifest_dir, "tests/test_ledger.beancount"]
         .iter()
         .collect();
     assert!(ledger_path.exists(), "path: {:?}", ledger_path.to_str());
@@ -15,9 +16,42 @@ fn parse_file() -> Result<(), String> {
     assert_eq!(result.balance.len(), 1);
     assert_eq!(result.commodity.len(), 3);
     assert_eq!(result.price.len(), 4);
-    assert_eq!(result.transactions.len(), 5);
-    println!("{}", result.unhandled_entries.join("\n--\n"));
-    assert_eq!(result.unhandled_entries.len(), 2);
+    assert_eq!(result.transactions.len(), 7);
+    for e in &result.errors {
+        println!("{e}");
+    }
+    assert!(result.errors.is_empty());
+    // The posting without an amount balances the other one.
+    let elided = &result.transactions[5].postings[1];
+    assert_eq!(&*elided.account, "Assets:Foo");
+    assert_eq!(elided.amount.to_string(), "490 CHF");
+
+    Ok(())
+}
+
+#[test]
+fn normalize_round_trip() -> Result<(), String> {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let ledger_path: path::PathBuf = [manifest_dir, "tests/test_ledger.beancount"]
+        .iter()
+        .collect();
+    let golden_path: path::PathBuf = [manifest_dir, "tests/test_ledger.normalized.beancount"]
+        .iter()
+        .collect();
+    // The golden file must not lock in postings the parser dropped.
+    let source = parser::parse_entries_from_file(&ledger_path).map_err(|e| e.to_string())?;
+    assert!(source.errors.is_empty(), "{:?}", source.errors);
+    let ledger = Ledger::load(&ledger_path).map_err(|e| e.to_string())?;
+    let normalized = ledger.normalize();
+    let golden = fs::read_to_string(&golden_path).map_err(|e| e.to_string())?;
+    assert_eq!(normalized, golden);
+
+    // Printing and parsing again yields the same entries.
+    let entries = parser::parse_entries_from_string(normalized.clone(), &golden_path)
+        .map_err(|e| e.to_string())?;
+    assert!(entries.errors.is_empty(), "{:?}", entries.errors);
+    assert_eq!(entries.len(), ledger.entries().len());
+    assert_eq!(Ledger::new(entries).normalize(), normalized);
 
     Ok(())
 }